@@ -0,0 +1,75 @@
+/*!
+The criterion benchmarks of the hot math: `cargo bench`.
+
+They keep the vector operations and the ray-sphere test honest from one change to the next,
+criterion remembers the times of the previous run and tells whether they changed beyond
+the noise. The crate is a program, not a library, so the benchmarks include the sources
+of the math as modules of their own, the math depends on nothing else.
+`raytracer bench` times the rest of the hot code, the camera and the scattering,
+with the scene around them.
+*/
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// LEARN:
+// The `path` of an inline module is the directory of the modules declared in it,
+// so the files of the math are found where the program has them.
+#[allow(dead_code)]
+#[path = "../src/math"]
+mod math {
+    pub mod rand;
+    pub mod sphere;
+    pub mod vec;
+}
+
+use math::rand::seed;
+use math::sphere::hit_sphere;
+use math::vec::{Ray, Vec3};
+
+/// How many different inputs the operations cycle through, a power of two
+/// small enough for all of them to stay in the cache.
+const INPUTS: usize = 1024;
+
+fn vectors() -> (Vec<Vec3>, Vec<Vec3>) {
+    seed(1);
+    let vectors = (0..INPUTS).map(|_| Vec3::random_in_unit_sphere()).collect();
+    let others = (0..INPUTS).map(|_| Vec3::random_in_unit_sphere()).collect();
+    (vectors, others)
+}
+
+fn vec3(c: &mut Criterion) {
+    let (vectors, others) = vectors();
+    let mut i = 0;
+    let mut next = || {
+        i = (i + 1) % INPUTS;
+        i
+    };
+    c.bench_function("vec3/dot", |b| b.iter(|| {
+        let i = next();
+        Vec3::dot(black_box(&vectors[i]), black_box(&others[i]))
+    }));
+    c.bench_function("vec3/cross", |b| b.iter(|| {
+        let i = next();
+        Vec3::cross(black_box(&vectors[i]), black_box(&others[i]))
+    }));
+    c.bench_function("vec3/unit", |b| b.iter(|| black_box(&vectors[next()]).unit()));
+}
+
+fn sphere(c: &mut Criterion) {
+    let (vectors, others) = vectors();
+    // the rays from in front of the sphere, most of them hit it and some pass by it
+    let rays: Vec<Ray> = vectors.iter()
+        .zip(&others)
+        .map(|(v, o)| Ray::from(Vec3::new(v.x(), v.y(), 3.), Vec3::new(0.3 * o.x(), 0.3 * o.y(), -1.)))
+        .collect();
+    let center = Vec3::zero();
+    let mut i = 0;
+    c.bench_function("sphere/hit", |b| b.iter(|| {
+        i = (i + 1) % INPUTS;
+        hit_sphere(black_box(&rays[i]), 0.001, f32::MAX, &center, 1.)
+    }));
+}
+
+criterion_group!(benches, vec3, sphere);
+criterion_main!(benches);
@@ -0,0 +1,168 @@
+/**
+The render of many scene files in one run: `raytracer batch <scene-file>... [OPTIONS]`,
+e.g. for a catalog of the images of the materials or of the models.
+
+The scenes share the options, and every one is written to the directory of the output
+under the name of its file: `scenes/glass.toml` becomes `out/glass.png` for `--output out/all.png`.
+The patterns like `glass_*.toml` are expanded here as well, for the scripts that quote them
+(only the name of the file may have the wildcards, not the directories).
+
+A scene uses all the cores, but not all the time: loading it, the last tiles of a pass
+and writing the image run on fewer. With `--parallel <n>` the n scenes render at once,
+each on its share of the threads, which keeps the cores busy with many small scenes.
+
+At the end a table of the scenes, how long each took and where it went is printed.
+*/
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::cli::Options;
+use crate::renderer::settings::RenderSettings;
+use crate::scene::file;
+
+/// What became of a scene: the time of its render or why it failed.
+struct Outcome {
+    scene: PathBuf,
+    output: PathBuf,
+    result: Result<Duration, String>,
+}
+
+/// The scene files of the paths and the patterns, the matches of a pattern in the order of their names.
+pub(crate) fn expand(patterns: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut scenes = Vec::new();
+    for pattern in patterns {
+        let path = Path::new(pattern);
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        if !name.contains(['*', '?']) {
+            scenes.push(path.to_path_buf());
+            continue;
+        }
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let entries = std::fs::read_dir(dir).map_err(|why| format!("cannot read {}: {}", dir.display(), why))?;
+        let mut matched: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_str().is_some_and(|file| wildcard(name, file)))
+            .map(|entry| path.with_file_name(entry.file_name()))
+            .collect();
+        if matched.is_empty() {
+            return Err(format!("no scene file matches {}", pattern));
+        }
+        matched.sort();
+        scenes.extend(matched);
+    }
+    Ok(scenes)
+}
+
+/// Whether the name matches the pattern, where `*` is any text and `?` is any character.
+fn wildcard(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    // LEARN:
+    // The matches of the prefixes of the pattern against all the prefixes of the name,
+    // one row of the table at a time: `*` matches the empty text (the cell to the left)
+    // or one more character (the cell above), so the row follows from the previous one.
+    let mut row: Vec<bool> = (0..=name.len()).map(|i| i == 0).collect();
+    for p in pattern {
+        let mut next = vec![p == '*' && row[0]; name.len() + 1];
+        for i in 1..=name.len() {
+            next[i] = match p {
+                '*' => next[i - 1] || row[i],
+                '?' => row[i - 1],
+                c => row[i - 1] && name[i - 1] == c,
+            };
+        }
+        row = next;
+    }
+    row[name.len()]
+}
+
+/// Renders the scenes with the options, `parallel` of them at once,
+/// and prints the table of the results. Returns whether all of them rendered.
+pub(crate) fn run(scenes: &[PathBuf], options: &Options, parallel: usize) -> bool {
+    let outputs = match outputs(scenes, &options.output) {
+        Ok(outputs) => outputs,
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            return false;
+        }
+    };
+    let parallel = parallel.clamp(1, scenes.len().max(1));
+    let settings = RenderSettings {
+        threads: (options.settings.thread_count() / parallel).max(1),
+        ..options.settings.clone()
+    };
+
+    // the scenes are taken in the order of the list by whichever render is free
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::new());
+    std::thread::scope(|s| {
+        for _ in 0..parallel {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= scenes.len() {
+                    break;
+                }
+                let result = render(&scenes[i], options, &settings, &outputs[i]);
+                outcomes.lock().unwrap().push((i, Outcome { scene: scenes[i].clone(), output: outputs[i].clone(), result }));
+            });
+        }
+    });
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|(i, _)| *i);
+    let outcomes: Vec<Outcome> = outcomes.into_iter().map(|(_, outcome)| outcome).collect();
+    print_table(&outcomes);
+    outcomes.iter().all(|outcome| outcome.result.is_ok())
+}
+
+/// The output of every scene: the name of the scene file with the extension of the output,
+/// in the directory of the output.
+fn outputs(scenes: &[PathBuf], output: &Path) -> Result<Vec<PathBuf>, String> {
+    let dir = output.parent().unwrap_or(Path::new(""));
+    let extension = output.extension().unwrap_or("ppm".as_ref());
+    let mut seen = HashSet::new();
+    scenes.iter()
+        .map(|scene| {
+            let stem = scene.file_stem().ok_or_else(|| format!("{} is not a file", scene.display()))?;
+            let output = dir.join(stem).with_extension(extension);
+            // two scenes of the same name in different directories would overwrite each other
+            if !seen.insert(output.clone()) {
+                return Err(format!("two scenes are written to {}, rename one of them", output.display()));
+            }
+            Ok(output)
+        })
+        .collect()
+}
+
+fn render(scene: &Path, options: &Options, settings: &RenderSettings, output: &Path) -> Result<Duration, String> {
+    let start = Instant::now();
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|why| format!("cannot create {}: {}", dir.display(), why))?;
+    }
+    let mut loaded = file::load(scene).map_err(|why| why.to_string())?;
+    for layer in &options.layers {
+        file::layer(&mut loaded, layer).map_err(|why| format!("{}: {}", layer.display(), why))?;
+    }
+    for edit in &options.edits {
+        edit.apply(&mut loaded)?;
+    }
+    crate::render(&mut loaded, settings, output).map_err(|why| why.to_string())?;
+    Ok(start.elapsed())
+}
+
+fn print_table(outcomes: &[Outcome]) {
+    let width = outcomes.iter().map(|outcome| outcome.scene.display().to_string().len()).max().unwrap_or(0).max(5);
+    println!("{:<width$}  {:>9}  output", "scene", "time");
+    for outcome in outcomes {
+        let scene = outcome.scene.display();
+        match &outcome.result {
+            Ok(time) => println!("{:<width$}  {:>7.1} s  {}", scene, time.as_secs_f64(), outcome.output.display()),
+            Err(why) => println!("{:<width$}  {:>9}  {}", scene, "failed", why),
+        }
+    }
+    let total: Duration = outcomes.iter().filter_map(|outcome| outcome.result.as_ref().ok()).sum();
+    let failed = outcomes.iter().filter(|outcome| outcome.result.is_err()).count();
+    println!("{} scenes in {:.1} s of rendering, {} failed", outcomes.len(), total.as_secs_f64(), failed);
+}
@@ -0,0 +1,115 @@
+/**
+The micro-benchmarks of the hot math: `raytracer bench [<filter>]`.
+
+A render calls the vector operations, the ray-sphere test and the scattering billions
+of times, a nanosecond lost in one of them is a minute of the render. The benchmarks
+time them alone, so a change of the math can be checked before and after it.
+
+The math alone is benchmarked by criterion too (`cargo bench`, see `benches/math.rs`),
+which includes its sources. The benchmarks of Cargo can only reach the public items
+of a library, and this crate is a program that keeps its items to itself, so the rest,
+which needs the scene around it, is timed here by a harness written by hand. It reports
+the median of several samples, which is what matters when the numbers are compared.
+
+LEARN:
+The compiler removes the work whose result is never used, and computes ahead the work
+whose inputs it knows, so a naive benchmark times nothing. `std::hint::black_box` hides
+the value from the optimizer: it has to assume the value is read and may be anything.
+*/
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::math::rand::{drand32, seed};
+use crate::math::vec::{Ray, Vec3};
+use crate::scene::arena::Arena;
+use crate::scene::camera::{Camera, RaySource};
+use crate::scene::material::{Material, Scatterable};
+use crate::scene::surfaces::hitable::{Hitable, HitRecord};
+use crate::scene::surfaces::Surface;
+
+/// How many different inputs the operations cycle through, a power of two
+/// small enough for all of them to stay in the cache.
+const INPUTS: usize = 1024;
+
+/// How long a sample takes at least, and how many samples are taken.
+const SAMPLE_TIME: Duration = Duration::from_millis(20);
+const SAMPLES: usize = 15;
+
+/// Runs the benchmarks whose names contain the filter and prints the time of an operation.
+pub(crate) fn run(filter: Option<&str>) {
+    seed(1);
+    let vectors: Vec<Vec3> = (0..INPUTS).map(|_| Vec3::random_in_unit_sphere()).collect();
+    let others: Vec<Vec3> = (0..INPUTS).map(|_| Vec3::random_in_unit_sphere()).collect();
+    let coordinates: Vec<(f32, f32)> = (0..INPUTS).map(|_| (drand32(), drand32())).collect();
+    // the rays from in front of the sphere, most of them hit it and some pass by it
+    let rays: Vec<Ray> = vectors.iter()
+        .zip(&others)
+        .map(|(v, o)| Ray::from(Vec3::new(v.x(), v.y(), 3.), Vec3::new(0.3 * o.x(), 0.3 * o.y(), -1.)))
+        .collect();
+
+    let mut materials = Arena::new();
+    let material = materials.alloc(Material::lambertian(Vec3::new(0.5, 0.5, 0.5)));
+    let sphere = Surface::sphere(Vec3::zero(), 1., material);
+    let camera = Camera::static_camera(2.);
+    let lambertian = Material::lambertian(Vec3::new(0.5, 0.5, 0.5));
+    let glass = Material::dielectric(1.5);
+    let record = |i: usize| HitRecord {
+        t: 1.,
+        p: vectors[i].clone(),
+        normal: vectors[i].unit(),
+        u: 0.,
+        v: 0.,
+        uv_rate: [0., 0.],
+        color: None,
+        tangent: None,
+        material,
+    };
+
+    // LEARN:
+    // Every closure is a type of its own, and the harness is generic over it, so every
+    // benchmark gets a copy of the harness with the operation inlined into its loop.
+    // A list of `&dyn Fn` would be shorter, but the indirect call would cost as much
+    // as the dot product it calls.
+    let mut matched = false;
+    let mut bench = |name: &str, operation: &dyn Fn() -> f64| {
+        if filter.is_none_or(|filter| name.contains(filter)) {
+            matched = true;
+            println!("{:<20} {:>8.2} ns", name, operation());
+        }
+    };
+    bench("vec3/dot", &|| median_time(|i| { black_box(Vec3::dot(&vectors[i], &others[i])); }));
+    bench("vec3/cross", &|| median_time(|i| { black_box(Vec3::cross(&vectors[i], &others[i])); }));
+    bench("vec3/unit", &|| median_time(|i| { black_box(vectors[i].unit()); }));
+    bench("vec3/algebra", &|| median_time(|i| { black_box(&vectors[i] + 2. * &others[i] - &vectors[i] * &others[i]); }));
+    bench("sphere/intersect", &|| median_time(|i| { black_box(sphere.intersect(&rays[i], 0.001, f32::MAX)); }));
+    bench("sphere/hit", &|| median_time(|i| { black_box(sphere.hit(&rays[i], 0.001, f32::MAX)); }));
+    bench("camera/get_ray", &|| median_time(|i| { black_box(camera.get_ray(coordinates[i].0, coordinates[i].1)); }));
+    bench("scatter/lambertian", &|| median_time(|i| { black_box(lambertian.scatter(&rays[i], record(i), 0.)); }));
+    bench("scatter/dielectric", &|| median_time(|i| { black_box(glass.scatter(&rays[i], record(i), 0.)); }));
+    if !matched {
+        println!("No benchmark matches {}", filter.unwrap_or_default());
+    }
+}
+
+/// The median time of the operation on the input of the index, in nanoseconds.
+fn median_time(operation: impl Fn(usize)) -> f64 {
+    // the batch grows until it takes long enough for the clock to time it precisely,
+    // which also warms up the caches and the branch predictor
+    let mut batch = 1;
+    while time_batch(&operation, batch) < SAMPLE_TIME {
+        batch *= 2;
+    }
+    let mut samples: Vec<f64> = (0..SAMPLES)
+        .map(|_| time_batch(&operation, batch).as_nanos() as f64 / batch as f64)
+        .collect();
+    samples.sort_by(f64::total_cmp);
+    samples[SAMPLES / 2]
+}
+
+fn time_batch(operation: &impl Fn(usize), batch: usize) -> Duration {
+    let start = Instant::now();
+    for i in 0..batch {
+        operation(black_box(i % INPUTS));
+    }
+    start.elapsed()
+}
@@ -0,0 +1,1047 @@
+/**
+This module parses the command line arguments.
+
+There are great crates for this (e.g. clap), but the options are simple enough
+to be parsed by hand, and it shows how to work with iterators over the arguments.
+*/
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::{self, Config};
+use crate::math::color::{Color, ColorSpace};
+use crate::math::vec::Vec3;
+use crate::renderer::accumulator::Precision;
+use crate::renderer::analysis::HistogramFormat;
+use crate::renderer::aov::Aov;
+use crate::renderer::denoise::Denoiser;
+use crate::renderer::exr;
+use crate::renderer::guard::NonFinite;
+use crate::renderer::lens::Lens;
+use crate::renderer::observer;
+use crate::renderer::output::{self, Dither, Format};
+use crate::renderer::postprocess::PostEffect;
+use crate::renderer::settings::{Preset, RenderSettings};
+use crate::renderer::stereo;
+use crate::renderer::tiles::TileOrder;
+use crate::renderer::video;
+use crate::scene::animation::Animation;
+use crate::scene::builder::{BuiltIn, SceneBuilder, Theme};
+use crate::scene::check::Severity;
+use crate::scene::drop;
+use crate::scene::edit::Edit;
+use crate::scene::file::{self, LoadError};
+use crate::scene::material::Material;
+use crate::scene::surfaces::stream;
+use crate::scene::Scene;
+
+pub(crate) const USAGE: &str = "\
+Usage: raytracer [OPTIONS]
+       raytracer check [--strict] [--search-path <dirs>] <scene-file>
+       raytracer diff <old-scene-file> <new-scene-file>
+       raytracer migrate <scene-file>...
+       raytracer hash [--expect <hash>] [OPTIONS]
+       raytracer resume <output>
+       raytracer bench [<filter>]
+       raytracer variants <variants-file> [OPTIONS]
+       raytracer batch <scene-file>... [--parallel <n>] [OPTIONS]
+       raytracer sweep [--seeds <n>] [OPTIONS]
+       raytracer layout [OPTIONS]
+       raytracer furnace [<material>...] [OPTIONS]
+       raytracer drop [<seed>] [--restitution <r>] [OPTIONS]
+       raytracer bake <name> [--ao <distance>] [OPTIONS]
+
+Check validates the scene file without rendering it and prints the problems,
+it fails on the errors, and with --strict on the warnings too; the missing textures,
+models and other files are looked for in the --search-path as the render does.
+
+Diff compares two versions of a scene file and prints the objects, the materials,
+the camera and the other settings that were added, removed or changed, the objects
+are matched by their names; it fails when the scenes differ, like diff.
+
+Migrate rewrites the scene files of the older versions of the format in the current one,
+the comments and the layout of the files are kept.
+
+Hash renders the scene in memory and prints the hash of the linear frame,
+with --expect it fails when the hash differs, e.g. to catch unintended changes
+of the image in CI. The options of the output files do not change the hash.
+
+Resume continues the interrupted --tiled render or animation of the output image,
+with the same options in the same directory, the finished tiles and frames are kept.
+The animations written into a video start over.
+
+Bench times the vector math, the ray-sphere test, the camera rays and the scattering,
+the ones whose names contain the filter, e.g. `bench vec3`, build it with --release.
+
+Variants renders the scene once for every [[variant]] of the file, each with its
+materials and moves of the named objects, to its own output next to --output.
+The scene is loaded and its models are built only once.
+
+Batch renders every scene file with the options, to the directory of --output
+under the name of the scene, e.g. `batch scenes/*.toml --output out/all.png`,
+n scenes at once with --parallel, and prints the table of the times and the outputs.
+
+Sweep renders the scene with n seeds (default: 16), each with few samples, e.g. --samples 4,
+writes the mean and the variance of every pixel next to the output, with the _mean
+and _variance suffixes, and prints the average variance, e.g. to compare the noise
+of the sampling before and after a change.
+
+Layout draws every object of the scene as a flat silhouette in a color of its own
+with its bounding box over it, one ray per pixel and no path tracing, to check
+where the objects of a huge scene are in a moment; a PNG when the output is .png.
+
+Furnace renders a sphere of every material, written as in the scene file, e.g.
+'{ type = \"metal\", albedo = [1, 1, 1], fuzz = 0.5 }', or of a few built-in ones,
+in the uniform white light and prints its average next to the albedo it should have:
+the material that returns less light loses some, e.g. to the rays scattered under
+the surface, and it fails when a material returns more light than it gets.
+
+Drop renders the animation of the spheres falling onto the floor, bouncing
+and settling, 72 frames by default, with the shutter half open, so the fast
+spheres blur: the demo of the animations and of the motion blur. The same seed,
+any text (default: 0), drops the same spheres; the restitution is the share of the speed
+they keep when they bounce (default: 0.6).
+
+Bake renders the light that falls on the objects of the name or the tag into
+their texture, the lightmap for the games, of the size of the image, e.g.
+--width 512 --height 512, to the output: every texel is the irradiance over π,
+the light the white matte surface would send there, the linear light when
+the output is .exr. Only the meshes and the spheres have the texture coordinates.
+With --ao it bakes the ambient occlusion instead, the share of the directions over
+the surface where nothing is closer than the distance. With the output .ply the meshes
+are written with the baked value as the color of every vertex instead.
+
+Options:
+  --config <path>           the configuration file, by default raytracer.toml
+                            in the current directory or its parents
+  --no-config               ignore the configuration file
+  --scene <name>            built-in scene to render: default, random or earth,
+                            the globe textured with textures/earthmap.ppm,
+                            the random spheres by night, in the fog or in
+                            the studio: random-night, random-foggy, random-studio,
+                            or focus-chart, the cards at the distances 1 to 10
+                            focused on the 5, for trying out --focus-pixel,
+                            or furnace, the white sphere in the white light
+                            (default: default); the random scenes take a seed, any text
+                            after a colon, e.g. random-night:blue whale, the same seed
+                            places the same spheres anywhere and the image notes it
+  --scene-file <path>       render the scene from the file instead
+  --layer <path>            add the scene file over the scene, e.g. a rig of the lights
+                            or a camera shared by many scenes: its surfaces and lights
+                            are added, its [image], [camera] and [fog] replace the ones
+                            of the scene; repeat it for more layers, the later win
+  --search-path <dirs>      the directories the models, the textures and the included
+                            files of the scene files are looked for in when they are
+                            not next to the file, separated as in PATH, before the ones
+                            of RAYTRACER_SEARCH_PATH
+  --material <name>=<material>
+                            make the objects of the name or the tag of the material,
+                            written as in the scene file, e.g.
+                            hero='{ type = \"metal\", albedo = [1, 0.8, 0.3], fuzz = 0.1 }'
+  --move <name>=<x,y,z>     move the objects of the name or the tag by the offset
+  --watch                   re-render the scene file with the preview quality
+                            every time it changes, until interrupted
+  --tweak                   adjust the scene with the sliders of a window, every change
+                            is rendered with a preview first and then refined pass
+                            by pass (needs the `tweaker` feature)
+  --thumbnail <width>       render a small denoised RGBA preview quickly
+                            and write it to the output as a PAM image
+  --output <path>           path of the PPM image (default: ../result.ppm),
+                            or of the PNG image when it ends in .png,
+                            or of the OpenEXR image of the linear light when it ends in .exr,
+                            relative to the output directory of the configuration,
+                            - writes the image to the standard output
+  --format <name>           ppm or png, the format of the image written to the standard
+                            output or to a path without the extension (default: ppm)
+  --turntable <frames>      render an animation of the camera orbiting the scene,
+                            the frames are numbered images next to the output,
+                            or an animated image when the output is .gif,
+                            or a video when it is .mp4, .webm, .mkv, .mov
+                            or .avi (needs ffmpeg)
+  --camera-path <path>      render an animation of the camera flying along the curve
+                            of the file, looking at a point or along a curve of its own,
+                            written as for --turntable, see the animation module
+  --frames <n>              render an animation of the camera of the scene standing still,
+                            e.g. for --script, written as for --turntable
+  --script <path>           run the Rhai script before every frame of the animation,
+                            it moves the objects, changes their materials, the lights
+                            and the camera by the frame and the time (needs the `script`
+                            feature), see src/scene/script.rs
+  --fps <n>                 frames per second of the video or GIF (default: 24)
+  --shutter <fraction>      blur what moves in the animation: the shutter is open for
+                            the fraction of the time between the frames, e.g. 0.5,
+                            0 is the sharp frames (default: 0)
+  --preset <name>           draft, medium or final: the bundles of the quality settings,
+                            the other options adjust the preset wherever they are given
+  --width <pixels>          image width, the scene's by default
+  --height <pixels>         image height, when only one of the sizes is given
+                            the other one keeps the aspect ratio of the scene
+  --pixel-aspect <ratio>    width of a pixel relative to its height (default: 1)
+  --resolution-scale <f>    render at this fraction of the size, e.g. 0.5
+  --render-scale <n>        render n times larger and downsample, e.g. 2 or 4
+  --no-dof                  render through the pinhole, without the depth of field
+                            of the camera of the scene
+  --focus-pixel <x> <y>     focus the camera on what is seen at the pixel,
+                            the top-left pixel is 0 0
+  --samples <n>             rays per pixel (default: 100)
+  --seed <n>                seed of the random numbers, the same seed renders
+                            the same image (default: 0)
+  --max-depth <n>           maximum number of bounces of a ray (default: 50)
+  --diffuse-depth <n>       maximum number of diffuse bounces of a ray
+  --reflection-depth <n>    maximum number of reflections of a ray
+  --refraction-depth <n>    maximum number of refractions of a ray,
+                            the kinds are limited only by --max-depth by default
+  --min-roughness <r>       blur the reflections and refractions after the second bounce
+                            at least this much, 0..1, removes the fireflies and lets
+                            the lights and the sun through them, the caustics (default: 0)
+  --unbiased                turn off the biased shortcuts: --min-roughness, the depths
+                            of the kinds, --denoise, the f16 accumulation, the pixel budget
+                            and the filtering of the textures, the image and the statistics
+                            list those used
+  --non-finite <mode>       black, magenta or panic: the samples that are NaN or infinite
+                            are black, make their pixels magenta, or stop the render
+                            and print the path of the sample (default: black)
+  --pixel-rays <n>          stop sampling a pixel after it traced n rays, the bounces
+                            and the shadow rays included, and log it
+  --pixel-time <ms>         stop sampling a pixel after it took this long, and log it;
+                            both keep the pixels that cost the most from holding up
+                            the render, the stopped pixels are noisier
+  --passes <n>              render the samples in n passes over the whole image,
+                            the image is written after every pass (default: 1)
+  --coarse-to-fine          render a coarse image first, then all the passes of its busy
+                            quarter, the edges and the noisy parts, before the rest;
+                            the coarse image stands in for the rest until its turn
+  --accumulation <name>     f32 or f16: the precision of the sums of the samples,
+                            f16 takes a third of the memory, clips above 65504
+                            and allows at most 65504 samples (default: f32)
+  --threads <n>             rendering threads (default: all the cores)
+  --tile-order <name>       spiral, hilbert or scanline: the order the tiles are started in,
+                            spiral renders the middle of the image first (default: spiral)
+  --progress <format>       human or json: json writes the progress to the standard error
+                            as a line of JSON per event, for the scripts (default: human)
+  --no-ray-cache            compute the camera rays for every sample instead of once
+                            for the frame, slower, the image is the same
+  --pin-threads             keep every rendering thread on a core of its own
+  --numa                    spread the threads over the NUMA nodes of a multi-socket
+                            machine and copy the scene into the memory of every node
+  --tiled <size>            write the tiles of size x size pixels to the disk as they
+                            are done and join them at the end, for very large images;
+                            cannot be combined with the effects on the whole frame
+  --memory-budget <MiB>     keep the render within the memory: the heavy meshes are
+                            memory-mapped from the disk and their trees are built
+                            when the rays need them, evicted when the budget is full;
+                            fails early when the rest of the render does not fit
+  --preview                 write a fast low resolution preview first,
+                            next to the output with the _preview suffix
+  --auto-exposure[=<key>]   expose the image so its average brightness is the key
+                            (default: 0.18, the middle gray)
+  --histogram[=<format>]    write the luminance histogram in stops next to the image,
+                            text or json (default: text)
+  --false-color             write the exposure zones of the image in false colors,
+                            red is clipped, green is the middle gray
+  --aovs <names>            also write the passes of the surfaces, comma separated:
+                            normal, depth, albedo and id, and the map of the convergence:
+                            samples (taken by every pixel) and error (estimated, relative),
+                            as the layers of the .exr output or as the images next to
+                            the others, e.g. result_normal.ppm
+  --web-viewer              also write the tiles of the image and the page that views them
+                            in the browser with the pan and the zoom, e.g. result_web/index.html
+  --anaglyph[=<separation>] render the red and cyan anaglyph of the two eyes, for the glasses,
+                            the eyes are the share of the distance in focus apart
+                            (default: 1/30), the surfaces in focus are at the screen
+  --bracket [<stops>]       also write images at the given exposure stops,
+                            comma separated (default: -2,0,2)
+  --color-space <name>      srgb, rec709 or linear (default: srgb)
+  --denoise, --no-denoise   smooth the noise of the image, blurs the fine details
+  --dither <name>           triangular, ordered or off (default: triangular)
+  --bloom <strength>        add the glow around highlights brighter than white
+  --vignette <strength>     darken the corners, 0..1
+  --depth-fog <near>,<far>,<density>[,<r>,<g>,<b>]
+                            fade the surfaces into the color of the fog by their
+                            distance, from near on and no more than at far,
+                            the cheap fog of the depth pass (default color: 0.7,0.75,0.8)
+                            post effects are applied in the order of the options
+  --distortion <k>          lens distortion, positive is barrel, negative is pincushion
+  --chromatic-aberration <amount>
+                            difference of red and blue magnification, e.g. 0.01
+  --lens-in-post            apply the lens effects to the rendered image (fast)
+                            instead of bending the camera rays (correct)
+  --help                    print this message";
+
+pub(crate) enum SceneSource {
+    BuiltIn(BuiltIn),
+    File(PathBuf),
+}
+
+impl SceneSource {
+    pub(crate) fn load(&self) -> Result<Scene, LoadError> {
+        match self {
+            SceneSource::BuiltIn(builder) => Ok(builder.build()),
+            SceneSource::File(path) => file::load(path),
+        }
+    }
+}
+
+pub(crate) struct Options {
+    pub(crate) scene: SceneSource,
+    /// The scene files layered over the scene, in the order of the options, see `file::layer`.
+    pub(crate) layers: Vec<PathBuf>,
+    /// The changes of the named objects of the scene, in the order of the options.
+    pub(crate) edits: Vec<Edit>,
+    /// The directories of `--search-path`, see `Options::apply_to_process`.
+    pub(crate) search_path: Vec<PathBuf>,
+    pub(crate) output: PathBuf,
+    pub(crate) settings: RenderSettings,
+    pub(crate) watch: bool,
+    pub(crate) thumbnail: Option<usize>,
+    #[cfg(feature = "tweaker")]
+    pub(crate) tweak: bool,
+}
+
+impl Options {
+    /// Applies the options that are the settings of the whole process: the search path
+    /// of the files of the scenes, see `file::locate`, and the streaming of the meshes
+    /// within the memory budget. They are applied once the command line is parsed,
+    /// so the one with a mistake changes nothing.
+    pub(crate) fn apply_to_process(&self) {
+        file::set_search_path(self.search_path.clone());
+        if self.settings.memory_budget.is_some() {
+            stream::enable();
+        }
+    }
+
+    /// The scene with the layers and then the edits of the options applied.
+    pub(crate) fn load_scene(&self) -> Result<Scene, LoadError> {
+        let mut scene = self.scene.load()?;
+        for path in &self.layers {
+            file::layer(&mut scene, path).map_err(|why| match why {
+                LoadError::Invalid(why) => LoadError::Invalid(format!("{}: {}", path.display(), why)),
+                other => other,
+            })?;
+        }
+        for edit in &self.edits {
+            edit.apply(&mut scene)?;
+        }
+        let (errors, warnings): (Vec<_>, Vec<_>) = scene.validate().into_iter()
+            .partition(|diagnostic| diagnostic.severity == Severity::Error);
+        for warning in &warnings {
+            eprintln!("Warning: {}: {}", warning.location, warning.message);
+        }
+        if !errors.is_empty() {
+            let errors: Vec<_> = errors.iter().map(|error| format!("{}: {}", error.location, error.message)).collect();
+            return Err(LoadError::Invalid(errors.join(", ")));
+        }
+        Ok(scene)
+    }
+}
+
+/// The result of the parsing: what to do with the options or the scene file.
+pub(crate) enum Command {
+    Render(Box<Options>),
+    Check { path: PathBuf, strict: bool, search_path: Vec<PathBuf> },
+    /// Compare the old and the new version of the scene file.
+    Diff { old: PathBuf, new: PathBuf },
+    /// Upgrade the scene files to the current version of the format.
+    Migrate(Vec<PathBuf>),
+    /// Render with the options and print the hash of the frame, or compare it with the expected one.
+    Hash { options: Box<Options>, expect: Option<u64> },
+    /// Continue the tiled render or the animation of the output image.
+    Resume(PathBuf),
+    /// Time the hot math, only the benchmarks whose names contain the filter.
+    Bench(Option<String>),
+    /// Render the variants of the file with the options.
+    Variants { path: PathBuf, options: Box<Options> },
+    /// Render the scene files, or the files matching the patterns, `parallel` of them at once.
+    Batch { scenes: Vec<String>, options: Box<Options>, parallel: usize },
+    /// Render the scene with the seeds and write the mean and the variance of the pixels.
+    Sweep { options: Box<Options>, seeds: usize },
+    /// Draw the silhouettes and the bounding boxes of the objects instead of rendering them.
+    Layout(Box<Options>),
+    /// Measure the albedo of the materials, written as in the scene file, in the furnace.
+    Furnace { materials: Vec<String>, options: Box<Options> },
+    /// Render the animation of the spheres of the seed dropped with the restitution.
+    Drop { seed: String, restitution: f32, options: Box<Options> },
+    /// Render the light on the objects of the name or the tag into their texture,
+    /// or the ambient occlusion within the distance.
+    Bake { name: String, occlusion: Option<f32>, options: Box<Options> },
+    Help,
+}
+
+impl Command {
+    /// The options of the render the command runs with, if it renders.
+    pub(crate) fn options(&self) -> Option<&Options> {
+        match self {
+            Command::Render(options) | Command::Layout(options) => Some(options),
+            Command::Hash { options, .. }
+            | Command::Variants { options, .. }
+            | Command::Batch { options, .. }
+            | Command::Sweep { options, .. }
+            | Command::Furnace { options, .. }
+            | Command::Drop { options, .. }
+            | Command::Bake { options, .. } => Some(options),
+            Command::Check { .. } | Command::Diff { .. } | Command::Migrate(_) | Command::Resume(_)
+            | Command::Bench(_) | Command::Help => None,
+        }
+    }
+}
+
+pub(crate) fn parse<I: Iterator<Item = String>>(args: I) -> Result<Command, String> {
+    let args: Vec<String> = args.collect();
+    if args.first().is_some_and(|arg| arg == "check") {
+        return parse_check(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "diff") {
+        return match &args[1..] {
+            [old, new] => Ok(Command::Diff { old: PathBuf::from(old), new: PathBuf::from(new) }),
+            _ => Err("diff needs the paths of the old and the new scene file".to_string()),
+        };
+    }
+    if args.first().is_some_and(|arg| arg == "migrate") {
+        return match &args[1..] {
+            [] => Err("migrate needs the paths of the scene files".to_string()),
+            files => Ok(Command::Migrate(files.iter().map(PathBuf::from).collect())),
+        };
+    }
+    if args.first().is_some_and(|arg| arg == "hash") {
+        return parse_hash(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "resume") {
+        return match &args[1..] {
+            [output] => Ok(Command::Resume(PathBuf::from(output))),
+            _ => Err("resume needs the path of the output image".to_string()),
+        };
+    }
+    if args.first().is_some_and(|arg| arg == "bench") {
+        return match &args[1..] {
+            [] => Ok(Command::Bench(None)),
+            [filter] => Ok(Command::Bench(Some(filter.clone()))),
+            _ => Err("bench takes at most one filter".to_string()),
+        };
+    }
+    if args.first().is_some_and(|arg| arg == "variants") {
+        return parse_variants(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "batch") {
+        return parse_batch(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "sweep") {
+        return parse_sweep(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "layout") {
+        return parse_layout(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "furnace") {
+        return parse_furnace(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "drop") {
+        return parse_drop(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "bake") {
+        return parse_bake(&args[1..]);
+    }
+
+    // The configuration file and the preset are the base the other options adjust,
+    // so they are applied first, wherever they are given.
+    let config = if args.iter().any(|arg| arg == "--no-config") {
+        Config::default()
+    } else {
+        match find_value(&args, "--config").map(PathBuf::from).or_else(config::find) {
+            Some(path) => config::load(&path)?,
+            None => Config::default(),
+        }
+    };
+    let mut settings = config.settings;
+    if let Some(name) = find_value(&args, "--preset") {
+        Preset::parse(&name)
+            .ok_or_else(|| format!("Unknown preset: {}", name))?
+            .apply(&mut settings);
+    }
+
+    let mut args = args.into_iter().peekable();
+    let mut fps = None;
+    let mut shutter = None;
+    #[cfg(feature = "script")]
+    let mut script = None;
+    let mut options = Options {
+        scene: SceneSource::BuiltIn(BuiltIn::default()),
+        layers: Vec::new(),
+        edits: Vec::new(),
+        search_path: Vec::new(),
+        output: PathBuf::from(if config.output_dir.is_some() { "result.ppm" } else { "../result.ppm" }),
+        settings,
+        watch: false,
+        thumbnail: None,
+        #[cfg(feature = "tweaker")]
+        tweak: false,
+    };
+
+    while let Some(arg) = args.next() {
+        // Both `--name value` and `--name=value` forms are accepted.
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        // the options of the optional value take the next argument unless it is another option
+        let value_follows = args.peek().is_some_and(|next| !next.starts_with("--"));
+
+        // LEARN:
+        // The closure borrows `args` mutably, so it can pull the next argument
+        // only when the option really needs a value.
+        let mut value = || inline_value.clone()
+            .or_else(|| args.next())
+            .ok_or_else(|| format!("Missing value for {}", name));
+
+        match name.as_str() {
+            "--scene" => {
+                let scene = value()?;
+                // e.g. `random-night:blue whale`, the seed is any text after the first colon
+                let (name, seed) = match scene.split_once(':') {
+                    Some((name, seed)) => (name, Some(seed)),
+                    None => (scene.as_str(), None),
+                };
+                options.scene = SceneSource::BuiltIn(match (name, seed, Theme::parse(name)) {
+                    (_, Some(seed), Some(theme)) => BuiltIn::seeded(theme, seed),
+                    (_, None, Some(theme)) => BuiltIn::themed(theme),
+                    (other, Some(_), None) => return Err(format!("Only the random scenes take a seed, not {}", other)),
+                    ("default", None, None) => BuiltIn::default(),
+                    ("earth", None, None) => BuiltIn::earth(),
+                    ("focus-chart", None, None) => BuiltIn::focus_chart(),
+                    ("furnace", None, None) => BuiltIn::furnace(Material::lambertian(Vec3::rgb(1., 1., 1.))),
+                    (other, None, None) => return Err(format!("Unknown scene: {}", other)),
+                })
+            }
+            "--scene-file" => options.scene = SceneSource::File(PathBuf::from(value()?)),
+            "--layer" => options.layers.push(PathBuf::from(value()?)),
+            "--search-path" => options.search_path = std::env::split_paths(&value()?).collect(),
+            "--material" => options.edits.push(Edit::material(&value()?)?),
+            "--move" => options.edits.push(Edit::translation(&value()?)?),
+            "--watch" => options.watch = true,
+            "--thumbnail" => options.thumbnail = Some(parse_count(&value()?)?),
+            #[cfg(feature = "tweaker")]
+            "--tweak" => options.tweak = true,
+            "--output" => options.output = PathBuf::from(value()?),
+            "--turntable" => options.settings.animation = Some(Animation::turntable(parse_count(&value()?)?)),
+            "--camera-path" => options.settings.animation = Some(Animation::flight(&PathBuf::from(value()?))?),
+            "--frames" => options.settings.animation = Some(Animation::still(parse_count(&value()?)?)),
+            #[cfg(feature = "script")]
+            "--script" => script = Some(crate::scene::script::Script::load(&PathBuf::from(value()?))?),
+            "--fps" => fps = Some(parse_count(&value()?)?),
+            "--shutter" => shutter = match parse_number(&value()?)? {
+                fraction if (0. ..=1.).contains(&fraction) => Some(fraction),
+                fraction => return Err(format!("The shutter is open for a fraction from 0 to 1, not {}", fraction)),
+            },
+            "--width" | "--height" => {
+                let (size, _) = file::validate_resolution(parse_count(&value()?)? as i64, 1)?;
+                if name == "--width" {
+                    options.settings.width = Some(size);
+                } else {
+                    options.settings.height = Some(size);
+                }
+            }
+            // applied before the other options
+            "--preset" | "--config" => {
+                value()?;
+            }
+            "--no-config" => {}
+            "--resolution-scale" => {
+                options.settings.resolution_scale = match parse_number(&value()?)? {
+                    scale if scale > 0. && scale <= 1. => scale,
+                    scale => return Err(format!("Resolution scale {} must be above 0 and at most 1", scale)),
+                }
+            }
+            "--pixel-aspect" => {
+                let pixel_aspect = parse_number(&value()?)?;
+                file::validate_pixel_aspect(pixel_aspect)?;
+                options.settings.pixel_aspect = Some(pixel_aspect);
+            }
+            "--render-scale" => {
+                options.settings.render_scale = match parse_count(&value()?)? {
+                    scale @ 1..=16 => scale,
+                    scale => return Err(format!("Render scale {} must be between 1 and 16", scale)),
+                }
+            }
+            "--no-dof" => options.settings.depth_of_field = false,
+            "--focus-pixel" => {
+                let x = parse_index(&value()?)?;
+                let y = parse_index(&args.next().ok_or("Missing y for --focus-pixel")?)?;
+                options.settings.focus_pixel = Some((x, y));
+            }
+            "--samples" => options.settings.samples = parse_count(&value()?)?,
+            "--seed" => {
+                let seed = value()?;
+                options.settings.seed = seed.trim().parse().map_err(|_| format!("Not a seed: {}", seed))?;
+            }
+            "--max-depth" => options.settings.max_depth = parse_count(&value()?)? as i32,
+            "--diffuse-depth" => options.settings.bounce_limits.diffuse = Some(parse_count(&value()?)? as i32),
+            "--reflection-depth" => options.settings.bounce_limits.reflection = Some(parse_count(&value()?)? as i32),
+            "--refraction-depth" => options.settings.bounce_limits.refraction = Some(parse_count(&value()?)? as i32),
+            "--min-roughness" => {
+                options.settings.min_roughness = match parse_number(&value()?)? {
+                    r if (0. ..=1.).contains(&r) => r,
+                    r => return Err(format!("Min roughness {} must be between 0 and 1", r)),
+                }
+            }
+            "--unbiased" => options.settings.unbiased = true,
+            "--non-finite" => {
+                let name = value()?;
+                options.settings.non_finite = NonFinite::parse(&name)
+                    .ok_or_else(|| format!("Unknown non-finite mode: {}", name))?;
+            }
+            "--pixel-rays" => options.settings.pixel_budget.rays = Some(parse_count(&value()?)?.max(1) as u64),
+            "--pixel-time" => options.settings.pixel_budget.time = Some(Duration::from_millis(parse_count(&value()?)?.max(1) as u64)),
+            "--passes" => options.settings.passes = parse_count(&value()?)?.max(1),
+            "--coarse-to-fine" => options.settings.coarse_to_fine = true,
+            "--accumulation" => {
+                let name = value()?;
+                options.settings.precision = Precision::parse(&name)
+                    .ok_or_else(|| format!("Unknown accumulation precision: {}", name))?;
+            }
+            "--threads" => options.settings.threads = parse_count(&value()?)?,
+            "--tile-order" => {
+                let name = value()?;
+                options.settings.tile_order = TileOrder::parse(&name)
+                    .ok_or_else(|| format!("Unknown tile order: {}", name))?;
+            }
+            "--progress" => {
+                let name = value()?;
+                options.settings.observer = observer::parse(&name)
+                    .ok_or_else(|| format!("Unknown progress format: {}", name))?;
+            }
+            "--no-ray-cache" => options.settings.ray_cache = false,
+            "--pin-threads" => options.settings.pin_threads = true,
+            "--numa" => options.settings.numa = true,
+            "--tiled" => options.settings.tiled = Some(parse_count(&value()?)?),
+            "--memory-budget" => options.settings.memory_budget = Some(parse_count(&value()?)? as u64 * 1024 * 1024),
+            "--preview" => options.settings.preview = true,
+            "--auto-exposure" => {
+                options.settings.auto_exposure = Some(match &inline_value {
+                    Some(key) => match parse_number(key)? {
+                        key if key > 0. => key,
+                        key => return Err(format!("Auto exposure key {} must be positive", key)),
+                    },
+                    None => RenderSettings::MIDDLE_GRAY,
+                })
+            }
+            "--histogram" => {
+                options.settings.histogram = Some(match &inline_value {
+                    Some(name) => HistogramFormat::parse(name)
+                        .ok_or_else(|| format!("Unknown histogram format: {}", name))?,
+                    None => HistogramFormat::Text,
+                })
+            }
+            "--false-color" => options.settings.false_color = true,
+            "--web-viewer" => options.settings.web_viewer = true,
+            "--aovs" => {
+                let mut aovs = Vec::new();
+                for name in value()?.split(',') {
+                    let aov = Aov::parse(name).ok_or_else(|| format!(
+                        "Unknown AOV: {}, the AOVs are {}", name, Aov::ALL.map(|aov| aov.name()).join(", ")))?;
+                    if !aovs.contains(&aov) {
+                        aovs.push(aov);
+                    }
+                }
+                options.settings.aovs = aovs;
+            }
+            "--anaglyph" => {
+                let separation = match &inline_value {
+                    Some(separation) => parse_number(separation)?,
+                    None => stereo::DEFAULT_SEPARATION,
+                };
+                if separation <= 0. || !separation.is_finite() {
+                    return Err(format!("The separation of the eyes must be positive: {}", separation));
+                }
+                options.settings.anaglyph = Some(separation);
+            }
+            "--bracket" => {
+                options.settings.exposure_stops = match inline_value.is_some() || value_follows {
+                    true => parse_list(&value()?)?,
+                    false => RenderSettings::default_bracket(),
+                }
+            }
+            "--color-space" => {
+                let name = value()?;
+                options.settings.color_space = ColorSpace::parse(&name)
+                    .ok_or_else(|| format!("Unknown color space: {}", name))?;
+            }
+            "--denoise" => {
+                options.settings.denoise.get_or_insert_with(Denoiser::default);
+            }
+            "--no-denoise" => options.settings.denoise = None,
+            "--format" => {
+                let name = value()?;
+                options.settings.format = Format::parse(&name)
+                    .ok_or_else(|| format!("Unknown image format: {}", name))?;
+            }
+            "--dither" => {
+                let name = value()?;
+                options.settings.dither = Dither::parse(&name)
+                    .ok_or_else(|| format!("Unknown dither: {}", name))?;
+            }
+            "--bloom" => {
+                let strength = parse_number(&value()?)?;
+                options.settings.post_effects.push(PostEffect::bloom(strength));
+            }
+            "--vignette" => {
+                let strength = parse_number(&value()?)?;
+                options.settings.post_effects.push(PostEffect::vignette(strength));
+            }
+            "--depth-fog" => {
+                let values = parse_list(&value()?)?;
+                let (near, far, density, color) = match values[..] {
+                    [near, far, density] => (near, far, density, Vec3::new(0.7, 0.75, 0.8)),
+                    [near, far, density, r, g, b] => (near, far, density, Vec3::new(r, g, b)),
+                    _ => return Err("--depth-fog needs <near>,<far>,<density> and optionally <r>,<g>,<b>".to_string()),
+                };
+                if !(near >= 0. && far > near && density >= 0.) {
+                    return Err(format!("--depth-fog needs 0 <= near < far and a non-negative density, not {},{},{}", near, far, density));
+                }
+                options.settings.post_effects.push(PostEffect::depth_fog(color, near, far, density));
+            }
+            "--distortion" => {
+                options.settings.lens.get_or_insert_with(Lens::default).distortion = parse_number(&value()?)?;
+            }
+            "--chromatic-aberration" => {
+                options.settings.lens.get_or_insert_with(Lens::default).chromatic_aberration = parse_number(&value()?)?;
+            }
+            "--lens-in-post" => {
+                options.settings.lens.get_or_insert_with(Lens::default).in_post = true;
+            }
+            "--help" | "-h" => return Ok(Command::Help),
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+    }
+
+    // the relative paths are in the output directory, the absolute ones replace it
+    if let Some(dir) = config.output_dir.filter(|_| !output::is_stdout(&options.output)) {
+        options.output = dir.join(&options.output);
+    }
+
+    if let Some(fps) = fps {
+        options.settings.animation.as_mut().ok_or("--fps needs an animation, e.g. --turntable")?.fps = fps;
+    }
+    if let Some(shutter) = shutter {
+        options.settings.animation.as_mut().ok_or("--shutter needs an animation, e.g. --turntable")?.shutter = Some(shutter);
+    }
+    #[cfg(feature = "script")]
+    if let Some(script) = script {
+        options.settings.animation.as_mut().ok_or("--script needs an animation, e.g. --frames or --turntable")?.script = Some(std::sync::Arc::new(script));
+    }
+
+    // after all the options, so `--unbiased` wins wherever it is given
+    if options.settings.unbiased {
+        options.settings.remove_bias();
+    }
+
+    if options.settings.animation.is_some() {
+        let settings = &options.settings;
+        if settings.preview || settings.tiled.is_some() || options.watch || output::is_stdout(&options.output) {
+            return Err("the animations cannot be combined with --preview, --tiled, --watch or --output -".to_string());
+        }
+        if video::is_video(&options.output)
+            && (settings.histogram.is_some() || settings.false_color || !settings.exposure_stops.is_empty()) {
+            return Err("the videos cannot be combined with --histogram, --false-color or --bracket, \
+                        write the frames as images instead".to_string());
+        }
+    } else if video::is_video(&options.output) {
+        return Err(format!("{} is a video, the videos are written only for the animations, e.g. --turntable",
+                           options.output.display()));
+    }
+
+    if output::is_stdout(&options.output) {
+        let settings = &options.settings;
+        #[cfg(feature = "tweaker")]
+        let tweak = options.tweak;
+        #[cfg(not(feature = "tweaker"))]
+        let tweak = false;
+        if settings.preview
+            || settings.histogram.is_some()
+            || settings.false_color
+            || settings.web_viewer
+            || !settings.aovs.is_empty()
+            || !settings.exposure_stops.is_empty()
+            || settings.tiled.is_some()
+            || options.watch
+            || tweak {
+            return Err("--output - writes a single image, it cannot be combined with --preview, --histogram, \
+                        --false-color, --web-viewer, --aovs, --bracket, --tiled, --watch or --tweak".to_string());
+        }
+    }
+
+    if options.watch && !matches!(options.scene, SceneSource::File(_)) {
+        return Err("--watch needs a --scene-file to watch".to_string());
+    }
+
+    if options.settings.anaglyph.is_some() && options.settings.aovs.iter().any(|aov| !aov.is_traced()) {
+        return Err("--anaglyph renders two views, it has no map of the convergence for --aovs samples or error".to_string());
+    }
+
+    if options.settings.tiled.is_some()
+        && (exr::is_exr(&options.output) || Format::of(&options.output, options.settings.format) != Format::Ppm) {
+        return Err("--tiled writes the PPM image, not the PNG or the OpenEXR one".to_string());
+    }
+
+    if options.settings.tiled.is_some() {
+        let settings = &options.settings;
+        if settings.render_scale > 1
+            || settings.auto_exposure.is_some()
+            || settings.histogram.is_some()
+            || settings.false_color
+            || settings.web_viewer
+            || !settings.aovs.is_empty()
+            || settings.anaglyph.is_some()
+            || settings.coarse_to_fine
+            || settings.denoise.is_some()
+            || !settings.exposure_stops.is_empty()
+            || !settings.post_effects.is_empty()
+            || settings.lens.as_ref().is_some_and(|lens| lens.in_post) {
+            return Err("--tiled cannot be combined with --render-scale, --auto-exposure, --histogram, \
+                        --false-color, --web-viewer, --aovs, --anaglyph, --coarse-to-fine, --denoise, --bracket, --bloom, --vignette, --depth-fog or --lens-in-post, \
+                        they need the whole frame".to_string());
+        }
+    }
+
+    Ok(Command::Render(Box::new(options)))
+}
+
+/// `check [--strict] [--search-path <dirs>] <scene-file>`, the other options of the render do not apply.
+fn parse_check(args: &[String]) -> Result<Command, String> {
+    let (dirs, args) = take_option(args, "--search-path")?;
+    let search_path = dirs.map(|dirs| std::env::split_paths(&dirs).collect()).unwrap_or_default();
+    let mut path = None;
+    let mut strict = false;
+    for arg in &args {
+        match arg.as_str() {
+            "--strict" => strict = true,
+            "--help" | "-h" => return Ok(Command::Help),
+            other if other.starts_with("--") => return Err(format!("Unknown option of check: {}", other)),
+            other if path.is_none() => path = Some(PathBuf::from(other)),
+            other => return Err(format!("Unexpected argument: {}", other)),
+        }
+    }
+    let path = path.ok_or("Missing the scene file to check")?;
+    Ok(Command::Check { path, strict, search_path })
+}
+
+/// `hash [--expect <hash>]` followed by the options of the render.
+fn parse_hash(args: &[String]) -> Result<Command, String> {
+    let (hash, rest) = take_option(args, "--expect")?;
+    let expect = hash.map(|hash| u64::from_str_radix(hash.trim(), 16).map_err(|_| format!("Not a hash: {}", hash)))
+        .transpose()?;
+    match parse(rest.into_iter())? {
+        Command::Render(options) => Ok(Command::Hash { options, expect }),
+        other => Ok(other),
+    }
+}
+
+/// `variants <variants-file> [OPTIONS]`, every variant is rendered to its own output.
+fn parse_variants(args: &[String]) -> Result<Command, String> {
+    let (path, rest) = args.split_first().ok_or("variants needs the path of the variants file")?;
+    match parse(rest.iter().cloned())? {
+        Command::Render(options) => {
+            reject("variants", &options, &[Conflict::Watch, Conflict::Tweak, Conflict::Thumbnail, Conflict::Tiled, Conflict::Stdout])?;
+            Ok(Command::Variants { path: PathBuf::from(path), options })
+        }
+        other => Ok(other),
+    }
+}
+
+/// `batch <scene-file>... [--parallel <n>] [OPTIONS]`, the scene files come before the options.
+fn parse_batch(args: &[String]) -> Result<Command, String> {
+    let files = args.iter().take_while(|arg| !arg.starts_with("--")).count();
+    if files == 0 {
+        return Err("batch needs the scene files".to_string());
+    }
+    let (count, rest) = take_option(&args[files..], "--parallel")?;
+    let parallel = count.map_or(Ok(1), |count| parse_count(&count))?.max(1);
+    match parse(rest.into_iter())? {
+        Command::Render(options) => {
+            reject("batch", &options, &[Conflict::Watch, Conflict::Tweak, Conflict::Thumbnail, Conflict::Tiled, Conflict::Stdout])?;
+            Ok(Command::Batch { scenes: args[..files].to_vec(), options, parallel })
+        }
+        other => Ok(other),
+    }
+}
+
+/// `sweep [--seeds <n>] [OPTIONS]`.
+fn parse_sweep(args: &[String]) -> Result<Command, String> {
+    let (count, rest) = take_option(args, "--seeds")?;
+    let seeds = match count.map_or(Ok(16), |count| parse_count(&count))? {
+        seeds @ 2.. => seeds,
+        seeds => return Err(format!("The variance needs at least 2 seeds, not {}", seeds)),
+    };
+    match parse(rest.into_iter())? {
+        Command::Render(options) => {
+            reject("sweep", &options, &[Conflict::Watch, Conflict::Tweak, Conflict::Thumbnail, Conflict::Tiled,
+                                        Conflict::Animation, Conflict::Stdout])?;
+            Ok(Command::Sweep { options, seeds })
+        }
+        other => Ok(other),
+    }
+}
+
+/// `layout [OPTIONS]`.
+fn parse_layout(args: &[String]) -> Result<Command, String> {
+    match parse(args.iter().cloned())? {
+        Command::Render(options) => {
+            reject("layout", &options, &[Conflict::Watch, Conflict::Tweak, Conflict::Thumbnail, Conflict::Tiled, Conflict::Animation])?;
+            Ok(Command::Layout(options))
+        }
+        other => Ok(other),
+    }
+}
+
+/// `furnace [<material>...] [OPTIONS]`, the materials are the arguments before the options.
+fn parse_furnace(args: &[String]) -> Result<Command, String> {
+    let count = args.iter().take_while(|arg| !arg.starts_with('-')).count();
+    match parse(args[count..].iter().cloned())? {
+        Command::Render(options) => {
+            reject("furnace", &options, &[Conflict::Watch, Conflict::Tweak, Conflict::Thumbnail, Conflict::Tiled, Conflict::Animation])?;
+            Ok(Command::Furnace { materials: args[..count].to_vec(), options })
+        }
+        other => Ok(other),
+    }
+}
+
+/// `drop [<seed>] [--restitution <r>] [OPTIONS]`, the seed is the argument before the options.
+/// The animation is the one of the options, `--frames 72` unless they have one.
+fn parse_drop(args: &[String]) -> Result<Command, String> {
+    let (seed, args) = match args.split_first() {
+        Some((seed, rest)) if !seed.starts_with('-') => (seed.clone(), rest),
+        _ => ("0".to_string(), args),
+    };
+    let (value, args) = take_option(args, "--restitution")?;
+    let restitution = match value.map_or(Ok(drop::DEFAULT_RESTITUTION), |value| parse_number(&value))? {
+        restitution if (0. ..=1.).contains(&restitution) => restitution,
+        restitution => return Err(format!("The restitution is from 0 to 1, not {}", restitution)),
+    };
+    let rest = ["--frames".to_string(), drop::DEFAULT_FRAMES.to_string()].into_iter().chain(args);
+    match parse(rest)? {
+        Command::Render(options) => {
+            reject("drop", &options, &[Conflict::Tweak, Conflict::Thumbnail])?;
+            if !matches!(options.scene, SceneSource::BuiltIn(BuiltIn::Default)) {
+                return Err("drop cannot be combined with --scene or --scene-file".to_string());
+            }
+            Ok(Command::Drop { seed, restitution, options })
+        }
+        other => Ok(other),
+    }
+}
+
+/// `bake <name> [--ao <distance>] [OPTIONS]`.
+fn parse_bake(args: &[String]) -> Result<Command, String> {
+    let (name, args) = match args.split_first() {
+        Some((name, args)) if !name.starts_with('-') => (name.clone(), args),
+        _ => return Err("bake needs the name or the tag of the objects".to_string()),
+    };
+    let (distance, rest) = take_option(args, "--ao")?;
+    let occlusion = match distance.map(|distance| parse_number(&distance)).transpose()? {
+        Some(distance) if !(distance > 0. && distance.is_finite()) =>
+            return Err(format!("The distance of the occlusion must be positive, not {}", distance)),
+        occlusion => occlusion,
+    };
+    match parse(rest.into_iter())? {
+        Command::Render(options) => {
+            reject("bake", &options, &[Conflict::Watch, Conflict::Tweak, Conflict::Thumbnail, Conflict::Tiled,
+                                       Conflict::Animation, Conflict::Stdout])?;
+            Ok(Command::Bake { name, occlusion, options })
+        }
+        other => Ok(other),
+    }
+}
+
+/// The options of the render a subcommand may not be able to do anything with.
+#[derive(Clone, Copy)]
+enum Conflict {
+    Watch,
+    Tweak,
+    Thumbnail,
+    Tiled,
+    Animation,
+    Stdout,
+}
+
+impl Conflict {
+    fn option(self) -> &'static str {
+        match self {
+            Conflict::Watch => "--watch",
+            Conflict::Tweak => "--tweak",
+            Conflict::Thumbnail => "--thumbnail",
+            Conflict::Tiled => "--tiled",
+            Conflict::Animation => "--turntable",
+            Conflict::Stdout => "--output -",
+        }
+    }
+
+    fn is_set(self, options: &Options) -> bool {
+        match self {
+            Conflict::Watch => options.watch,
+            #[cfg(feature = "tweaker")]
+            Conflict::Tweak => options.tweak,
+            #[cfg(not(feature = "tweaker"))]
+            Conflict::Tweak => false,
+            Conflict::Thumbnail => options.thumbnail.is_some(),
+            Conflict::Tiled => options.settings.tiled.is_some(),
+            Conflict::Animation => options.settings.animation.is_some(),
+            Conflict::Stdout => output::is_stdout(&options.output),
+        }
+    }
+}
+
+/// Fails when the options have any of the conflicts of the subcommand, the error lists them all.
+fn reject(command: &str, options: &Options, conflicts: &[Conflict]) -> Result<(), String> {
+    if !conflicts.iter().any(|conflict| conflict.is_set(options)) {
+        return Ok(());
+    }
+    let names: Vec<&str> = conflicts.iter().map(|conflict| conflict.option()).collect();
+    let (last, others) = names.split_last().expect("a conflict is set");
+    Err(match others {
+        [] => format!("{} cannot be combined with {}", command, last),
+        _ => format!("{} cannot be combined with {} or {}", command, others.join(", "), last),
+    })
+}
+
+/// Takes the option given as `--name value` or `--name=value` out of the arguments,
+/// returns its value, the last one when it is repeated, and the other arguments.
+fn take_option(args: &[String], name: &str) -> Result<(Option<String>, Vec<String>), String> {
+    let mut value = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix(name) {
+            Some("") => value = Some(args.next().ok_or_else(|| format!("Missing value for {}", name))?.clone()),
+            Some(inline) if inline.starts_with('=') => value = Some(inline[1..].to_string()),
+            _ => rest.push(arg.clone()),
+        }
+    }
+    Ok((value, rest))
+}
+
+/// The value of the option given as `--name value` or `--name=value`.
+fn find_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .enumerate()
+        .find_map(|(i, arg)| match arg.strip_prefix(name) {
+            Some("") => Some(args.get(i + 1).cloned().unwrap_or_default()),
+            Some(inline) => inline.strip_prefix('=').map(str::to_string),
+            None => None,
+        })
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    match s.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("Not a positive integer: {}", s)),
+    }
+}
+
+fn parse_index(s: &str) -> Result<usize, String> {
+    s.trim().parse::<usize>().map_err(|_| format!("Not a non-negative integer: {}", s))
+}
+
+fn parse_number(s: &str) -> Result<f32, String> {
+    s.trim().parse::<f32>().map_err(|_| format!("Not a number: {}", s))
+}
+
+/// Parses a comma separated list of numbers, e.g. `-2,0,2`.
+fn parse_list(list: &str) -> Result<Vec<f32>, String> {
+    list.split(',').map(parse_number).collect()
+}
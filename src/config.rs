@@ -0,0 +1,233 @@
+/**
+The project configuration: the defaults of the render settings shared by a team.
+
+The file `raytracer.toml` is looked up in the current directory and its parents,
+so a project can keep it at the top and render from any subdirectory.
+The settings are applied in layers, every next one overrides the previous:
+the built-in defaults, the preset of the file, the settings of the file,
+the `--preset` option and then the other command line options.
+
+```toml
+[render]
+preset = "medium"       # optional, draft, medium or final
+samples = 64
+passes = 4              # the image is written after every pass
+accumulation = "f32"    # f16 takes a third of the memory
+seed = 0                # the same seed renders the same image
+max_depth = 16
+threads = 8             # 0 uses all the cores
+tile_order = "spiral"   # spiral, hilbert or scanline
+progress = "human"      # human or json
+pin_threads = false     # keep every thread on a core
+ray_cache = true        # the camera rays computed once, the image is the same
+depth_of_field = true   # false renders through the pinhole whatever the camera
+numa = false            # a copy of the scene on every NUMA node
+width = 1920
+height = 1080
+resolution_scale = 0.5
+render_scale = 2
+color_space = "srgb"
+dither = "triangular"
+min_roughness = 0.2
+unbiased = false        # true turns off the biased shortcuts
+non_finite = "black"    # magenta or panic find the NaN samples
+pixel_rays = 100000     # stop sampling the pixels that cost the most
+pixel_time = 500        # milliseconds
+
+[output]
+directory = "renders"   # relative to this file, the relative output paths are in it
+
+[denoise]
+enabled = true
+radius = 2              # pixels
+strength = 0.1          # larger is smoother
+```
+*/
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::math::color::ColorSpace;
+use crate::renderer::accumulator::Precision;
+use crate::renderer::denoise::Denoiser;
+use crate::renderer::guard::NonFinite;
+use crate::renderer::observer;
+use crate::renderer::output::Dither;
+use crate::renderer::settings::{Preset, RenderSettings};
+use crate::renderer::tiles::TileOrder;
+use crate::scene::file;
+use crate::toml::{self, boolean, integer, number, optional, string, table, Table};
+
+pub(crate) const FILE_NAME: &str = "raytracer.toml";
+
+#[derive(Default)]
+pub(crate) struct Config {
+    pub(crate) settings: RenderSettings,
+    /// The directory of the output images.
+    pub(crate) output_dir: Option<PathBuf>,
+}
+
+/// The nearest `raytracer.toml` in the current directory or its parents.
+pub(crate) fn find() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+pub(crate) fn load(path: &Path) -> Result<Config, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+    let mut config = parse(&text).map_err(|why| format!("{}: {}", path.display(), why))?;
+    // the project directory, not the one the renderer is started in
+    if let (Some(dir), Some(project)) = (&config.output_dir, path.parent()) {
+        config.output_dir = Some(project.join(dir));
+    }
+    Ok(config)
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    let doc = toml::parse(text).map_err(|why| format!("syntax error at {}", why))?;
+    known_keys(&doc, "", &["render", "output", "denoise"])?;
+
+    let mut settings = RenderSettings::default();
+    if let Some(render) = table(&doc, "render")? {
+        parse_render(render, &mut settings)?;
+    }
+    if let Some(denoise) = table(&doc, "denoise")? {
+        known_keys(denoise, "denoise.", &["enabled", "radius", "strength"])?;
+        let defaults = Denoiser::default();
+        let denoiser = Denoiser {
+            radius: optional(denoise, "radius", integer)?.map_or(Ok(defaults.radius), |r| usize::try_from(r)
+                .map_err(|_| "`radius` must not be negative".to_string()))?,
+            strength: optional(denoise, "strength", number)?.unwrap_or(defaults.strength),
+        };
+        // the parameters alone configure the denoiser for when it is turned on
+        settings.denoise = match optional(denoise, "enabled", boolean)? {
+            Some(false) => None,
+            Some(true) => Some(denoiser),
+            None => settings.denoise.map(|_| denoiser),
+        };
+    }
+
+    let output_dir = match table(&doc, "output")? {
+        Some(output) => {
+            known_keys(output, "output.", &["directory"])?;
+            optional(output, "directory", string)?.map(PathBuf::from)
+        }
+        None => None,
+    };
+
+    Ok(Config { settings, output_dir })
+}
+
+fn parse_render(render: &Table, settings: &mut RenderSettings) -> Result<(), String> {
+    known_keys(render, "render.", &[
+        "preset", "samples", "passes", "accumulation", "seed", "max_depth", "threads", "tile_order", "progress", "ray_cache", "depth_of_field", "pin_threads", "numa", "width", "height", "resolution_scale",
+        "render_scale", "color_space", "dither", "min_roughness", "unbiased", "non_finite",
+        "pixel_rays", "pixel_time",
+    ])?;
+
+    if let Some(name) = optional(render, "preset", string)? {
+        *settings = RenderSettings::preset(Preset::parse(&name).ok_or(format!("unknown preset `{}`", name))?);
+    }
+    let count = |key: &str| -> Result<Option<usize>, String> {
+        match optional(render, key, integer)? {
+            Some(n) if n < 0 => Err(format!("`{}` must not be negative", key)),
+            n => Ok(n.map(|n| n as usize)),
+        }
+    };
+    // the counts the command line takes only above zero
+    let positive = |key: &str| -> Result<Option<usize>, String> {
+        match count(key)? {
+            Some(0) => Err(format!("`{}` must be positive", key)),
+            n => Ok(n),
+        }
+    };
+    if let Some(samples) = positive("samples")? {
+        settings.samples = samples;
+    }
+    if let Some(passes) = positive("passes")? {
+        settings.passes = passes;
+    }
+    if let Some(name) = optional(render, "accumulation", string)? {
+        settings.precision = Precision::parse(&name).ok_or(format!("unknown accumulation precision `{}`", name))?;
+    }
+    if let Some(seed) = count("seed")? {
+        settings.seed = seed as u64;
+    }
+    if let Some(max_depth) = positive("max_depth")? {
+        settings.max_depth = max_depth as i32;
+    }
+    if let Some(threads) = count("threads")? {
+        settings.threads = threads;
+    }
+    if let Some(name) = optional(render, "tile_order", string)? {
+        settings.tile_order = TileOrder::parse(&name).ok_or(format!("unknown tile order `{}`", name))?;
+    }
+    if let Some(name) = optional(render, "progress", string)? {
+        settings.observer = observer::parse(&name).ok_or(format!("unknown progress format `{}`", name))?;
+    }
+    if let Some(cache) = optional(render, "ray_cache", boolean)? {
+        settings.ray_cache = cache;
+    }
+    if let Some(dof) = optional(render, "depth_of_field", boolean)? {
+        settings.depth_of_field = dof;
+    }
+    if let Some(pin) = optional(render, "pin_threads", boolean)? {
+        settings.pin_threads = pin;
+    }
+    if let Some(numa) = optional(render, "numa", boolean)? {
+        settings.numa = numa;
+    }
+    if let Some(w) = count("width")? {
+        settings.width = Some(file::validate_resolution(w as i64, 1)?.0);
+    }
+    if let Some(h) = count("height")? {
+        settings.height = Some(file::validate_resolution(h as i64, 1)?.0);
+    }
+    if let Some(scale) = optional(render, "resolution_scale", number)? {
+        settings.resolution_scale = match scale {
+            scale if scale > 0. && scale <= 1. => scale,
+            scale => return Err(format!("`resolution_scale` {} must be above 0 and at most 1", scale)),
+        };
+    }
+    if let Some(scale) = count("render_scale")? {
+        settings.render_scale = match scale {
+            scale @ 1..=16 => scale,
+            scale => return Err(format!("`render_scale` {} must be between 1 and 16", scale)),
+        };
+    }
+    if let Some(name) = optional(render, "color_space", string)? {
+        settings.color_space = ColorSpace::parse(&name).ok_or(format!("unknown color space `{}`", name))?;
+    }
+    if let Some(name) = optional(render, "dither", string)? {
+        settings.dither = Dither::parse(&name).ok_or(format!("unknown dither `{}`", name))?;
+    }
+    if let Some(roughness) = optional(render, "min_roughness", number)? {
+        settings.min_roughness = match roughness {
+            r if (0. ..=1.).contains(&r) => r,
+            r => return Err(format!("`min_roughness` {} must be between 0 and 1", r)),
+        };
+    }
+    if let Some(unbiased) = optional(render, "unbiased", boolean)? {
+        settings.unbiased = unbiased;
+    }
+    if let Some(name) = optional(render, "non_finite", string)? {
+        settings.non_finite = NonFinite::parse(&name).ok_or(format!("unknown non-finite mode `{}`", name))?;
+    }
+    if let Some(rays) = positive("pixel_rays")? {
+        settings.pixel_budget.rays = Some(rays as u64);
+    }
+    if let Some(millis) = positive("pixel_time")? {
+        settings.pixel_budget.time = Some(Duration::from_millis(millis as u64));
+    }
+    Ok(())
+}
+
+/// The typos in the shared configuration would be silently ignored otherwise.
+fn known_keys(table: &Table, prefix: &str, known: &[&str]) -> Result<(), String> {
+    match table.keys().find(|key| !known.contains(key)) {
+        Some(key) => Err(format!("unknown setting `{}{}`", prefix, key)),
+        None => Ok(()),
+    }
+}
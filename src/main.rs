@@ -1,16 +1,440 @@
-use std::path::Path;
-use crate::scene::builder::SceneBuilder;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
+use crate::cli::{Command, Options, SceneSource};
+use crate::math::color::Color;
+use crate::math::vec::Vec3;
+use crate::renderer::bake;
+use crate::renderer::furnace;
+use crate::renderer::job::{self, Job};
+use crate::renderer::settings::RenderSettings;
+use crate::renderer::sweep;
+use crate::renderer::tiled;
+use crate::renderer::video;
+use crate::scene::Scene;
+use crate::scene::check::Severity;
+use crate::scene::edit;
+
+mod batch;
+mod bench;
+mod cli;
+mod config;
 mod renderer;
 mod scene;
 mod math;
+mod toml;
+#[cfg(feature = "tweaker")]
+mod tweaker;
+mod watch;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let parsed = cli::parse(args.clone().into_iter());
+    if let Some(options) = parsed.as_ref().ok().and_then(Command::options) {
+        options.apply_to_process();
+    }
+    let (options, args) = match parsed {
+        Ok(Command::Render(options)) => (*options, args),
+        Ok(Command::Resume(output)) => match resume(&output) {
+            Ok(resumed) => resumed,
+            Err(why) => {
+                eprintln!("Error: {}", why);
+                return ExitCode::FAILURE;
+            }
+        },
+        Ok(Command::Check { path, strict, search_path }) => {
+            scene::file::set_search_path(search_path);
+            return check(&path, strict);
+        }
+        Ok(Command::Diff { old, new }) => return diff(&old, &new),
+        Ok(Command::Migrate(paths)) => return migrate(&paths),
+        Ok(Command::Hash { options, expect }) => return hash(&options, expect),
+        Ok(Command::Variants { path, options }) => return variants(&path, &options),
+        Ok(Command::Batch { scenes, options, parallel }) => {
+            return match batch::expand(&scenes) {
+                Ok(scenes) if batch::run(&scenes, &options, parallel) => ExitCode::SUCCESS,
+                Ok(_) => ExitCode::FAILURE,
+                Err(why) => {
+                    eprintln!("Error: {}", why);
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Ok(Command::Sweep { options, seeds }) => return sweep(&options, seeds),
+        Ok(Command::Layout(options)) => return layout(&options),
+        Ok(Command::Furnace { materials, options }) => return furnace(&materials, &options),
+        Ok(Command::Drop { seed, restitution, options }) => return drop_scene(&seed, restitution, *options),
+        Ok(Command::Bake { name, occlusion, options }) => return bake(&name, occlusion, &options),
+        Ok(Command::Bench(filter)) => {
+            bench::run(filter.as_deref());
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(why) => {
+            eprintln!("Error: {}\nRun with --help to see the options.", why);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // the output directory of the configuration may not exist yet
+    if let Some(dir) = options.output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if let Err(why) = std::fs::create_dir_all(dir) {
+            eprintln!("Error: cannot create {}: {}", dir.display(), why);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let (true, SceneSource::File(path)) = (options.watch, &options.scene) {
+        watch::watch(path, &options.layers, &options.edits, &options.settings, &options.output);
+    }
+
+    let mut scene = match options.load_scene() {
+        Ok(scene) => scene,
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Some(width) = options.thumbnail {
+        let thumbnail = scene.render_thumbnail(width);
+        return match renderer::pam::write(&options.output, &thumbnail) {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(why) => {
+                eprintln!("Error: {}", why);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    #[cfg(feature = "tweaker")]
+    if options.tweak {
+        return match tweaker::run(scene, &options.settings, &options.output) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(why) => {
+                eprintln!("Error: {}", why);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    if let Err(why) = start_job(&scene, &options, args) {
+        eprintln!("Error: cannot start the job of the render: {}", why);
+        return ExitCode::FAILURE;
+    }
+
+    match render(&mut scene, &options.settings, &options.output) {
+        Ok(_) => {
+            eprintln!("Image successfully created.");
+            ExitCode::SUCCESS
+        }
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Renders the image, or the animation when the settings have one.
+pub(crate) fn render(scene: &mut Scene, settings: &RenderSettings, output: &Path) -> std::io::Result<()> {
+    match &settings.animation {
+        Some(animation) => renderer::render_animation(scene, animation, settings, output),
+        None => renderer::render_scene(scene, settings, output),
+    }.map(|_| ())
+}
+
+/// Renders every variant of the file to its own image, the relative outputs are next to
+/// the output of the options. The failed variant does not stop the others.
+fn variants(path: &Path, options: &Options) -> ExitCode {
+    let loaded = edit::load_variants(path).and_then(|variants| Ok((variants, options.load_scene()?)));
+    let (variants, scene) = match loaded {
+        Ok(loaded) => loaded,
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            return ExitCode::FAILURE;
+        }
+    };
+    let dir = options.output.parent().unwrap_or(Path::new(""));
+    let mut failed = 0;
+    for variant in &variants {
+        let output = dir.join(&variant.output);
+        // every variant edits its own copy, the trees of the models are copied, not built again
+        let mut scene = scene.clone();
+        let rendered = variant.edits.iter()
+            .try_for_each(|edit| edit.apply(&mut scene))
+            .and_then(|_| match output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                Some(dir) => std::fs::create_dir_all(dir).map_err(|why| format!("cannot create {}: {}", dir.display(), why)),
+                None => Ok(()),
+            })
+            .and_then(|_| render(&mut scene, &options.settings, &output).map_err(|why| why.to_string()));
+        match rendered {
+            Ok(_) => eprintln!("Variant {} is done.", output.display()),
+            Err(why) => {
+                eprintln!("Error: {}: {}", output.display(), why);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        eprintln!("{} of {} variants failed.", failed, variants.len());
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// Prints the problems of the scene file, the exit code tells the pipelines whether it is usable.
+fn check(path: &Path, strict: bool) -> ExitCode {
+    let diagnostics = scene::check::check(path);
+    for diagnostic in &diagnostics {
+        eprintln!("{}: {}", path.display(), diagnostic);
+    }
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    let warnings = diagnostics.len() - errors;
+    println!("{}: {} errors, {} warnings", path.display(), errors, warnings);
+    if errors > 0 || (strict && warnings > 0) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Prints the differences of the scene files, the exit code tells whether there are any,
+/// 1 when the scenes differ and 2 when a file cannot be compared, like diff.
+fn diff(old: &Path, new: &Path) -> ExitCode {
+    match scene::diff::diff_files(old, new) {
+        Ok(changes) if changes.is_empty() => {
+            eprintln!("The scenes are the same.");
+            ExitCode::SUCCESS
+        }
+        Ok(changes) => {
+            for change in &changes {
+                println!("{}", change);
+            }
+            eprintln!("{} differences.", changes.len());
+            ExitCode::from(1)
+        }
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Upgrades the scene files to the current version of the format, a file that fails
+/// does not stop the others.
+fn migrate(paths: &[PathBuf]) -> ExitCode {
+    let mut failed = 0;
+    for path in paths {
+        match scene::schema::migrate_file(path) {
+            Ok(Some(changes)) if changes.is_empty() => println!("{}: upgraded to the version {}", path.display(), scene::schema::VERSION),
+            Ok(Some(changes)) => println!("{}: upgraded to the version {}: {}", path.display(), scene::schema::VERSION, changes.join(", ")),
+            Ok(None) => println!("{}: already the version {}", path.display(), scene::schema::VERSION),
+            Err(why) => {
+                eprintln!("Error: {}: {}", path.display(), why);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// Prints the hash of the rendered frame, and fails when it is not the expected one.
+fn hash(options: &Options, expect: Option<u64>) -> ExitCode {
+    let scene = match options.load_scene() {
+        Ok(scene) => scene,
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            return ExitCode::FAILURE;
+        }
+    };
+    let hash = renderer::render_to_buffer(&scene, &options.settings).content_hash();
+    println!("{:016x}", hash);
+    match expect {
+        Some(expected) if expected != hash => {
+            eprintln!("Error: the image has changed, expected the hash {:016x}", expected);
+            ExitCode::FAILURE
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+/// Renders the seed sweep and prints the average variance of the pixels.
+fn sweep(options: &Options, seeds: usize) -> ExitCode {
+    let swept = options.load_scene()
+        .map_err(|why| why.to_string())
+        .and_then(|scene| sweep::render_sweep(&scene, &options.settings, seeds, &options.output).map_err(|why| why.to_string()));
+    match swept {
+        Ok(sweep) => {
+            println!("variance {:.6} of {} samples over {} seeds", sweep.mean_variance(), options.settings.samples, seeds);
+            ExitCode::SUCCESS
+        }
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Draws the layout of the scene, as a PNG when the output is .png, and prints how long it took.
+fn layout(options: &Options) -> ExitCode {
+    let start = std::time::Instant::now();
+    let drawn = options.load_scene().map_err(|why| why.to_string()).and_then(|scene| {
+        let image = renderer::layout::render(&scene, &options.settings);
+        renderer::output::write(&options.output, &image, options.settings.format)
+            .map(|_| scene.world().size())
+            .map_err(|why| why.to_string())
+    });
+    match drawn {
+        Ok(objects) => {
+            eprintln!("Layout of {} objects drawn in {} ms.", objects, start.elapsed().as_millis());
+            ExitCode::SUCCESS
+        }
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Simulates the spheres of the seed falling for the frames of the animation of the options
+/// and renders them, see `scene::drop`.
+fn drop_scene(seed: &str, restitution: f32, mut options: Options) -> ExitCode {
+    let mut animation = options.settings.animation.take().expect("drop always has an animation");
+    let mut scene = scene::drop::scene(seed, &mut animation, restitution);
+    let frames = animation.frames;
+    options.settings.animation = Some(animation);
+    let rendered = match options.output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => std::fs::create_dir_all(dir),
+        None => Ok(()),
+    }.and_then(|_| render(&mut scene, &options.settings, &options.output));
+    match rendered {
+        Ok(_) => {
+            eprintln!("Drop of {} frames successfully created.", frames);
+            ExitCode::SUCCESS
+        }
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Bakes the light, or the ambient occlusion within the distance, on the objects of the name
+/// into the texture, or into the colors of the vertices of the PLY output, and prints how long it took.
+fn bake(name: &str, occlusion: Option<f32>, options: &Options) -> ExitCode {
+    let start = std::time::Instant::now();
+    let quantity = occlusion.map_or(bake::Quantity::Light, bake::Quantity::Occlusion);
+    let to_vertices = options.output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ply"));
+    let baked = options.load_scene().map_err(|why| why.to_string()).and_then(|scene| {
+        if to_vertices {
+            let model = bake::bake_vertices(&scene, name, quantity, &options.settings)?;
+            scene::ply::write(&options.output, &model).map_err(|why| why.to_string())?;
+            return Ok(format!("{} vertices", model.points.len()));
+        }
+        let (lightmap, texels) = bake::bake(&scene, name, quantity, &options.settings)?;
+        renderer::write_buffer(&scene, &options.settings, &options.output, &lightmap).map_err(|why| why.to_string())?;
+        Ok(format!("{} texels", texels))
+    });
+    match baked {
+        Ok(baked) => {
+            eprintln!("Baked {} into {} in {} ms.", baked, options.output.display(), start.elapsed().as_millis());
+            ExitCode::SUCCESS
+        }
+        Err(why) => {
+            eprintln!("Error: {}", why);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Measures the materials in the furnace and prints them next to their expected albedo,
+/// fails when a material cannot be read or returns more light than it gets.
+fn furnace(materials: &[String], options: &Options) -> ExitCode {
+    let texts: Vec<&str> = if materials.is_empty() {
+        furnace::MATERIALS.to_vec()
+    } else {
+        materials.iter().map(String::as_str).collect()
+    };
+    let rgb = |color: &Vec3| format!("{:.3} {:.3} {:.3}", color.r(), color.g(), color.b());
+    let mut passed = true;
+    for text in texts {
+        let material = match scene::file::parse_inline_material(text, Path::new("")) {
+            Ok(material) => material,
+            Err(why) => {
+                eprintln!("Error: {}: {}", text, why);
+                passed = false;
+                continue;
+            }
+        };
+        let measurement = furnace::measure(material, &options.settings);
+        let status = match measurement.error() {
+            _ if measurement.excess() > furnace::TOLERANCE => {
+                passed = false;
+                format!("gains {:.1}%", 100. * measurement.excess())
+            }
+            None => "no reference".to_string(),
+            Some(error) if error.abs() <= furnace::TOLERANCE => "ok".to_string(),
+            Some(error) if error < 0. => format!("loses {:.1}%", -100. * error),
+            Some(error) => {
+                passed = false;
+                format!("gains {:.1}%", 100. * error)
+            }
+        };
+        let expected = measurement.expected.as_ref().map_or("-".to_string(), rgb);
+        println!("{:<60} expected {:<17} measured {:<17} {}", text, expected, rgb(&measurement.measured), status);
+    }
+    if passed { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+/// Loads the job of the interrupted tiled render or animation and enters its directory,
+/// the options are parsed from the recorded command line again.
+fn resume(output: &Path) -> Result<(Options, Vec<String>), String> {
+    let frames = job::frames_dir(output);
+    let job = match frames.exists() {
+        true => Job::load(&frames)?,
+        false => Job::load(&tiled::tiles_dir(output))?,
+    };
+    std::env::set_current_dir(&job.directory)
+        .map_err(|why| format!("cannot enter {}: {}", job.directory.display(), why))?;
+    match cli::parse(job.args.clone().into_iter())? {
+        Command::Render(options) if options.settings.tiled.is_some() || options.settings.animation.is_some() => {
+            options.apply_to_process();
+            Ok((*options, job.args))
+        }
+        _ => Err(format!("{} is neither a tiled render nor an animation", output.display())),
+    }
+}
 
-fn main() {
-    let scene_builder = scene::builder::BuiltIn::default();
-    let scene = scene_builder.build();
-    let file_path = Path::new("../result.ppm");
-    match renderer::render_scene(&scene, file_path) {
-        Ok(_) => println!("Image successfully created."),
-        Err(why) => println!("Error: {}", why),
+/// Records the command line of the tiled render next to its tiles, or of the animation
+/// next to its frames, or picks up the tiles or the frames of the same render that was
+/// interrupted. The other renders have no job.
+fn start_job(scene: &Scene, options: &Options, args: Vec<String>) -> std::io::Result<()> {
+    let (dir, tile_size, frames) = match (&options.settings.animation, options.settings.tiled) {
+        (_, Some(tile_size)) => (tiled::tiles_dir(&options.output), tile_size, 0),
+        (Some(animation), None) if !video::is_video(&options.output) =>
+            (job::frames_dir(&options.output), 0, animation.frames),
+        _ => return Ok(()),
+    };
+    let (w, h, _) = options.settings.resolution(scene);
+    let job = Job {
+        directory: std::env::current_dir()?,
+        args,
+        width: w as usize,
+        height: h as usize,
+        tile_size,
+        frames,
+    };
+    let done = job.start(&dir)?;
+    match (done, frames) {
+        (0, _) => {}
+        (_, 0) => eprintln!("Resuming the tiled render, {} tiles are already done.", done),
+        _ => eprintln!("Resuming the animation, {} frames are already done.", done),
     }
+    Ok(())
 }
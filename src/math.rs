@@ -9,3 +9,5 @@
 pub mod vec;
 pub mod color;
 pub mod rand;
+pub mod half;
+pub mod sphere;
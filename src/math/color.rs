@@ -30,4 +30,81 @@ impl Color for Vec3 {
     fn b(&self) -> f32 {
         self.z()
     }
-}
\ No newline at end of file
+}
+
+/// The color space the linear radiance is encoded to when the image is written.
+///
+/// sRGB and Rec.709 share the same primaries, so the only difference between them
+/// is the transfer function (the "gamma") applied to each channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum ColorSpace {
+    #[default]
+    Srgb,
+    Rec709,
+    /// No transfer function, the values are written as rendered.
+    Linear,
+}
+
+impl ColorSpace {
+    pub(crate) fn parse(name: &str) -> Option<ColorSpace> {
+        match name.to_ascii_lowercase().as_str() {
+            "srgb" => Some(ColorSpace::Srgb),
+            "rec709" | "rec.709" => Some(ColorSpace::Rec709),
+            "linear" => Some(ColorSpace::Linear),
+            _ => None,
+        }
+    }
+
+    /// The name the output files are tagged with.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "sRGB",
+            ColorSpace::Rec709 => "Rec.709",
+            ColorSpace::Linear => "linear",
+        }
+    }
+
+    /// Converts a linear channel value in [0, 1] to the encoded value in [0, 1].
+    pub(crate) fn encode(&self, linear: f32) -> f32 {
+        let l = linear.clamp(0., 1.);
+        match self {
+            ColorSpace::Srgb => {
+                if l <= 0.003_130_8 {
+                    12.92 * l
+                } else {
+                    1.055 * l.powf(1. / 2.4) - 0.055
+                }
+            }
+            ColorSpace::Rec709 => {
+                if l < 0.018 {
+                    4.5 * l
+                } else {
+                    1.099 * l.powf(0.45) - 0.099
+                }
+            }
+            ColorSpace::Linear => l,
+        }
+    }
+
+    /// Converts an encoded channel value in [0, 1] back to the linear value, the inverse of `encode`.
+    pub(crate) fn decode(&self, encoded: f32) -> f32 {
+        let e = encoded.clamp(0., 1.);
+        match self {
+            ColorSpace::Srgb => {
+                if e <= 0.040_45 {
+                    e / 12.92
+                } else {
+                    ((e + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            ColorSpace::Rec709 => {
+                if e < 0.081 {
+                    e / 4.5
+                } else {
+                    ((e + 0.099) / 1.099).powf(1. / 0.45)
+                }
+            }
+            ColorSpace::Linear => e,
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// LEARN:
+// The half precision float (IEEE 754 binary16) has 1 sign bit, 5 bits of the exponent
+// and 10 bits of the mantissa: about 3 significant decimal digits, up to 65504.
+// Rust has no stable `f16` type yet, so the values are kept as the bits in `u16`
+// and converted by moving the fields of the bits around, the way the GPUs do it.
+
+/// The bits of the nearest half precision float, the ties round to the even one.
+/// The values beyond 65504 become the infinity.
+pub fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // the infinity stays one, NaN stays NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    // the exponent of the half, biased by 15 instead of 127
+    let e = exponent - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    // below the normal halves the implicit leading 1 becomes a bit of the mantissa
+    let (half, dropped) = if e <= 0 {
+        if e < -10 {
+            return sign;
+        }
+        (0, (14 - e) as u32)
+    } else {
+        ((e as u32) << 10, 13)
+    };
+    let significand = if e <= 0 { mantissa | 0x80_0000 } else { mantissa };
+    let half = half | significand >> dropped;
+    let rest = significand & ((1 << dropped) - 1);
+    let halfway = 1 << (dropped - 1);
+    // the carry of the rounding may go into the exponent, up to the infinity, which is right
+    let rounded = if rest > halfway || (rest == halfway && half & 1 == 1) { half + 1 } else { half };
+    sign | rounded as u16
+}
+
+/// The value of the half precision float bits.
+pub fn from_half(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1. } else { 1. };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        // zero and the subnormal halves, without the implicit leading 1
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0. => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1. + mantissa / 1024.) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_half, to_half};
+
+    #[test]
+    fn converts_the_exact_values() {
+        for (value, half) in [(0., 0x0000), (1., 0x3c00), (-2., 0xc000), (65504., 0x7bff), (f32::INFINITY, 0x7c00)] {
+            assert_eq!(to_half(value), half, "{}", value);
+            assert_eq!(from_half(half), value, "{:#06x}", half);
+        }
+    }
+
+    #[test]
+    fn rounds_to_the_nearest_even() {
+        // halfway between 0x3c00 and 0x3c01 goes to the even one, between 0x3c01 and 0x3c02 too
+        assert_eq!(to_half(1.000_488_3), 0x3c00);
+        assert_eq!(to_half(1.001_464_8), 0x3c02);
+        assert_eq!(to_half(0.1), 0x2e66);
+        // beyond the largest half the carry goes into the infinity
+        assert_eq!(to_half(65520.), 0x7c00);
+    }
+
+    #[test]
+    fn keeps_the_subnormals_and_nan() {
+        assert_eq!(to_half(2f32.powi(-24)), 0x0001);
+        assert_eq!(from_half(0x0001), 2f32.powi(-24));
+        assert_eq!(to_half(1e-8), 0x0000);
+        assert!(from_half(to_half(f32::NAN)).is_nan());
+    }
+}
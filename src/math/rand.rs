@@ -1,27 +1,64 @@
 // LEARN:
-// This is an example of a foreign function interface (FFI) used to call a C function from Rust.
-// We could use a random number generator from the standard library,
-// or use drand48 from the libc crate, but we want to show how to use FFI.
+// The first version of this module called drand48 from the C library through FFI.
+// drand48 keeps its state in a global variable, so calling it from several threads
+// is a data race, and the sequence depends on how the threads interleave.
 //
-// `link` tells Rust to link the math library libm.so or libm.a (like on my Ubuntu system).
-// Obviously, you need to have the math library installed in the system.
-//
-// If the library does not have a conventional prefix `lib` or it has a unusual location
-// then you need to tell Rust where to seacrh the library and how to link it.
-// There are multiple ways of doing that: the most common is to use the environment variables or
-// in the build.rs script.
-#[cfg(target_family = "unix")]
-#[link(name = "m")]
-extern {
-    fn drand48() -> f64;
+// Here every thread has its own generator in a `thread_local!` variable.
+// The threads do not share anything, so no locks are needed, and the renderer
+// can reseed the generator at the start of every row of a tile to get the same image
+// no matter which thread rendered the tile.
+use std::cell::Cell;
+
+thread_local! {
+    // LEARN:
+    // `Cell` gives the interior mutability for `Copy` values: the value is replaced
+    // as a whole, so no references to the inside can be held while it changes.
+    static STATE: Cell<u64> = const { Cell::new(0x853c_49e6_748f_ea9b) };
+}
+
+/// Restarts the sequence of the current thread.
+pub fn seed(seed: u64) {
+    // the state of xorshift must never be zero, mixing the seed also makes
+    // the close seeds (e.g. the indices of the tiles) start far from each other
+    let mixed = splitmix64(seed);
+    STATE.with(|state| state.set(if mixed == 0 { 1 } else { mixed }));
+}
+
+/// Restarts the sequence of the current thread at one of the independent streams
+/// of the seed, e.g. the stream of a tile of the image. The seed 0 gives the same
+/// sequences as `seed(stream)`.
+pub fn seed_stream(seed: u64, stream: u64) {
+    self::seed(stream ^ seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
 }
 
-/// LEARN:
-/// Even though the function is safe to use, we need to mark it as unsafe
-/// because it uses FFI to call the function.
-/// Also, we drop some precision by casting the result to f32.
+/// Uniformly distributed random number in [0, 1).
+///
+/// xorshift64* is not a cryptographic generator, but it is fast,
+/// and its quality is more than enough for the Monte Carlo integration.
 pub fn drand32() -> f32 {
-    unsafe {
-        drand48() as f32
-    }
-}
\ No newline at end of file
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        // the upper 24 bits fill the mantissa of f32 exactly
+        (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40) as f32 / (1u64 << 24) as f32
+    })
+}
+
+/// The seed of the text, e.g. of a random scene: the same on every machine and in every
+/// version, unlike the hashers of the standard library, so the text shared with an image
+/// regenerates it anywhere. The bytes are hashed by FNV-1a.
+pub fn text_seed(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Mixes the bits of the number, close inputs give unrelated outputs.
+/// It also serves as the hash of the procedural textures.
+pub fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,26 @@
+// The ray-sphere test alone, of the vectors only, so the benchmarks (see `benches/math.rs`)
+// can time it without the scene around it.
+use crate::math::vec::{Ray, Vec3};
+
+// LEARN: In the book the hit_sphere accepts the hit_record as a mutable reference and returns bool
+// In Rust the idiomatic way is to return an Option instead, here of the distance alone,
+// the record is computed by the sphere of the scene only for the hit that is shaded.
+/// The nearest distance along the ray between the bounds where it meets the sphere.
+pub fn hit_sphere(r: &Ray, t_min: f32, t_max: f32, center: &Vec3, radius: f32) -> Option<f32> {
+    let oc = r.origin() - center;
+    let a = Vec3::dot(r.direction(), r.direction());
+    let b = Vec3::dot(&oc, r.direction());
+    let c = Vec3::dot(&oc, &oc) - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant > 0.0 {
+        let mut t = (-b - discriminant.sqrt()) / a;
+        if t < t_max && t > t_min {
+            return Some(t);
+        }
+        t = (-b + discriminant.sqrt()) / a;
+        if t < t_max && t > t_min {
+            return Some(t);
+        }
+    }
+    None
+}
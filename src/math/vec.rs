@@ -1,10 +1,10 @@
-use crate::math::rand::drand32;
+//! You almost always want to operate with vectors using algebraic expressions.
+//!
+//! This module contains the structs that required operate with 3D models
+//! and implement traits for the basic algebraic operations over them.
+//! The main structs are Vec3 and Ray.
 
-/// You almost always want to operate with vectors using algebraic expressions.
-///
-/// This module contains the structs that required operate with 3D models
-/// and implement traits for the basic algebraic operations over them.
-/// The main structs are Vec3 and Ray.
+use crate::math::rand::drand32;
 
 /// This struct represents a 3D vector. 3D vectors are used to represent
 /// points, directions, offsets, and even colors in the RGB space.
@@ -25,39 +25,75 @@ pub(crate) struct Ray {
     /// For purists this should be a unit vector, but for our purposes
     /// it is enough to have any vector that points in the right direction.
     direction: Vec3,
+
+    /// How the ray was produced, the surfaces can be hidden from some kinds of rays.
+    kind: RayType,
+
+    /// The width of the beam of the light the ray stands for, at its origin,
+    /// and how much wider it gets per unit of the distance along it.
+    /// The textures are blurred over the width of the beam where it hits them.
+    width: f32,
+    spread: f32,
+
+    /// The moment the ray is traced at while the shutter is open, as the share of the open time,
+    /// the objects that move meanwhile are seen that far along their way, see `World::set_motion`.
+    time: f32,
+}
+
+/// The event that produced the ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum RayType {
+    /// Starts at the camera, the surface it hits is seen directly.
+    Camera,
+    /// Scattered by a diffuse surface, it brings the indirect light.
+    Diffuse,
+    /// Reflected by a mirror-like surface (metal or the outside of glass).
+    Reflection,
+    /// Passed through a transparent surface.
+    Refraction,
+    /// Tests whether anything blocks the way to a light, it casts the shadows and never bounces.
+    Shadow,
 }
 
 impl Vec3 {
 
+    #[inline]
     pub(crate) fn new(x: f32, y: f32, z: f32) -> Vec3 {
         Vec3 { x, y, z }
     }
 
+    #[inline]
     pub(crate) fn x(&self) -> f32 {
         self.x
     }
 
+    #[inline]
     pub(crate) fn y(&self) -> f32 {
         self.y
     }
 
+    #[inline]
     pub(crate) fn z(&self) -> f32 {
         self.z
     }
 
+    #[inline]
     pub(crate) fn length(&self) -> f32 {
         (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
     }
 
+    #[inline]
     pub(crate) fn squared_length(&self) -> f32 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
+    #[inline]
     /// Scalar product of two vectors.
     pub(crate) fn dot(v1: &Vec3, v2: &Vec3) -> f32 {
         v1.x * v2.x + v1.y * v2.y + v1.z * v2.z
     }
 
+    #[inline]
     /// Cross product of two vectors.
     pub(crate) fn cross(v1: &Vec3, v2: &Vec3) -> Vec3 {
         Vec3 {
@@ -67,14 +103,17 @@ impl Vec3 {
         }
     }
 
+    #[inline]
     pub(crate) fn unit(&self) -> Vec3 {
         self / self.length()
     }
 
+    #[inline]
     pub(crate) fn basis() -> Vec3 {
         Vec3::new(1.0, 1.0, 1.0)
     }
 
+    #[inline]
     pub(crate) fn zero() -> Vec3 {
         Vec3::new(0.0, 0.0, 0.0)
     }
@@ -105,18 +144,68 @@ impl Vec3 {
 }
 
 impl Ray {
+    #[inline]
+    /// The ray from the camera.
     pub(crate) fn from(origin: Vec3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray { origin, direction, kind: RayType::Camera, width: 0., spread: 0., time: 0. }
+    }
+
+    #[inline]
+    /// The ray that continues the path after it hit a surface.
+    pub(crate) fn scattered(origin: Vec3, direction: Vec3, kind: RayType) -> Ray {
+        Ray { origin, direction, kind, width: 0., spread: 0., time: 0. }
+    }
+
+    #[inline]
+    /// The same ray standing for the beam of the width that grows by the spread per unit of the distance.
+    pub(crate) fn with_footprint(self, width: f32, spread: f32) -> Ray {
+        Ray { width, spread, ..self }
+    }
+
+    #[inline]
+    /// The same ray at the moment of the open shutter, the rays of a path share the moment of its camera ray.
+    pub(crate) fn at_time(self, time: f32) -> Ray {
+        Ray { time, ..self }
+    }
+
+    #[inline]
+    /// The same ray moved by the offset, e.g. into the place of the object before it moved.
+    pub(crate) fn translated(&self, offset: &Vec3) -> Ray {
+        Ray { origin: &self.origin + offset, direction: self.direction.clone(), ..*self }
     }
 
+    #[inline]
+    pub(crate) fn time(&self) -> f32 {
+        self.time
+    }
+
+    #[inline]
+    pub(crate) fn spread(&self) -> f32 {
+        self.spread
+    }
+
+    #[inline]
+    /// The width of the beam at the point `point_at(t)`.
+    pub(crate) fn footprint_at(&self, t: f32) -> f32 {
+        self.width + self.spread * t * self.direction.length()
+    }
+
+    #[inline]
+    pub(crate) fn kind(&self) -> RayType {
+        self.kind
+    }
+
+    #[inline]
     pub(crate) fn origin(&self) -> &Vec3 {
         &self.origin
     }
 
+    #[inline]
     pub(crate) fn direction(&self) -> &Vec3 {
         &self.direction
     }
 
+    #[inline]
     /// Returns the point at the given distance along the ray.
     pub(crate) fn point_at(&self, t: f32) -> Vec3 {
         &self.origin + &(t * &self.direction)
@@ -139,6 +228,7 @@ mod algebra {
     impl Index<usize> for Vec3 {
         type Output = f32;
 
+        #[inline]
         fn index(&self, index: usize) -> &f32 {
             assert!(index < 3);
             match index {
@@ -153,6 +243,7 @@ mod algebra {
     impl Add<&Vec3> for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn add(self, other: &Vec3) -> Vec3 {
             Vec3 {
                 x: self.x + other.x,
@@ -165,6 +256,7 @@ mod algebra {
     impl Add<Vec3> for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn add(self, other: Vec3) -> Vec3 {
             self + &other
         }
@@ -173,6 +265,7 @@ mod algebra {
     impl Add<&Vec3> for Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn add(self, other: &Vec3) -> Vec3 {
             &self + other
         }
@@ -181,12 +274,14 @@ mod algebra {
     impl Add<Vec3> for Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn add(self, other: Vec3) -> Vec3 {
             &self + &other
         }
     }
 
     impl AddAssign<&Vec3> for &mut Vec3 {
+        #[inline]
         fn add_assign(&mut self, other: &Vec3) {
             self.x += other.x;
             self.y += other.y;
@@ -195,6 +290,7 @@ mod algebra {
     }
 
     impl AddAssign<Vec3> for Vec3 {
+        #[inline]
         fn add_assign(&mut self, other: Vec3) {
             self.x += other.x;
             self.y += other.y;
@@ -205,6 +301,7 @@ mod algebra {
     impl Sub<&Vec3> for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn sub(self, other: &Vec3) -> Vec3 {
             Vec3 {
                 x: self.x - other.x,
@@ -217,6 +314,7 @@ mod algebra {
     impl Sub<&Vec3> for Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn sub(self, other: &Vec3) -> Vec3 {
             &self - other
         }
@@ -225,6 +323,7 @@ mod algebra {
     impl Sub<Vec3> for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn sub(self, other: Vec3) -> Vec3 {
             self - &other
         }
@@ -233,12 +332,14 @@ mod algebra {
     impl Sub<Vec3> for Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn sub(self, other: Vec3) -> Vec3 {
             &self - &other
         }
     }
 
     impl SubAssign<&Vec3> for &mut Vec3 {
+        #[inline]
         fn sub_assign(&mut self, other: &Vec3) {
             self.x -= other.x;
             self.y -= other.y;
@@ -249,6 +350,7 @@ mod algebra {
     impl Mul<&Vec3> for f32 {
         type Output = Vec3;
 
+        #[inline]
         fn mul(self, other: &Vec3) -> Vec3 {
             Vec3 {
                 x: self * other.x,
@@ -261,6 +363,7 @@ mod algebra {
     impl Mul<&Vec3> for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn mul(self, other: &Vec3) -> Vec3 {
             Vec3 {
                 x: self.x * other.x,
@@ -273,6 +376,7 @@ mod algebra {
     impl Mul<Vec3> for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn mul(self, other: Vec3) -> Vec3 {
             self * &other
         }
@@ -281,12 +385,14 @@ mod algebra {
     impl Mul<Vec3> for f32 {
         type Output = Vec3;
 
+        #[inline]
         fn mul(self, other: Vec3) -> Vec3 {
             self * &other
         }
     }
 
     impl MulAssign<&Vec3> for &mut Vec3 {
+        #[inline]
         fn mul_assign(&mut self, other: &Vec3) {
             self.x *= other.x;
             self.y *= other.y;
@@ -297,6 +403,7 @@ mod algebra {
     impl Mul<f32> for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn mul(self, other: f32) -> Vec3 {
             Vec3 {
                 x: self.x * other,
@@ -307,6 +414,16 @@ mod algebra {
     }
 
     impl MulAssign<f32> for &mut Vec3 {
+        #[inline]
+        fn mul_assign(&mut self, other: f32) {
+            self.x *= other;
+            self.y *= other;
+            self.z *= other;
+        }
+    }
+
+    impl MulAssign<f32> for Vec3 {
+        #[inline]
         fn mul_assign(&mut self, other: f32) {
             self.x *= other;
             self.y *= other;
@@ -317,6 +434,7 @@ mod algebra {
     impl Div<&Vec3> for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn div(self, other: &Vec3) -> Vec3 {
             Vec3 {
                 x: self.x / other.x,
@@ -327,6 +445,7 @@ mod algebra {
     }
 
     impl DivAssign<&Vec3> for &mut Vec3 {
+        #[inline]
         fn div_assign(&mut self, other: &Vec3) {
             self.x /= other.x;
             self.y /= other.y;
@@ -337,6 +456,7 @@ mod algebra {
     impl Div<f32> for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn div(self, other: f32) -> Vec3 {
             Vec3 {
                 x: self.x / other,
@@ -349,12 +469,14 @@ mod algebra {
     impl Div<f32> for Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn div(self, other: f32) -> Vec3 {
             &self / other
         }
     }
 
     impl DivAssign<f32> for &mut Vec3 {
+        #[inline]
         fn div_assign(&mut self, other: f32) {
             self.x /= other;
             self.y /= other;
@@ -363,6 +485,7 @@ mod algebra {
     }
 
     impl DivAssign<f32> for Vec3 {
+        #[inline]
         fn div_assign(&mut self, other: f32) {
             self.x /= other;
             self.y /= other;
@@ -373,6 +496,7 @@ mod algebra {
     impl Neg for Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn neg(self) -> Vec3 {
             Vec3 {
                 x: -self.x,
@@ -385,6 +509,7 @@ mod algebra {
     impl Neg for &Vec3 {
         type Output = Vec3;
 
+        #[inline]
         fn neg(self) -> Vec3 {
             Vec3 {
                 x: -self.x,
@@ -1,75 +1,744 @@
 /**
-This module contains the implementation of the PPM image creation.
+This module contains the rendering loop and the output of the rendered images.
 */
-use std::fs::File;
-use std::io::{Error, Write};
-use std::path::Path;
-use std::time::Instant;
+use std::convert::Infallible;
+use std::io::{Error, ErrorKind};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::math::color::Color;
-use crate::math::rand::drand32;
-use crate::math::vec::{Ray, Vec3};
-use crate::scene::camera::{RaySource};
+use crate::math::rand::{self, drand32};
+use crate::math::vec::{Ray, RayType, Vec3};
+use crate::renderer::accumulator::{Accumulator, CompensatedSum, PixelSum, Precision};
+use crate::renderer::analysis::Histogram;
+use crate::renderer::aov::{Aov, Layer};
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::guard::NonFinite;
+use crate::renderer::lens::Lens;
+use crate::renderer::media::{Interface, Media};
+use crate::renderer::observer::Progress;
+use crate::renderer::postprocess::PostEffect;
+use crate::renderer::rays::PixelGrid;
+use crate::renderer::settings::{BounceLimits, RenderSettings};
+use crate::renderer::stats::RenderStats;
+use crate::renderer::tiles::{Queue, Tile};
+use crate::renderer::watchdog::Stopped;
+use crate::scene::animation::Animation;
+use crate::scene::arena::Id;
+use crate::scene::camera::RaySource;
 use crate::scene::material::Scatterable;
 use crate::scene::Scene;
+use crate::scene::surfaces::bvh::Bounds;
 use crate::scene::surfaces::hitable::Hitable;
+use crate::scene::surfaces::stream;
 use crate::scene::surfaces::world::World;
+use crate::scene::surfaces::Surface;
+
+pub(crate) mod accumulator;
+pub(crate) mod affinity;
+pub(crate) mod analysis;
+pub(crate) mod aov;
+pub(crate) mod bake;
+pub(crate) mod coarse;
+pub(crate) mod denoise;
+pub(crate) mod exr;
+pub(crate) mod framebuffer;
+pub(crate) mod furnace;
+pub(crate) mod gif;
+pub(crate) mod guard;
+pub(crate) mod job;
+pub(crate) mod layout;
+pub(crate) mod lens;
+pub(crate) mod media;
+pub(crate) mod observer;
+pub(crate) mod output;
+pub(crate) mod pam;
+pub(crate) mod png;
+pub(crate) mod postprocess;
+pub(crate) mod ppm;
+pub(crate) mod rays;
+pub(crate) mod settings;
+pub(crate) mod stats;
+pub(crate) mod stereo;
+pub(crate) mod sweep;
+pub(crate) mod thumbnail;
+pub(crate) mod tiled;
+pub(crate) mod tiles;
+pub(crate) mod video;
+pub(crate) mod watchdog;
+pub(crate) mod web;
 
 ///
 /// This is the main function to render the scene directly to the file.
+/// The messages go to the standard error, the standard output may be the image itself.
 ///
-pub(crate) fn render_scene(scene: &Scene, path: &Path) -> Result<(), Error> {
+pub(crate) fn render_scene(scene: &Scene, settings: &RenderSettings, path: &Path) -> Result<RenderStats, Error> {
+
+    let (w, h, _) = settings.resolution(scene);
+    check_settings(scene, settings)?;
+
+    // A mistake in the framing is obvious from the preview after a few seconds,
+    // rather than after the final render is done.
+    if settings.preview {
+        let now = Instant::now();
+        let preview_path = suffixed_path(path, "_preview");
+        render_to_file(
+            scene,
+            &settings.preview_settings(),
+            (w / RenderSettings::PREVIEW_DOWNSCALE).max(1),
+            (h / RenderSettings::PREVIEW_DOWNSCALE).max(1),
+            &preview_path)?;
+        eprintln!("Preview {} is ready in {} ms, rendering the final image.",
+                 preview_path.display(), now.elapsed().as_millis());
+    }
 
     let now = Instant::now();
 
-    // LEARN:
-    // The ? is a shortcut for the match statement that returns the error
-    // we could write match File::create(&path) { Ok(file) => file, Err(why) => return Err(why) }
-    // but look how much cleaner the code is with the ? operator.
-    // The ? operator can be used in functions that return Result type.
-    let mut img_file = File::create(&path)?;
+    let written = render_to_file(scene, settings, w, h, path)?;
+
+    let stats = RenderStats {
+        file_size: written.size,
+        render_time: now.elapsed(),
+        objects: scene.world().size(),
+        scene_memory: scene.world().memory(),
+        bounds: scene.world().bounding_box().map(Bounds::corners),
+        peak_memory: stats::peak_memory(),
+        non_finite: written.non_finite,
+        biases: settings.biases(scene),
+    };
+    settings.observer.on_stats(&stats);
+    report_streaming();
+
+    Ok(stats)
+}
+
+/// Validates the settings against the scene before the long work starts.
+fn check_settings(scene: &Scene, settings: &RenderSettings) -> Result<(), Error> {
+    let (w, h, _) = settings.resolution(scene);
+
+    if let Some((x, y)) = settings.focus_pixel {
+        if x >= w as usize || y >= h as usize {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                                  format!("the focus pixel ({}, {}) is outside of the {}x{} image", x, y, w, h)));
+        }
+        match focus_distance(scene, settings, (x, y)) {
+            Some(focus_dist) => eprintln!("Focused at the distance {:.3} seen at the pixel ({}, {}).", focus_dist, x, y),
+            None => eprintln!("There is nothing to focus on at the pixel ({}, {}), the focus is not changed.", x, y),
+        }
+    }
 
-    write!(img_file, "P3\n{} {}\n255\n", scene.w, scene.h)?;
+    if settings.precision == Precision::Half && settings.samples > Precision::MAX_HALF_SAMPLES {
+        return Err(Error::new(ErrorKind::InvalidInput, format!(
+            "the half precision accumulation counts at most {} samples, not {}",
+            Precision::MAX_HALF_SAMPLES, settings.samples)));
+    }
 
-    render_to_file(scene, &mut img_file)?;
+    // Better to fail right away than after hours of rendering, or to make the machine swap.
+    if let Some(budget) = settings.memory_budget {
+        let needed = estimate_memory(scene, settings, w as usize, h as usize);
+        if needed > budget {
+            return Err(Error::new(ErrorKind::OutOfMemory, format!(
+                "the render needs about {} MiB, more than the budget of {} MiB{}",
+                needed.div_ceil(1024 * 1024),
+                budget / (1024 * 1024),
+                if settings.tiled.is_none() { ", try --tiled" } else { "" })));
+        }
+        // the trees of the streamed meshes take what is left, see `stream`
+        stream::set_cache_limit(Some((budget - needed) as usize));
+    }
+    Ok(())
+}
 
-    img_file.sync_all()?;
+/// Tells how busy the cache of the trees of the streamed meshes was, when there are any.
+fn report_streaming() {
+    let (built, evicted) = stream::activity();
+    if built > 0 {
+        eprintln!("Built {} trees of the streamed meshes when the rays needed them, evicted {} of them.", built, evicted);
+    }
+}
+
+/// Renders the frames of the animation. They are written as the numbered images,
+/// e.g. `result_0001.ppm`, or into a single file when the output is a video or a GIF.
+///
+/// The camera of the scene is replaced for every frame, and the world too when
+/// the animation moves the objects, and they are restored at the end.
+///
+/// The numbered images are recorded in the manifest of the job as they are written,
+/// and the frames the manifest has are skipped, see `job`.
+pub(crate) fn render_animation(scene: &mut Scene, animation: &Animation, settings: &RenderSettings, path: &Path) -> Result<RenderStats, Error> {
+    let (w, h, _) = settings.resolution(scene);
+    check_settings(scene, settings)?;
+
+    let now = Instant::now();
+    let original = scene.camera.with_aspect(scene.aspect());
+    let mut video = match video::is_video(path) {
+        true => Some(video::create(path, w as usize, h as usize, animation.fps)?),
+        false => None,
+    };
 
-    let size = img_file.metadata()?.len();
+    // every frame of the moving objects starts from the scene as it was loaded
+    let loaded = animation.moves_objects().then(|| scene.clone());
+    // the scene at the time in the frames
+    let pose = |scene: &mut Scene, time: f32| -> Result<(), Error> {
+        if let Some(loaded) = &loaded {
+            *scene = loaded.clone();
+        }
+        scene.camera = animation.camera(&original, time, scene.aspect());
+        if let Some(motion) = &animation.motion {
+            scene.world.translate(motion.at(time));
+        }
+        #[cfg(feature = "script")]
+        if let Some(script) = &animation.script {
+            let seconds = time as f64 / animation.fps as f64;
+            script.apply(scene, time as usize, animation.frames, seconds).map_err(Error::other)?;
+        }
+        Ok(())
+    };
+    let shutter = animation.shutter.filter(|&shutter| shutter > 0.);
+    let job_dir = job::frames_dir(path);
+    let done = match video {
+        Some(_) => Vec::new(),
+        None => job::done_frames(&job_dir),
+    };
 
     // LEARN:
-    // The idiomatic way to control how long the file is open is to use a scope { }.
+    // The frames are rendered in a closure, so the `?` inside it returns from the closure
+    // and the camera is restored below on the errors too.
+    let mut render_frames = || -> Result<Written, Error> {
+        let mut written = Written { size: 0, non_finite: 0 };
+        for i in 0..animation.frames {
+            if done.contains(&i) {
+                let frame_path = suffixed_path(path, &format!("_{:04}", i + 1));
+                written.size += std::fs::metadata(&frame_path).map_or(0, |metadata| metadata.len());
+                eprintln!("Frame {} of {} was done before.", i + 1, animation.frames);
+                continue;
+            }
+            let blurred = match shutter {
+                // the camera that moves is blurred by the moments, the objects by their motion
+                Some(shutter) if animation.moves_camera() => Some(render_open_shutter(scene, &pose, i, shutter, settings, w, h)?),
+                Some(shutter) => {
+                    pose_in_motion(scene, &pose, i, shutter)?;
+                    None
+                }
+                None => {
+                    pose(scene, i as f32)?;
+                    None
+                }
+            };
+            match video.as_mut() {
+                Some(video) => {
+                    let (frame, non_finite) = blurred.unwrap_or_else(|| render_frame(scene, settings, w, h));
+                    let exposure = exposure(&frame, settings, path);
+                    video.write(&output::encode(&frame, exposure, settings))?;
+                    written.non_finite += non_finite;
+                }
+                None => {
+                    let frame_path = suffixed_path(path, &format!("_{:04}", i + 1));
+                    let frame = match blurred {
+                        Some((frame, non_finite)) => write_frame(scene, settings, &frame_path, &frame, non_finite, Vec::new())?,
+                        None => render_to_file(scene, settings, w, h, &frame_path)?,
+                    };
+                    written.size += frame.size;
+                    written.non_finite += frame.non_finite;
+                    job::mark_frame_done(&job_dir, i)?;
+                }
+            }
+            eprintln!("Frame {} of {} is done.", i + 1, animation.frames);
+        }
+        Ok(written)
+    };
+    let rendered = render_frames();
+    if let Some(loaded) = loaded {
+        *scene = loaded;
+    }
+    scene.camera = original;
 
-    println!("File size {} bytes. Render time {} secs", size, now.elapsed().as_secs());
+    let written = match video {
+        Some(video) => {
+            video.finish()?;
+            let rendered = rendered?;
+            Written { size: std::fs::metadata(path)?.len(), ..rendered }
+        }
+        None => rendered?,
+    };
+    // all the frames are there, nothing is left to resume
+    if job_dir.exists() {
+        std::fs::remove_dir_all(&job_dir)?;
+    }
+
+    let stats = RenderStats {
+        file_size: written.size,
+        render_time: now.elapsed(),
+        objects: scene.world().size(),
+        scene_memory: scene.world().memory(),
+        bounds: scene.world().bounding_box().map(Bounds::corners),
+        peak_memory: stats::peak_memory(),
+        non_finite: written.non_finite,
+        biases: settings.biases(scene),
+    };
+    settings.observer.on_stats(&stats);
+    report_streaming();
 
+    Ok(stats)
+}
+
+/// Poses the scene at the frame with the objects moving while the shutter is open: the motion
+/// of every object is the way from where it is when the shutter opens to where it is
+/// when it closes, so whatever moves them, the simulation or the script, blurs them
+/// without saying how, and the frame is rendered once, every ray at a moment of its own.
+fn pose_in_motion<P>(scene: &mut Scene, pose: &P, frame: usize, shutter: f32) -> Result<(), Error>
+where
+    P: Fn(&mut Scene, f32) -> Result<(), Error>,
+{
+    pose(scene, frame as f32 + shutter)?;
+    let closed: Vec<Vec3> = scene.world.objects().iter().map(Surface::center).collect();
+    pose(scene, frame as f32)?;
+    let motions: Vec<(Id<Surface>, Vec3)> = scene.world.ids()
+        .zip(closed)
+        .map(|(id, closed)| (id, closed - scene.world.object(id).center()))
+        .filter(|(_, motion)| motion.squared_length() > 0.)
+        .collect();
+    for (id, motion) in motions {
+        scene.world.set_motion(id, motion);
+    }
     Ok(())
 }
 
-/// LEARN:
-/// Here we demonstrate the power of iterators in Rust.
+/// The frames of the open shutter are the average of this many moments, see `animation`.
+const SHUTTER_MOMENTS: usize = 8;
+
+/// Renders the frame of the animation with the shutter open for the share of the time
+/// to the next frame: the scene is posed at the moments while it is open, and the images
+/// of the moments, each with its share of the samples and a seed of its own, are averaged.
+/// Returns the frame and how many samples were not finite.
+fn render_open_shutter<P>(scene: &mut Scene, pose: &P, frame: usize, shutter: f32, settings: &RenderSettings, w: i32, h: i32) -> Result<(FrameBuffer, u64), Error>
+where
+    P: Fn(&mut Scene, f32) -> Result<(), Error>,
+{
+    let moments = SHUTTER_MOMENTS.min(settings.samples);
+    let mut sum = FrameBuffer::from_pixels(w as usize, h as usize, vec![Vec3::zero(); (w * h) as usize]);
+    let mut non_finite = 0;
+    for moment in 0..moments {
+        pose(scene, frame as f32 + shutter * (moment as f32 + 0.5) / moments as f32)?;
+        let samples = settings.samples / moments + usize::from(moment < settings.samples % moments);
+        let moment_settings = RenderSettings { samples, seed: settings.seed.wrapping_add(moment as u64), ..settings.clone() };
+        let (image, image_non_finite) = render_frame(scene, &moment_settings, w, h);
+        let weight = samples as f32 / settings.samples as f32;
+        for (total, pixel) in sum.pixels_mut().iter_mut().zip(image.pixels()) {
+            *total = &*total + &(weight * pixel);
+        }
+        non_finite += image_non_finite;
+    }
+    Ok((sum, non_finite))
+}
+
+/// What the render of an image file did: the size of the main image file,
+/// and how many samples were not finite.
+pub(crate) struct Written {
+    size: u64,
+    non_finite: u64,
+}
+
+/// Renders the scene at the given resolution and writes the image (and the bracketed images).
+fn render_to_file(scene: &Scene, settings: &RenderSettings, w: i32, h: i32, path: &Path) -> Result<Written, Error> {
+    if let Some(tile_size) = settings.tiled {
+        return tiled::render_to_file(scene, settings, w as usize, h as usize, tile_size, path);
+    }
+
+    // every pass but the last is written over the image, the final one replaces it
+    let (frame, non_finite, convergence) = render_frame_progressively(scene, settings, w, h, |frame, pass, passes| -> Result<(), Error> {
+        if output::is_stdout(path) {
+            return Ok(());
+        }
+        write_image(scene, settings, path, frame, exposure(frame, settings, path), &[])?;
+        eprintln!("Pass {} of {} is written to {}.", pass + 1, passes, path.display());
+        Ok(())
+    })?;
+    write_frame(scene, settings, path, &frame, non_finite, convergence)
+}
+
+/// Writes the rendered frame to the image file with the layers of its convergence,
+/// and the files the settings ask for next to it: the passes of the surfaces,
+/// the histogram, the false colors, the brackets and the web viewer.
+fn write_frame(scene: &Scene, settings: &RenderSettings, path: &Path, frame: &FrameBuffer, non_finite: u64, convergence: Vec<Layer>) -> Result<Written, Error> {
+    let (w, h) = (frame.width(), frame.height());
+    let exposure = exposure(frame, settings, path);
+    let notes = notes(scene, settings);
+    let encode = |exposure: f32| output::Image { biases: settings.biases(scene), notes: notes.clone(), ..output::encode(frame, exposure, settings) };
+
+    // the passes of the surfaces do not depend on the samples, they are taken once for the final image
+    let layers = match settings.aovs.iter().any(Aov::is_traced) {
+        true => {
+            let (_, _, pixel_aspect) = settings.resolution(scene);
+            aov::render(scene, &settings.aovs, w, h, w as f32 * pixel_aspect / h as f32)
+        }
+        false => Vec::new(),
+    };
+    let layers: Vec<Layer> = layers.into_iter().chain(convergence).collect();
+    let size = match exr::is_exr(path) {
+        true => write_image(scene, settings, path, frame, exposure, &layers)?,
+        false => {
+            // e.g. `result_normal.ppm` next to `result.ppm`
+            for layer in &layers {
+                output::write(&suffixed_path(path, &format!("_{}", layer.aov.name())), &aov::show(layer, w, h, settings), settings.format)?;
+            }
+            output::write(path, &encode(exposure), settings.format)?
+        }
+    };
+
+    if let Some(format) = settings.histogram {
+        let histogram_path = suffixed_path(path, "_histogram").with_extension(format.extension());
+        std::fs::write(histogram_path, Histogram::of(frame, exposure).format(format))?;
+    }
+    if settings.false_color {
+        // the false colors are 8-bit, the OpenEXR image gets the PPM next to it
+        ppm::write(&suffixed_path(path, "_false_color").with_extension("ppm"), &analysis::false_color(frame, exposure))?;
+    }
+
+    // The linear frame is rendered once, so the bracketed images are almost free.
+    // E.g. `result.ppm` becomes `result_-2ev.ppm` for -2 stops.
+    for &stop in settings.exposure_stops.iter() {
+        let bracket_path = suffixed_path(path, &format!("_{:+}ev", stop));
+        write_image(scene, settings, &bracket_path, frame, exposure + stop, &[])?;
+    }
+
+    // e.g. `result_web/index.html` for `result.ppm`
+    if settings.web_viewer {
+        let dir = suffixed_path(path, "_web").with_extension("");
+        web::export(&encode(exposure), &dir)?;
+        eprintln!("The web viewer is written to {}.", dir.join("index.html").display());
+    }
+
+    Ok(Written { size, non_finite })
+}
+
+/// Writes the frame as the 8-bit image in the format of the path, see `output::Format::of`,
+/// or as the OpenEXR image of the linear light with the layers of the passes when the path
+/// ends in .exr, and returns the size of the file.
+fn write_image(scene: &Scene, settings: &RenderSettings, path: &Path, frame: &FrameBuffer, exposure: f32, layers: &[Layer]) -> Result<u64, Error> {
+    if !exr::is_exr(path) {
+        let image = output::Image { biases: settings.biases(scene), notes: notes(scene, settings), ..output::encode(frame, exposure, settings) };
+        return output::write(path, &image, settings.format);
+    }
+    let scale = 2f32.powf(exposure);
+    let mut channels: Vec<(String, Vec<f32>)> = ["R", "G", "B"].into_iter().enumerate()
+        .map(|(c, name)| (name.to_string(), frame.pixels().iter().map(|pixel| scale * pixel[c]).collect()))
+        .collect();
+    for layer in layers {
+        let names = layer.aov.channels();
+        for (c, name) in names.iter().enumerate() {
+            let values = layer.values.iter().skip(c).step_by(names.len()).copied().collect();
+            channels.push((format!("{}.{}", layer.aov.name(), name), values));
+        }
+    }
+    let (_, _, pixel_aspect) = settings.resolution(scene);
+    exr::write(path, frame.width(), frame.height(), pixel_aspect, channels)
+}
+
+/// Renders the scene at the resolution of the settings into the memory.
 ///
-/// What method is doing:
-/// We iterate over each line from bottom to top and for each line we iterate
-/// over each pixel from left to right and calculate the color of the pixel.
-/// Then write the pixel's color to the image file.
+/// The frame is linear and has all the effects applied, but not the exposure and the encoding,
+/// it is what the image files are written from.
+pub(crate) fn render_to_buffer(scene: &Scene, settings: &RenderSettings) -> FrameBuffer {
+    let rendered: Result<FrameBuffer, Infallible> = render_to_buffer_progressively(scene, settings, |_, _, _| Ok(()));
+    rendered.unwrap_or_else(|never| match never {})
+}
+
+/// Renders the scene into the memory as `render_to_buffer` does, pass by pass, every pass
+/// but the last one gives the image of the samples so far to `on_pass` with its number
+/// and the number of the passes. The rendering stops at the first error returned by `on_pass`.
+pub(crate) fn render_to_buffer_progressively<E, F>(scene: &Scene, settings: &RenderSettings, on_pass: F) -> Result<FrameBuffer, E>
+where
+    F: FnMut(&FrameBuffer, usize, usize) -> Result<(), E>,
+{
+    let (w, h, _) = settings.resolution(scene);
+    Ok(render_frame_progressively(scene, settings, w, h, on_pass)?.0)
+}
+
+/// Writes the frame rendered into the memory as the image of the path is written
+/// by `render_scene`, returns the size of the file.
+pub(crate) fn write_buffer(scene: &Scene, settings: &RenderSettings, path: &Path, frame: &FrameBuffer) -> Result<u64, Error> {
+    write_image(scene, settings, path, frame, exposure(frame, settings, path), &[])
+}
+
+/// Renders the frame at the render scale, downsamples it and applies the effects.
+/// Returns the frame and how many samples were not finite.
+fn render_frame(scene: &Scene, settings: &RenderSettings, w: i32, h: i32) -> (FrameBuffer, u64) {
+    let rendered: Result<(FrameBuffer, u64, Vec<Layer>), Infallible> = render_frame_progressively(scene, settings, w, h, |_, _, _| Ok(()));
+    let (frame, non_finite, _) = rendered.unwrap_or_else(|never| match never {});
+    (frame, non_finite)
+}
+
+/// Renders the frame in `settings.passes` passes, every pass but the last one gives
+/// the image of the samples so far to `on_pass` with its number and the number of the passes.
+/// With `settings.coarse_to_fine` the coarse image and the passes of the busy tiles
+/// and then of the rest are the stages given to `on_pass` instead, see `coarse`.
+/// The rendering stops at the first error returned by `on_pass`.
+/// Returns the frame, how many samples were not finite, and the layers of the convergence
+/// of the frame the settings ask for, see `aov::convergence` (none for the anaglyph).
+fn render_frame_progressively<E, F>(scene: &Scene, settings: &RenderSettings, w: i32, h: i32, mut on_pass: F) -> Result<(FrameBuffer, u64, Vec<Layer>), E>
+where
+    F: FnMut(&FrameBuffer, usize, usize) -> Result<(), E>,
+{
+    if let Some(separation) = settings.anaglyph {
+        let (frame, non_finite) = render_anaglyph(scene, settings, w, h, separation);
+        return Ok((frame, non_finite, Vec::new()));
+    }
+
+    // the depth pass is of the final image, the same for all the passes
+    let depths = settings.post_effects.iter().any(PostEffect::uses_depth).then(|| {
+        let (_, _, pixel_aspect) = settings.resolution(scene);
+        let mut layers = aov::render(scene, &[Aov::Depth], w as usize, h as usize, w as f32 * pixel_aspect / h as f32);
+        layers.remove(0).values
+    });
+
+    let scale = settings.render_scale.max(1);
+    let (w, h) = (w as usize * scale, h as usize * scale);
+    let mut accumulator = Accumulator::new(w, h, settings.precision);
+    if settings.aovs.iter().any(|aov| !aov.is_traced()) {
+        accumulator = accumulator.with_convergence();
+    }
+    let mut tiles = tiles::split(w, h, TILE_SIZE);
+    settings.tile_order.arrange(&mut tiles, w, h, TILE_SIZE);
+
+    let passes = settings.passes.clamp(1, settings.samples.max(1));
+    let mut non_finite = 0;
+    // the groups of the tiles that take all their passes one after another, see `coarse`
+    let (groups, coarse) = match settings.coarse_to_fine {
+        true => {
+            let coarse = render_coarse(scene, settings, w, h);
+            let (busy, rest) = coarse::busy_first(&coarse, tiles, w, h);
+            (vec![busy, rest], Some(coarse))
+        }
+        false => (vec![tiles], None),
+    };
+    // the coarse image is the first of the intermediate images
+    let stages = groups.len() * passes + usize::from(coarse.is_some());
+    let mut stage = 0;
+    let mut rendered: Vec<Tile> = Vec::new();
+    let resolve = |accumulator: &Accumulator, rendered: &[Tile]| {
+        let mut frame = accumulator.resolve();
+        if let Some(coarse) = &coarse {
+            coarse::fill(&mut frame, coarse, rendered);
+        }
+        finish_frame(frame, settings, depths.as_deref())
+    };
+    if coarse.is_some() {
+        on_pass(&resolve(&accumulator, &rendered), stage, stages)?;
+        stage += 1;
+    }
+    for mut tiles in groups {
+        for index in 0..passes {
+            // the samples are split as evenly as they can
+            let samples = settings.samples * (index + 1) / passes - settings.samples * index / passes;
+            let pass = Pass { index, passes, samples };
+            let mut measured = Vec::with_capacity(tiles.len());
+            let added: Result<u64, Infallible> = render_tiles(scene, settings, w, h, tiles, pass, |tile, sums, spent| {
+                accumulator.add(&tile, &sums);
+                measured.push((tile, spent));
+                Ok(())
+            });
+            non_finite += added.unwrap_or_else(|never| match never {});
+            if index == 0 {
+                rendered.extend(measured.iter().map(|(tile, _)| *tile));
+            }
+            // the next pass starts with the tiles that took the longest in this one,
+            // `tile_order` is the order of the first pass only
+            tiles = tiles::balance(measured, settings.thread_count());
+            stage += 1;
+            if stage < stages {
+                on_pass(&resolve(&accumulator, &rendered), stage - 1, stages)?;
+            }
+        }
+    }
+    let convergence = accumulator.convergence(scale).map_or_else(Vec::new, |convergence| aov::convergence(convergence, &settings.aovs));
+    Ok((resolve(&accumulator, &rendered), non_finite, convergence))
+}
+
+/// Renders the coarse image of `coarse` with the settings of the preview, without the effects
+/// of the final image, and without counting its samples that are not finite.
+fn render_coarse(scene: &Scene, settings: &RenderSettings, w: usize, h: usize) -> FrameBuffer {
+    let (w, h) = coarse::size(w, h);
+    let settings = RenderSettings { coarse_to_fine: false, ..settings.preview_settings() };
+    let mut accumulator = Accumulator::new(w, h, Precision::Single);
+    let added: Result<u64, Infallible> = render_tiles(scene, &settings, w, h, tiles::split(w, h, TILE_SIZE), Pass::whole(&settings), |tile, sums, _| {
+        accumulator.add(&tile, &sums);
+        Ok(())
+    });
+    added.unwrap_or_else(|never| match never {});
+    accumulator.resolve()
+}
+
+/// Renders the views of the two eyes the separation apart, a share of the distance in focus,
+/// and makes the anaglyph of them, without the intermediate passes.
+fn render_anaglyph(scene: &Scene, settings: &RenderSettings, w: i32, h: i32, separation: f32) -> (FrameBuffer, u64) {
+    let view = |eye: f32| render_frame(scene, &RenderSettings { anaglyph: None, eye, ..settings.clone() }, w, h);
+    let (left, left_non_finite) = view(-separation / 2.);
+    let (right, right_non_finite) = view(separation / 2.);
+    (stereo::anaglyph(&left, &right), left_non_finite + right_non_finite)
+}
+
+/// Downsamples the frame rendered at the render scale and applies the effects,
+/// `depths` is the depth pass of the frame for the effects that use it.
+fn finish_frame(rendered: FrameBuffer, settings: &RenderSettings, depths: Option<&[f32]>) -> FrameBuffer {
+    let mut frame = rendered.downsample(settings.render_scale.max(1));
+
+    if let Some(denoiser) = settings.denoise {
+        denoiser.apply(&mut frame);
+    }
+
+    if let Some(lens) = settings.lens.as_ref().filter(|lens| lens.in_post) {
+        lens.apply(&mut frame);
+    }
+
+    for effect in settings.post_effects.iter() {
+        effect.apply(&mut frame, depths);
+    }
+
+    frame
+}
+
+/// The exposure of the frame written to the path, metered when the auto exposure is on.
+fn exposure(frame: &FrameBuffer, settings: &RenderSettings, path: &Path) -> f32 {
+    match settings.auto_exposure {
+        Some(key) => {
+            let exposure = output::metered_exposure(frame, key);
+            eprintln!("Auto exposure {:+.2} EV for {}.", exposure, path.display());
+            exposure
+        }
+        None => 0.,
+    }
+}
+
+/// The focus distance to the surface seen at the pixel of the final image, the top-left is (0, 0).
+/// The pixel is the same for the preview, which is rendered at a smaller size.
+fn focus_distance(scene: &Scene, settings: &RenderSettings, (x, y): (usize, usize)) -> Option<f32> {
+    let (w, h, pixel_aspect) = settings.resolution(scene);
+    let u = (x as f32 + 0.5) / w as f32;
+    let v = 1. - (y as f32 + 0.5) / h as f32;
+    scene.focus_distance_at(u, v, w as f32 * pixel_aspect / h as f32)
+}
+
+/// What the image shows that its pixels do not tell, noted in the image files:
+/// the scene and the seed of the random one, and the distance in focus along the view,
+/// when the lens blurs the rest.
+fn notes(scene: &Scene, settings: &RenderSettings) -> Vec<String> {
+    let mut notes: Vec<String> = scene.origin.iter().map(|origin| format!("scene: {}", origin)).collect();
+    let setup = scene.camera().setup();
+    if !settings.depth_of_field || setup.aperture <= 0. {
+        return notes;
+    }
+    let focus_dist = settings.focus_pixel
+        .and_then(|pixel| focus_distance(scene, settings, pixel))
+        .unwrap_or(setup.focus_dist);
+    notes.push(format!("in focus: {:.3} from the camera along the view, aperture {}", focus_dist, setup.aperture));
+    notes
+}
+
+/// The bytes the scene and the frames of the render are expected to take at the peak.
+fn estimate_memory(scene: &Scene, settings: &RenderSettings, w: usize, h: usize) -> u64 {
+    let pixel = size_of::<Vec3>();
+    let frames = match settings.tiled {
+        // every thread holds a tile, and as many finished tiles wait in the queue
+        Some(tile_size) => 2 * settings.thread_count() * tile_size * tile_size * size_of::<PixelSum>(),
+        None => {
+            let scale = settings.render_scale.max(1);
+            // the sums of the samples and the image resolved from them
+            let rendered = w * scale * h * scale * (settings.precision.bytes_per_pixel() + pixel);
+            let downsampled = if scale > 1 { w * h * pixel } else { 0 };
+            // the 8-bit image, the copies made by the post effects and their depth pass
+            let encoded = w * h * 3;
+            let post = if settings.post_effects.is_empty() && settings.lens.is_none() { 0 } else { w * h * pixel };
+            let depths = if settings.post_effects.iter().any(PostEffect::uses_depth) { w * h * size_of::<f32>() } else { 0 };
+            let convergence = match settings.aovs.iter().any(|aov| !aov.is_traced()) {
+                true => w * scale * h * scale * Accumulator::CONVERGENCE_BYTES_PER_PIXEL,
+                false => 0,
+            };
+            rendered + downsampled + encoded + post + depths + convergence
+        }
+    };
+    let copies = if settings.numa { affinity::nodes().len() } else { 1 };
+    // the cache of the trees of the streamed meshes holds at least the one a ray needs now,
+    // the copies of the world share it
+    let tree = scene.world().objects().iter()
+        .map(|surface| match surface {
+            Surface::Mesh { mesh, .. } => mesh.tree_memory(),
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0);
+    (scene.world().memory() * copies + frames + tree) as u64
+}
+
+/// Builds the path of an additional image next to the main output,
+/// the suffix is appended to the file name before the extension.
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}{}.{}", stem, suffix, ext),
+        None => format!("{}{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+/// The tiles of the in-memory renders, small enough to balance the work between the threads.
+const TILE_SIZE: usize = 32;
+
+/// A pass over the image: its number of all the passes, and the samples it takes of every pixel.
+#[derive(Clone, Copy)]
+struct Pass {
+    index: usize,
+    passes: usize,
+    samples: usize,
+}
+
+impl Pass {
+    /// The single pass of all the samples.
+    fn whole(settings: &RenderSettings) -> Pass {
+        Pass { index: 0, passes: 1, samples: settings.samples }
+    }
+}
+
+/// Renders the tiles of the image on `settings.threads` threads.
 ///
-/// LEARN:
-/// In contrast to Java's Streams those iterators are Zero Cost Abstractions,
-/// meaning the compiler will optimize them away and the cost will be
-/// the same as of the `for` loop.
-/// Nothing is allocated on the heap, only stack is used, normally all closures are inlined.
-/// The space cost of a closure is fn ptr + captured variables but even that
-/// can be optimized away if inlined.
+/// The tiles are started in the order of the list. Every finished tile is passed to `on_tile`
+/// on the calling thread in the order they are done, the sums of the samples of the pixels
+/// go row by row from the top-left corner of the tile, with the time its rows took
+/// on all the threads, see `tiles::balance`.
+/// The rendering stops at the first error returned by `on_tile`.
+/// The observer of the settings is told when the tiles start and finish.
+/// Returns how many samples were not finite, see `guard`.
 ///
-/// The method also demonstrates the idiomatic Rust way to handle sequential IO operations.
-/// Result<Vec<()>, std::io::Error> is a Result type that collects the results
-/// of the individual io operations. Result implements the FromIterator trait,
-/// so we can use collect() to aggregate the results of the io operations.
-/// The iterator will stop at the first error and return it.
-
-/// This function is used to render different images, so it's generic over the color function.
-fn render_to_file(scene: &Scene, img: &mut File) -> Result<(), Error> {
-    let ns = 100;
+/// The random generator is seeded with the seed of the settings, the index of the tile,
+/// the row in it (of the whole tile for the pieces of a split one) and the pass, so the image is the same no matter how many threads render it
+/// and which of them renders the row.
+fn render_tiles<E, F>(scene: &Scene, settings: &RenderSettings, w: usize, h: usize, tiles: Vec<Tile>, pass: Pass, mut on_tile: F) -> Result<u64, E>
+where
+    F: FnMut(Tile, Vec<PixelSum>, Duration) -> Result<(), E>,
+{
+    let ns = pass.samples;
+    let aspect = w as f32 / h as f32;
+    // The camera of the scene is built for the size of the scene,
+    // the image may be rendered at any other size.
+    let (_, _, pixel_aspect) = settings.resolution(scene);
+    let camera = scene.camera().with_aspect(aspect * pixel_aspect);
+    let camera = match settings.focus_pixel.and_then(|pixel| focus_distance(scene, settings, pixel)) {
+        Some(focus_dist) => camera.with_focus_dist(focus_dist),
+        None => camera,
+    };
+    let camera = if settings.depth_of_field { camera } else { camera.pinhole() };
+    let camera = match settings.eye {
+        0. => camera,
+        eye => camera.eye(eye * camera.setup().focus_dist),
+    };
+    let lens = settings.lens.as_ref().filter(|lens| !lens.in_post && !lens.is_identity());
+    // the unbiased rays have no footprint, the textures are sampled at the points they hit
+    let spread = if settings.unbiased { 0. } else { camera.pixel_spread(h) };
+    let grid = PixelGrid::new(&camera, w, h, settings.ray_cache);
 
     // LEARN:
     // The closure captures the world and camera values
@@ -80,92 +749,405 @@ fn render_to_file(scene: &Scene, img: &mut File) -> Result<(), Error> {
     // Compiler chooses the least restrictive trait that fits the closure.
     // Here the closure implements the Fn trait that can be used multiple times,
     // i.e. for each iteration, which is what we need.
-    let render_pixel = |(x, y)| {
-        let mut col = Vec3::rgb(0.0, 0.0, 0.0);
+    //
+    // The captured references point to the data that is never changed (and is `Sync`),
+    // so the closure can also be called from many threads at the same time.
+    let non_finite = AtomicU64::new(0);
+    let stopped = Stopped::default();
+    let render_pixel = |world: &World, (x, y): (usize, usize)| {
+        let mut col = CompensatedSum::zero();
+        // How many samples each channel received.
+        let mut weight = Vec3::zero();
+        let (mut samples, mut luminance, mut squares) = (0, 0., 0.);
+        let mut bad = 0;
+        let watch = settings.pixel_budget.start();
+        // The lens bends the rays differently for each color when there is
+        // the chromatic aberration, so every sample traces the three channels along their own rays.
+        let channels = if lens.is_some_and(|lens| lens.has_chromatic_aberration()) { 3 } else { 1 };
         // Antialiasing loop
-        for _ in 0..ns {
-            let u = (x + drand32()) / scene.w as f32;
-            let v = (y + drand32()) / scene.h as f32;
+        for s in 0..ns {
+            let (u, v) = match lens {
+                None => (0., 0.),
+                Some(_) => ((x as f32 + drand32()) / w as f32, (y as f32 + drand32()) / h as f32),
+            };
+            let mut sample = Vec3::zero();
+            for channel in 0..channels {
+                let (mask, ray) = match lens {
+                    None => (Vec3::basis(), camera.ray_along(grid.direction(x, y))),
+                    Some(lens) if channels == 3 => {
+                        let (u, v) = lens.map(u, v, aspect, channel);
+                        (Lens::channel_mask(channel), camera.get_ray(u, v))
+                    }
+                    Some(lens) => {
+                        let (u, v) = lens.map(u, v, aspect, 1);
+                        (Vec3::basis(), camera.get_ray(u, v))
+                    }
+                };
 
-            let ray = scene.camera().get_ray(u, v);
-            col += color(scene.world(), &ray, 0);
+                let ray = ray.with_footprint(0., spread);
+                // the objects that move are seen at a random moment while the shutter is open
+                let ray = if world.is_moving() { ray.at_time(drand32()) } else { ray };
+                settings.non_finite.start_path();
+                // the bad sample still counts, as a black one
+                match settings.non_finite.check(color(world, &ray, Bounces::default(), Media::default(), settings), (x, h - 1 - y), s) {
+                    Some(radiance) => sample += &mask * radiance,
+                    None => bad += 1,
+                }
+            }
+            let lum = output::luminance(&sample);
+            luminance += lum;
+            squares += lum * lum;
+            col.add(&sample);
+            weight += Vec3::basis();
+            samples += 1;
+            // the pixel is the average of the samples it took
+            if let Some(spent) = watch.exceeded().filter(|_| s + 1 < ns) {
+                stopped.report((x, h - 1 - y), s + 1, ns, &spent);
+                break;
+            }
+        }
+        if bad == 0 {
+            return PixelSum { color: col.sum(), weight, samples, luminance, squares };
+        }
+        non_finite.fetch_add(bad, Ordering::Relaxed);
+        match settings.non_finite {
+            NonFinite::Magenta => PixelSum { color: &NonFinite::highlight() * &weight, weight, samples, luminance, squares },
+            _ => PixelSum { color: col.sum(), weight, samples, luminance, squares },
         }
-        col /= ns as f32;
-        col
     };
 
     // LEARN:
-    // No 2D creation is happening here, we're just defining the iterator
-    // over the 2D array of points. move |x| (x as f32, y as f32) creates a closure
-    // that captures the y value from the outer scope.
-    let xy_iter = (0..scene.h).into_iter().rev()
-        .flat_map(|y| (0..scene.w).into_iter().map(move |x| (x as f32, y as f32)));
-
-    // LEARN:
-    // Note that the last `map` operation returns the `Result<(), Error>` type.
-    // The `collect()` is a generic method over the element's type.
-    // Compiler uses the impl of the `FromIterator` trait for the `Result` type.
+    // Here we demonstrate the power of iterators in Rust.
+    // We iterate over each pixel of the row from left to right and calculate the color of it.
     //
-    // `Result`s `FromIterator` impl allows to collect the results of the iterator
-    // into a single Result of Vec<results> or stop on the first error.
+    // No array of the points is created here, we're just defining the iterator
+    // over them. `move |x| ...` creates a closure that captures the row from the outer scope.
     //
-    // Inspired by the Haskell's `traverse` function for sequences.
-    // or in FunctionalJava:
-    // <B> Option<Seq<B>> traverseOption(F<A, Option<B>> f){...} in Seq.java
-    let result: Result<Vec<()>, Error> = xy_iter
-        .map(render_pixel)
-        .map(write_color_to_file(img))
-        .collect();
+    // In contrast to Java's Streams those iterators are Zero Cost Abstractions,
+    // meaning the compiler will optimize them away and the cost will be
+    // the same as of the `for` loop.
+    let render_row = |world: &World, tile: &Tile, row: usize| -> Vec<PixelSum> {
+        // every row has its own stream, whichever thread renders it
+        rand::seed_stream(settings.seed, ((pass.index as u64) << 56) | ((tile.index as u64) << 32) | (tile.first_row + row) as u64);
+        // the rows of the image go from the top, but v grows upwards
+        let v = h - 1 - (tile.y + row);
+        (tile.x..tile.x + tile.w).map(move |x| render_pixel(world, (x, v))).collect()
+    };
 
-    result.map(|_| ())
-}
+    let tile_count = tiles.len();
+    let queue = Queue::new(tiles);
+    let threads = settings.thread_count().min(queue.len()).max(1);
+    let nodes = if settings.numa || settings.pin_threads { affinity::nodes() } else { Vec::new() };
+    // the copy of the scene made by a thread of the node is in the memory of the node
+    let replicas: Vec<World> = if settings.numa && nodes.len() > 1 {
+        thread::scope(|scope| {
+            let copies: Vec<_> = nodes.iter()
+                .map(|cpus| scope.spawn(move || {
+                    affinity::pin(cpus);
+                    scene.world().clone()
+                }))
+                .collect();
+            copies.into_iter().map(|copy| copy.join().expect("copying the scene failed")).collect()
+        })
+    } else {
+        Vec::new()
+    };
+    // The queue of the finished tiles is bounded, so the workers wait
+    // when `on_tile` (e.g. writing to a slow disk) cannot keep up.
+    let (sender, receiver) = mpsc::sync_channel(threads);
 
-fn write_color_to_file(img: &mut File) -> impl FnMut(Vec3) -> Result<(), Error> + '_ {
-    |color: Vec3| {
-        // There is a bug in the book, probably.
-        // According to the book the color should be divided by ns.
-        // But the image turns to be very dark.
-        // let mut col = color / ns;
-        // Gamma correction (gamma 2) is applied to the color to make the objects lighter.
-        let col = Vec3::new(color.r().sqrt(), color.g().sqrt(), color.b().sqrt());
-        // normalize the color values to [0, 255] and convert them to integers
-        let ir = (255.99 * col.r()) as i32;
-        let ig = (255.99 * col.g()) as i32;
-        let ib = (255.99 * col.b()) as i32;
+    // LEARN:
+    // The scoped threads can borrow the local variables (the scene, the tiles),
+    // because the scope does not end until all the threads spawned in it are finished.
+    // `std::thread::spawn` would require the `'static` data, e.g. an `Arc<Scene>`.
+    thread::scope(|scope| {
+        for i in 0..threads {
+            let sender = sender.clone();
+            let (queue, render_row, nodes, replicas) = (&queue, &render_row, &nodes, &replicas);
+            scope.spawn(move || {
+                // the threads take the nodes in turns, and the cores of the node in turns
+                let node = (!nodes.is_empty()).then(|| i % nodes.len());
+                if let Some(cpus) = node.map(|node| &nodes[node]) {
+                    if settings.pin_threads {
+                        affinity::pin(&[cpus[i / nodes.len() % cpus.len()]]);
+                    } else {
+                        affinity::pin(cpus);
+                    }
+                }
+                let world = node.and_then(|node| replicas.get(node)).unwrap_or(scene.world());
+                while let Some(work) = queue.take() {
+                    while let Some(row) = work.take_row() {
+                        if row == 0 {
+                            settings.observer.on_tile_start(&work.tile, pass.index);
+                        }
+                        let started = Instant::now();
+                        let line = render_row(world, &work.tile, row);
+                        let Some((pixels, spent)) = queue.finish_row(&work, row, &line, started.elapsed()) else {
+                            continue;
+                        };
+                        // Sending fails only when the receiver is gone, i.e. on an error.
+                        if sender.send((work.tile, pixels, spent)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        // The workers own the other senders, the loop below ends when all of them are done.
+        drop(sender);
 
-        // LEARN:
-        // Here no heap allocations are happening.
-        // No new strings are created. Format is a const string.
-        // write! macro splits the format, and writes the pieces and arguments
-        // to the file buffer.
-        // Compare to C++ std::cout << ir << " " << ig << " " << ib << std::endl;
-        write!(img, "{} {} {}\n", ir, ig, ib)
-    }
+        let mut progress = Progress { pass: pass.index, passes: pass.passes, samples: ns, tiles_done: 0, tiles: tile_count };
+        for (tile, pixels, spent) in receiver {
+            progress.tiles_done += 1;
+            settings.observer.on_tile_finish(&tile, progress);
+            on_tile(tile, pixels, spent)?;
+        }
+        settings.observer.on_pass_complete(progress);
+        if stopped.count() > 0 {
+            eprintln!("{} pixels were stopped by the pixel budget.", stopped.count());
+        }
+        Ok(non_finite.load(Ordering::Relaxed))
+    })
 }
 
-fn color(w: &World, r: &Ray, recurs_dep: i32) -> Vec3 {
+/// The spread of the beams scattered by the diffuse surfaces, a rough fit of the wide lobe.
+/// Their textures are blurred much more than the ones seen directly, the noise of
+/// the indirect light hides the difference anyway.
+const DIFFUSE_SPREAD: f32 = 0.5;
+
+fn color(w: &World, r: &Ray, bounces: Bounces, media: Media, settings: &RenderSettings) -> Vec3 {
     // 0.001 as a min value is chosen to avoid the
     // shadow acne problem (too white or too dark spots).
-    match w.hit(r, 0.001, f32::MAX) {
+    watchdog::count_ray();
+    let hit = w.intersect(r, 0.001, f32::MAX);
+    // the escaping rays pass through the fog up to the infinity
+    let t = hit.as_ref().map_or(f32::MAX, |hit| hit.t);
+    let radiance = match hit {
         Some(hit) => {
-            if recurs_dep < 50 {
-                match hit.material.scatter(r, hit) {
-                    Some((s, a)) => {
-                        a * color(w, &s, recurs_dep + 1)
+            if bounces.total < settings.max_depth {
+                // the path that ends here needs no shading of the hit, only its distance
+                let hit = w.shade(r, &hit);
+                let min_roughness = if bounces.total >= RenderSettings::REGULARIZE_AFTER {
+                    settings.min_roughness
+                } else {
+                    0.
+                };
+                let width = r.footprint_at(hit.t);
+                let material = w.material(hit.material);
+                let (p, normal) = (hit.p.clone(), hit.normal.clone());
+                // the record goes to the material, the shader sees a copy of it
+                let shader = w.shader(hit.material).map(|shader| (shader, hit.clone()));
+                let bounce = bounces.total as usize;
+                settings.non_finite.trace(|| format!("bounce {}: the {:?} ray from {:?} along {:?} hits {:?} at t = {}, the normal {:?}",
+                                                     bounces.total, r.kind(), r.origin(), r.direction(), p, hit.t, normal));
+                let (scattered, refracted) = match media.cross(w, hit.material, r, &normal) {
+                    // the surface within the medium of a higher priority is not there for the ray
+                    Interface::Hidden(inside) => {
+                        let through = Ray::scattered(p, r.direction().clone(), r.kind()).with_footprint(width, r.spread()).at_time(r.time());
+                        return absorbed(w, &media, t, r, color(w, &through, bounces, inside, settings));
+                    }
+                    Interface::Crossing { ref_idx, refracted } => (material.scatter_between(r, hit, min_roughness, ref_idx), refracted),
+                    Interface::Untracked => (material.scatter(r, hit, min_roughness), media),
+                };
+                let radiance = match scattered {
+                    // the path that runs out of the bounces of its kind is absorbed
+                    Some((s, a)) => match bounces.after(s.kind(), &settings.bounce_limits) {
+                        Some(bounces) => {
+                            // the light gathered directly is the one the scattered ray could bring,
+                            // so it obeys the same limits
+                            let direct = match s.kind() {
+                                RayType::Diffuse => {
+                                    direct_light(w, &p, r.time(), |to_light| material.diffuse_response(r, &normal, to_light), true)
+                                }
+                                // the regularized mirrors and glass see the lights blurred around the ray
+                                _ if min_roughness > 0. => {
+                                    direct_light(w, &p, r.time(), |to_light| regularized_response(s.direction(), to_light, min_roughness), false)
+                                }
+                                _ => Vec3::zero(),
+                            };
+                            // the mirrors keep the beam as narrow as it came, as if they were flat,
+                            // the diffuse surfaces spread it over the half of the sphere
+                            let spread = if s.kind() == RayType::Diffuse && !settings.unbiased { DIFFUSE_SPREAD } else { r.spread() };
+                            settings.non_finite.trace(|| format!("  scattered {:?} along {:?}, the attenuation {:?}, the direct light {:?}",
+                                                                 s.kind(), s.direction(), a, direct));
+                            let media = if s.kind() == RayType::Refraction { refracted } else { media };
+                            &a * (direct + color(w, &s.with_footprint(width, spread).at_time(r.time()), bounces, media, settings))
+                        }
+                        None => Vec3::zero(),
                     },
-                    None => Vec3::zero(),
+                    // the path ends at a lamp with its light, or is absorbed
+                    None => material.emitted(),
+                };
+                match shader {
+                    Some((shader, hit)) => shader.shade(&hit, r, bounce, radiance),
+                    None => radiance,
                 }
             } else {
                 Vec3::zero()
             }
         },
+        // the sky seen through a portal from a diffuse hit is already counted there
+        None if r.kind() == RayType::Diffuse && w.portals().iter().any(|portal| portal.crosses(r)) => Vec3::zero(),
+        // and so is the environment map when it is gathered at the diffuse hits
+        None if r.kind() == RayType::Diffuse && gathers_environment(w) => Vec3::zero(),
+        None => {
+            let radiance = environment(w, r);
+            settings.non_finite.trace(|| format!("bounce {}: the {:?} ray from {:?} along {:?} escapes, the environment {:?}",
+                                                 bounces.total, r.kind(), r.origin(), r.direction(), radiance));
+            radiance
+        }
+    };
+    let radiance = absorbed(w, &media, t, r, radiance);
+    settings.non_finite.trace(|| format!("bounce {} returns {:?}", bounces.total, radiance));
+    radiance
+}
+
+/// The radiance that comes to the origin of the ray from the distance `t` along it,
+/// through the medium the path is in, or the fog of the world around them.
+fn absorbed(w: &World, media: &Media, t: f32, r: &Ray, radiance: Vec3) -> Vec3 {
+    let distance = t * r.direction().length();
+    if let Some(transmittance) = media.transmittance(w, distance).filter(|_| t < f32::MAX) {
+        return &transmittance * radiance;
+    }
+    // the fog is between the camera and the surfaces, and between the bounces too
+    match w.fog() {
+        Some(fog) if fog.is_lit() => {
+            let sun = w.sky().map(|sky| sky.sun()).map(|(to_sun, sunlight)| (to_sun.clone(), sunlight));
+            let visible = |shadow: &Ray, reach: f32| {
+                watchdog::count_ray();
+                !w.occluded(shadow, 0.001, reach)
+            };
+            fog.apply(r, t, radiance) + fog.scattered(r, t, w.lights(), sun, visible)
+        }
+        Some(fog) => fog.apply(r, t, radiance),
+        None => radiance,
+    }
+}
+
+/// The light of the lights and the sun that reaches the hit unblocked at the time of the path,
+/// times the share of it the material scatters along the ray, its `response` to the direction of the light.
+///
+/// The sky of the portals and the environment map are gathered only at the diffuse hits,
+/// the diffuse rays that leave through the portals (or at all, for the map) are the only ones
+/// that do not bring it.
+fn direct_light(w: &World, p: &Vec3, time: f32, response: impl Fn(&Vec3) -> f32, through_portals: bool) -> Vec3 {
+    // the shadow ray goes up to `reach`, the light is as far along it
+    let shine = |to_light: Vec3, reach: f32, light: Vec3| {
+        let response = response(&to_light);
+        if response <= 0. {
+            return Vec3::zero();
+        }
+        let shadow = Ray::scattered(p.clone(), to_light, RayType::Shadow).at_time(time);
+        watchdog::count_ray();
+        if w.occluded(&shadow, 0.001, reach) {
+            Vec3::zero()
+        } else {
+            response * light
+        }
+    };
+    let mut sum = Vec3::zero();
+    for light in w.lights() {
+        // the way to the light is as long as the distance to it
+        if let Some((to_light, light)) = light.illuminate(p) {
+            // the lit fog dims the light on the way, see `Fog::scattered`
+            let light = match w.fog().filter(|fog| fog.is_lit()) {
+                Some(fog) => fog.transmittance(&Ray::scattered(p.clone(), to_light.clone(), RayType::Shadow), 1.) * light,
+                None => light,
+            };
+            sum += shine(to_light, 1., light);
+        }
+    }
+    if let Some(sky) = w.sky() {
+        let (to_sun, sunlight) = sky.sun();
+        if to_sun.y() > 0. {
+            sum += shine(to_sun.clone(), f32::MAX, sunlight);
+        }
+    }
+    for portal in w.portals().iter().filter(|_| through_portals) {
+        // the sky behind a random point of the window, the point is picked with
+        // the density of one over the area, the solid angle of it shrinks with the distance
+        let to_window = portal.sample() - p;
+        let squared_distance = to_window.squared_length();
+        if squared_distance == 0. {
+            continue;
+        }
+        let sky = environment(w, &Ray::scattered(p.clone(), to_window.clone(), RayType::Diffuse));
+        let weight = portal.cosine(&to_window) * portal.area() / squared_distance;
+        sum += shine(to_window, f32::MAX, weight * sky);
+    }
+    if let Some(environment) = w.environment().filter(|_| through_portals && gathers_environment(w)) {
+        if let Some((to_sky, light)) = environment.sample() {
+            sum += shine(to_sky, f32::MAX, light);
+        }
+    }
+    sum
+}
+
+/// Whether the diffuse hits gather the environment map. The portals already send the rays
+/// to the sky that can be seen from the room, a map sampled too would count it twice.
+fn gathers_environment(w: &World) -> bool {
+    w.environment().is_some() && w.portals().is_empty()
+}
+
+/// The response of the mirror or the glass to the light near the direction it scattered
+/// the ray to, the same within the cone of the roughness around it, 1 over its solid angle.
+///
+/// LEARN:
+/// The point lights and the sun are never hit by the rays, a perfect mirror or glass
+/// would have to reflect them exactly into the direction of the ray, which has no chance.
+/// The light reflected by the mirror onto the floor (a caustic) would stay black forever.
+/// Blurring the mirror a little for the lights (the path regularization) lets the light
+/// through, slightly spread, the bias the renderer trades for the missing light.
+fn regularized_response(scattered: &Vec3, to_light: &Vec3, roughness: f32) -> f32 {
+    let cone = 1. / (1. + roughness * roughness).sqrt();
+    let cosine = Vec3::dot(&scattered.unit(), &to_light.unit());
+    if cosine >= cone { 1. / (2. * std::f32::consts::PI * (1. - cone)) } else { 0. }
+}
+
+/// The light that comes from far away along the ray, the environment map, the stars, the sky or the gradient.
+fn environment(w: &World, r: &Ray) -> Vec3 {
+    if let Some(environment) = w.environment() {
+        return environment.radiance(r.direction());
+    }
+    if let Some(stars) = w.stars() {
+        return stars.radiance(r.direction());
+    }
+    match w.sky() {
+        Some(sky) => sky.radiance(r.direction()),
         None => background(r),
     }
 }
 
+/// How many times the path has bounced so far, in total and of each kind.
+#[derive(Clone, Copy, Default)]
+struct Bounces {
+    total: i32,
+    diffuse: i32,
+    reflection: i32,
+    refraction: i32,
+}
+
+impl Bounces {
+    /// Counts one more bounce of the kind, `None` when it exceeds the limit of the kind.
+    fn after(mut self, kind: RayType, limits: &BounceLimits) -> Option<Bounces> {
+        self.total += 1;
+        let count = match kind {
+            RayType::Camera | RayType::Shadow => return Some(self),
+            RayType::Diffuse => &mut self.diffuse,
+            RayType::Reflection => &mut self.reflection,
+            RayType::Refraction => &mut self.refraction,
+        };
+        *count += 1;
+        match limits.limit(kind) {
+            Some(limit) if *count > limit => None,
+            _ => Some(self),
+        }
+    }
+}
+
 /// Simple linear interpolation of the blue color channel on the Y axis.
 fn background(r: &Ray) -> Vec3 {
     let unit_direction = r.direction().unit();
     let t = 0.5 * (unit_direction.y() + 1.0);
     (1.0 - t) * Vec3::basis() + t * Vec3::rgb(0.5, 0.7, 1.0)
-}
\ No newline at end of file
+}
@@ -0,0 +1,262 @@
+/**
+The accumulation of the samples over the passes of the progressive render.
+
+The progressive render splits the samples of every pixel into passes over the whole image,
+so a noisy image of the whole frame is there after the first pass and it gets cleaner
+with every next one, the render can be judged (or stopped) long before it is finished.
+
+The accumulator keeps the sum of the samples of every pixel, not the average, so a pass
+only adds to it, and the average is taken when the image is needed.
+
+LEARN:
+The threads never share the accumulator. Every thread renders its rows into a buffer
+of its own tile, and the finished tiles go to the single owner of the accumulator,
+which adds them. Adding a tile is a tiny fraction of the work of rendering it, so
+the threads neither lock nor wait for each other per sample (even the atomic additions
+would make the cores fight over the cache lines), and the render scales with the cores.
+
+The very large frames can keep the sums in the half precision, see `Precision`.
+
+LEARN:
+A float keeps 24 bits of the value, so adding a sample to the sum of thousands of them
+rounds away the bits of the sample below the last bit of the sum. The lost bits add up,
+and a render of many samples comes out subtly wrong. The compensated (Kahan) summation
+keeps the rounding error of every addition and adds it back to the next sample,
+so the sum is as precise as if it was summed in the double precision.
+*/
+use crate::math::half::{from_half, to_half};
+use crate::math::vec::Vec3;
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::tiles::Tile;
+
+/// The sum of the samples of a pixel and how many of them every channel received.
+#[derive(Clone, Debug)]
+pub(crate) struct PixelSum {
+    pub(crate) color: Vec3,
+    pub(crate) weight: Vec3,
+    /// How many samples the pixel took, fewer than asked when the pixel budget stopped it.
+    pub(crate) samples: u32,
+    /// The sum of the luminances of the samples and of their squares, for the error
+    /// of the pixel, see `Convergence`.
+    pub(crate) luminance: f32,
+    pub(crate) squares: f32,
+}
+
+/// The sum of the colors that keeps the bits every addition rounds away, see the module.
+#[derive(Clone, Debug)]
+pub(crate) struct CompensatedSum {
+    sum: Vec3,
+    /// The part of the sum that was rounded away, negated.
+    compensation: Vec3,
+}
+
+impl CompensatedSum {
+    pub(crate) fn zero() -> CompensatedSum {
+        CompensatedSum { sum: Vec3::zero(), compensation: Vec3::zero() }
+    }
+
+    pub(crate) fn add(&mut self, value: &Vec3) {
+        let corrected = value - &self.compensation;
+        let sum = &self.sum + &corrected;
+        // what the addition has lost: the floats are never reordered by the compiler,
+        // so this is not optimized to zero
+        self.compensation = &(&sum - &self.sum) - &corrected;
+        self.sum = sum;
+    }
+
+    pub(crate) fn sum(&self) -> Vec3 {
+        self.sum.clone()
+    }
+}
+
+impl PixelSum {
+    pub(crate) fn zero() -> PixelSum {
+        PixelSum { color: Vec3::zero(), weight: Vec3::zero(), samples: 0, luminance: 0., squares: 0. }
+    }
+
+    /// The average of the samples.
+    pub(crate) fn mean(&self) -> Vec3 {
+        &self.color / &self.weight
+    }
+}
+
+/// How the accumulator stores the pixels.
+///
+/// A 16384x16384 frame takes 9 GB of the single precision sums, the half precision
+/// takes 3 GB. The half keeps about 3 significant digits, an error of 0.05%, well below
+/// the step of the 8-bit image (0.4%), but the values above 65504 are clipped,
+/// so the glints brighter than that are darker, and the half precision weights count
+/// the samples exactly only up to 2048 (beyond that the passes are weighted a little
+/// unevenly, which adds no bias). The render with it must take at most 65504 samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Precision {
+    /// The compensated sums of the samples in `f32`, 36 bytes per pixel.
+    #[default]
+    Single,
+    /// The averages of the samples and their weights in `f16`, 12 bytes per pixel.
+    Half,
+}
+
+impl Precision {
+    /// The most samples a pixel can take at the precision.
+    pub(crate) const MAX_HALF_SAMPLES: usize = 65504;
+
+    pub(crate) fn parse(name: &str) -> Option<Precision> {
+        match name.to_ascii_lowercase().as_str() {
+            "f32" | "single" => Some(Precision::Single),
+            "f16" | "half" => Some(Precision::Half),
+            _ => None,
+        }
+    }
+
+    /// The memory a pixel takes in the accumulator.
+    pub(crate) fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Precision::Single => size_of::<CompensatedSum>() + size_of::<Vec3>(),
+            Precision::Half => size_of::<[u16; 6]>(),
+        }
+    }
+}
+
+/// The pixels, row by row from the top-left corner of the image.
+enum Storage {
+    Single { colors: Vec<CompensatedSum>, weights: Vec<Vec3> },
+    /// The averages of the three channels and their weights. The averages, not the sums,
+    /// the sums of many samples would soon be too large for the few digits of the half.
+    Half(Vec<[u16; 6]>),
+}
+
+/// The sums of all the pixels.
+pub(crate) struct Accumulator {
+    w: usize,
+    h: usize,
+    storage: Storage,
+    convergence: Option<Vec<Moments>>,
+}
+
+/// How many samples a pixel took and the sums of their luminances and of their squares,
+/// in the double precision: the variance is the small difference of the two large sums.
+#[derive(Clone, Copy, Default)]
+struct Moments {
+    samples: u32,
+    luminance: f64,
+    squares: f64,
+}
+
+/// The samples every pixel took and the estimated error of the pixel: the standard error
+/// of its luminance (how far the average of the samples is from the converged value,
+/// typically) relative to the luminance, so the same in the dark and in the bright parts.
+/// Infinite for the pixels of a single sample, one sample tells nothing of the error.
+/// Both are pixel by pixel, row by row from the top.
+pub(crate) struct Convergence {
+    pub(crate) samples: Vec<f32>,
+    pub(crate) error: Vec<f32>,
+}
+
+impl Accumulator {
+    pub(crate) fn new(w: usize, h: usize, precision: Precision) -> Accumulator {
+        let storage = match precision {
+            Precision::Single => Storage::Single {
+                colors: vec![CompensatedSum::zero(); w * h],
+                weights: vec![Vec3::zero(); w * h],
+            },
+            Precision::Half => Storage::Half(vec![[0; 6]; w * h]),
+        };
+        Accumulator { w, h, storage, convergence: None }
+    }
+
+    /// The memory a pixel of the convergence takes, see `with_convergence`.
+    pub(crate) const CONVERGENCE_BYTES_PER_PIXEL: usize = size_of::<Moments>();
+
+    /// Also counts the samples of every pixel and the sums for its error, see `Convergence`.
+    pub(crate) fn with_convergence(self) -> Accumulator {
+        Accumulator { convergence: Some(vec![Moments::default(); self.w * self.h]), ..self }
+    }
+
+    /// Adds the sums of the tile, row by row from its top-left corner.
+    pub(crate) fn add(&mut self, tile: &Tile, sums: &[PixelSum]) {
+        for (row, line) in sums.chunks(tile.w).enumerate() {
+            let start = (tile.y + row) * self.w + tile.x;
+            let pixels = start..start + tile.w;
+            if let Some(moments) = &mut self.convergence {
+                for (moments, pixel) in moments[pixels.clone()].iter_mut().zip(line) {
+                    moments.samples += pixel.samples;
+                    moments.luminance += pixel.luminance as f64;
+                    moments.squares += pixel.squares as f64;
+                }
+            }
+            match &mut self.storage {
+                Storage::Single { colors, weights } => {
+                    // the weights count the samples, the floats count them exactly up to 2^24
+                    for ((color, weight), pixel) in colors[pixels.clone()].iter_mut().zip(&mut weights[pixels]).zip(line) {
+                        color.add(&pixel.color);
+                        *weight = &*weight + &pixel.weight;
+                    }
+                }
+                Storage::Half(stored) => {
+                    for (halves, pixel) in stored[pixels].iter_mut().zip(line) {
+                        let sums = [pixel.color.x(), pixel.color.y(), pixel.color.z()];
+                        let weights = [pixel.weight.x(), pixel.weight.y(), pixel.weight.z()];
+                        for channel in 0..3 {
+                            let (mean, weight) = (from_half(halves[channel]), from_half(halves[channel + 3]));
+                            let total = weight + weights[channel];
+                            if total > 0. {
+                                halves[channel] = to_half((mean * weight + sums[channel]) / total);
+                                halves[channel + 3] = to_half(total);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The image of the samples added so far.
+    pub(crate) fn resolve(&self) -> FrameBuffer {
+        let pixels = match &self.storage {
+            Storage::Single { colors, weights } => colors.iter()
+                .zip(weights)
+                .map(|(color, weight)| &color.sum() / weight)
+                .collect(),
+            Storage::Half(stored) => stored.iter()
+                .map(|halves| Vec3::new(from_half(halves[0]), from_half(halves[1]), from_half(halves[2])))
+                .collect(),
+        };
+        FrameBuffer::from_pixels(self.w, self.h, pixels)
+    }
+
+    /// The convergence of the image `factor` times smaller, as `FrameBuffer::downsample`
+    /// makes it: every pixel is of all the samples of the pixels it is made of.
+    /// `None` unless the accumulator counts it, see `with_convergence`.
+    pub(crate) fn convergence(&self, factor: usize) -> Option<Convergence> {
+        let moments = self.convergence.as_ref()?;
+        let factor = factor.max(1);
+        let (w, h) = (self.w / factor, self.h / factor);
+        let (samples, error) = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w * factor, i / w * factor);
+                let block = (y..y + factor).flat_map(|y| moments[y * self.w + x..y * self.w + x + factor].iter());
+                let total = block.fold(Moments::default(), |total, pixel| Moments {
+                    samples: total.samples + pixel.samples,
+                    luminance: total.luminance + pixel.luminance,
+                    squares: total.squares + pixel.squares,
+                });
+                (total.samples as f32, relative_error(&total))
+            })
+            .unzip();
+        Some(Convergence { samples, error })
+    }
+}
+
+/// The standard error of the mean luminance of the samples relative to it, see `Convergence`.
+fn relative_error(moments: &Moments) -> f32 {
+    if moments.samples < 2 {
+        return f32::INFINITY;
+    }
+    let n = moments.samples as f64;
+    let mean = moments.luminance / n;
+    // the unbiased variance of the samples, the rounding may make it a little negative
+    let variance = ((moments.squares / n - mean * mean) * n / (n - 1.)).max(0.);
+    // the small constant keeps the black pixels from having an infinite error
+    ((variance / n).sqrt() / (mean + 0.01)) as f32
+}
@@ -0,0 +1,81 @@
+/**
+The placement of the rendering threads on the cores of the large render nodes.
+
+A machine with several processor sockets is a NUMA machine (the non-uniform memory access):
+every socket has its own memory, and reading the memory of the other socket is slower.
+The operating system moves the threads between the cores as it likes, so a thread
+may find itself far from the memory it works with, and from the cache it warmed up.
+
+Pinning a thread keeps it on its cores. With `numa`, the threads are spread over
+the nodes (the sockets) and every node gets its own copy of the scene, so the rays
+of a thread never read the memory of the other socket. The copies of the textures
+are shared, the images are read far less often than the geometry.
+
+The topology is read from `/sys/devices/system/node` of Linux, the other systems
+(and the machines of a single node) render as without the options.
+*/
+use std::fs;
+use std::path::Path;
+
+/// The CPUs of every NUMA node, a single node with all the CPUs when the topology is unknown.
+pub(crate) fn nodes() -> Vec<Vec<usize>> {
+    let known = fs::read_dir("/sys/devices/system/node").ok().map(|entries| {
+        let mut nodes: Vec<(usize, Vec<usize>)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let number = name.strip_prefix("node")?.parse().ok()?;
+                Some((number, cpu_list(&entry.path().join("cpulist"))?))
+            })
+            .filter(|(_, cpus)| !cpus.is_empty())
+            .collect();
+        nodes.sort();
+        nodes.into_iter().map(|(_, cpus)| cpus).collect::<Vec<_>>()
+    });
+    match known {
+        Some(nodes) if !nodes.is_empty() => nodes,
+        _ => {
+            let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+            vec![(0..cores).collect()]
+        }
+    }
+}
+
+/// Reads the list of the CPUs like `0-15,32-47`.
+fn cpu_list(path: &Path) -> Option<Vec<usize>> {
+    let text = fs::read_to_string(path).ok()?;
+    let mut cpus = Vec::new();
+    for range in text.trim().split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// Keeps the current thread on the CPUs, returns whether the system allowed it.
+#[cfg(target_os = "linux")]
+pub(crate) fn pin(cpus: &[usize]) -> bool {
+    // LEARN:
+    // The standard library does not pin the threads, but it links the C library anyway,
+    // so its function can be declared and called directly (the FFI), without a crate.
+    // The mask is the bit set of the CPUs, as many 64-bit words as the largest CPU needs.
+    extern "C" {
+        fn sched_setaffinity(pid: i32, size: usize, mask: *const u64) -> i32;
+    }
+    let Some(&last) = cpus.iter().max() else {
+        return false;
+    };
+    let mut mask = vec![0u64; last / 64 + 1];
+    for &cpu in cpus {
+        mask[cpu / 64] |= 1 << (cpu % 64);
+    }
+    // SAFETY: the mask is as long as the size says, the pid 0 is the calling thread
+    unsafe { sched_setaffinity(0, mask.len() * 8, mask.as_ptr()) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pin(_cpus: &[usize]) -> bool {
+    false
+}
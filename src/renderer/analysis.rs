@@ -0,0 +1,166 @@
+/**
+The exposure analysis of the rendered frame: the luminance histogram and the false-color map.
+
+Both measure the luminance in stops relative to the middle gray (0.18), after the exposure
+compensation, the way the light meters and the monitors of the cinema cameras do.
+The clipped highlights and the crushed shadows are obvious in them, and the noise
+shows as the speckles of the neighbouring colors in the false-color map.
+*/
+use std::fmt::Write;
+
+use crate::math::color::{Color, ColorSpace};
+use crate::math::vec::Vec3;
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::output::{self, Image};
+use crate::renderer::settings::RenderSettings;
+
+/// The format of the histogram file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum HistogramFormat {
+    Text,
+    Json,
+}
+
+impl HistogramFormat {
+    pub(crate) fn parse(name: &str) -> Option<HistogramFormat> {
+        match name {
+            "text" | "txt" => Some(HistogramFormat::Text),
+            "json" => Some(HistogramFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            HistogramFormat::Text => "txt",
+            HistogramFormat::Json => "json",
+        }
+    }
+}
+
+/// The darkest and the brightest bins in stops from the middle gray,
+/// the darker and the brighter pixels are counted in the edge bins.
+const MIN_STOP: i32 = -8;
+const MAX_STOP: i32 = 4;
+
+/// The counts of the pixels by their luminance, a bin per stop.
+pub(crate) struct Histogram {
+    /// `bins[i]` counts the pixels from `MIN_STOP + i` to `MIN_STOP + i + 1` stops.
+    bins: Vec<usize>,
+    /// Pixels with a channel above white, they lose the detail in the 8-bit image.
+    clipped: usize,
+    /// Pixels that receive no light at all.
+    black: usize,
+    total: usize,
+}
+
+impl Histogram {
+    pub(crate) fn of(frame: &FrameBuffer, exposure: f32) -> Histogram {
+        let scale = 2f32.powf(exposure);
+        let mut histogram = Histogram {
+            bins: vec![0; (MAX_STOP - MIN_STOP) as usize],
+            clipped: 0,
+            black: 0,
+            total: frame.pixels().len(),
+        };
+        for c in frame.pixels() {
+            let c = scale * c;
+            if c.r() > 1. || c.g() > 1. || c.b() > 1. {
+                histogram.clipped += 1;
+            }
+            match stops(&c) {
+                None => histogram.black += 1,
+                Some(stops) => {
+                    let bin = (stops.floor() as i32).clamp(MIN_STOP, MAX_STOP - 1) - MIN_STOP;
+                    histogram.bins[bin as usize] += 1;
+                }
+            }
+        }
+        histogram
+    }
+
+    pub(crate) fn format(&self, format: HistogramFormat) -> String {
+        match format {
+            HistogramFormat::Text => self.to_text(),
+            HistogramFormat::Json => self.to_json(),
+        }
+    }
+
+    /// A bar chart of the bins with the percentages.
+    fn to_text(&self) -> String {
+        const WIDTH: usize = 50;
+        let percent = |count: usize| 100. * count as f64 / self.total.max(1) as f64;
+        let largest = self.bins.iter().copied().max().unwrap_or(0).max(1);
+
+        let mut text = String::from("stops from the middle gray\n");
+        // LEARN:
+        // `write!` works with any `std::fmt::Write`, and `String` is one of them,
+        // so the text is formatted in place without the temporary strings.
+        for (i, &count) in self.bins.iter().enumerate().rev() {
+            let bar = "#".repeat((count * WIDTH).div_ceil(largest));
+            let _ = writeln!(text, "{:+3} {:6.2}% {}", MIN_STOP + i as i32, percent(count), bar);
+        }
+        let _ = writeln!(text, "clipped {:.2}%, black {:.2}%", percent(self.clipped), percent(self.black));
+        text
+    }
+
+    fn to_json(&self) -> String {
+        let bins: Vec<String> = self.bins.iter()
+            .enumerate()
+            .map(|(i, count)| format!("{{\"stop\": {}, \"count\": {}}}", MIN_STOP + i as i32, count))
+            .collect();
+        format!(
+            "{{\n  \"pixels\": {},\n  \"clipped\": {},\n  \"black\": {},\n  \"bins\": [\n    {}\n  ]\n}}\n",
+            self.total, self.clipped, self.black, bins.join(",\n    "))
+    }
+}
+
+/// Paints every pixel with the color of its exposure zone.
+pub(crate) fn false_color(frame: &FrameBuffer, exposure: f32) -> Image {
+    // the colors follow the common convention of the exposure tools:
+    // cold colors for the shadows, gray for the middle, warm colors for the highlights
+    const ZONES: [(f32, [u8; 3]); 8] = [
+        (-6., [40, 0, 60]),         // purple: crushed
+        (-4., [20, 40, 200]),       // blue: deep shadows
+        (-2., [40, 140, 200]),      // cyan: shadows
+        (-0.5, [100, 100, 100]),    // dark gray: lower midtones
+        (0.5, [60, 180, 60]),       // green: the middle gray
+        (1.5, [170, 170, 170]),     // light gray: upper midtones
+        (2.5, [240, 220, 40]),      // yellow: highlights
+        (f32::INFINITY, [250, 130, 0]), // orange: near white
+    ];
+    const CLIPPED: [u8; 3] = [255, 0, 0];
+
+    let scale = 2f32.powf(exposure);
+    let pixels = frame.pixels().iter()
+        .map(|c| {
+            let c = scale * c;
+            if c.r() > 1. || c.g() > 1. || c.b() > 1. {
+                return CLIPPED;
+            }
+            match stops(&c) {
+                None => [0, 0, 0],
+                Some(stops) => ZONES.iter().find(|(upper, _)| stops < *upper).map_or(CLIPPED, |zone| zone.1),
+            }
+        })
+        .collect();
+    Image {
+        w: frame.width(),
+        h: frame.height(),
+        // the colors are meant for the display as they are
+        color_space: ColorSpace::Srgb,
+        pixels,
+        biases: Vec::new(),
+        notes: Vec::new(),
+    }
+}
+
+/// The luminance in stops from the middle gray, `None` for black.
+fn stops(c: &Vec3) -> Option<f32> {
+    let luminance = output::luminance(c);
+    if luminance > 0. {
+        Some((luminance / RenderSettings::MIDDLE_GRAY).log2())
+    } else {
+        None
+    }
+}
@@ -0,0 +1,175 @@
+/**
+The passes of the surfaces seen through the pixels, the AOVs ("arbitrary output variables")
+of the compositors: the normal, the depth, the albedo and the id of the surface.
+They are not lit, so the compositor can relight, fog, mask or denoise the image with them.
+
+The samples and the error are the passes of the render itself, the map of its convergence:
+how many samples every pixel took (fewer where the pixel budget stopped it) and how far
+the pixel is from the converged image, see `Convergence`. They show whether the samples
+went where the image needed them, the error is high where it needs more.
+
+The passes of the surfaces are taken by a single ray through the center of every pixel from the center
+of the lens, they have no noise and cost little next to the image. They are written
+as the layers of the OpenEXR image with the image (`--output result.exr`), see `exr`,
+or as the images next to it for the other formats, e.g. `result_normal.ppm`,
+which only show them: the 8 bits cannot hold their values.
+*/
+use crate::math::color::ColorSpace;
+use crate::math::rand::splitmix64;
+use crate::math::vec::Vec3;
+use crate::renderer::accumulator::Convergence;
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::output::{self, Image};
+use crate::renderer::settings::RenderSettings;
+use crate::scene::camera::{CameraSetup, RaySource};
+use crate::scene::surfaces::hitable::Hitable;
+use crate::scene::Scene;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Aov {
+    /// The normal of the surface in the space of the scene, outwards.
+    Normal,
+    /// The distance along the view, infinite for the sky.
+    Depth,
+    /// The color of the surface without the light, see `Material::albedo`.
+    Albedo,
+    /// The index of the object in the scene from 1, 0 for the sky.
+    Id,
+    /// How many samples the pixel took.
+    Samples,
+    /// The estimated error of the pixel relative to its luminance.
+    Error,
+}
+
+/// A pass of the image: the values of its channels, pixel by pixel, row by row from the top.
+pub(crate) struct Layer {
+    pub(crate) aov: Aov,
+    pub(crate) values: Vec<f32>,
+}
+
+impl Aov {
+    pub(crate) const ALL: [Aov; 6] = [Aov::Normal, Aov::Depth, Aov::Albedo, Aov::Id, Aov::Samples, Aov::Error];
+
+    pub(crate) fn parse(name: &str) -> Option<Aov> {
+        Aov::ALL.into_iter().find(|aov| aov.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Albedo => "albedo",
+            Aov::Id => "id",
+            Aov::Samples => "samples",
+            Aov::Error => "error",
+        }
+    }
+
+    /// The names of the channels of the pass, as the compositors expect them.
+    pub(crate) fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Aov::Normal => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::Albedo => &["R", "G", "B"],
+            Aov::Id | Aov::Samples => &["V"],
+            Aov::Error => &["Y"],
+        }
+    }
+
+    /// Whether the pass is of the surfaces, taken by `render`, not of the render itself.
+    pub(crate) fn is_traced(&self) -> bool {
+        !matches!(self, Aov::Samples | Aov::Error)
+    }
+}
+
+/// The passes of the surfaces of the image of the size, the passes of the render
+/// among the passes are skipped, see `Aov::is_traced`.
+pub(crate) fn render(scene: &Scene, aovs: &[Aov], w: usize, h: usize, aspect: f32) -> Vec<Layer> {
+    let setup = scene.camera().setup();
+    let pinhole = CameraSetup { aperture: 0., ..setup.clone() }.build(aspect);
+    let world = scene.world();
+    let mut layers: Vec<Layer> = aovs.iter()
+        .filter(|aov| aov.is_traced())
+        .map(|&aov| Layer { aov, values: Vec::with_capacity(w * h * aov.channels().len()) })
+        .collect();
+    for i in 0..w * h {
+        let u = ((i % w) as f32 + 0.5) / w as f32;
+        let v = 1. - ((i / w) as f32 + 0.5) / h as f32;
+        let r = pinhole.get_ray(u, v);
+        let hit = world.intersect(&r, 0.001, f32::MAX).map(|hit| (hit.object(), world.shade(&r, &hit)));
+        for layer in layers.iter_mut() {
+            match (layer.aov, &hit) {
+                (Aov::Normal, Some((_, hit))) => layer.values.extend([hit.normal.x(), hit.normal.y(), hit.normal.z()]),
+                (Aov::Depth, Some((_, hit))) => layer.values.push(setup.distance_along_view(&hit.p)),
+                (Aov::Albedo, Some((_, hit))) => {
+                    let albedo = world.material(hit.material).albedo(hit);
+                    layer.values.extend([albedo.x(), albedo.y(), albedo.z()]);
+                }
+                (Aov::Id, Some((object, _))) => layer.values.push((object + 1) as f32),
+                (Aov::Depth, None) => layer.values.push(f32::INFINITY),
+                (aov, None) => layer.values.extend(aov.channels().iter().map(|_| 0.)),
+                (Aov::Samples | Aov::Error, _) => unreachable!("the passes of the render are not traced"),
+            }
+        }
+    }
+    layers
+}
+
+/// The layers of the convergence of the render for the passes of the render among the passes.
+pub(crate) fn convergence(convergence: Convergence, aovs: &[Aov]) -> Vec<Layer> {
+    let Convergence { samples, error } = convergence;
+    let mut layers = Vec::new();
+    if aovs.contains(&Aov::Samples) {
+        layers.push(Layer { aov: Aov::Samples, values: samples });
+    }
+    if aovs.contains(&Aov::Error) {
+        layers.push(Layer { aov: Aov::Error, values: error });
+    }
+    layers
+}
+
+/// The 8-bit image that shows the pass: the normals in the colors of their directions,
+/// the depth from the white near to the black far, the albedo as the image,
+/// every id in a color of its own, the samples and the error from the black none
+/// to the white most of them (the error of the single samples is white).
+pub(crate) fn show(layer: &Layer, w: usize, h: usize, settings: &RenderSettings) -> Image {
+    let byte = |value: f32| (value.clamp(0., 1.) * 255.).round() as u8;
+    let pixels = match layer.aov {
+        Aov::Normal => layer.values.chunks_exact(3)
+            .map(|normal| [0, 1, 2].map(|axis| byte(0.5 + 0.5 * normal[axis])))
+            .collect(),
+        Aov::Depth => {
+            let finite = layer.values.iter().copied().filter(|depth| depth.is_finite());
+            let (near, far) = finite.fold((f32::MAX, f32::MIN), |(near, far), depth| (near.min(depth), far.max(depth)));
+            layer.values.iter()
+                .map(|&depth| match depth.is_finite() {
+                    true => [byte(1. - (depth - near) / (far - near).max(f32::MIN_POSITIVE)); 3],
+                    false => [0; 3],
+                })
+                .collect()
+        }
+        Aov::Albedo => {
+            let colors = layer.values.chunks_exact(3).map(|c| Vec3::new(c[0], c[1], c[2])).collect();
+            return output::encode(&FrameBuffer::from_pixels(w, h, colors), 0., settings);
+        }
+        Aov::Samples | Aov::Error => {
+            let most = layer.values.iter().copied().filter(|value| value.is_finite()).fold(0., f32::max);
+            layer.values.iter()
+                .map(|&value| match value.is_finite() {
+                    true => [byte(value / most.max(f32::MIN_POSITIVE)); 3],
+                    false => [255; 3],
+                })
+                .collect()
+        }
+        Aov::Id => layer.values.iter()
+            .map(|&id| match id {
+                0. => [0; 3],
+                id => {
+                    let hash = splitmix64(id as u64);
+                    [hash as u8, (hash >> 8) as u8, (hash >> 16) as u8]
+                }
+            })
+            .collect(),
+    };
+    Image { w, h, color_space: ColorSpace::Linear, pixels, biases: Vec::new(), notes: Vec::new() }
+}
@@ -0,0 +1,209 @@
+/**
+The light baking: `raytracer bake <name> [OPTIONS]` renders the light that falls
+on the surface of the object into its texture, the lightmap, instead of the image
+the camera sees. The games draw the lightmap over the texture of the object,
+so the soft shadows and the light bounced off the walls cost them nothing while they run.
+
+Every texel of the map is a point of the surface, found by its texture coordinates,
+and the rays leave it into the half of the sphere over the surface, traced by the same
+paths as the rays of the camera: the lights, the sky, the bounces off the other objects.
+The texel is the irradiance over π, the light the white matte surface would send
+in every direction there, so the map times the albedo is the lit surface. The map is
+the size of the image (e.g. `--width 512 --height 512`), written as the image is:
+the linear light in the OpenEXR image, tone mapped in the others.
+
+The objects of the name or the tag are baked into the one map, the parts of a model
+share their texture. The texels no surface covers are filled by their neighbours,
+so the filtering of the texture does not bleed the black into the edges of the islands.
+
+The ambient occlusion (`--ao 0.5`) is baked instead of the light: the share of the directions
+over the surface where nothing is closer than the distance, 1 in the open, darker
+in the creases and the corners. It depends on the shapes only, not on the lights,
+so it stays right wherever the model is put. With the output .ply the mesh is written
+with the baked value at every vertex as its color instead of the texture, for the models
+without the texture coordinates, e.g. the scans.
+
+LEARN:
+The directions of the rays are picked with the density cos/π, the density the light
+falls on the surface by (the beam at the grazing angle spreads over more of it),
+so the average of the light the rays bring is the irradiance over π itself,
+no ray is wasted on the directions that add little.
+*/
+use std::f32::consts::PI;
+
+use crate::math::rand;
+use crate::math::vec::{Ray, RayType, Vec3};
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::media::Media;
+use crate::renderer::settings::RenderSettings;
+use crate::renderer::{color, direct_light, Bounces};
+use crate::scene::obj::Model;
+use crate::scene::surfaces::hitable::Hitable;
+use crate::scene::surfaces::Surface;
+use crate::scene::Scene;
+
+/// How many texels around the islands of the map are filled by their neighbours.
+const PADDING: usize = 4;
+
+/// What is baked, see the module.
+#[derive(Clone, Copy)]
+pub(crate) enum Quantity {
+    /// The irradiance over π.
+    Light,
+    /// The ambient occlusion within the distance.
+    Occlusion(f32),
+}
+
+/// The lightmap of the objects of the name or the tag, at the resolution of the settings,
+/// and the number of the texels on their surfaces.
+pub(crate) fn bake(scene: &Scene, name: &str, quantity: Quantity, settings: &RenderSettings) -> Result<(FrameBuffer, usize), String> {
+    let (w, h, _) = settings.resolution(scene);
+    let (w, h) = (w as usize, h as usize);
+    // the first object that covers the texel bakes it
+    let mut texels = vec![None; w * h];
+    let mut mapped = false;
+    for surface in named(scene, name)? {
+        let Some(points) = surface.texels(w, h) else { continue };
+        mapped = true;
+        for (texel, point) in texels.iter_mut().zip(points) {
+            if texel.is_none() {
+                *texel = point;
+            }
+        }
+    }
+    if !mapped {
+        return Err(format!("{} has no texture coordinates, only the meshes and the spheres can be baked", name));
+    }
+    let covered = texels.iter().filter(|texel| texel.is_some()).count();
+
+    let mut frame = FrameBuffer::from_pixels(w, h, sample_all(scene, &texels, quantity, settings));
+    let mut filled: Vec<bool> = texels.iter().map(Option::is_some).collect();
+    for _ in 0..PADDING {
+        pad(&mut frame, &mut filled);
+    }
+    Ok((frame, covered))
+}
+
+/// The meshes of the name or the tag as one model, with the baked value at every vertex as its color.
+pub(crate) fn bake_vertices(scene: &Scene, name: &str, quantity: Quantity, settings: &RenderSettings) -> Result<Model, String> {
+    let mut model = Model { points: Vec::new(), colors: Vec::new(), faces: Vec::new() };
+    let mut normals = Vec::new();
+    for surface in named(scene, name)? {
+        let Surface::Mesh { mesh, .. } = surface else { continue };
+        let offset = model.points.len();
+        model.points.extend(mesh.vertices().map(|vertex| (vertex.p.clone(), vertex.uv)));
+        model.faces.extend(mesh.faces().map(|face| (face.map(|i| offset + i), None)));
+        normals.extend(mesh.vertex_normals());
+    }
+    if model.points.is_empty() {
+        return Err(format!("{} has no vertices, only the meshes can be baked into the colors of the vertices", name));
+    }
+    let points: Vec<_> = model.points.iter().zip(normals).map(|((p, _), normal)| Some((p.clone(), normal))).collect();
+    model.colors = sample_all(scene, &points, quantity, settings);
+    Ok(model)
+}
+
+/// The surfaces of the name or the tag.
+fn named<'a>(scene: &'a Scene, name: &str) -> Result<Vec<&'a Surface>, String> {
+    let world = scene.world();
+    match world.find(name) {
+        ids if ids.is_empty() => Err(format!("there is no object named or tagged {}", name)),
+        ids => Ok(ids.into_iter().map(|id| world.object(id)).collect()),
+    }
+}
+
+/// The baked value at every point and normal, the ones that are `None` stay black.
+fn sample_all(scene: &Scene, points: &[Option<(Vec3, Vec3)>], quantity: Quantity, settings: &RenderSettings) -> Vec<Vec3> {
+    let mut values = vec![Vec3::zero(); points.len()];
+    // the points are split evenly between the threads, the ones off the surface cost nothing
+    let chunk = points.len().div_ceil(settings.thread_count()).max(1);
+    std::thread::scope(|scope| {
+        for (part, values) in values.chunks_mut(chunk).enumerate() {
+            scope.spawn(move || {
+                for (i, value) in values.iter_mut().enumerate() {
+                    let index = part * chunk + i;
+                    if let Some((p, normal)) = &points[index] {
+                        // every point has its own random numbers, the result is the same on any threads
+                        rand::seed_stream(settings.seed, index as u64);
+                        *value = match quantity {
+                            Quantity::Light => irradiance(scene, p, normal, settings),
+                            Quantity::Occlusion(distance) => occlusion(scene, p, normal, distance, settings),
+                        };
+                    }
+                }
+            });
+        }
+    });
+    values
+}
+
+/// The irradiance over π at the point of the surface, the average of the light
+/// the samples of the settings bring from over it.
+fn irradiance(scene: &Scene, p: &Vec3, normal: &Vec3, settings: &RenderSettings) -> Vec3 {
+    let world = scene.world();
+    let after = Bounces::default().after(RayType::Diffuse, &settings.bounce_limits);
+    let mut sum = Vec3::zero();
+    for _ in 0..settings.samples {
+        let direct = direct_light(world, p, 0., |to_light| Vec3::dot(&to_light.unit(), normal).max(0.) / PI, true);
+        let direction = cosine_direction(normal);
+        let indirect = match after {
+            Some(bounces) if direction.squared_length() > 1e-6 => {
+                let ray = Ray::scattered(p.clone(), direction, RayType::Diffuse);
+                color(world, &ray, bounces, Media::default(), settings)
+            }
+            _ => Vec3::zero(),
+        };
+        let radiance = direct + indirect;
+        // the sample that is not finite would spoil the whole texel, it counts as the dark one
+        if radiance.x().is_finite() && radiance.y().is_finite() && radiance.z().is_finite() {
+            sum += radiance;
+        }
+    }
+    sum / settings.samples.max(1) as f32
+}
+
+/// The share of the samples of the settings that leave the point along the directions
+/// of the density cos/π and meet nothing within the distance, gray.
+fn occlusion(scene: &Scene, p: &Vec3, normal: &Vec3, distance: f32, settings: &RenderSettings) -> Vec3 {
+    let open = (0..settings.samples)
+        .filter(|_| {
+            let direction = cosine_direction(normal);
+            // the direction along the surface meets it at once
+            direction.squared_length() > 1e-6 && {
+                let ray = Ray::scattered(p.clone(), direction.unit(), RayType::Diffuse);
+                !scene.world().occluded(&ray, 0.001, distance)
+            }
+        })
+        .count();
+    let share = open as f32 / settings.samples.max(1) as f32;
+    Vec3::new(share, share, share)
+}
+
+/// The normal and a random point of the unit sphere around its tip, a direction of the density cos/π.
+fn cosine_direction(normal: &Vec3) -> Vec3 {
+    normal + Vec3::random_in_unit_sphere().unit()
+}
+
+/// Fills the texels next to the filled ones by the average of those.
+fn pad(frame: &mut FrameBuffer, filled: &mut [bool]) {
+    let (w, h) = (frame.width(), frame.height());
+    let before = filled.to_vec();
+    for y in 0..h {
+        for x in 0..w {
+            if before[y * w + x] {
+                continue;
+            }
+            let neighbours: Vec<usize> = [(-1, 0), (1, 0), (0, -1), (0, 1)].into_iter()
+                .filter_map(|(dx, dy)| Some((x.checked_add_signed(dx)?, y.checked_add_signed(dy)?)))
+                .filter(|&(x, y)| x < w && y < h && before[y * w + x])
+                .map(|(x, y)| y * w + x)
+                .collect();
+            if neighbours.is_empty() {
+                continue;
+            }
+            let sum = neighbours.iter().fold(Vec3::zero(), |sum, &i| sum + &frame.pixels()[i]);
+            frame.pixels_mut()[y * w + x] = sum / neighbours.len() as f32;
+            filled[y * w + x] = true;
+        }
+    }
+}
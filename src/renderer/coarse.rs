@@ -0,0 +1,106 @@
+/**
+The coarse-to-fine order of the progressive render (`--coarse-to-fine`): a quick image
+of a quarter of the size is rendered first, and the tiles where it is busy, the edges,
+the textures, the caustics and the noise of the hard light, take all their samples
+before the rest of the image takes any. The quiet rest (the sky, the walls) is shown
+by the coarse image until its turn, so the intermediate images show the interesting
+parts of the frame converged long before the whole frame is.
+
+The final image is the same as without the mode: every tile still takes the samples
+of every pass, with the random streams of its pass, only the order of the work changes.
+The coarse image has a sixteenth of the pixels and the few samples of the preview,
+it costs a small part of a pass.
+
+LEARN:
+The busy region is found by the local contrast of the coarse image: the spread of
+the luminance of the 3x3 pixels around every pixel relative to their mean, so a dark
+detailed corner counts as much as a bright one. The few samples of the coarse image
+make the noisy regions busy as well, and those are the ones that need the most samples.
+*/
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::output::luminance;
+use crate::renderer::tiles::Tile;
+
+/// The coarse image is this many times smaller than the image in both directions.
+pub(crate) const DOWNSCALE: usize = 4;
+
+/// The share of the tiles rendered first.
+const BUSY_SHARE: f32 = 0.25;
+
+/// The size of the coarse image of the image of the size.
+pub(crate) fn size(w: usize, h: usize) -> (usize, usize) {
+    (w.div_ceil(DOWNSCALE), h.div_ceil(DOWNSCALE))
+}
+
+/// Splits the tiles of the image of the size into the busy ones by the coarse image,
+/// the busiest first, and the rest in their order.
+pub(crate) fn busy_first(coarse: &FrameBuffer, tiles: Vec<Tile>, w: usize, h: usize) -> (Vec<Tile>, Vec<Tile>) {
+    let contrast = contrast(coarse);
+    let (cw, ch) = (coarse.width(), coarse.height());
+    // the average contrast of the coarse pixels the tile covers, at least one of them
+    let score = |tile: &Tile| {
+        let (x0, y0) = (tile.x * cw / w, tile.y * ch / h);
+        let (x1, y1) = (((tile.x + tile.w) * cw).div_ceil(w).max(x0 + 1), ((tile.y + tile.h) * ch).div_ceil(h).max(y0 + 1));
+        let sum: f32 = (y0..y1).flat_map(|y| (x0..x1).map(move |x| (x, y))).map(|(x, y)| contrast[y * cw + x]).sum();
+        sum / ((x1 - x0) * (y1 - y0)) as f32
+    };
+    let scores: Vec<f32> = tiles.iter().map(score).collect();
+    let mut ranked: Vec<usize> = (0..tiles.len()).collect();
+    ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    ranked.truncate((tiles.len() as f32 * BUSY_SHARE).ceil() as usize);
+    let mut is_busy = vec![false; tiles.len()];
+    for &index in &ranked {
+        is_busy[index] = true;
+    }
+    let busy = ranked.iter().map(|&index| tiles[index]).collect();
+    let rest = tiles.into_iter().zip(is_busy).filter(|(_, busy)| !busy).map(|(tile, _)| tile).collect();
+    (busy, rest)
+}
+
+/// The local contrast of every pixel, see the module.
+fn contrast(image: &FrameBuffer) -> Vec<f32> {
+    let (w, h) = (image.width(), image.height());
+    let lum: Vec<f32> = image.pixels().iter().map(|c| Some(luminance(c)).filter(|l| l.is_finite()).unwrap_or(0.)).collect();
+    (0..w * h)
+        .map(|i| {
+            let (x, y) = (i % w, i / w);
+            let around: Vec<f32> = (y.saturating_sub(1)..(y + 2).min(h))
+                .flat_map(|y| (x.saturating_sub(1)..(x + 2).min(w)).map(move |x| (x, y)))
+                .map(|(x, y)| lum[y * w + x])
+                .collect();
+            let mean = around.iter().sum::<f32>() / around.len() as f32;
+            let variance = around.iter().map(|l| (l - mean) * (l - mean)).sum::<f32>() / around.len() as f32;
+            // the small constant keeps the black from being the busiest
+            variance.sqrt() / (mean + 0.05)
+        })
+        .collect()
+}
+
+/// Fills the pixels of the frame outside the rendered tiles with the coarse image,
+/// interpolated between its pixels.
+pub(crate) fn fill(frame: &mut FrameBuffer, coarse: &FrameBuffer, rendered: &[Tile]) {
+    let (w, h) = (frame.width(), frame.height());
+    let mut done = vec![false; w * h];
+    for tile in rendered {
+        for y in tile.y..tile.y + tile.h {
+            done[y * w + tile.x..y * w + tile.x + tile.w].fill(true);
+        }
+    }
+    let (cw, ch) = (coarse.width(), coarse.height());
+    let source = coarse.pixels();
+    let at = |x: usize, y: usize| &source[y.min(ch - 1) * cw + x.min(cw - 1)];
+    for (i, pixel) in frame.pixels_mut().iter_mut().enumerate() {
+        if done[i] {
+            continue;
+        }
+        // the centers of the pixels line up, the edges clamp
+        let u = (((i % w) as f32 + 0.5) * cw as f32 / w as f32 - 0.5).max(0.);
+        let v = (((i / w) as f32 + 0.5) * ch as f32 / h as f32 - 0.5).max(0.);
+        let (x, y) = (u as usize, v as usize);
+        let (fx, fy) = (u - x as f32, v - y as f32);
+        let top = &(at(x, y) * (1. - fx)) + &(at(x + 1, y) * fx);
+        let bottom = &(at(x, y + 1) * (1. - fx)) + &(at(x + 1, y + 1) * fx);
+        *pixel = &(&top * (1. - fy)) + &(&bottom * fy);
+    }
+}
+
@@ -0,0 +1,57 @@
+use crate::math::vec::Vec3;
+use crate::renderer::framebuffer::FrameBuffer;
+
+/// The parameters of the bilateral filter applied to the rendered frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Denoiser {
+    /// How far the neighbours are averaged, in pixels.
+    pub(crate) radius: usize,
+    /// How different the colors may be to still be averaged, larger is smoother.
+    pub(crate) strength: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser { radius: 2, strength: 0.1 }
+    }
+}
+
+impl Denoiser {
+    pub(crate) fn apply(&self, frame: &mut FrameBuffer) {
+        bilateral(frame, self.radius, self.strength);
+    }
+}
+
+/// A bilateral filter: every pixel is averaged with its neighbours, but the neighbours
+/// of a very different color get little weight, so the noise is smoothed while the edges
+/// of the objects stay sharp.
+///
+/// It is far from the production denoisers that use the normals and the albedo of
+/// the surfaces, but it makes a low sample render presentable at a small size.
+pub(crate) fn bilateral(frame: &mut FrameBuffer, radius: usize, sigma_color: f32) {
+    let (w, h) = (frame.width(), frame.height());
+    let src = frame.pixels().to_vec();
+    let sigma_space = radius.max(1) as f32 / 2.;
+
+    for (i, pixel) in frame.pixels_mut().iter_mut().enumerate() {
+        let (x, y) = (i % w, i / w);
+        let center = &src[i];
+        let mut sum = Vec3::zero();
+        let mut total = 0.;
+
+        for ny in y.saturating_sub(radius)..=(y + radius).min(h - 1) {
+            for nx in x.saturating_sub(radius)..=(x + radius).min(w - 1) {
+                let neighbour = &src[ny * w + nx];
+                let dx = nx as f32 - x as f32;
+                let dy = ny as f32 - y as f32;
+                let spatial = (dx * dx + dy * dy) / (2. * sigma_space * sigma_space);
+                let range = (neighbour - center).squared_length() / (2. * sigma_color * sigma_color);
+                let weight = (-spatial - range).exp();
+                sum += weight * neighbour;
+                total += weight;
+            }
+        }
+
+        *pixel = sum / total;
+    }
+}
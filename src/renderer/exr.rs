@@ -0,0 +1,91 @@
+/**
+This module writes the OpenEXR images, the format of the compositors: the channels
+of the 32-bit floats hold the light as it was rendered, beyond the white and without
+the transfer function, and any number of them fit in one file, so the passes of `aov`
+are the layers of the image, e.g. `normal.X` or `depth.Z`, next to its `R`, `G` and `B`.
+
+LEARN:
+The file is the magic number and the version, the header of the named attributes,
+the table of the offsets of the blocks of the rows, and the blocks. Without
+the compression every block is a single row, and the row is channel after channel,
+each one all its pixels. The channels are always in the alphabetical order of their names.
+*/
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+
+/// The pixel type of the 32-bit float.
+const FLOAT: i32 = 2;
+
+/// Whether the path is of an OpenEXR image.
+pub(crate) fn is_exr(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| ext.eq_ignore_ascii_case("exr"))
+}
+
+/// Writes the channels of the image of the size as an uncompressed scanline OpenEXR image
+/// and returns the size of the file. Every channel is its name and its value for every pixel,
+/// row by row from the top.
+pub(crate) fn write(path: &Path, w: usize, h: usize, pixel_aspect: f32, mut channels: Vec<(String, Vec<f32>)>) -> Result<u64, Error> {
+    let too_large = || Error::other(format!("{}x{} is too large for an OpenEXR image", w, h));
+    let (right, bottom) = (i32::try_from(w).map_err(|_| too_large())? - 1, i32::try_from(h).map_err(|_| too_large())? - 1);
+
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2u32.to_le_bytes());
+    let mut list = Vec::new();
+    for (name, _) in &channels {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&FLOAT.to_le_bytes());
+        // not perceptually linear, the reserved bytes, no subsampling
+        list.extend_from_slice(&[0; 4]);
+        list.extend_from_slice(&1i32.to_le_bytes());
+        list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(&mut header, "channels", "chlist", &list);
+    attribute(&mut header, "compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, right, bottom].iter().flat_map(|v| v.to_le_bytes()).collect();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &pixel_aspect.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    let row_size = w * channels.len() * 4;
+    let row_bytes = u32::try_from(row_size).map_err(|_| too_large())?;
+    // every block is the number of the row, the size of the data and the data
+    let first = (header.len() + h * 8) as u64;
+    let block = (8 + row_size) as u64;
+
+    let mut exr = BufWriter::new(File::create(path)?);
+    exr.write_all(&header)?;
+    for y in 0..h as u64 {
+        exr.write_all(&(first + y * block).to_le_bytes())?;
+    }
+    for y in 0..h {
+        exr.write_all(&(y as i32).to_le_bytes())?;
+        exr.write_all(&row_bytes.to_le_bytes())?;
+        for (_, values) in &channels {
+            for value in &values[y * w..(y + 1) * w] {
+                exr.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    exr.flush()?;
+    Ok(first + h as u64 * block)
+}
+
+/// Appends the attribute of the header: its name, its type, the size of the value and the value.
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
@@ -0,0 +1,149 @@
+use crate::math::vec::Vec3;
+
+/// The linear (not gamma corrected) result of the rendering.
+///
+/// Keeping the linear radiance around instead of writing the pixels to the file
+/// right away allows to produce several outputs from the same render,
+/// e.g. the same image at different exposures.
+///
+/// Pixels are stored row by row from the top-left corner of the image.
+pub(crate) struct FrameBuffer {
+    w: usize,
+    h: usize,
+    pixels: Vec<Vec3>,
+}
+
+impl FrameBuffer {
+    pub(crate) fn from_pixels(w: usize, h: usize, pixels: Vec<Vec3>) -> FrameBuffer {
+        assert_eq!(w * h, pixels.len(), "Pixel count does not match the frame size");
+        FrameBuffer { w, h, pixels }
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.w
+    }
+
+    pub(crate) fn height(&self) -> usize {
+        self.h
+    }
+
+    pub(crate) fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    pub(crate) fn pixels_mut(&mut self) -> &mut [Vec3] {
+        &mut self.pixels
+    }
+}
+
+impl FrameBuffer {
+    /// A hash of the size and the exact values of the pixels.
+    ///
+    /// The render is deterministic for the same seed, so the hash changes only when the image
+    /// does, even by a bit of a single pixel. It is FNV-1a over the little-endian bytes,
+    /// the same on every platform. The floating point math of different CPUs or compilers
+    /// (e.g. the fused multiply-add) may still round differently, so the golden hashes
+    /// are best compared on the same kind of machine.
+    pub(crate) fn content_hash(&self) -> u64 {
+        // LEARN:
+        // `std::hash::DefaultHasher` is not guaranteed to give the same values
+        // in the other versions of Rust, so a hash written down for later needs its own algorithm.
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+        let sizes = [self.w as u64, self.h as u64].map(u64::to_le_bytes);
+        let channels = self.pixels.iter()
+            .flat_map(|c| [c.x(), c.y(), c.z()])
+            .map(|channel| channel.to_bits().to_le_bytes());
+        sizes.into_iter().flatten()
+            .chain(channels.flatten())
+            .fold(OFFSET, |hash, byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+    }
+
+    /// Reduces the frame `factor` times in both directions.
+    ///
+    /// The box filter (plain averaging) would blur the image, so every output pixel
+    /// is the weighted sum of the input pixels under the Mitchell-Netravali filter,
+    /// which keeps the edges crisp without noticeable ringing.
+    pub(crate) fn downsample(&self, factor: usize) -> FrameBuffer {
+        if factor <= 1 {
+            return FrameBuffer::from_pixels(self.w, self.h, self.pixels.clone());
+        }
+        let (w, h) = (self.w / factor, self.h / factor);
+        // LEARN:
+        // The filter is separable, so instead of the 2D convolution we filter the rows
+        // and then the columns of the result, which is much cheaper for wide filters.
+        let rows = resample(&self.pixels, self.w, self.h, w, factor, true);
+        let pixels = resample(&rows, w, self.h, h, factor, false)
+            .into_iter()
+            // the negative lobes of the filter can produce slightly negative values
+            .map(|c| Vec3::new(c.x().max(0.), c.y().max(0.), c.z().max(0.)))
+            .collect();
+        FrameBuffer::from_pixels(w, h, pixels)
+    }
+}
+
+/// Mitchell-Netravali filter with B = C = 1/3, non-zero on (-2, 2).
+fn mitchell(x: f32) -> f32 {
+    let x = x.abs();
+    let (b, c) = (1. / 3., 1. / 3.);
+    if x < 1. {
+        ((12. - 9. * b - 6. * c) * x * x * x + (-18. + 12. * b + 6. * c) * x * x + (6. - 2. * b)) / 6.
+    } else if x < 2. {
+        ((-b - 6. * c) * x * x * x + (6. * b + 30. * c) * x * x + (-12. * b - 48. * c) * x + (8. * b + 24. * c)) / 6.
+    } else {
+        0.
+    }
+}
+
+/// Resamples the `src` image of `w`x`h` pixels along one axis to `len` pixels.
+fn resample(src: &[Vec3], w: usize, h: usize, len: usize, factor: usize, horizontal: bool) -> Vec<Vec3> {
+    let (src_len, lines) = if horizontal { (w, h) } else { (h, w) };
+    let (out_w, out_h) = if horizontal { (len, h) } else { (w, len) };
+    let scale = factor as f32;
+    let radius = (2. * scale).ceil() as isize;
+    let mut dst = vec![Vec3::zero(); out_w * out_h];
+
+    for line in 0..lines {
+        for i in 0..len {
+            // the center of the output pixel in the input pixel coordinates
+            let center = (i as f32 + 0.5) * scale - 0.5;
+            let mut sum = Vec3::zero();
+            let mut total = 0.;
+            for j in (center as isize - radius)..=(center as isize + radius) {
+                let weight = mitchell((j as f32 - center) / scale);
+                if weight == 0. {
+                    continue;
+                }
+                let j = j.clamp(0, src_len as isize - 1) as usize;
+                let pixel = if horizontal { &src[line * w + j] } else { &src[j * w + line] };
+                sum += weight * pixel;
+                total += weight;
+            }
+            let out = if horizontal { line * out_w + i } else { i * out_w + line };
+            dst[out] = sum / total;
+        }
+    }
+    dst
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameBuffer;
+    use crate::math::vec::Vec3;
+
+    #[test]
+    fn content_hash_is_stable() {
+        // the golden hashes written down by the users depend on this value
+        let frame = FrameBuffer::from_pixels(2, 1, vec![Vec3::new(1., 0.5, 0.), Vec3::new(-2., 0.25, 3.)]);
+        assert_eq!(frame.content_hash(), 0x37d5_f2de_08d4_4c84);
+    }
+
+    #[test]
+    fn content_hash_sees_the_size_and_every_bit() {
+        let pixels = vec![Vec3::new(1., 0.5, 0.), Vec3::new(-2., 0.25, 3.)];
+        let frame = FrameBuffer::from_pixels(2, 1, pixels.clone());
+        assert_ne!(frame.content_hash(), FrameBuffer::from_pixels(1, 2, pixels).content_hash());
+        let nudged = vec![Vec3::new(1., 0.5, 0.), Vec3::new(-2., 0.25, f32::from_bits(3f32.to_bits() + 1))];
+        assert_ne!(frame.content_hash(), FrameBuffer::from_pixels(2, 1, nudged).content_hash());
+    }
+}
@@ -0,0 +1,93 @@
+/**
+The furnace test of the materials: `raytracer furnace [<material>...] [OPTIONS]`.
+
+A sphere of the material is put in the furnace, the uniform white light from every
+direction, and the camera sees nothing but the sphere. The light a surface sends
+to the camera there is the light it got, everywhere the same, times the share of it
+the material keeps, so the average of the image is the albedo of the material.
+A white material that keeps all the light disappears in the furnace (the white furnace):
+any darker spot is the light the material loses, any brighter one the light it makes
+from nothing, both are the bugs of its `scatter` the eye would hardly tell in a scene.
+
+LEARN:
+The sphere is convex, a ray scattered from it outside never hits it again,
+so the lambertian and the metal spheres return the light after one bounce,
+exactly their albedo. The light that enters the glass or the particles bounces
+inside any number of times, only a material that keeps all of it has the exact answer, 1.
+*/
+use crate::math::color::Color;
+use crate::math::vec::Vec3;
+use crate::renderer::settings::RenderSettings;
+use crate::renderer;
+use crate::scene::builder::{BuiltIn, SceneBuilder};
+use crate::scene::material::Material;
+
+/// The materials tested when none are given, written as in the scene file.
+pub(crate) const MATERIALS: &[&str] = &[
+    r#"{ type = "lambertian", albedo = [1, 1, 1] }"#,
+    r#"{ type = "lambertian", albedo = [0.8, 0.5, 0.2] }"#,
+    r#"{ type = "metal", albedo = [1, 1, 1], fuzz = 0 }"#,
+    r#"{ type = "metal", albedo = [0.9, 0.6, 0.3], fuzz = 0.5 }"#,
+    r#"{ type = "dielectric", ior = 1.5 }"#,
+    r#"{ type = "hair", color = [1, 1, 1], roughness = 0.3 }"#,
+    r#"{ type = "isotropic", albedo = [1, 1, 1] }"#,
+];
+
+/// The relative difference from the expected albedo the noise may explain.
+pub(crate) const TOLERANCE: f32 = 0.02;
+
+pub(crate) struct Measurement {
+    /// The albedo the material should have in the furnace, `None` when it has no simple answer.
+    pub(crate) expected: Option<Vec3>,
+    /// The average of the image of the sphere.
+    pub(crate) measured: Vec3,
+    /// The brightest channel of the light the material sends itself.
+    emitted: f32,
+}
+
+impl Measurement {
+    /// The largest relative difference of the channels, negative when the material loses the light.
+    pub(crate) fn error(&self) -> Option<f32> {
+        let expected = self.expected.as_ref()?;
+        let channels = [(self.measured.r(), expected.r()), (self.measured.g(), expected.g()), (self.measured.b(), expected.b())];
+        channels.iter()
+            .map(|&(measured, expected)| (measured - expected) / expected.max(1e-3))
+            .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+    }
+
+    /// How much more light than the furnace gives the sphere returns, whatever the material
+    /// promises: a surface can only lose the light, unless it is a lamp.
+    pub(crate) fn excess(&self) -> f32 {
+        self.measured.r().max(self.measured.g()).max(self.measured.b()) / self.emitted.max(1.) - 1.
+    }
+}
+
+/// Renders the sphere of the material in the furnace with the samples and the size of the settings.
+/// The effects that change the image after the rendering and the biased shortcuts are turned off.
+pub(crate) fn measure(material: Material, settings: &RenderSettings) -> Measurement {
+    let mut settings = settings.clone();
+    settings.remove_bias();
+    settings.lens = None;
+    settings.post_effects.clear();
+    let expected = expected(&material);
+    let emitted = material.emitted();
+    let emitted = emitted.r().max(emitted.g()).max(emitted.b());
+    let frame = renderer::render_to_buffer(&BuiltIn::furnace(material).build(), &settings);
+    let sum = frame.pixels().iter().fold(Vec3::zero(), |sum, pixel| sum + pixel);
+    Measurement { expected, measured: sum / frame.pixels().len() as f32, emitted }
+}
+
+/// The albedo of the sphere of the material in the furnace.
+fn expected(material: &Material) -> Option<Vec3> {
+    let white = |color: &Vec3| color.r() == 1. && color.g() == 1. && color.b() == 1.;
+    match material {
+        Material::Lambertian { albedo, texture: None } | Material::Metal { albedo, conductor: None, .. } => Some(albedo.clone()),
+        // the light that bounces inside is all kept only by the white materials
+        Material::Dielectric { attenuation, absorption, .. }
+            if white(attenuation) && absorption.squared_length() == 0. => Some(Vec3::rgb(1., 1., 1.)),
+        Material::Hair { color, .. } | Material::Isotropic { albedo: color } if white(color) => Some(Vec3::rgb(1., 1., 1.)),
+        // the lamp sends its own light and reflects none
+        Material::Emissive { radiance } => Some(radiance.clone()),
+        _ => None,
+    }
+}
@@ -0,0 +1,176 @@
+/**
+This module writes the animations as animated GIF images.
+
+A GIF is the simplest way to share a short turntable: every browser and chat shows it,
+and no external tools are needed to make it. The format has at most 256 colors,
+so every frame is reduced to the fixed palette of the 6x6x6 color cube with
+the ordered dithering. The fixed pattern does not crawl from frame to frame
+like the error diffusion would, and the same palette serves all the frames,
+so they are written as soon as they are rendered.
+
+The pixels are compressed with LZW, the variable-length code GIF is built around.
+*/
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+
+use crate::renderer::output::{Dither, Image};
+use crate::renderer::video::VideoWriter;
+
+/// The levels of each channel in the palette, 6 * 6 * 6 = 216 colors.
+const LEVELS: usize = 6;
+
+pub(crate) struct Gif {
+    file: BufWriter<File>,
+    w: usize,
+    h: usize,
+    /// The time a frame is shown, in the hundredths of a second.
+    delay: u16,
+}
+
+impl Gif {
+    pub(crate) fn create(path: &Path, w: usize, h: usize, fps: usize) -> Result<Gif, Error> {
+        let size = |n: usize| u16::try_from(n).map_err(|_| Error::other(format!("{}x{} is too large for a GIF", w, h)));
+        let mut file = BufWriter::new(File::create(path)?);
+
+        file.write_all(b"GIF89a")?;
+        file.write_all(&size(w)?.to_le_bytes())?;
+        file.write_all(&size(h)?.to_le_bytes())?;
+        // the global palette of 256 colors of 8 bits each
+        file.write_all(&[0xf7, 0, 0])?;
+        let mut palette = [0u8; 256 * 3];
+        for (i, color) in palette.chunks_exact_mut(3).take(LEVELS * LEVELS * LEVELS).enumerate() {
+            let level = |n: usize| (n % LEVELS * 255 / (LEVELS - 1)) as u8;
+            color.copy_from_slice(&[level(i / (LEVELS * LEVELS)), level(i / LEVELS), level(i)]);
+        }
+        file.write_all(&palette)?;
+        // the NETSCAPE2.0 extension loops the animation forever
+        file.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+        // the browsers show the shorter delays as 0.1 s
+        let delay = (100 / fps.max(1)).max(2) as u16;
+        Ok(Gif { file, w, h, delay })
+    }
+}
+
+impl VideoWriter for Gif {
+    fn write(&mut self, frame: &Image) -> Result<(), Error> {
+        let indices: Vec<u8> = frame.pixels.iter()
+            .enumerate()
+            .map(|(i, rgb)| {
+                let (x, y) = (i % frame.w, i / frame.w);
+                let level = |c: usize| {
+                    let step = rgb[c] as f32 / 255. * (LEVELS - 1) as f32;
+                    (step + Dither::Ordered.offset(x, y, c)).round().clamp(0., (LEVELS - 1) as f32) as usize
+                };
+                (level(0) * LEVELS * LEVELS + level(1) * LEVELS + level(2)) as u8
+            })
+            .collect();
+
+        // the graphic control extension with the delay
+        let [lo, hi] = self.delay.to_le_bytes();
+        self.file.write_all(&[0x21, 0xf9, 4, 0, lo, hi, 0, 0])?;
+        // the image descriptor of the whole screen without a local palette
+        self.file.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.file.write_all(&(self.w as u16).to_le_bytes())?;
+        self.file.write_all(&(self.h as u16).to_le_bytes())?;
+        self.file.write_all(&[0, MIN_CODE_SIZE])?;
+        // the data goes in the blocks of at most 255 bytes, each prefixed with its length
+        for block in lzw(&indices).chunks(255) {
+            self.file.write_all(&[block.len() as u8])?;
+            self.file.write_all(block)?;
+        }
+        self.file.write_all(&[0])
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        self.file.write_all(&[0x3b])?;
+        self.file.flush()
+    }
+}
+
+/// The bits of the palette indices, the codes start one bit wider.
+const MIN_CODE_SIZE: u8 = 8;
+
+/// Compresses the palette indices with the variable-length LZW of GIF.
+///
+/// The dictionary starts with the single indices, and every code written adds
+/// the sequence it stands for plus the next index. The codes grow from 9 to 12 bits
+/// as the dictionary grows, and the dictionary starts over when it is full.
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    const CLEAR: u16 = 1 << MIN_CODE_SIZE;
+    const END: u16 = CLEAR + 1;
+    const MAX_CODES: u16 = 4096;
+
+    let mut bits = BitWriter::default();
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = END + 1;
+    let mut size = MIN_CODE_SIZE as u32 + 1;
+
+    bits.write(CLEAR, size);
+    let mut prefix: Option<u16> = None;
+    for &index in indices {
+        let Some(code) = prefix else {
+            prefix = Some(index as u16);
+            continue;
+        };
+        if let Some(&longer) = dictionary.get(&(code, index)) {
+            prefix = Some(longer);
+            continue;
+        }
+        bits.write(code, size);
+        if next == MAX_CODES {
+            bits.write(CLEAR, size);
+            dictionary.clear();
+            next = END + 1;
+            size = MIN_CODE_SIZE as u32 + 1;
+        } else {
+            dictionary.insert((code, index), next);
+            next += 1;
+            // LEARN:
+            // The decoder adds its entries one code later than the encoder,
+            // so the code gets wider only when the decoder is about to need it.
+            if next > 1 << size && size < 12 {
+                size += 1;
+            }
+        }
+        prefix = Some(index as u16);
+    }
+    if let Some(code) = prefix {
+        bits.write(code, size);
+        // the decoder adds an entry after the last code too, and may need a wider end code
+        if next + 1 > 1 << size && size < 12 {
+            size += 1;
+        }
+    }
+    bits.write(END, size);
+    bits.finish()
+}
+
+/// Packs the codes into bytes from the least significant bit, as GIF expects.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u32) {
+        self.buffer |= (code as u32) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
@@ -0,0 +1,84 @@
+/**
+The guard of the radiance against the samples that are not numbers.
+
+A single NaN (0 / 0, the square root of a negative, the infinity minus the infinity)
+in a sample makes the sum of the pixel NaN, and so its average, whatever the other
+samples were, and the encoding writes it as a black or a white pixel without a word.
+An infinite sample does the same to the whole pixel. The guard checks every sample
+before it is added, replaces the bad ones and counts them for the statistics of the render.
+
+The bad sample becomes black by default, the pixel is as if the path was absorbed.
+`--non-finite magenta` paints its pixel magenta instead, so the places the scene breaks
+the math are easy to find, and `--non-finite panic` stops at the first bad sample
+and prints every bounce of the path that produced it.
+
+LEARN:
+The path is recorded only in the panic mode, as text of every bounce, into a list kept
+by the thread (`thread_local!`): every sample is traced on a single thread, so the threads
+never share it and never lock it. The other modes do not format anything, the recording
+is a closure that is never called, and a check of the mode per bounce costs nothing.
+*/
+use std::cell::RefCell;
+
+use crate::math::color::Color;
+use crate::math::vec::Vec3;
+
+/// What becomes of the sample whose radiance is NaN or infinite.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum NonFinite {
+    /// The sample is black.
+    #[default]
+    Black,
+    /// The whole pixel is magenta.
+    Magenta,
+    /// The render panics with the path of the sample, for debugging.
+    Panic,
+}
+
+impl NonFinite {
+    pub(crate) fn parse(name: &str) -> Option<NonFinite> {
+        match name.to_ascii_lowercase().as_str() {
+            "black" => Some(NonFinite::Black),
+            "magenta" => Some(NonFinite::Magenta),
+            "panic" => Some(NonFinite::Panic),
+            _ => None,
+        }
+    }
+
+    /// The color of the pixel that received a bad sample, in the magenta mode.
+    pub(crate) fn highlight() -> Vec3 {
+        Vec3::rgb(1., 0., 1.)
+    }
+
+    /// Starts the path of a new sample of the pixel.
+    pub(crate) fn start_path(&self) {
+        if *self == NonFinite::Panic {
+            PATH.with(|path| path.borrow_mut().clear());
+        }
+    }
+
+    /// Records a bounce of the path, `describe` is called only in the panic mode.
+    pub(crate) fn trace(&self, describe: impl FnOnce() -> String) {
+        if *self == NonFinite::Panic {
+            PATH.with(|path| path.borrow_mut().push(describe()));
+        }
+    }
+
+    /// The radiance of the sample when it is finite, `None` when the sample is bad.
+    /// Panics with the recorded path in the panic mode.
+    pub(crate) fn check(&self, radiance: Vec3, (x, y): (usize, usize), sample: usize) -> Option<Vec3> {
+        if radiance.x().is_finite() && radiance.y().is_finite() && radiance.z().is_finite() {
+            return Some(radiance);
+        }
+        if *self == NonFinite::Panic {
+            let path = PATH.with(|path| path.borrow().join("\n  "));
+            panic!("the sample {} of the pixel ({}, {}) is {:?}, its path:\n  {}", sample, x, y, radiance, path);
+        }
+        None
+    }
+}
+
+thread_local! {
+    /// The bounces of the sample being traced on the thread, in the panic mode.
+    static PATH: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
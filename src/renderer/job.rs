@@ -0,0 +1,186 @@
+/**
+The manifest of a tiled render or of an animation, so an interrupted render continues
+where it stopped.
+
+The tiled render keeps `job.toml` next to its tiles (`result.ppm.tiles/job.toml`),
+the animation of the numbered images in a directory next to them (`result.ppm.frames/job.toml`).
+It records the command line the render was started with, and every tile is appended
+to it once its file is complete:
+
+```toml
+directory = "/home/me/project"
+args = ["--scene-file", "poster.toml", "--tiled", "256"]
+width = 16384
+height = 16384
+tile_size = 256
+frames = 0
+
+[[done]]
+x = 0
+y = 0
+```
+
+and every frame of the animation once its image is written, counted from 0:
+
+```toml
+[[done]]
+frame = 0
+```
+
+`raytracer resume result.ppm` runs the same command in the same directory again,
+and running the same command by hand does the same. The tiles of the manifest
+are not rendered again. Every tile seeds the random generator by its index,
+so the resumed image is the same as the one rendered in one go. Every frame poses
+the scene anew by its time, so the frames rendered after the resume are the same too.
+A different command or image size starts the render over.
+
+The animation written into a video cannot be resumed, the video is a single stream.
+*/
+use std::fs::{self, OpenOptions};
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::renderer::tiles::Tile;
+use crate::toml::{self, integer, optional, string, tables, Table};
+
+const FILE_NAME: &str = "job.toml";
+
+/// What the tiled render or the animation was started with.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Job {
+    /// The working directory, the relative paths of the arguments are relative to it.
+    pub(crate) directory: PathBuf,
+    pub(crate) args: Vec<String>,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    /// 0 for the animation.
+    pub(crate) tile_size: usize,
+    /// 0 for the single image.
+    pub(crate) frames: usize,
+}
+
+impl Job {
+    /// Reads the job from the manifest in the directory of the tiles.
+    pub(crate) fn load(dir: &Path) -> Result<Job, String> {
+        let path = dir.join(FILE_NAME);
+        let text = fs::read_to_string(&path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+        // the header alone, a torn `[[done]]` entry at the end does not lose the job
+        let (header, _) = split(&text);
+        let doc = toml::parse(header).map_err(|why| format!("{}: syntax error at {}", path.display(), why))?;
+        let args = doc.get("args")
+            .and_then(|args| args.as_array())
+            .and_then(|args| args.iter().map(|arg| arg.as_str().map(str::to_string)).collect())
+            .ok_or(format!("{}: `args` must be an array of strings", path.display()))?;
+        let size = |key: &str| integer(&doc, key).map(|n| n.max(0) as usize);
+        Ok(Job {
+            directory: PathBuf::from(string(&doc, "directory")?),
+            args,
+            width: size("width")?,
+            height: size("height")?,
+            tile_size: size("tile_size")?,
+            // the manifests of the tiled renders written before the animations had them
+            frames: optional(&doc, "frames", integer)?.map_or(0, |n| n.max(0) as usize),
+        })
+    }
+
+    /// Continues the job recorded in the directory, or starts it over
+    /// when the manifest is missing or was written for another job.
+    /// Returns the number of the tiles or the frames that are already done.
+    ///
+    /// The manifest that cannot be read is an error, the directory is deleted
+    /// only for another job, never for a damaged manifest of what may be this one.
+    pub(crate) fn start(&self, dir: &Path) -> Result<usize, Error> {
+        match Job::load(dir) {
+            Ok(recorded) if recorded == *self => {
+                // the torn entry is cut off, or the next one appended to it would complete it
+                let path = dir.join(FILE_NAME);
+                let text = fs::read_to_string(&path)?;
+                let (header, entries) = split(&text);
+                OpenOptions::new().write(true).open(&path)?.set_len((header.len() + entries.len()) as u64)?;
+                return Ok(done_entries(dir, |_| Some(())).len());
+            }
+            Ok(_) => fs::remove_dir_all(dir)?,
+            Err(_) if !dir.join(FILE_NAME).exists() => {}
+            Err(why) => return Err(Error::other(format!("{}, delete {} to start the render over", why, dir.display()))),
+        }
+        fs::create_dir_all(dir)?;
+        let args: Vec<String> = self.args.iter().map(|arg| toml::quote(arg)).collect();
+        fs::write(dir.join(FILE_NAME), format!(
+            "# The tiled render or the animation, `raytracer resume` continues it.\n\
+             directory = {}\nargs = [{}]\nwidth = {}\nheight = {}\ntile_size = {}\nframes = {}\n",
+            toml::quote(&self.directory.to_string_lossy()),
+            args.join(", "),
+            self.width,
+            self.height,
+            self.tile_size,
+            self.frames))?;
+        Ok(0)
+    }
+}
+
+/// The directory of the manifest of the animation written as the numbered images.
+pub(crate) fn frames_dir(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".frames");
+    path.with_file_name(name)
+}
+
+/// The positions of the finished tiles, none without a valid manifest.
+pub(crate) fn done(dir: &Path) -> Vec<(usize, usize)> {
+    done_entries(dir, |tile| Some((integer(tile, "x").ok()? as usize, integer(tile, "y").ok()? as usize)))
+}
+
+/// The indices of the finished frames, none without a valid manifest.
+pub(crate) fn done_frames(dir: &Path) -> Vec<usize> {
+    done_entries(dir, |frame| Some(integer(frame, "frame").ok()? as usize))
+}
+
+/// The `[[done]]` entries of the manifest that `read` makes sense of.
+fn done_entries<T>(dir: &Path, read: impl Fn(&Table) -> Option<T>) -> Vec<T> {
+    let text = fs::read_to_string(dir.join(FILE_NAME)).unwrap_or_default();
+    let doc = match toml::parse(split(&text).1) {
+        Ok(doc) => doc,
+        Err(_) => return Vec::new(),
+    };
+    tables(&doc, "done").unwrap_or_default()
+        .into_iter()
+        .filter_map(read)
+        .collect()
+}
+
+/// The header of the job and the `[[done]]` entries of the manifest, without the last entry
+/// when it is torn.
+fn split(text: &str) -> (&str, &str) {
+    let (header, entries) = text.split_at(text.find("\n[[done]]").unwrap_or(text.len()));
+    // every entry ends with the newline, the one that does not was cut by a crash
+    match entries.ends_with('\n') || entries.is_empty() {
+        true => (header, entries),
+        false => (header, &entries[..entries.rfind("\n[[done]]").unwrap_or(0)]),
+    }
+}
+
+/// Records the tile as done, its file must be complete by now.
+/// Nothing is recorded for the renders without a job, e.g. in the watch mode.
+pub(crate) fn mark_done(dir: &Path, tile: &Tile) -> Result<(), Error> {
+    append(dir, &format!("\n[[done]]\nx = {}\ny = {}\n", tile.x, tile.y))
+}
+
+/// Records the frame as done, its image must be written by now, see `mark_done`.
+pub(crate) fn mark_frame_done(dir: &Path, frame: usize) -> Result<(), Error> {
+    append(dir, &format!("\n[[done]]\nframe = {}\n", frame))
+}
+
+/// LEARN:
+/// The file is opened in the append mode and every entry is a single write at the end,
+/// so a crash does not touch the header and the entries written before. It can still
+/// stop in the middle of the write and leave the last entry torn, e.g. `y = 1` of `y = 12`,
+/// which is why `split` leaves out the entry without its closing newline and `Job::start`
+/// cuts it off before the render appends the next one.
+fn append(dir: &Path, entry: &str) -> Result<(), Error> {
+    let mut manifest = match OpenOptions::new().append(true).open(dir.join(FILE_NAME)) {
+        Ok(manifest) => manifest,
+        Err(why) if why.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(why) => return Err(why),
+    };
+    manifest.write_all(entry.as_bytes())
+}
@@ -0,0 +1,148 @@
+/**
+The layout of the scene: every object as a flat silhouette with its bounding box drawn
+over it, `raytracer layout --scene-file huge.toml --output layout.png`.
+
+The path tracing of a huge scene takes minutes before the first noisy image tells whether
+the objects are where they should be. The layout takes one ray per pixel, with no bounces,
+no lights and no materials, and paints every object in a color of its own, darker where
+it turns away from the camera, so the shapes read. The bounding boxes are projected onto
+the image and drawn as lines in the colors of their objects, they also show the objects
+hidden behind the others, and the size of the ones too small to be seen.
+
+LEARN:
+A line of a box may start behind the camera, where the projection turns it upside down.
+Every edge is first cut at a plane just in front of the lens (the near plane
+of the rasterizers), then the part that is left is cut to the edges of the image,
+so a box around the camera draws only what is in front of it, and the far end
+of a huge box does not walk through millions of pixels outside of the image.
+*/
+use crate::math::color::ColorSpace;
+use crate::math::vec::Vec3;
+use crate::renderer::output::Image;
+use crate::renderer::settings::RenderSettings;
+use crate::scene::camera::{Camera, RaySource};
+use crate::scene::surfaces::hitable::Hitable;
+use crate::scene::Scene;
+
+/// The color of the pixels where no object is.
+const BACKGROUND: [u8; 3] = [24, 24, 24];
+
+/// How far in front of the lens the edges are cut.
+const NEAR: f32 = 1e-3;
+
+/// Draws the layout of the scene at the resolution of the settings, through the pinhole.
+pub(crate) fn render(scene: &Scene, settings: &RenderSettings) -> Image {
+    let (w, h, pixel_aspect) = settings.resolution(scene);
+    let (w, h) = (w as usize, h as usize);
+    let camera = scene.camera().with_aspect(w as f32 * pixel_aspect / h as f32).pinhole();
+    let world = scene.world();
+    let colors: Vec<Vec3> = (0..world.size()).map(color).collect();
+
+    let mut pixels = vec![BACKGROUND; w * h];
+    // the rows are split evenly between the threads, every ray costs about the same
+    let rows = h.div_ceil(settings.thread_count());
+    std::thread::scope(|scope| {
+        for (chunk, part) in pixels.chunks_mut(rows * w).enumerate() {
+            let (camera, colors) = (&camera, &colors);
+            scope.spawn(move || {
+                for (i, pixel) in part.iter_mut().enumerate() {
+                    let (x, y) = (i % w, chunk * rows + i / w);
+                    let ray = camera.get_ray((x as f32 + 0.5) / w as f32, 1. - (y as f32 + 0.5) / h as f32);
+                    if let Some(hit) = world.intersect(&ray, 0.001, f32::MAX) {
+                        let normal = world.shade(&ray, &hit).normal;
+                        let facing = Vec3::dot(&normal, &ray.direction().unit()).abs();
+                        *pixel = encode(&((0.2 + 0.5 * facing) * &colors[hit.object()]));
+                    }
+                }
+            });
+        }
+    });
+
+    let mut image = Image { w, h, color_space: ColorSpace::Srgb, pixels, biases: Vec::new(), notes: Vec::new() };
+    for (object, color) in world.objects().iter().zip(&colors) {
+        let (min, max) = object.bounding_box().corners();
+        if min.x() > max.x() {
+            // the empty object has no box
+            continue;
+        }
+        // the corners are numbered by the bits of x, y and z, 1 takes the max of the axis
+        let corner = |i: usize| Vec3::new(
+            if i & 1 == 0 { min.x() } else { max.x() },
+            if i & 2 == 0 { min.y() } else { max.y() },
+            if i & 4 == 0 { min.z() } else { max.z() });
+        for i in 0..8 {
+            for bit in [1, 2, 4].into_iter().filter(|bit| i & bit == 0) {
+                draw_edge(&mut image, &camera, &corner(i), &corner(i | bit), encode(color));
+            }
+        }
+    }
+    image
+}
+
+/// The color of the object of the index, the neighbors differ in the hue.
+fn color(index: usize) -> Vec3 {
+    // the golden ratio spreads the hues of any number of the objects evenly
+    let hue = (index as f32 * 0.618_034).fract() * 6.;
+    let channel = |n: f32| {
+        let k = (n + hue) % 6.;
+        1. - 0.6 * k.min(4. - k).clamp(0., 1.)
+    };
+    Vec3::new(channel(5.), channel(3.), channel(1.))
+}
+
+fn encode(color: &Vec3) -> [u8; 3] {
+    [color.x(), color.y(), color.z()].map(|c| (c.clamp(0., 1.) * 255.).round() as u8)
+}
+
+/// Draws the line between the points of the scene, the part of it in the view.
+fn draw_edge(image: &mut Image, camera: &Camera, a: &Vec3, b: &Vec3, color: [u8; 3]) {
+    let (da, db) = (camera.project(a).2, camera.project(b).2);
+    if da < NEAR && db < NEAR {
+        return;
+    }
+    // the end behind the near plane is moved along the edge onto it
+    let cut = |from: &Vec3, to: &Vec3, d_from: f32, d_to: f32| {
+        let t = (NEAR - d_from) / (d_to - d_from);
+        from + t * &(to - from)
+    };
+    let a = if da < NEAR { cut(a, b, da, db) } else { a.clone() };
+    let b = if db < NEAR { cut(b, &a, db, da) } else { b.clone() };
+
+    let to_pixel = |p: &Vec3| {
+        let (s, t, _) = camera.project(p);
+        (s * image.w as f32, (1. - t) * image.h as f32)
+    };
+    let (from, to) = (to_pixel(&a), to_pixel(&b));
+    if ![from.0, from.1, to.0, to.1].iter().all(|c| c.is_finite()) {
+        return;
+    }
+    let Some(((x0, y0), (x1, y1))) = clip(from, to, image.w as f32, image.h as f32) else {
+        return;
+    };
+    let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil() as usize;
+    for step in 0..=steps {
+        let t = if steps == 0 { 0. } else { step as f32 / steps as f32 };
+        let x = ((x0 + t * (x1 - x0)) as usize).min(image.w - 1);
+        let y = ((y0 + t * (y1 - y0)) as usize).min(image.h - 1);
+        image.pixels[y * image.w + x] = color;
+    }
+}
+
+/// The part of the line within the rectangle from (0, 0) to (w, h), `None` when it is outside.
+fn clip((x0, y0): (f32, f32), (x1, y1): (f32, f32), w: f32, h: f32) -> Option<((f32, f32), (f32, f32))> {
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    // the line is x0 + t * dx, the parameters of its part inside of every side
+    let (mut t0, mut t1) = (0f32, 1f32);
+    for (p, q) in [(-dx, x0), (dx, w - x0), (-dy, y0), (dy, h - y0)] {
+        if p == 0. {
+            if q < 0. {
+                return None;
+            }
+        } else if p < 0. {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    (t0 <= t1).then_some(((x0 + t0 * dx, y0 + t0 * dy), (x0 + t1 * dx, y0 + t1 * dy)))
+}
@@ -0,0 +1,81 @@
+use crate::math::vec::Vec3;
+use crate::renderer::framebuffer::FrameBuffer;
+
+/// Imperfections of a real photographic lens.
+///
+/// Both effects are radial: the image is magnified differently depending on the distance
+/// from the center. The lateral chromatic aberration is just a slightly different
+/// magnification for each color channel, which makes colored fringes on the edges.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Lens {
+    /// Radial distortion coefficient. Positive values make the barrel distortion
+    /// (straight lines bulge outwards), negative values make the pincushion.
+    pub(crate) distortion: f32,
+    /// Difference of the magnification between the red and the blue channels.
+    pub(crate) chromatic_aberration: f32,
+    /// Apply the effects by resampling the rendered frame instead of bending the camera rays.
+    /// It is much cheaper, but the chromatic aberration is smeared by the interpolation
+    /// and the edges of the image lose some resolution.
+    pub(crate) in_post: bool,
+}
+
+impl Lens {
+    pub(crate) fn is_identity(&self) -> bool {
+        self.distortion == 0. && self.chromatic_aberration == 0.
+    }
+
+    pub(crate) fn has_chromatic_aberration(&self) -> bool {
+        self.chromatic_aberration != 0.
+    }
+
+    /// Maps the position on the image (both coordinates in [0, 1]) to the position
+    /// where the lens takes the light of the given channel (0 - red, 1 - green, 2 - blue) from.
+    pub(crate) fn map(&self, u: f32, v: f32, aspect: f32, channel: usize) -> (f32, f32) {
+        let x = (u - 0.5) * aspect;
+        let y = v - 0.5;
+        // r = 1 in the corners regardless of the aspect ratio
+        let r2 = (x * x + y * y) / (0.25 * (aspect * aspect + 1.));
+        let scale = (1. + self.distortion * r2)
+            * (1. + self.chromatic_aberration * (channel as f32 - 1.));
+        (x * scale / aspect + 0.5, y * scale + 0.5)
+    }
+
+    /// Selects the channel of a sample when the channels are traced along different rays.
+    pub(crate) fn channel_mask(channel: usize) -> Vec3 {
+        match channel {
+            0 => Vec3::new(1., 0., 0.),
+            1 => Vec3::new(0., 1., 0.),
+            _ => Vec3::new(0., 0., 1.),
+        }
+    }
+
+    /// The post-process version of the lens: every output pixel is interpolated
+    /// from the position the lens maps it to.
+    pub(crate) fn apply(&self, frame: &mut FrameBuffer) {
+        let (w, h) = (frame.width(), frame.height());
+        let aspect = w as f32 / h as f32;
+        let src = frame.pixels().to_vec();
+        for (i, pixel) in frame.pixels_mut().iter_mut().enumerate() {
+            // the frame is stored from the top, the image coordinates go from the bottom
+            let u = ((i % w) as f32 + 0.5) / w as f32;
+            let v = 1. - ((i / w) as f32 + 0.5) / h as f32;
+            let channel = |c: usize| {
+                let (su, sv) = self.map(u, v, aspect, c);
+                bilinear(&src, w, h, su * w as f32 - 0.5, (1. - sv) * h as f32 - 0.5)[c]
+            };
+            *pixel = Vec3::new(channel(0), channel(1), channel(2));
+        }
+    }
+}
+
+/// Samples the pixels at the fractional position, the positions outside are clamped to the edge.
+fn bilinear(src: &[Vec3], w: usize, h: usize, x: f32, y: f32) -> Vec3 {
+    let x = x.clamp(0., (w - 1) as f32);
+    let y = y.clamp(0., (h - 1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let top = (1. - fx) * &src[y0 * w + x0] + fx * &src[y0 * w + x1];
+    let bottom = (1. - fx) * &src[y1 * w + x0] + fx * &src[y1 * w + x1];
+    (1. - fy) * top + fy * bottom
+}
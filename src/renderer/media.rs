@@ -0,0 +1,149 @@
+/**
+The media the path is inside of, for the nested dielectrics: the glass of water with the bubbles
+of air in it.
+
+A dielectric alone knows only its own index of refraction, so it bends every ray as if
+the air was on the other side of its surface, and absorbs the light only on the way
+to its own surface. The water in the glass meets the glass, not the air, and the stone
+in the water is seen through the water all the way to it. The path keeps the stack of
+the dielectrics it has entered and not left, so the surface it hits bends it by the ratio
+of the indices of the media on both sides, and every segment of the path is absorbed
+by the medium it runs through.
+
+The solids of the nested media are modeled to overlap a little, a surface of the water
+exactly on the surface of the glass would be hit by the rounding. The medium of the higher
+`priority` fills the overlap: the surfaces of the others within it are not there for
+the ray, it passes them by and only notes that it entered or left them.
+
+LEARN:
+The stack is a small array of a fixed size, not a `Vec`: it is copied with every bounce
+of the path, as the count of the bounces is, so every branch of the recursion has
+the stack of its own and nothing is allocated. The glass of water with a bubble
+is three media deep, the media nested deeper than the stack are untracked.
+*/
+use crate::math::vec::{Ray, Vec3};
+use crate::scene::arena::Id;
+use crate::scene::material::Material;
+use crate::scene::surfaces::world::World;
+
+/// The deepest nesting of the media that is tracked.
+const DEPTH: usize = 4;
+
+/// The dielectrics the path is inside of, the last entered last.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Media {
+    entered: [Option<Id<Material>>; DEPTH],
+    len: usize,
+}
+
+/// What the surface of the dielectric the ray hits is to the path.
+pub(crate) enum Interface {
+    /// The surface is inside the medium of a higher priority, the ray goes on
+    /// through it unchanged, in the media it leaves it in.
+    Hidden(Media),
+    /// The ray crosses from one medium to another, the index of refraction of the medium
+    /// on the inner side of the surface over the one on the outer side, and the media
+    /// the refracted ray is in.
+    Crossing { ref_idx: f32, refracted: Media },
+    /// Not a dielectric, or the dielectric the path is not known to be inside of,
+    /// e.g. the water around the camera, it scatters the ray on its own.
+    Untracked,
+}
+
+impl Media {
+    /// The medium the path runs through: the one of the highest priority it is inside of,
+    /// the last entered of the equal ones.
+    fn medium<'a>(&self, world: &'a World) -> Option<(Id<Material>, &'a Material)> {
+        self.entered[..self.len].iter().flatten()
+            .map(|&id| (id, world.material(id)))
+            .fold(None, |best, (id, material)| match best {
+                Some((_, best_material)) if priority(best_material) > priority(material) => best,
+                _ => Some((id, material)),
+            })
+    }
+
+    fn contains(&self, id: Id<Material>) -> bool {
+        self.entered[..self.len].iter().flatten().any(|entered| entered.index() == id.index())
+    }
+
+    /// The media after the path enters the dielectric, unchanged when they are too deep already.
+    fn enter(mut self, id: Id<Material>) -> Media {
+        if self.len < DEPTH {
+            self.entered[self.len] = Some(id);
+            self.len += 1;
+        }
+        self
+    }
+
+    /// The media after the path leaves the dielectric it entered last.
+    fn leave(mut self, id: Id<Material>) -> Media {
+        if let Some(i) = self.entered[..self.len].iter().rposition(|entered| entered.is_some_and(|entered| entered.index() == id.index())) {
+            self.entered.copy_within(i + 1..self.len, i);
+            self.len -= 1;
+            self.entered[self.len] = None;
+        }
+        self
+    }
+
+    /// What the surface of the material at the hit is to the ray in these media,
+    /// the normal is the geometric one, outwards.
+    pub(crate) fn cross(&self, world: &World, id: Id<Material>, r: &Ray, normal: &Vec3) -> Interface {
+        let material = world.material(id);
+        let Material::Dielectric { ref_idx, .. } = material else {
+            return Interface::Untracked;
+        };
+        let ior = |media: &Media| media.medium(world).map_or(1., |(_, material)| ior(material));
+        let current = self.medium(world);
+        if Vec3::dot(r.direction(), normal) < 0. {
+            // into the dielectric, unless a medium of a higher priority is already there
+            let refracted = self.enter(id);
+            if current.is_some_and(|(_, medium)| priority(medium) > priority(material)) {
+                return Interface::Hidden(refracted);
+            }
+            Interface::Crossing { ref_idx: ior(&refracted) / ior(self), refracted }
+        } else {
+            if !self.contains(id) {
+                // the solid turned inside out, e.g. the sphere of the negative radius that hollows
+                // the glass sphere out, leads out of the medium the path is in
+                return match current {
+                    Some((medium, _)) => {
+                        let refracted = self.leave(medium);
+                        Interface::Crossing { ref_idx: *ref_idx / ior(&refracted), refracted }
+                    }
+                    None => Interface::Untracked,
+                };
+            }
+            let refracted = self.leave(id);
+            // out of the dielectric that is not the medium of the overlap
+            if current.is_some_and(|(medium, _)| medium.index() != id.index()) {
+                return Interface::Hidden(refracted);
+            }
+            Interface::Crossing { ref_idx: *ref_idx / ior(&refracted), refracted }
+        }
+    }
+
+    /// The share of the light left after the distance through the medium the path runs through.
+    pub(crate) fn transmittance(&self, world: &World, distance: f32) -> Option<Vec3> {
+        let Some((_, Material::Dielectric { absorption, .. })) = self.medium(world) else {
+            return None;
+        };
+        // the Beer-Lambert law, as in the dielectric itself
+        let absorbed = |a: f32| (-a * distance).exp();
+        (absorption.squared_length() > 0.)
+            .then(|| Vec3::new(absorbed(absorption.x()), absorbed(absorption.y()), absorbed(absorption.z())))
+    }
+}
+
+fn priority(material: &Material) -> i32 {
+    match material {
+        Material::Dielectric { priority, .. } => *priority,
+        _ => 0,
+    }
+}
+
+fn ior(material: &Material) -> f32 {
+    match material {
+        Material::Dielectric { ref_idx, .. } => *ref_idx,
+        _ => 1.,
+    }
+}
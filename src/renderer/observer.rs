@@ -0,0 +1,155 @@
+/**
+The events of the render for whoever reports its progress: a dashboard, a progress bar,
+or the exporter of the metrics of a render farm.
+
+The renderer does not print its progress itself, it tells the observer of the settings
+what happens, and the observer decides what to show and where. The console observer
+shows the progress of the passes and prints the summary of the render to the standard error.
+The JSON observer writes the events as the lines of JSON instead (`--progress json`),
+for the programs that run the renderer and read its progress.
+
+LEARN:
+The methods of the trait have the default bodies that do nothing, so an observer
+implements only the events it cares about. The tiles are started on the rendering
+threads, so the observer must be `Sync` (shared by the threads) and `Send`,
+and it must be quick, a slow observer slows the render down.
+*/
+use std::io::{IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::renderer::stats::RenderStats;
+use crate::renderer::tiles::Tile;
+
+/// Where the render is: the pass of all the passes of the frame, the passes count from 0,
+/// the samples of every pixel the pass takes, and how many tiles of the pass are done.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Progress {
+    pub(crate) pass: usize,
+    pub(crate) passes: usize,
+    pub(crate) samples: usize,
+    pub(crate) tiles_done: usize,
+    pub(crate) tiles: usize,
+}
+
+pub(crate) trait RenderObserver: Send + Sync {
+    /// A thread starts the tile in the pass, called on that thread.
+    fn on_tile_start(&self, _tile: &Tile, _pass: usize) {}
+
+    /// The tile is done, its pixels are added to the image.
+    fn on_tile_finish(&self, _tile: &Tile, _progress: Progress) {}
+
+    /// All the tiles of the pass are done.
+    fn on_pass_complete(&self, _progress: Progress) {}
+
+    /// The image or the animation is written.
+    fn on_stats(&self, _stats: &RenderStats) {}
+}
+
+/// Prints the statistics of the finished render to the standard error, and the percentage
+/// of the pass being rendered when the standard error is a terminal, not a log.
+pub(crate) struct Console;
+
+/// Wide enough to wipe the line of the progress.
+const PROGRESS_WIDTH: usize = 40;
+
+impl RenderObserver for Console {
+    fn on_tile_finish(&self, _tile: &Tile, progress: Progress) {
+        if std::io::stderr().is_terminal() {
+            // the carriage return moves back to the start of the line, the next update overwrites it
+            eprint!("\r{:>3}% of the pass {} of {}", 100 * progress.tiles_done / progress.tiles, progress.pass + 1, progress.passes);
+        }
+    }
+
+    fn on_pass_complete(&self, _progress: Progress) {
+        if std::io::stderr().is_terminal() {
+            eprint!("\r{:PROGRESS_WIDTH$}\r", "");
+        }
+    }
+
+    fn on_stats(&self, stats: &RenderStats) {
+        eprintln!("{}", stats);
+    }
+}
+
+/// The observer of the name of `--progress`: `human` or `json`.
+pub(crate) fn parse(name: &str) -> Option<Arc<dyn RenderObserver>> {
+    match name.to_ascii_lowercase().as_str() {
+        "human" | "text" => Some(Arc::new(Console)),
+        "json" => Some(Arc::new(JsonProgress::default())),
+        _ => None,
+    }
+}
+
+/// Writes every event as a line of JSON to the standard error (the newline-delimited JSON):
+///
+/// ```text
+/// {"event": "tile", "pass": 0, "passes": 4, "tiles_done": 12, "tiles": 96, "elapsed": 1.250, "eta": 38.750, "rays_per_second": 1843200}
+/// {"event": "pass", "pass": 0, "passes": 4, "tiles_done": 96, "tiles": 96, "elapsed": 10.021, "eta": 30.063, "rays_per_second": 1839714}
+/// {"event": "done", "file_size": 2764854, "render_time": 40.180, "objects": 488, "non_finite": 0, "biases": ["max depth 50", "texture filtering"]}
+/// ```
+///
+/// The time is in seconds from the start of the frame. The estimate of the time left
+/// assumes the rest of the frame renders as fast as the part that is done. The rays
+/// are the camera rays, one per sample of a pixel, the bounces after them are not counted.
+#[derive(Default)]
+pub(crate) struct JsonProgress {
+    frame: Mutex<Frame>,
+}
+
+/// The frame being rendered: when it started and how many camera rays are traced.
+#[derive(Default)]
+struct Frame {
+    start: Option<Instant>,
+    rays: u64,
+}
+
+impl JsonProgress {
+    fn write_progress(&self, event: &str, progress: Progress, frame: &Frame) {
+        let elapsed = frame.start.map_or(0., |start| start.elapsed().as_secs_f64());
+        // the part of the frame that is done, the passes take about the same time
+        let done = (progress.pass as f64 + progress.tiles_done as f64 / progress.tiles.max(1) as f64) / progress.passes.max(1) as f64;
+        let eta = if done > 0. { elapsed * (1. - done) / done } else { 0. };
+        let rays_per_second = if elapsed > 0. { frame.rays as f64 / elapsed } else { 0. };
+        write_line(&format!(
+            "{{\"event\": \"{}\", \"pass\": {}, \"passes\": {}, \"tiles_done\": {}, \"tiles\": {}, \"elapsed\": {:.3}, \"eta\": {:.3}, \"rays_per_second\": {:.0}}}",
+            event, progress.pass, progress.passes, progress.tiles_done, progress.tiles, elapsed, eta, rays_per_second));
+    }
+}
+
+impl RenderObserver for JsonProgress {
+    fn on_tile_start(&self, _tile: &Tile, _pass: usize) {
+        let mut frame = self.frame.lock().unwrap();
+        frame.start.get_or_insert_with(Instant::now);
+    }
+
+    fn on_tile_finish(&self, tile: &Tile, progress: Progress) {
+        let mut frame = self.frame.lock().unwrap();
+        frame.rays += (tile.w * tile.h * progress.samples) as u64;
+        self.write_progress("tile", progress, &frame);
+    }
+
+    fn on_pass_complete(&self, progress: Progress) {
+        let mut frame = self.frame.lock().unwrap();
+        self.write_progress("pass", progress, &frame);
+        if progress.pass + 1 == progress.passes {
+            // the next frame of an animation starts from zero
+            *frame = Frame::default();
+        }
+    }
+
+    fn on_stats(&self, stats: &RenderStats) {
+        write_line(&format!(
+            "{{\"event\": \"done\", \"file_size\": {}, \"render_time\": {:.3}, \"objects\": {}, \"non_finite\": {}, \"biases\": [{}]}}",
+            stats.file_size, stats.render_time.as_secs_f64(), stats.objects, stats.non_finite,
+            stats.biases.iter().map(|bias| format!("\"{}\"", bias)).collect::<Vec<_>>().join(", ")));
+    }
+}
+
+/// Writes the line at once, so the lines of the events are never interleaved with the other output.
+fn write_line(line: &str) {
+    // LEARN:
+    // `eprintln!` panics when the standard error is closed (the reader of the pipe is gone),
+    // the progress is not worth failing the render for, so the error is ignored.
+    let _ = writeln!(std::io::stderr().lock(), "{}", line);
+}
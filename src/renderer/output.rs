@@ -0,0 +1,188 @@
+use std::io::Error;
+use std::path::Path;
+
+use crate::math::color::{Color, ColorSpace};
+use crate::math::vec::Vec3;
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::{png, ppm};
+use crate::renderer::settings::RenderSettings;
+
+/// An 8-bit per channel image ready to be written by one of the image formats.
+pub(crate) struct Image {
+    pub(crate) w: usize,
+    pub(crate) h: usize,
+    /// The color space the pixels are encoded in, the formats tag the files with it.
+    pub(crate) color_space: ColorSpace,
+    /// RGB triples row by row from the top-left corner.
+    pub(crate) pixels: Vec<[u8; 3]>,
+    /// The biased options the image was rendered with, the formats note them.
+    pub(crate) biases: Vec<String>,
+    /// What the image shows that its pixels do not tell, e.g. the plane in focus,
+    /// the formats note them too.
+    pub(crate) notes: Vec<String>,
+}
+
+/// The output path `-` writes the image to the standard output, so it can be piped
+/// into the other tools, e.g. `raytracer --output - | magick ppm:- result.png`.
+pub(crate) fn is_stdout(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// The formats of the 8-bit images.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Format {
+    /// The plain PPM, the simplest format to write and to read back.
+    #[default]
+    Ppm,
+    /// The PNG every browser and viewer shows.
+    Png,
+}
+
+impl Format {
+    pub(crate) fn parse(name: &str) -> Option<Format> {
+        match name {
+            "ppm" => Some(Format::Ppm),
+            "png" => Some(Format::Png),
+            _ => None,
+        }
+    }
+
+    /// The format of the path by its extension, the standard output and the paths
+    /// with the other extensions are written as `fallback`.
+    pub(crate) fn of(path: &Path, fallback: Format) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("png") => Format::Png,
+            Some(ext) if ext.eq_ignore_ascii_case("ppm") => Format::Ppm,
+            _ => fallback,
+        }
+    }
+}
+
+/// Writes the image in the format of the path, see `Format::of`, and returns the size of the file.
+pub(crate) fn write(path: &Path, image: &Image, fallback: Format) -> Result<u64, Error> {
+    match Format::of(path, fallback) {
+        Format::Ppm => ppm::write(path, image),
+        Format::Png => png::write(path, image),
+    }
+}
+
+/// How the quantization error is spread before the values are rounded to 8 bits.
+///
+/// Smooth gradients like the sky have fewer distinct 8-bit values than pixels,
+/// so without dithering they break into visible bands.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Dither {
+    /// Plain rounding.
+    Off,
+    /// Triangular distributed noise of ±1 step, the error becomes a fine unstructured grain.
+    #[default]
+    Triangular,
+    /// 4x4 Bayer matrix, a regular pattern that survives image compression better.
+    Ordered,
+}
+
+impl Dither {
+    pub(crate) fn parse(name: &str) -> Option<Dither> {
+        match name {
+            "off" | "none" => Some(Dither::Off),
+            "triangular" => Some(Dither::Triangular),
+            "ordered" => Some(Dither::Ordered),
+            _ => None,
+        }
+    }
+
+    /// The offset in quantization steps added before the rounding.
+    pub(crate) fn offset(&self, x: usize, y: usize, channel: usize) -> f32 {
+        const BAYER: [[u8; 4]; 4] = [
+            [0, 8, 2, 10],
+            [12, 4, 14, 6],
+            [3, 11, 1, 9],
+            [15, 7, 13, 5],
+        ];
+        match self {
+            Dither::Off => 0.,
+            // the sum of two uniform values has a triangular distribution
+            Dither::Triangular => {
+                let seed = ((y * 0x9E37 + x) * 3 + channel) as u32;
+                hash_to_unit(seed) + hash_to_unit(seed ^ 0x5bd1_e995) - 1.
+            }
+            Dither::Ordered => (BAYER[y % 4][x % 4] as f32 + 0.5) / 16. - 0.5,
+        }
+    }
+}
+
+/// Converts the linear frame to the displayable 8-bit image.
+///
+/// `exposure` is the exposure compensation in stops applied to the linear colors
+/// before the transfer function, 0 keeps the image as rendered. The biases and the notes
+/// depend on the scene, the image files get them from the renderer.
+pub(crate) fn encode(frame: &FrameBuffer, exposure: f32, settings: &RenderSettings) -> Image {
+    encode_at(frame, exposure, settings, (0, 0))
+}
+
+/// Encodes a part of a larger image, `origin` is the position of the frame in the image.
+/// The dithering depends on the position, so the parts join without seams.
+pub(crate) fn encode_at(frame: &FrameBuffer, exposure: f32, settings: &RenderSettings, origin: (usize, usize)) -> Image {
+    let scale = 2f32.powf(exposure);
+    let color_space = settings.color_space;
+    let dither = settings.dither;
+
+    // normalize the color values to [0, 255] and convert them to integers,
+    // overexposed values are clipped to white by the transfer function.
+    let quantize = |linear: f32, x: usize, y: usize, channel: usize| {
+        let value = 255. * color_space.encode(scale * linear) + 0.5 + dither.offset(x, y, channel);
+        value.clamp(0., 255.) as u8
+    };
+
+    let pixels = frame.pixels().iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let (x, y) = (origin.0 + i % frame.width(), origin.1 + i / frame.width());
+            [quantize(c.r(), x, y, 0), quantize(c.g(), x, y, 1), quantize(c.b(), x, y, 2)]
+        })
+        .collect();
+
+    Image {
+        w: frame.width(),
+        h: frame.height(),
+        color_space,
+        pixels,
+        biases: Vec::new(),
+        notes: Vec::new(),
+    }
+}
+
+/// The exposure compensation in stops that brings the log-average luminance
+/// of the frame to `key`, e.g. 0.18 for the middle gray.
+///
+/// The logarithmic average is used because the eye perceives the brightness
+/// logarithmically: a few very bright pixels (the lights, the sun) would dominate
+/// the plain average and make the rest of the image too dark.
+pub(crate) fn metered_exposure(frame: &FrameBuffer, key: f32) -> f32 {
+    // keeps the logarithm of the black pixels finite
+    const DELTA: f32 = 1e-4;
+    let pixels = frame.pixels();
+    let log_sum: f64 = pixels.iter()
+        .map(|c| ((DELTA + luminance(c)).ln()) as f64)
+        .sum();
+    let log_average = (log_sum / pixels.len().max(1) as f64).exp() as f32;
+    (key / log_average).log2()
+}
+
+/// The luminance of the linear Rec.709 (and sRGB) color.
+pub(crate) fn luminance(c: &Vec3) -> f32 {
+    0.2126 * c.r().max(0.) + 0.7152 * c.g().max(0.) + 0.0722 * c.b().max(0.)
+}
+
+/// Integer hash (by Chris Wellons) mapped to [0, 1).
+///
+/// The dithering noise must not depend on the order the pixels are encoded in,
+/// so instead of a random generator the noise is a function of the pixel position.
+fn hash_to_unit(mut x: u32) -> f32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    (x >> 8) as f32 / (1 << 24) as f32
+}
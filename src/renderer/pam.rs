@@ -0,0 +1,30 @@
+/**
+This module writes the PAM images, the extension of the PPM format with the alpha channel.
+*/
+use std::fs::File;
+use std::io::{self, BufWriter, Error, Write};
+use std::path::Path;
+
+use crate::renderer::output;
+use crate::renderer::thumbnail::Thumbnail;
+
+/// Writes the thumbnail as a binary PAM image with the RGB_ALPHA tuple type,
+/// the path `-` is the standard output.
+pub(crate) fn write(path: &Path, thumbnail: &Thumbnail) -> Result<(), Error> {
+    // LEARN:
+    // `Box<dyn Write>` picks the destination at runtime, the rest of the code
+    // does not care whether it writes to a file or to a pipe.
+    let out: Box<dyn Write> = if output::is_stdout(path) {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(path)?)
+    };
+    let mut img = BufWriter::new(out);
+    write!(
+        img,
+        "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+        thumbnail.w,
+        thumbnail.h)?;
+    img.write_all(&thumbnail.rgba)?;
+    img.flush()
+}
@@ -0,0 +1,122 @@
+/**
+This module writes the PNG images, the format every browser, viewer and chat shows
+without converting it first.
+
+LEARN:
+A PNG is the signature and a list of chunks: the length, the type, the data, and the CRC-32
+of the type and the data that catches a damaged file. The rows of the pixels are compressed
+with zlib (deflate), but deflate also has the "stored" blocks, copied as they are,
+so a valid PNG is written without a compressor. The file is as large as the raw pixels,
+which is fine for the previews that are looked at and thrown away.
+*/
+use std::fs::File;
+use std::io::{self, BufWriter, Error, Write};
+use std::path::Path;
+
+use crate::renderer::output::{self, Image};
+
+/// The largest stored block of deflate.
+const BLOCK: usize = 65535;
+
+/// Writes the image as an 8-bit RGB PNG, the path `-` is the standard output,
+/// and returns the size of the file.
+pub(crate) fn write(path: &Path, image: &Image) -> Result<u64, Error> {
+    let out: Box<dyn Write> = if output::is_stdout(path) {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(path)?)
+    };
+    let mut png = BufWriter::new(out);
+    png.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let size = |n: usize| u32::try_from(n).map_err(|_| Error::other(format!("{}x{} is too large for a PNG", image.w, image.h)));
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&size(image.w)?.to_be_bytes());
+    header.extend_from_slice(&size(image.h)?.to_be_bytes());
+    // 8 bits per channel, RGB, deflate, the adaptive filters, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header)?;
+
+    // every row starts with its filter, 0 leaves the row as it is
+    let mut rows = Vec::with_capacity(image.h * (1 + 3 * image.w));
+    for row in image.pixels.chunks_exact(image.w) {
+        rows.push(0);
+        rows.extend(row.iter().flatten());
+    }
+    let mut zlib = Vec::with_capacity(rows.len() + rows.len() / BLOCK * 5 + 11);
+    // deflate with the 32 KiB window, no dictionary
+    zlib.extend_from_slice(&[0x78, 0x01]);
+    // the rows are never empty, every row has its filter
+    let blocks = rows.len().div_ceil(BLOCK);
+    for (i, block) in rows.chunks(BLOCK).enumerate() {
+        let len = block.len() as u16;
+        zlib.push((i + 1 == blocks) as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&rows).to_be_bytes());
+    write_chunk(&mut png, b"IDAT", &zlib)?;
+    write_chunk(&mut png, b"IEND", &[])?;
+    png.flush()?;
+    // the signature, and every chunk has 12 bytes of the length, the type and the CRC
+    Ok((8 + 3 * 12 + header.len() + zlib.len()) as u64)
+}
+
+fn write_chunk(png: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> Result<(), Error> {
+    let len = u32::try_from(data.len()).map_err(|_| Error::other("the PNG chunk is too large"))?;
+    png.write_all(&len.to_be_bytes())?;
+    png.write_all(kind)?;
+    png.write_all(data)?;
+    png.write_all(&crc32(&[kind, data]).to_be_bytes())
+}
+
+/// The CRC-32 of the parts one after another, the polynomial of PNG and zip.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        *entry = (0..8).fold(n as u32, |c, _| if c & 1 == 1 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 });
+    }
+    let crc = parts.iter()
+        .flat_map(|part| part.iter())
+        .fold(!0u32, |crc, &byte| table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8));
+    !crc
+}
+
+/// The checksum of zlib: the sums of the bytes and of those sums, modulo the largest prime below 2^16.
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, crc32, write};
+    use crate::math::color::ColorSpace;
+    use crate::renderer::output::Image;
+
+    #[test]
+    fn checksums_match_the_known_values() {
+        assert_eq!(crc32(&[b"IEND"]), 0xae42_6082);
+        assert_eq!(crc32(&[b"IE", b"ND"]), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn writes_the_signature_and_the_header() {
+        let image = Image { w: 2, h: 1, color_space: ColorSpace::Srgb, pixels: vec![[255, 0, 0], [0, 128, 255]], biases: Vec::new(), notes: Vec::new() };
+        let path = std::env::temp_dir().join(format!("raytracer-test-{}.png", std::process::id()));
+        let size = write(&path, &image).unwrap();
+        let bytes = std::fs::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        let bytes = bytes.unwrap();
+        assert_eq!(size, bytes.len() as u64);
+        assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+        // the length 13, IHDR, the width 2, the height 1, 8-bit RGB
+        assert_eq!(&bytes[8..29], b"\0\0\0\x0dIHDR\0\0\0\x02\0\0\0\x01\x08\x02\0\0\0");
+        assert_eq!(&bytes[bytes.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+    }
+}
@@ -0,0 +1,143 @@
+use crate::math::vec::Vec3;
+use crate::renderer::framebuffer::FrameBuffer;
+
+/// Effects applied to the linear frame after the rendering and before the encoding,
+/// in the order they are listed in the settings.
+///
+/// They work on the linear radiance, so they behave like the real optics would:
+/// the bloom spreads the energy of the highlights and the vignette takes the light away.
+#[derive(Clone, Debug)]
+pub(crate) enum PostEffect {
+    /// The light scattered in the lens: everything brighter than `threshold` is blurred
+    /// and added back on top of the image, so highlights glow instead of clipping harshly.
+    Bloom {
+        threshold: f32,
+        strength: f32,
+        /// Blur radius as a fraction of the image width.
+        radius: f32,
+    },
+    /// Darkening towards the corners, 0 is off and 1 makes the corners black.
+    Vignette {
+        strength: f32,
+    },
+    /// The fog of the depth pass, the cheap stand-in of the fog of the scene: the pixels
+    /// fade into the color by the distance to the surface they see, nothing before `near`,
+    /// `density` per unit of the distance beyond it, and no more than at `far`, the sky too.
+    DepthFog {
+        color: Vec3,
+        near: f32,
+        far: f32,
+        density: f32,
+    },
+}
+
+impl PostEffect {
+    pub(crate) fn bloom(strength: f32) -> PostEffect {
+        PostEffect::Bloom { threshold: 1., strength, radius: 0.02 }
+    }
+
+    pub(crate) fn vignette(strength: f32) -> PostEffect {
+        PostEffect::Vignette { strength }
+    }
+
+    pub(crate) fn depth_fog(color: Vec3, near: f32, far: f32, density: f32) -> PostEffect {
+        PostEffect::DepthFog { color, near, far, density }
+    }
+
+    /// Whether the effect needs the depth pass of the frame.
+    pub(crate) fn uses_depth(&self) -> bool {
+        matches!(self, PostEffect::DepthFog { .. })
+    }
+
+    /// Applies the effect, `depths` is the depth pass of the frame when an effect uses it.
+    pub(crate) fn apply(&self, frame: &mut FrameBuffer, depths: Option<&[f32]>) {
+        match self {
+            PostEffect::Bloom { threshold, strength, radius } =>
+                bloom(frame, *threshold, *strength, *radius),
+            PostEffect::Vignette { strength } =>
+                vignette(frame, *strength),
+            PostEffect::DepthFog { color, near, far, density } => {
+                if let Some(depths) = depths {
+                    depth_fog(frame, depths, color, *near, *far, *density);
+                }
+            }
+        }
+    }
+}
+
+fn bloom(frame: &mut FrameBuffer, threshold: f32, strength: f32, radius: f32) {
+    let (w, h) = (frame.width(), frame.height());
+
+    // bright pass: keep only the energy above the threshold
+    let bright: Vec<Vec3> = frame.pixels().iter()
+        .map(|c| Vec3::new(
+            (c.x() - threshold).max(0.),
+            (c.y() - threshold).max(0.),
+            (c.z() - threshold).max(0.)))
+        .collect();
+
+    let kernel = gaussian_kernel(radius * w as f32);
+    let horizontal = blur(&bright, w, h, &kernel, 1, w);
+    let glow = blur(&horizontal, h, w, &kernel, w, 1);
+
+    for (pixel, g) in frame.pixels_mut().iter_mut().zip(glow.iter()) {
+        *pixel += strength * g;
+    }
+}
+
+/// Normalized 1D gaussian kernel where the radius covers 3 sigmas.
+fn gaussian_kernel(radius: f32) -> Vec<f32> {
+    let r = radius.ceil().max(1.) as i32;
+    let sigma = (radius / 3.).max(0.5);
+    let weights: Vec<f32> = (-r..=r)
+        .map(|i| (-(i * i) as f32 / (2. * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = weights.iter().sum();
+    weights.iter().map(|w| w / sum).collect()
+}
+
+/// One pass of the separable blur.
+///
+/// The same function blurs rows and columns: `len` pixels are blurred along
+/// the `step` stride, and `lines` such lines start every `line_step` pixels.
+fn blur(src: &[Vec3], len: usize, lines: usize, kernel: &[f32], step: usize, line_step: usize) -> Vec<Vec3> {
+    let r = (kernel.len() / 2) as isize;
+    let mut dst = vec![Vec3::zero(); src.len()];
+    for line in 0..lines {
+        let start = line * line_step;
+        for i in 0..len as isize {
+            let mut acc = Vec3::zero();
+            for (k, weight) in kernel.iter().enumerate() {
+                // the edges are clamped, so the energy does not leak out of the image
+                let j = (i + k as isize - r).clamp(0, len as isize - 1) as usize;
+                acc += *weight * &src[start + j * step];
+            }
+            dst[start + i as usize * step] = acc;
+        }
+    }
+    dst
+}
+
+fn vignette(frame: &mut FrameBuffer, strength: f32) {
+    let (w, h) = (frame.width() as f32, frame.height() as f32);
+    let half_diagonal = (w * w + h * h).sqrt() / 2.;
+    let width = frame.width();
+    for (i, pixel) in frame.pixels_mut().iter_mut().enumerate() {
+        let dx = (i % width) as f32 + 0.5 - w / 2.;
+        let dy = (i / width) as f32 + 0.5 - h / 2.;
+        let r = (dx * dx + dy * dy).sqrt() / half_diagonal;
+        // the natural vignetting of a lens follows the cos^4 law,
+        // the smooth falloff below is a cheap approximation of it.
+        let falloff = 1. - strength * r * r * (3. - 2. * r);
+        *pixel *= falloff.max(0.);
+    }
+}
+
+fn depth_fog(frame: &mut FrameBuffer, depths: &[f32], color: &Vec3, near: f32, far: f32, density: f32) {
+    for (pixel, depth) in frame.pixels_mut().iter_mut().zip(depths) {
+        // the same law as the fog of the scene, of the uniform density
+        let distance = (depth.min(far) - near).max(0.);
+        let transmittance = (-density * distance).exp();
+        *pixel = transmittance * pixel.clone() + (1. - transmittance) * color.clone();
+    }
+}
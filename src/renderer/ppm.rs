@@ -0,0 +1,206 @@
+/**
+This module contains the implementation of the PPM image creation,
+and the reading of the PPM images used as the textures.
+*/
+use std::fs::File;
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+use crate::math::color::ColorSpace;
+use crate::renderer::output::{self, Image};
+
+/// Writes the image to the file as a plain text PPM image and returns the size of the file.
+/// The path `-` is the standard output.
+pub(crate) fn write(path: &Path, image: &Image) -> Result<u64, Error> {
+    if output::is_stdout(path) {
+        let mut out = Counted { inner: BufWriter::new(io::stdout().lock()), count: 0 };
+        write_pixels(&mut out, image)?;
+        out.flush()?;
+        return Ok(out.count);
+    }
+
+    // LEARN:
+    // The ? is a shortcut for the match statement that returns the error
+    // we could write match File::create(&path) { Ok(file) => file, Err(why) => return Err(why) }
+    // but look how much cleaner the code is with the ? operator.
+    // The ? operator can be used in functions that return Result type.
+    let file = File::create(path)?;
+
+    // LEARN:
+    // Writing the pixels one by one directly to the file means a system call per pixel.
+    // BufWriter collects the small writes in memory and flushes them in big chunks.
+    let mut img = BufWriter::new(file);
+    write_pixels(&mut img, image)?;
+    finish(img)
+}
+
+fn write_pixels<W: Write>(img: &mut W, image: &Image) -> Result<(), Error> {
+    write_header(img, image.color_space, &image.biases, &image.notes, image.w, image.h)?;
+
+    // LEARN:
+    // Note that the last `map` operation returns the `Result<(), Error>` type.
+    // The `collect()` is a generic method over the element's type.
+    // Compiler uses the impl of the `FromIterator` trait for the `Result` type.
+    //
+    // `Result`s `FromIterator` impl allows to collect the results of the iterator
+    // into a single Result of Vec<results> or stop on the first error.
+    //
+    // Inspired by the Haskell's `traverse` function for sequences.
+    // or in FunctionalJava:
+    // <B> Option<Seq<B>> traverseOption(F<A, Option<B>> f){...} in Seq.java
+    let result: Result<Vec<()>, Error> = image.pixels.iter()
+        .map(write_color(img))
+        .collect();
+    result.map(|_| ())
+}
+
+pub(crate) fn write_header<W: Write>(img: &mut W, color_space: ColorSpace, biases: &[String], notes: &[String], w: usize, h: usize) -> Result<(), Error> {
+    // PPM has no color profiles, but allows comments in the header.
+    // The tag at least tells the viewer (and the human) how to interpret the values.
+    writeln!(img, "P3\n# color space: {}", color_space.name())?;
+    // and the one comparing the image with a reference what it may differ by
+    if !biases.is_empty() {
+        writeln!(img, "# biased by: {}", biases.join(", "))?;
+    }
+    for note in notes {
+        writeln!(img, "# {}", note)?;
+    }
+    writeln!(img, "{} {}\n255", w, h)
+}
+
+/// Flushes the buffer to the disk and returns the size of the file.
+pub(crate) fn finish(img: BufWriter<File>) -> Result<u64, Error> {
+    // LEARN:
+    // The idiomatic way to control how long the file is open is to use a scope { }.
+    // Here the file is closed when `img` goes out of scope, but we want to be sure
+    // the content reached the disk before reporting the size.
+    let file = img.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+
+    Ok(file.metadata()?.len())
+}
+
+pub(crate) fn write_color<W: Write>(img: &mut W) -> impl FnMut(&[u8; 3]) -> Result<(), Error> + '_ {
+    |&[ir, ig, ib]: &[u8; 3]| {
+        // LEARN:
+        // Here no heap allocations are happening.
+        // No new strings are created. Format is a const string.
+        // write! macro splits the format, and writes the pieces and arguments
+        // to the file buffer.
+        // Compare to C++ std::cout << ir << " " << ig << " " << ib << std::endl;
+        writeln!(img, "{} {} {}", ir, ig, ib)
+    }
+}
+
+/// Counts the bytes written through it, the size of a stream is not known otherwise.
+struct Counted<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
+
+/// Reads a PPM image, both the plain text (P3) and the binary (P6) one.
+///
+/// The color space is taken from the tag written by `write_header`,
+/// the images of the other programs are sRGB.
+pub(crate) fn read(path: &Path) -> Result<Image, Error> {
+    let data = std::fs::read(path)?;
+    let invalid = |why: &str| Error::new(ErrorKind::InvalidData, format!("{} is not a PPM image: {}", path.display(), why));
+
+    // The header is the magic number, the width, the height and the largest value,
+    // separated by the whitespace and the comments. A single whitespace follows it.
+    let mut tokens = Vec::new();
+    let mut color_space = ColorSpace::Srgb;
+    let mut pos = 0;
+    while tokens.len() < 4 {
+        match data.get(pos) {
+            None => return Err(invalid("the header is incomplete")),
+            Some(b'#') => {
+                let end = data[pos..].iter().position(|&b| b == b'\n').map_or(data.len(), |n| pos + n);
+                let comment = String::from_utf8_lossy(&data[pos + 1..end]);
+                if let Some(name) = comment.trim().strip_prefix("color space:") {
+                    color_space = ColorSpace::parse(name.trim()).unwrap_or(color_space);
+                }
+                pos = end;
+            }
+            Some(b) if b.is_ascii_whitespace() => pos += 1,
+            Some(_) => {
+                let end = data[pos..].iter().position(|b| b.is_ascii_whitespace()).map_or(data.len(), |n| pos + n);
+                tokens.push(String::from_utf8_lossy(&data[pos..end]).into_owned());
+                pos = end;
+            }
+        }
+    }
+    let number = |token: &str| token.parse::<usize>().map_err(|_| invalid("the size is not a number"));
+    let (w, h, max) = (number(&tokens[1])?, number(&tokens[2])?, number(&tokens[3])?);
+    if w == 0 || h == 0 || max == 0 || max > 255 {
+        return Err(invalid("only the 8-bit images of a non-zero size are supported"));
+    }
+    let scale = |value: usize| (value.min(max) * 255 / max) as u8;
+
+    let values: Vec<u8> = match tokens[0].as_str() {
+        "P6" => data.get(pos + 1..pos + 1 + w * h * 3).ok_or_else(|| invalid("the pixels are incomplete"))?
+            .iter()
+            .map(|&value| scale(value as usize))
+            .collect(),
+        "P3" => String::from_utf8_lossy(&data[pos..])
+            .split_ascii_whitespace()
+            .take(w * h * 3)
+            .map(|value| value.parse::<usize>().map(scale).map_err(|_| invalid("a value is not a number")))
+            .collect::<Result<_, _>>()?,
+        _ => return Err(invalid("only P3 and P6 are supported")),
+    };
+    if values.len() < w * h * 3 {
+        return Err(invalid("the pixels are incomplete"));
+    }
+    let pixels = values.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+    Ok(Image { w, h, color_space, pixels, biases: Vec::new(), notes: Vec::new() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write, write_pixels};
+    use crate::math::color::ColorSpace;
+    use crate::renderer::output::Image;
+
+    fn image() -> Image {
+        Image {
+            w: 2,
+            h: 1,
+            color_space: ColorSpace::Srgb,
+            pixels: vec![[255, 0, 0], [0, 128, 255]],
+            biases: vec!["max depth 50".to_string()],
+            notes: vec!["focused at 2.5".to_string()],
+        }
+    }
+
+    #[test]
+    fn writes_the_header_and_the_pixels() {
+        let mut bytes = Vec::new();
+        write_pixels(&mut bytes, &image()).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(),
+                   "P3\n# color space: sRGB\n# biased by: max depth 50\n# focused at 2.5\n2 1\n255\n255 0 0\n0 128 255\n");
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let path = std::env::temp_dir().join(format!("raytracer-test-{}.ppm", std::process::id()));
+        let size = write(&path, &image()).unwrap();
+        let read = read(&path);
+        std::fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+        assert_eq!(size, 92);
+        assert_eq!((read.w, read.h, read.pixels), (2, 1, image().pixels));
+    }
+}
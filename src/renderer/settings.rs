@@ -0,0 +1,395 @@
+use std::sync::Arc;
+
+use crate::math::color::ColorSpace;
+use crate::math::vec::RayType;
+use crate::renderer::accumulator::Precision;
+use crate::renderer::analysis::HistogramFormat;
+use crate::renderer::aov::Aov;
+use crate::renderer::denoise::Denoiser;
+use crate::renderer::guard::NonFinite;
+use crate::renderer::lens::Lens;
+use crate::renderer::observer::{Console, RenderObserver};
+use crate::renderer::output::{Dither, Format};
+use crate::renderer::postprocess::PostEffect;
+use crate::renderer::tiles::TileOrder;
+use crate::renderer::watchdog::PixelBudget;
+use crate::scene::animation::Animation;
+use crate::scene::Scene;
+
+/// Options that control how the scene is rendered and written to the disk.
+///
+/// Unlike the `Scene`, these do not describe what is rendered, so the same scene
+/// can be rendered with different settings without rebuilding it.
+#[derive(Clone)]
+pub(crate) struct RenderSettings {
+    /// Number of rays traced per pixel, more samples means less noise.
+    pub(crate) samples: usize,
+
+    /// The seed of the random numbers. The same seed and settings render the same image
+    /// on any number of threads, another seed gives another pattern of the noise.
+    pub(crate) seed: u64,
+
+    /// How many times a ray can bounce before it is considered absorbed.
+    pub(crate) max_depth: i32,
+
+    /// The limits of the bounces of each kind, on top of `max_depth`.
+    pub(crate) bounce_limits: BounceLimits,
+
+    /// The least roughness of the metals and the glass after `REGULARIZE_AFTER` bounces, 0..1.
+    ///
+    /// The light that finds its way through a chain of mirrors and glass is rare and bright,
+    /// which shows as the fireflies. Blurring the later bounces spreads that light evenly
+    /// at the cost of a small bias, the reflections seen directly stay sharp.
+    /// The blurred mirrors and glass also gather the point lights and the sun, so the caustics
+    /// of them (the bulb seen through the glass in a mirror) are not black, 0 turns both off.
+    pub(crate) min_roughness: f32,
+
+    /// Turn off every shortcut that trades a bias for less noise or time, see `remove_bias`,
+    /// so the image converges to what a reference path tracer renders of the scene.
+    pub(crate) unbiased: bool,
+
+    /// What becomes of the samples whose radiance is NaN or infinite, see `guard`.
+    pub(crate) non_finite: NonFinite,
+
+    /// The rays and the time a pixel may take before it stops sampling, see `watchdog`.
+    pub(crate) pixel_budget: PixelBudget,
+
+    /// Overrides of the image size of the scene. When only one of the sizes is given,
+    /// the other one keeps the aspect ratio of the scene.
+    pub(crate) width: Option<i32>,
+    pub(crate) height: Option<i32>,
+    /// Overrides the pixel aspect of the scene.
+    pub(crate) pixel_aspect: Option<f32>,
+
+    /// The size of the image relative to the resolution of the scene (or the overrides),
+    /// e.g. 0.5 renders a quick draft at the half size.
+    pub(crate) resolution_scale: f32,
+
+    /// The image is rendered this many times larger and then reduced to the output size,
+    /// which smooths the edges and the fine details better than just more samples.
+    pub(crate) render_scale: usize,
+
+    /// Focus the camera on the surface seen at this pixel of the image, the top-left is (0, 0).
+    pub(crate) focus_pixel: Option<(usize, usize)>,
+
+    /// Blur what is out of focus by the aperture of the camera, false renders through
+    /// the pinhole whatever the scene says, e.g. for the crisp previews.
+    /// (The motion blur of the animations is turned off by `--shutter 0`, see `Animation::shutter`.)
+    pub(crate) depth_of_field: bool,
+
+    /// Render the red and cyan anaglyph of the two eyes this share of the distance in focus apart,
+    /// see `stereo`.
+    pub(crate) anaglyph: Option<f32>,
+
+    /// The eye that sees the image, the offset to the right of the camera as a share
+    /// of the distance in focus, 0 is the camera itself. The anaglyph renders the two eyes.
+    pub(crate) eye: f32,
+
+    /// Render the frames of the animation instead of a still image.
+    pub(crate) animation: Option<Animation>,
+
+    /// Render a fast low resolution preview before the final image.
+    pub(crate) preview: bool,
+
+    /// Meter the rendered frame and adjust the exposure so its log-average luminance
+    /// becomes this value (0.18 is the middle gray). The bracketed stops are relative to it.
+    pub(crate) auto_exposure: Option<f32>,
+
+    /// Write the luminance histogram of the final frame next to the image.
+    pub(crate) histogram: Option<HistogramFormat>,
+
+    /// Write the false-color exposure map of the final frame next to the image.
+    pub(crate) false_color: bool,
+
+    /// Exposure stops (EV) of the additional bracketed outputs.
+    /// Every stop doubles (or halves) the amount of light, so +2 is 4 times brighter.
+    /// Empty when only the main image is written.
+    pub(crate) exposure_stops: Vec<f32>,
+
+    /// The passes of the surfaces written with the image, as its layers in the OpenEXR image
+    /// or as the images next to it, see `aov`.
+    pub(crate) aovs: Vec<Aov>,
+
+    /// Also write the image as the pyramid of the tiles and the page that views them
+    /// in the browser, into the directory next to the image, see `web`.
+    pub(crate) web_viewer: bool,
+
+    /// The color space of the written images.
+    pub(crate) color_space: ColorSpace,
+
+    /// Dithering applied when the colors are quantized to 8 bits.
+    pub(crate) dither: Dither,
+
+    /// The format of the 8-bit images written to the standard output
+    /// or to the paths without the .png or .ppm extension.
+    pub(crate) format: Format,
+
+    /// Smooth the noise of the rendered frame with the bilateral filter,
+    /// it makes a low sample render presentable but blurs the fine details.
+    pub(crate) denoise: Option<Denoiser>,
+
+    /// Distortion and chromatic aberration of the camera lens.
+    pub(crate) lens: Option<Lens>,
+
+    /// Post-processing effects applied to the linear frame in this order.
+    pub(crate) post_effects: Vec<PostEffect>,
+
+    /// Number of the rendering threads, 0 uses all the cores.
+    pub(crate) threads: usize,
+
+    /// The order the tiles are started in, the image is the same in any order.
+    pub(crate) tile_order: TileOrder,
+
+    /// Split the samples into this many passes over the whole image and write the image
+    /// after every pass, so it can be judged long before the render is done, see `accumulator`.
+    pub(crate) passes: usize,
+
+    /// Render a coarse image first and the passes of its busy tiles before the rest of
+    /// the image, so the intermediate images show the details early, see `coarse`.
+    pub(crate) coarse_to_fine: bool,
+
+    /// How precisely the samples are summed over the passes, the half precision
+    /// takes a third of the memory of the frame, see `Precision` for what it costs.
+    pub(crate) precision: Precision,
+
+    /// Compute the directions of the camera rays through the pixels once for the frame,
+    /// instead of for every sample, see `rays`. The image is the same either way.
+    pub(crate) ray_cache: bool,
+
+    /// Keep every rendering thread on a core of its own, the system does not move it.
+    pub(crate) pin_threads: bool,
+
+    /// Spread the rendering threads over the NUMA nodes (the sockets) of the machine,
+    /// keep them there and give every node its own copy of the scene, see `affinity`.
+    pub(crate) numa: bool,
+
+    /// Write the tiles of this size to the disk as soon as they are rendered,
+    /// instead of keeping the whole frame in memory, for the very large images.
+    /// The effects that need the whole frame are not available then.
+    pub(crate) tiled: Option<usize>,
+
+    /// The memory in bytes the render may use. The render fails before it starts
+    /// when the scene and the frame are estimated to need more.
+    pub(crate) memory_budget: Option<u64>,
+
+    /// Who is told about the progress of the render, see `observer`.
+    pub(crate) observer: Arc<dyn RenderObserver>,
+}
+
+/// How many bounces of each kind a path can make.
+///
+/// The kinds matter differently: a glass object needs many refractions to look right,
+/// while the light after a few diffuse bounces is too dim to change the image,
+/// so limiting the diffuse bounces saves time without a visible difference.
+/// `None` leaves the kind limited only by the total `max_depth`.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct BounceLimits {
+    pub(crate) diffuse: Option<i32>,
+    pub(crate) reflection: Option<i32>,
+    pub(crate) refraction: Option<i32>,
+}
+
+impl BounceLimits {
+    pub(crate) fn limit(&self, kind: RayType) -> Option<i32> {
+        match kind {
+            RayType::Camera | RayType::Shadow => None,
+            RayType::Diffuse => self.diffuse,
+            RayType::Reflection => self.reflection,
+            RayType::Refraction => self.refraction,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// The number of bounces that keep their roughness before `min_roughness` applies.
+    pub(crate) const REGULARIZE_AFTER: i32 = 2;
+
+    /// The resolution of the preview is this many times smaller than of the final image.
+    pub(crate) const PREVIEW_DOWNSCALE: i32 = 4;
+
+    /// The key of the auto exposure: the middle gray, 18% reflectance.
+    pub(crate) const MIDDLE_GRAY: f32 = 0.18;
+
+    /// The classic bracketing: two stops under, normal and two stops over.
+    pub(crate) fn default_bracket() -> Vec<f32> {
+        vec![-2., 0., 2.]
+    }
+
+    /// The size of the image and the pixel aspect the scene is rendered with.
+    pub(crate) fn resolution(&self, scene: &Scene) -> (i32, i32, f32) {
+        let pixel_aspect = self.pixel_aspect.unwrap_or(scene.pixel_aspect);
+        let scale = |size: i32, from: i32, to: i32| ((size as i64 * to as i64 / from as i64) as i32).max(1);
+        let (w, h) = match (self.width, self.height) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, scale(w, scene.w, scene.h)),
+            (None, Some(h)) => (scale(h, scene.h, scene.w), h),
+            (None, None) => (scene.w, scene.h),
+        };
+        let resize = |size: i32| ((size as f32 * self.resolution_scale).round() as i32).max(1);
+        (resize(w), resize(h), pixel_aspect)
+    }
+
+    /// Turns off the biased shortcuts: the regularization of the mirrors and the glass,
+    /// the limits of the bounces of each kind, the denoiser and the half precision sums.
+    /// The textures are also sampled at the points the rays hit, not filtered over their
+    /// footprints, the renderer checks `unbiased` for that.
+    ///
+    /// The paths still end after `max_depth` bounces, which darkens the interreflections
+    /// a little, so the comparisons with a reference need a large `max_depth`.
+    pub(crate) fn remove_bias(&mut self) {
+        self.unbiased = true;
+        self.min_roughness = 0.;
+        self.bounce_limits = BounceLimits::default();
+        self.denoise = None;
+        self.precision = Precision::Single;
+        self.pixel_budget = PixelBudget::default();
+    }
+
+    /// The biased options the scene is rendered with, for the statistics and the metadata
+    /// of the image, e.g. `max depth 50`, `min roughness 0.3` or `denoise`.
+    pub(crate) fn biases(&self, scene: &Scene) -> Vec<String> {
+        let mut biases = vec![format!("max depth {}", self.max_depth)];
+        if self.min_roughness > 0. {
+            biases.push(format!("min roughness {}", self.min_roughness));
+        }
+        let limits = [("diffuse", self.bounce_limits.diffuse), ("reflection", self.bounce_limits.reflection), ("refraction", self.bounce_limits.refraction)];
+        for (kind, limit) in limits {
+            if let Some(limit) = limit {
+                biases.push(format!("{} depth {}", kind, limit));
+            }
+        }
+        if self.denoise.is_some() {
+            biases.push("denoise".to_string());
+        }
+        if self.precision == Precision::Half {
+            biases.push("f16 accumulation".to_string());
+        }
+        if self.pixel_budget.is_limited() {
+            biases.push("pixel budget".to_string());
+        }
+        if !self.unbiased && scene.world().has_textures() {
+            biases.push("texture filtering".to_string());
+        }
+        biases
+    }
+
+    /// The number of the threads to render with.
+    pub(crate) fn thread_count(&self) -> usize {
+        match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    /// Cheap settings to check the framing and the lighting: few samples and bounces,
+    /// no bracketing and analysis. Everything that changes how the image looks is kept.
+    pub(crate) fn preview_settings(&self) -> RenderSettings {
+        RenderSettings {
+            samples: 4,
+            max_depth: 8,
+            render_scale: 1,
+            preview: false,
+            exposure_stops: Vec::new(),
+            histogram: None,
+            false_color: false,
+            tiled: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// The bundles of the settings for the common tradeoffs of the quality and the speed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Preset {
+    /// Seconds: the half size, few samples, denoised. Good to check the framing.
+    Draft,
+    /// A minute: the full size, denoised. Good to check the materials and the lighting.
+    Medium,
+    /// As long as it takes: many samples, supersampled, no denoising to blur the details.
+    Final,
+}
+
+impl Preset {
+    pub(crate) fn parse(name: &str) -> Option<Preset> {
+        match name {
+            "draft" => Some(Preset::Draft),
+            "medium" => Some(Preset::Medium),
+            "final" => Some(Preset::Final),
+            _ => None,
+        }
+    }
+}
+
+impl Preset {
+    /// Sets the quality settings of the preset, the rest of the settings are kept.
+    pub(crate) fn apply(&self, settings: &mut RenderSettings) {
+        let (samples, max_depth, resolution_scale, render_scale, denoise) = match self {
+            Preset::Draft => (8, 8, 0.5, 1, true),
+            Preset::Medium => (64, 16, 1., 1, true),
+            Preset::Final => (256, 50, 1., 2, false),
+        };
+        settings.samples = samples;
+        settings.max_depth = max_depth;
+        settings.resolution_scale = resolution_scale;
+        settings.render_scale = render_scale;
+        settings.denoise = if denoise { Some(settings.denoise.unwrap_or_default()) } else { None };
+    }
+}
+
+impl RenderSettings {
+    /// The default settings adjusted by the preset.
+    pub(crate) fn preset(preset: Preset) -> RenderSettings {
+        let mut settings = RenderSettings::default();
+        preset.apply(&mut settings);
+        settings
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            samples: 100,
+            seed: 0,
+            max_depth: 50,
+            bounce_limits: BounceLimits::default(),
+            min_roughness: 0.,
+            unbiased: false,
+            non_finite: NonFinite::default(),
+            pixel_budget: PixelBudget::default(),
+            width: None,
+            height: None,
+            pixel_aspect: None,
+            resolution_scale: 1.,
+            render_scale: 1,
+            focus_pixel: None,
+            depth_of_field: true,
+            anaglyph: None,
+            eye: 0.,
+            animation: None,
+            preview: false,
+            auto_exposure: None,
+            histogram: None,
+            false_color: false,
+            exposure_stops: Vec::new(),
+            aovs: Vec::new(),
+            web_viewer: false,
+            color_space: ColorSpace::default(),
+            dither: Dither::default(),
+            format: Format::default(),
+            denoise: None,
+            lens: None,
+            post_effects: Vec::new(),
+            threads: 0,
+            tile_order: TileOrder::default(),
+            passes: 1,
+            coarse_to_fine: false,
+            precision: Precision::default(),
+            ray_cache: true,
+            pin_threads: false,
+            numa: false,
+            tiled: None,
+            memory_budget: None,
+            observer: Arc::new(Console),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use crate::math::vec::Vec3;
+
+/// What the render of an image took, printed when it is done.
+pub(crate) struct RenderStats {
+    /// The size of the main image file in bytes.
+    pub(crate) file_size: u64,
+    pub(crate) render_time: Duration,
+    /// The number of the surfaces in the scene.
+    pub(crate) objects: usize,
+    /// The bytes held by the surfaces and the materials of the scene.
+    pub(crate) scene_memory: usize,
+    /// The corners of the box around the surfaces, none when there are none.
+    pub(crate) bounds: Option<(Vec3, Vec3)>,
+    /// The largest resident memory of the process in bytes, when the OS reports it.
+    pub(crate) peak_memory: Option<u64>,
+    /// The samples whose radiance was NaN or infinite, see `guard`.
+    pub(crate) non_finite: u64,
+    /// The biased options of the render, see `RenderSettings::biases`.
+    pub(crate) biases: Vec<String>,
+}
+
+impl Display for RenderStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "File size {} bytes. Render time {} secs. {} objects in {} KiB",
+               self.file_size, self.render_time.as_secs(), self.objects, self.scene_memory.div_ceil(1024))?;
+        if let Some((min, max)) = &self.bounds {
+            write!(f, " from ({:.2}, {:.2}, {:.2}) to ({:.2}, {:.2}, {:.2})", min.x(), min.y(), min.z(), max.x(), max.y(), max.z())?;
+        }
+        match self.peak_memory {
+            Some(bytes) => write!(f, ", peak memory {} MiB.", bytes.div_ceil(1024 * 1024))?,
+            None => write!(f, ".")?,
+        }
+        write!(f, " Biased by {}.", self.biases.join(", "))?;
+        if self.non_finite > 0 {
+            write!(f, " {} samples were NaN or infinite.", self.non_finite)?;
+        }
+        Ok(())
+    }
+}
+
+/// The peak resident set size of the process.
+///
+/// Linux reports it as `VmHWM` (the "high water mark") in `/proc/self/status`,
+/// other systems would need their own API, so there it is unknown.
+pub(crate) fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    // e.g. "VmHWM:      9876 kB"
+    let kb = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kb * 1024)
+}
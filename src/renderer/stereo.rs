@@ -0,0 +1,21 @@
+use crate::math::vec::Vec3;
+use crate::renderer::framebuffer::FrameBuffer;
+
+/// The separation of the eyes by default, a thirtieth of the distance in focus,
+/// the rule of thumb of the stereo photographers: the depth is clear and the eyes
+/// do not strain to fuse the near objects.
+pub(crate) const DEFAULT_SEPARATION: f32 = 1. / 30.;
+
+/// The red and cyan anaglyph of the views of the left and the right eye: the red channel
+/// of the left view and the green and the blue of the right one, the glasses of the same
+/// colors show every eye its own view.
+///
+/// The eyes look from the both sides of the camera at the same image plane in focus,
+/// so the surfaces in focus are at the depth of the screen, the nearer ones come out
+/// of it and the farther ones go in.
+pub(crate) fn anaglyph(left: &FrameBuffer, right: &FrameBuffer) -> FrameBuffer {
+    let pixels = left.pixels().iter().zip(right.pixels())
+        .map(|(left, right)| Vec3::new(left.x(), right.y(), right.z()))
+        .collect();
+    FrameBuffer::from_pixels(left.width(), left.height(), pixels)
+}
@@ -0,0 +1,78 @@
+/**
+The seed sweep: the same scene rendered with many seeds, `raytracer sweep [--seeds <n>] [OPTIONS]`.
+
+Every seed gives another pattern of the noise of the same image. The mean of the renders
+is the image of n times the samples, and the variance of a pixel over the renders is how noisy
+the pixel is at the samples of one render. A change of the sampling that lowers the variance
+and keeps the mean is an improvement, one that moves the mean is a bias. The numbers
+before and after the change tell it far more reliably than a look at two noisy images.
+
+The renders are meant to be quick, a few samples each, the sweep takes many of them.
+*/
+use std::io::Error;
+use std::path::Path;
+
+use crate::math::vec::Vec3;
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::settings::RenderSettings;
+use crate::renderer::{output, ppm, render_to_buffer, suffixed_path};
+use crate::scene::Scene;
+
+/// The mean and the variance of every pixel over the renders of the seeds.
+pub(crate) struct Sweep {
+    pub(crate) mean: FrameBuffer,
+    pub(crate) variance: FrameBuffer,
+}
+
+impl Sweep {
+    /// The variance of the pixels on average, of the channels on average,
+    /// the single number of how noisy the render of the settings is.
+    pub(crate) fn mean_variance(&self) -> f32 {
+        let pixels = self.variance.pixels();
+        let sum: f64 = pixels.iter().map(|v| (v.x() + v.y() + v.z()) as f64 / 3.).sum();
+        (sum / pixels.len().max(1) as f64) as f32
+    }
+}
+
+/// Renders the scene with the seeds from the seed of the settings on.
+pub(crate) fn sweep(scene: &Scene, settings: &RenderSettings, seeds: usize) -> Sweep {
+    let mut mean: Vec<Vec3> = Vec::new();
+    // the sums of the squared differences from the mean
+    let mut squares: Vec<Vec3> = Vec::new();
+    let (mut w, mut h) = (0, 0);
+    for i in 0..seeds {
+        let settings = RenderSettings { seed: settings.seed.wrapping_add(i as u64), ..settings.clone() };
+        let frame = render_to_buffer(scene, &settings);
+        if i == 0 {
+            (w, h) = (frame.width(), frame.height());
+            mean = vec![Vec3::zero(); w * h];
+            squares = vec![Vec3::zero(); w * h];
+        }
+        // LEARN:
+        // The variance as the mean of the squares minus the square of the mean loses
+        // all the digits when the two are close, i.e. for the pixels of little noise.
+        // The Welford's algorithm updates the mean and the squared differences from it
+        // with every new value instead, and stays precise.
+        let n = (i + 1) as f32;
+        for ((mean, squares), x) in mean.iter_mut().zip(&mut squares).zip(frame.pixels()) {
+            let delta = x - &*mean;
+            *mean = &*mean + (1. / n) * &delta;
+            *squares = &*squares + &delta * &(x - &*mean);
+        }
+        eprintln!("Seed {} of {} is done.", i + 1, seeds);
+    }
+    // the unbiased variance of the sample of the renders divides by one less than their number
+    let scale = 1. / (seeds.max(2) - 1) as f32;
+    let variance = squares.iter().map(|squares| scale * squares).collect();
+    Sweep { mean: FrameBuffer::from_pixels(w, h, mean), variance: FrameBuffer::from_pixels(w, h, variance) }
+}
+
+/// Renders the sweep and writes the mean and the variance next to the path, e.g. `result_mean.ppm`
+/// and `result_variance.ppm`. The variance is encoded as the colors are, the brighter the noisier.
+pub(crate) fn render_sweep(scene: &Scene, settings: &RenderSettings, seeds: usize, path: &Path) -> Result<Sweep, Error> {
+    let sweep = sweep(scene, settings, seeds);
+    let encode = |frame| output::Image { biases: settings.biases(scene), ..output::encode(frame, 0., settings) };
+    ppm::write(&suffixed_path(path, "_mean"), &encode(&sweep.mean))?;
+    ppm::write(&suffixed_path(path, "_variance"), &encode(&sweep.variance))?;
+    Ok(sweep)
+}
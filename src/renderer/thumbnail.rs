@@ -0,0 +1,41 @@
+use crate::renderer::denoise::Denoiser;
+use crate::renderer::output;
+use crate::renderer::settings::RenderSettings;
+use crate::scene::Scene;
+
+/// A small RGBA image of the scene for the asset browsers and the documentation.
+pub(crate) struct Thumbnail {
+    pub(crate) w: usize,
+    pub(crate) h: usize,
+    /// RGBA bytes row by row from the top-left corner, the alpha is always opaque.
+    pub(crate) rgba: Vec<u8>,
+}
+
+impl Scene {
+    /// Renders a quick preview of the scene, the height follows the aspect ratio of the scene.
+    ///
+    /// LEARN:
+    /// The `impl` block does not have to be in the module of the type.
+    /// The scene does not know anything about the rendering, but the renderer
+    /// can extend it with a method.
+    pub(crate) fn render_thumbnail(&self, width: usize) -> Thumbnail {
+        let w = width.max(1);
+        let h = ((w as f32 / self.aspect() * self.pixel_aspect) as usize).max(1);
+
+        let settings = RenderSettings {
+            samples: 16,
+            max_depth: 8,
+            ..RenderSettings::default()
+        };
+
+        let (mut frame, _) = super::render_frame(self, &settings, w as i32, h as i32);
+        Denoiser::default().apply(&mut frame);
+
+        let image = output::encode(&frame, 0., &settings);
+        let rgba = image.pixels.iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 255])
+            .collect();
+
+        Thumbnail { w, h, rgba }
+    }
+}
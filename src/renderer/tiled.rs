@@ -0,0 +1,125 @@
+/**
+This module renders the images too large to keep in memory, e.g. 16k posters.
+
+A 16384x16384 frame of `Vec3` takes 3 GB before any post-processing.
+Instead, every tile is encoded and written to a directory next to the output
+(`result.ppm.tiles/`) as soon as it is rendered, so only the tiles in flight are in memory.
+The assembly step then streams the tiles row by row into the final image
+and removes the directory.
+
+The finished tiles are recorded in the manifest of the job (see `job`),
+so an interrupted render renders only the missing tiles when it is run again.
+
+The effects that need the whole frame (bloom, the lens in post, the render scale,
+the bracketing) are not available in this mode.
+*/
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::renderer::accumulator::PixelSum;
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::output::{self, Image};
+use crate::renderer::job;
+use crate::renderer::ppm;
+use crate::renderer::settings::RenderSettings;
+use crate::renderer::tiles::{self, Tile};
+use crate::renderer::{Pass, Written};
+use crate::scene::Scene;
+
+/// Renders the scene tile by tile to the disk and assembles the final image.
+/// Returns the size of the image file and how many samples were not finite.
+pub(crate) fn render_to_file(
+    scene: &Scene,
+    settings: &RenderSettings,
+    w: usize,
+    h: usize,
+    tile_size: usize,
+    path: &Path,
+) -> Result<Written, Error> {
+    let dir = tiles_dir(path);
+    fs::create_dir_all(&dir)?;
+
+    let done = job::done(&dir);
+    let mut todo: Vec<Tile> = tiles::split(w, h, tile_size)
+        .into_iter()
+        .filter(|tile| !done.contains(&(tile.x, tile.y)))
+        .collect();
+    settings.tile_order.arrange(&mut todo, w, h, tile_size);
+
+    let non_finite = super::render_tiles(scene, settings, w, h, todo, Pass::whole(settings), |tile, sums, _| {
+        let frame = FrameBuffer::from_pixels(tile.w, tile.h, sums.iter().map(PixelSum::mean).collect());
+        write_tile(&tile_path(&dir, &tile), &output::encode_at(&frame, 0., settings, (tile.x, tile.y)))?;
+        job::mark_done(&dir, &tile)
+    })?;
+
+    let size = assemble(&dir, w, h, tile_size, scene, settings, path)?;
+    fs::remove_dir_all(&dir)?;
+    Ok(Written { size, non_finite })
+}
+
+/// Joins the tiles written by `render_to_file` into the final PPM image.
+///
+/// Only one row of the tiles is open at a time, and a single line
+/// of every tile is in memory.
+fn assemble(dir: &Path, w: usize, h: usize, tile_size: usize, scene: &Scene, settings: &RenderSettings, path: &Path) -> Result<u64, Error> {
+    let mut img = BufWriter::new(File::create(path)?);
+    ppm::write_header(&mut img, settings.color_space, &settings.biases(scene), &super::notes(scene, settings), w, h)?;
+
+    let tiles = tiles::split(w, h, tile_size);
+    // the tiles are in the scanline order, so a row of tiles is a chunk of the list
+    for band in tiles.chunks(w.div_ceil(tile_size)) {
+        let mut readers = band.iter()
+            .map(|tile| open_tile(&tile_path(dir, tile), tile))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut line = Vec::new();
+        for _ in 0..band[0].h {
+            for (reader, tile) in readers.iter_mut().zip(band) {
+                line.resize(tile.w * 3, 0);
+                reader.read_exact(&mut line)?;
+                let mut write = ppm::write_color(&mut img);
+                for rgb in line.chunks_exact(3) {
+                    write(&[rgb[0], rgb[1], rgb[2]])?;
+                }
+            }
+        }
+    }
+
+    ppm::finish(img)
+}
+
+/// `result.ppm` keeps its tiles in `result.ppm.tiles/`.
+pub(crate) fn tiles_dir(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tiles");
+    path.with_file_name(name)
+}
+
+fn tile_path(dir: &Path, tile: &Tile) -> PathBuf {
+    dir.join(format!("tile_{}_{}.ppm", tile.x, tile.y))
+}
+
+/// The tiles are binary PPM images, they are 4 times smaller than the text ones
+/// and can be opened by any viewer to check the progress.
+fn write_tile(path: &Path, image: &Image) -> Result<(), Error> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", image.w, image.h)?;
+    for pixel in image.pixels.iter() {
+        file.write_all(pixel)?;
+    }
+    file.flush()
+}
+
+/// Opens the tile and skips its header, the reader is left at the first pixel.
+fn open_tile(path: &Path, tile: &Tile) -> Result<BufReader<File>, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = String::new();
+    for _ in 0..3 {
+        reader.read_line(&mut header)?;
+    }
+    if header != format!("P6\n{} {}\n255\n", tile.w, tile.h) {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} is not a {}x{} tile", path.display(), tile.w, tile.h)));
+    }
+    Ok(reader)
+}
@@ -0,0 +1,244 @@
+use std::cmp::Reverse;
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::renderer::accumulator::PixelSum;
+
+/// A rectangular part of the image rendered as one piece of work.
+///
+/// The image is split into tiles to render them in parallel, and to write them
+/// out as they are done, without keeping the whole image in memory.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Tile {
+    /// The position in the scanline order of the tiles, the random generator is seeded with it,
+    /// so the order they are rendered in does not change the image.
+    pub(crate) index: usize,
+    /// The top-left corner in the image, the rows go from the top.
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) w: usize,
+    pub(crate) h: usize,
+    /// The row of the tile of the index this one starts at, 0 but for the pieces
+    /// of a split tile, see `balance`. The rows are seeded as the rows of the whole tile.
+    pub(crate) first_row: usize,
+}
+
+/// Splits the image into the tiles of `size`x`size` pixels (smaller on the right
+/// and the bottom edges) in the scanline order.
+pub(crate) fn split(w: usize, h: usize, size: usize) -> Vec<Tile> {
+    let size = size.max(1);
+    let mut tiles = Vec::new();
+    for y in (0..h).step_by(size) {
+        for x in (0..w).step_by(size) {
+            tiles.push(Tile {
+                index: tiles.len(),
+                x,
+                y,
+                w: size.min(w - x),
+                h: size.min(h - y),
+                first_row: 0,
+            });
+        }
+    }
+    tiles
+}
+
+/// How many pieces of about the same time every thread gets, the more the less
+/// the threads wait for the last ones at the end of the pass.
+const PIECES_PER_THREAD: u32 = 8;
+
+/// The tiles of the next pass balanced by the times their rows took in the previous one
+/// on all the threads: the tiles that took longer than the share of a piece of work
+/// are split into the bands of the rows that take about the share each, and the longest
+/// pieces start first, the quick ones fill in the gaps at the end.
+///
+/// LEARN:
+/// The threads already steal the rows of a tile when no tile is left, but the tile
+/// started last, e.g. the one full of glass spheres at the edge of the spiral, is then
+/// rendered by a few threads while the rest of them wait. Starting the longest work first
+/// (the LPT rule of the schedulers) leaves only the short pieces for the end.
+/// The pieces keep the index and the rows of the tile, so the image stays the same.
+pub(crate) fn balance(measured: Vec<(Tile, Duration)>, threads: usize) -> Vec<Tile> {
+    let total: Duration = measured.iter().map(|(_, spent)| *spent).sum();
+    let share = total / (threads.max(1) as u32 * PIECES_PER_THREAD);
+    let mut pieces: Vec<(Tile, Duration)> = Vec::with_capacity(measured.len());
+    for (tile, spent) in measured {
+        let count = match share.is_zero() {
+            true => 1,
+            false => (spent.as_secs_f64() / share.as_secs_f64()).ceil().clamp(1., tile.h as f64) as usize,
+        };
+        // the bands differ by a row at most
+        for band in 0..count {
+            let (start, end) = (tile.h * band / count, tile.h * (band + 1) / count);
+            let piece = Tile { y: tile.y + start, h: end - start, first_row: tile.first_row + start, ..tile };
+            pieces.push((piece, spent * (end - start) as u32 / tile.h as u32));
+        }
+    }
+    pieces.sort_by_key(|(_, estimate)| Reverse(*estimate));
+    pieces.into_iter().map(|(piece, _)| piece).collect()
+}
+
+/// The order the tiles are rendered in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum TileOrder {
+    /// From the center out, the subject of the image, usually in the middle, is done first.
+    #[default]
+    Spiral,
+    /// Along the Hilbert curve, every next tile touches the one before,
+    /// so the threads work on the same part of the scene.
+    Hilbert,
+    /// Row by row from the top, like the image is read.
+    Scanline,
+}
+
+impl TileOrder {
+    pub(crate) fn parse(name: &str) -> Option<TileOrder> {
+        match name.to_ascii_lowercase().as_str() {
+            "spiral" => Some(TileOrder::Spiral),
+            "hilbert" => Some(TileOrder::Hilbert),
+            "scanline" => Some(TileOrder::Scanline),
+            _ => None,
+        }
+    }
+
+    /// Sorts the tiles split from the image of `w`x`h` pixels by `size` into the order.
+    pub(crate) fn arrange(&self, tiles: &mut [Tile], w: usize, h: usize, size: usize) {
+        let size = size.max(1);
+        let (columns, rows) = (w.div_ceil(size), h.div_ceil(size));
+        let cell = |tile: &Tile| (tile.x / size, tile.y / size);
+        match self {
+            TileOrder::Spiral => {
+                let center = ((columns as f32 - 1.) / 2., (rows as f32 - 1.) / 2.);
+                // the ring of the tiles around the center, and the angle around it within the ring
+                let key = |tile: &Tile| {
+                    let (column, row) = cell(tile);
+                    let (dx, dy) = (column as f32 - center.0, row as f32 - center.1);
+                    (dx.abs().max(dy.abs()), dy.atan2(dx))
+                };
+                tiles.sort_by(|a, b| {
+                    let (a, b) = (key(a), key(b));
+                    a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1))
+                });
+            }
+            TileOrder::Hilbert => {
+                let n = columns.max(rows).next_power_of_two();
+                tiles.sort_by_key(|tile| {
+                    let (column, row) = cell(tile);
+                    hilbert(n, column, row)
+                });
+            }
+            TileOrder::Scanline => tiles.sort_by_key(|tile| tile.index),
+        }
+    }
+}
+
+/// The distance along the Hilbert curve through the square of `n`x`n` cells (a power of 2)
+/// to the cell.
+///
+/// LEARN:
+/// The curve visits the four quadrants of the square one after another, and every
+/// quadrant the same way at the half size, turned so the curve enters it where
+/// the previous one left. Every bit of the coordinates picks the quadrant at one level.
+fn hilbert(n: usize, mut x: usize, mut y: usize) -> usize {
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        // turn the quadrant so its curve starts at the corner the previous one ended at
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+/// The tiles shared by the rendering threads: the ones nobody has started and the ones
+/// being rendered.
+///
+/// Every thread takes the next tile nobody has started, so the fast tiles (e.g. the sky)
+/// do not leave the threads idle. When none are left, the idle threads do not wait
+/// for the slow tiles (e.g. the ones full of glass) to be finished by a single thread each,
+/// they steal the rows nobody has taken yet from the tile with the most of them.
+pub(crate) struct Queue {
+    tiles: Vec<Tile>,
+    next: AtomicUsize,
+    in_flight: Mutex<Vec<Arc<Work>>>,
+}
+
+/// A tile being rendered, its rows are taken one by one by the threads rendering it.
+pub(crate) struct Work {
+    pub(crate) tile: Tile,
+    /// The next row nobody has taken yet, from the top of the tile.
+    next_row: AtomicUsize,
+    /// The pixels of the tile, and how many of its rows are finished.
+    pixels: Mutex<(Vec<PixelSum>, usize)>,
+    /// The time its rows took, on all the threads, in nanoseconds.
+    spent: AtomicU64,
+}
+
+impl Queue {
+    pub(crate) fn new(tiles: Vec<Tile>) -> Queue {
+        Queue { tiles, next: AtomicUsize::new(0), in_flight: Mutex::new(Vec::new()) }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// The next tile nobody has started, or the one being rendered with the most rows
+    /// nobody has taken, `None` when all the rows are taken.
+    pub(crate) fn take(&self) -> Option<Arc<Work>> {
+        // LEARN:
+        // The lock is held only while the list changes, never while a row is rendered,
+        // so the threads almost never wait for each other here.
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(tile) = self.tiles.get(self.next.fetch_add(1, Ordering::Relaxed)) {
+            let work = Arc::new(Work {
+                tile: *tile,
+                next_row: AtomicUsize::new(0),
+                pixels: Mutex::new((vec![PixelSum::zero(); tile.w * tile.h], 0)),
+                spent: AtomicU64::new(0),
+            });
+            in_flight.push(work.clone());
+            return Some(work);
+        }
+        in_flight.iter().filter(|work| work.rows_left() > 0).max_by_key(|work| work.rows_left()).cloned()
+    }
+
+    /// Stores the pixels of the row of the tile and the time it took, returns all the pixels
+    /// of the tile and the time of all its rows when it was the last row to finish.
+    pub(crate) fn finish_row(&self, work: &Work, row: usize, line: &[PixelSum], spent: Duration) -> Option<(Vec<PixelSum>, Duration)> {
+        work.spent.fetch_add(spent.as_nanos() as u64, Ordering::Relaxed);
+        let mut pixels = work.pixels.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (tile_pixels, finished) = &mut *pixels;
+        tile_pixels[row * work.tile.w..(row + 1) * work.tile.w].clone_from_slice(line);
+        *finished += 1;
+        if *finished < work.tile.h {
+            return None;
+        }
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        in_flight.retain(|other| !std::ptr::eq(Arc::as_ptr(other), work));
+        Some((mem::take(tile_pixels), Duration::from_nanos(work.spent.load(Ordering::Relaxed))))
+    }
+}
+
+impl Work {
+    /// The next row of the tile nobody has taken yet.
+    pub(crate) fn take_row(&self) -> Option<usize> {
+        let row = self.next_row.fetch_add(1, Ordering::Relaxed);
+        (row < self.tile.h).then_some(row)
+    }
+
+    fn rows_left(&self) -> usize {
+        self.tile.h.saturating_sub(self.next_row.load(Ordering::Relaxed))
+    }
+}
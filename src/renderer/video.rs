@@ -0,0 +1,96 @@
+/**
+This module writes the animations into the video files: the GIFs by itself
+and the rest with ffmpeg.
+
+An animation of a few thousand frames as separate images takes a lot of disk space
+and the time to write them and read them back. Instead ffmpeg runs as a child process
+and the raw RGB frames are piped into its standard input as soon as they are rendered.
+ffmpeg is not a dependency of the build, it is looked up in the `PATH` only when
+a video is written.
+*/
+use std::io::{Error, ErrorKind, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use crate::renderer::gif::Gif;
+use crate::renderer::output::Image;
+
+/// The outputs that are written as videos, ffmpeg picks the codec by the extension.
+const EXTENSIONS: [&str; 6] = ["gif", "mp4", "webm", "mkv", "mov", "avi"];
+
+pub(crate) fn is_video(path: &Path) -> bool {
+    extension(path).is_some_and(|ext| EXTENSIONS.contains(&ext.as_str()))
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase)
+}
+
+/// Writes the frames of an animation, as they are rendered, into a single file.
+pub(crate) trait VideoWriter {
+    fn write(&mut self, frame: &Image) -> Result<(), Error>;
+
+    /// Completes the file after the last frame.
+    ///
+    /// LEARN:
+    /// `self: Box<Self>` consumes the writer behind the `Box<dyn VideoWriter>`,
+    /// a plain `self` cannot be moved out of a trait object, its size is unknown.
+    fn finish(self: Box<Self>) -> Result<(), Error>;
+}
+
+/// Creates the writer of the video of `w`x`h` frames by the extension of the path.
+pub(crate) fn create(path: &Path, w: usize, h: usize, fps: usize) -> Result<Box<dyn VideoWriter>, Error> {
+    match extension(path).as_deref() {
+        Some("gif") => Ok(Box::new(Gif::create(path, w, h, fps)?)),
+        _ => Ok(Box::new(Ffmpeg::start(path, w, h, fps)?)),
+    }
+}
+
+struct Ffmpeg {
+    child: Child,
+    stdin: ChildStdin,
+}
+
+impl Ffmpeg {
+    /// Starts ffmpeg writing the video of `w`x`h` frames to the path.
+    fn start(path: &Path, w: usize, h: usize, fps: usize) -> Result<Ffmpeg, Error> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{}x{}", w, h), "-r", &fps.to_string(), "-i", "-"])
+            // yuv420p is what the players support, and it needs the even sizes
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|why| match why.kind() {
+                ErrorKind::NotFound => Error::new(ErrorKind::NotFound,
+                    "ffmpeg is not found, install it or write the frames as images"),
+                _ => why,
+            })?;
+        let stdin = child.stdin.take().ok_or_else(|| Error::other("cannot write to ffmpeg"))?;
+        Ok(Ffmpeg { child, stdin })
+    }
+}
+
+impl VideoWriter for Ffmpeg {
+    fn write(&mut self, frame: &Image) -> Result<(), Error> {
+        // LEARN:
+        // `[[u8; 3]]` is laid out in memory exactly like `[u8]` three times longer,
+        // `as_flattened` views it that way without copying the frame.
+        self.stdin.write_all(frame.pixels.as_flattened())
+    }
+
+    /// Closes the input of ffmpeg and waits until the video is written.
+    fn finish(self: Box<Self>) -> Result<(), Error> {
+        let Ffmpeg { mut child, stdin } = *self;
+        // ffmpeg finishes the video when its input is closed
+        drop(stdin);
+        let status = child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::other(format!("ffmpeg failed, {}", status)))
+        }
+    }
+}
@@ -0,0 +1,96 @@
+/**
+The watchdog of the pixels that take too long: `--pixel-rays <n>` and `--pixel-time <ms>`.
+
+A few pixels of a scene may cost orders of magnitude more than their neighbors,
+e.g. the ones that see a glass inside a glass, where every path bounces between
+the surfaces up to the max depth. The render waits for the slowest of them.
+With the budget a pixel stops taking the samples when it has traced that many rays
+(the shadow rays included) or spent that much time, and it is the average of the samples
+it took. The stopped pixels are logged, so the geometry that costs so much can be found.
+
+The stopped pixel is noisier than its neighbors, and the budget is a bias,
+the image depends on the speed of the machine when the time is limited.
+
+LEARN:
+The rays are counted in a counter of the thread (`thread_local!`, see `guard`),
+a pixel is rendered by a single thread from the start to the end, so the counter
+needs no atomic operations and no locks, the cheapest possible count.
+*/
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How many stopped pixels are logged, the rest are only counted.
+const LOGGED: u64 = 20;
+
+/// The limits of the work spent on a single pixel, none by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct PixelBudget {
+    /// The rays traced for the pixel, the camera rays, the bounces and the shadow rays.
+    pub(crate) rays: Option<u64>,
+    pub(crate) time: Option<Duration>,
+}
+
+impl PixelBudget {
+    pub(crate) fn is_limited(&self) -> bool {
+        self.rays.is_some() || self.time.is_some()
+    }
+
+    /// Starts the work on a new pixel of the thread.
+    pub(crate) fn start(&self) -> Watch {
+        RAYS.with(|rays| rays.set(0));
+        Watch { budget: *self, started: self.time.map(|_| Instant::now()) }
+    }
+}
+
+/// The work on a pixel watched against the budget.
+pub(crate) struct Watch {
+    budget: PixelBudget,
+    started: Option<Instant>,
+}
+
+impl Watch {
+    /// What of the budget the pixel has spent, `None` while it may take more samples.
+    pub(crate) fn exceeded(&self) -> Option<String> {
+        let rays = RAYS.with(Cell::get);
+        if let Some(limit) = self.budget.rays.filter(|&limit| rays >= limit) {
+            return Some(format!("{} rays of the budget of {}", rays, limit));
+        }
+        let (limit, started) = self.budget.time.zip(self.started)?;
+        let elapsed = started.elapsed();
+        (elapsed >= limit).then(|| format!("{} ms of the budget of {} ms, {} rays", elapsed.as_millis(), limit.as_millis(), rays))
+    }
+}
+
+/// Counts a ray traced on the thread.
+pub(crate) fn count_ray() {
+    RAYS.with(|rays| rays.set(rays.get() + 1));
+}
+
+/// The pixels of a pass over the image stopped by the budget.
+#[derive(Default)]
+pub(crate) struct Stopped {
+    count: AtomicU64,
+}
+
+impl Stopped {
+    /// Logs the pixel, the top-left is (0, 0), unless enough pixels were already logged.
+    pub(crate) fn report(&self, (x, y): (usize, usize), samples: usize, of: usize, spent: &str) {
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count <= LOGGED {
+            eprintln!("The pixel ({}, {}) stopped after {} of {} samples, {}.", x, y, samples, of, spent);
+        }
+        if count == LOGGED {
+            eprintln!("The further stopped pixels are only counted.");
+        }
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+thread_local! {
+    /// The rays traced for the pixel being rendered on the thread.
+    static RAYS: Cell<u64> = const { Cell::new(0) };
+}
@@ -0,0 +1,187 @@
+/**
+This module exports the image for the browser: the pyramid of the tiles and a tiny page
+that views them, e.g. `result_web/index.html` next to `result.png`, so the large render
+can be put on any static web server (or opened from the disk) and looked at closely
+without sending the whole file first.
+
+The level 0 is the image itself, every next level is half the size of the previous one,
+down to the one that fits a single tile. The page draws the smallest level at once
+and the tiles of the level that matches the zoom as they arrive, over it,
+so the image is there from the start and sharpens where it is looked at.
+The wheel zooms around the pointer and the drag pans the image.
+
+LEARN:
+The tiles are the PNG images of `png`, the browsers show them without any code.
+The page keeps the loaded tiles in a map by their names, so panning back
+to where the image was looked at before costs nothing.
+*/
+use std::io::Error;
+use std::path::Path;
+
+use crate::renderer::output::Image;
+use crate::renderer::png;
+
+/// The width and the height of the tiles, the last ones of the rows and the columns are smaller.
+const TILE_SIZE: usize = 256;
+
+/// Writes the tiles of all the levels of the image and the page into the directory,
+/// the directory is created, the files that are in it are replaced.
+pub(crate) fn export(image: &Image, dir: &Path) -> Result<(), Error> {
+    let mut level = Image { pixels: image.pixels.clone(), biases: Vec::new(), notes: Vec::new(), ..*image };
+    let mut levels = 0;
+    loop {
+        let level_dir = dir.join("tiles").join(levels.to_string());
+        std::fs::create_dir_all(&level_dir)?;
+        for ty in 0..level.h.div_ceil(TILE_SIZE) {
+            for tx in 0..level.w.div_ceil(TILE_SIZE) {
+                png::write(&level_dir.join(format!("{}_{}.png", tx, ty)), &tile(&level, tx, ty))?;
+            }
+        }
+        levels += 1;
+        if level.w <= TILE_SIZE && level.h <= TILE_SIZE {
+            break;
+        }
+        level = half(&level);
+    }
+    std::fs::write(dir.join("index.html"), page(image.w, image.h, levels))
+}
+
+/// The tile of the column and the row of the tiles of the image.
+fn tile(image: &Image, tx: usize, ty: usize) -> Image {
+    let (x0, y0) = (tx * TILE_SIZE, ty * TILE_SIZE);
+    let (w, h) = (TILE_SIZE.min(image.w - x0), TILE_SIZE.min(image.h - y0));
+    let pixels = (y0..y0 + h)
+        .flat_map(|y| image.pixels[y * image.w + x0..y * image.w + x0 + w].iter().copied())
+        .collect();
+    Image { w, h, pixels, biases: Vec::new(), notes: Vec::new(), ..*image }
+}
+
+/// The image half the size, every pixel is the average of the 2x2 ones, the odd last row
+/// and column are averaged with themselves. The encoded values are averaged, it is
+/// a little darker than averaging the light, but the levels are only seen on the way in.
+fn half(image: &Image) -> Image {
+    let (w, h) = (image.w.div_ceil(2), image.h.div_ceil(2));
+    let pixel = |x: usize, y: usize| image.pixels[y.min(image.h - 1) * image.w + x.min(image.w - 1)];
+    let pixels = (0..w * h)
+        .map(|i| {
+            let (x, y) = (2 * (i % w), 2 * (i / w));
+            let quad = [pixel(x, y), pixel(x + 1, y), pixel(x, y + 1), pixel(x + 1, y + 1)];
+            [0, 1, 2].map(|c| ((quad.iter().map(|p| p[c] as u32).sum::<u32>() + 2) / 4) as u8)
+        })
+        .collect();
+    Image { w, h, pixels, biases: Vec::new(), notes: Vec::new(), ..*image }
+}
+
+/// The page of the viewer of the image of the size and the number of the levels.
+fn page(w: usize, h: usize, levels: usize) -> String {
+    VIEWER
+        .replace("{w}", &w.to_string())
+        .replace("{h}", &h.to_string())
+        .replace("{levels}", &levels.to_string())
+        .replace("{tile}", &TILE_SIZE.to_string())
+}
+
+const VIEWER: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{w}x{h}</title>
+<style>
+html, body { margin: 0; height: 100%; overflow: hidden; background: #202020; }
+canvas { display: block; cursor: grab; }
+</style>
+</head>
+<body>
+<canvas id="view"></canvas>
+<script>
+const W = {w}, H = {h}, LEVELS = {levels}, TILE = {tile};
+const canvas = document.getElementById("view");
+const context = canvas.getContext("2d");
+const tiles = new Map();
+// the image pixels per screen pixel, and the image point at the top-left corner
+let scale = 1, left = 0, top = 0;
+
+function tile(level, x, y) {
+    const name = level + "/" + x + "_" + y;
+    let image = tiles.get(name);
+    if (!image) {
+        image = new Image();
+        image.onload = draw;
+        image.src = "tiles/" + name + ".png";
+        tiles.set(name, image);
+    }
+    return image.complete && image.naturalWidth > 0 ? image : null;
+}
+
+function drawLevel(level) {
+    const size = TILE * Math.pow(2, level);
+    const [x0, y0] = [Math.max(0, Math.floor(left / size)), Math.max(0, Math.floor(top / size))];
+    const x1 = Math.min(Math.ceil(W / size), Math.ceil((left + canvas.width * scale) / size));
+    const y1 = Math.min(Math.ceil(H / size), Math.ceil((top + canvas.height * scale) / size));
+    for (let y = y0; y < y1; y++) {
+        for (let x = x0; x < x1; x++) {
+            const image = tile(level, x, y);
+            if (image) {
+                const [sx, sy] = [(x * size - left) / scale, (y * size - top) / scale];
+                const [sw, sh] = [image.naturalWidth * size / TILE / scale, image.naturalHeight * size / TILE / scale];
+                context.drawImage(image, sx, sy, sw + 0.5, sh + 0.5);
+            }
+        }
+    }
+}
+
+function draw() {
+    context.clearRect(0, 0, canvas.width, canvas.height);
+    context.imageSmoothingEnabled = scale > 1;
+    // the smallest level is always there, the one of the zoom is drawn over it as it loads
+    const level = Math.max(0, Math.min(LEVELS - 1, Math.floor(Math.log2(Math.max(scale, 1)))));
+    drawLevel(LEVELS - 1);
+    if (level < LEVELS - 1) {
+        drawLevel(level);
+    }
+}
+
+function fit() {
+    canvas.width = window.innerWidth;
+    canvas.height = window.innerHeight;
+    scale = Math.max(W / canvas.width, H / canvas.height);
+    left = (W - canvas.width * scale) / 2;
+    top = (H - canvas.height * scale) / 2;
+    draw();
+}
+
+canvas.addEventListener("wheel", event => {
+    event.preventDefault();
+    const factor = Math.pow(1.0015, event.deltaY);
+    const [x, y] = [left + event.offsetX * scale, top + event.offsetY * scale];
+    scale = Math.min(Math.max(scale * factor, 1 / 32), 2 * Math.max(W, H));
+    left = x - event.offsetX * scale;
+    top = y - event.offsetY * scale;
+    draw();
+}, { passive: false });
+
+let drag = null;
+canvas.addEventListener("pointerdown", event => {
+    drag = [event.clientX, event.clientY];
+    canvas.setPointerCapture(event.pointerId);
+    canvas.style.cursor = "grabbing";
+});
+canvas.addEventListener("pointermove", event => {
+    if (drag) {
+        left -= (event.clientX - drag[0]) * scale;
+        top -= (event.clientY - drag[1]) * scale;
+        drag = [event.clientX, event.clientY];
+        draw();
+    }
+});
+canvas.addEventListener("pointerup", () => {
+    drag = null;
+    canvas.style.cursor = "grab";
+});
+canvas.addEventListener("dblclick", fit);
+window.addEventListener("resize", fit);
+fit();
+</script>
+</body>
+</html>
+"#;
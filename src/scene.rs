@@ -1,16 +1,50 @@
 use surfaces::world::World;
-use crate::scene::camera::Camera;
+use crate::scene::arena::Id;
+use crate::scene::camera::{Camera, CameraSetup, RaySource};
+use crate::scene::check::Diagnostic;
+use crate::scene::surfaces::hitable::Hitable;
+use crate::scene::surfaces::Surface;
 
+pub(crate) mod animation;
+pub(crate) mod arena;
 pub(crate) mod builder;
 pub mod surfaces;
 pub mod material;
 pub(crate) mod camera;
+pub(crate) mod check;
+pub(crate) mod conductor;
+pub(crate) mod diff;
+pub(crate) mod drop;
+pub(crate) mod edit;
+pub(crate) mod environment;
+pub(crate) mod file;
+pub(crate) mod fog;
+pub(crate) mod hdr;
+pub(crate) mod light;
+pub(crate) mod obj;
+pub(crate) mod ply;
+pub(crate) mod portal;
+pub(crate) mod schema;
+#[cfg(feature = "script")]
+pub(crate) mod script;
+pub(crate) mod shader;
+pub(crate) mod sky;
+pub(crate) mod stars;
+pub(crate) mod texture;
+pub(crate) mod waves;
 
+#[derive(Clone)]
 pub(crate) struct Scene {
     pub(crate) camera: Camera,
     pub(crate) world: World,
     pub(crate) w: i32,
     pub(crate) h: i32,
+    /// Width of a pixel relative to its height when the image is displayed,
+    /// e.g. 2 for the anamorphic lenses that squeeze the image twice horizontally.
+    pub(crate) pixel_aspect: f32,
+    /// How the built-in scene was made, noted in the image so it can be made again,
+    /// e.g. `random-night:blue whale`, the scene and the seed of its random spheres.
+    pub(crate) origin: Option<String>,
 }
 
 impl Scene {
@@ -20,4 +54,47 @@ impl Scene {
     pub(crate) fn world(&self) -> &World {
         &self.world
     }
+
+    /// The objects of the name or the tag given in the scene file, the handles to change them
+    /// before the render, e.g. to render the same scene with several materials of the hero:
+    ///
+    /// ```text
+    /// for id in scene.find("hero") {
+    ///     let gold = scene.world.add_material(Material::metal(Vec3::new(1., 0.78, 0.34), 0.1));
+    ///     scene.world.set_material(id, gold);
+    /// }
+    /// ```
+    pub(crate) fn find(&self, name: &str) -> Vec<Id<Surface>> {
+        self.world.find(name)
+    }
+
+    /// Adds the objects, the materials and the lights of the other scene to this one,
+    /// e.g. of the rig of the lights shared by many scenes, see `World::merge`.
+    /// The camera and the size of the image of this one stay, the files layered over
+    /// the scene replace them by their own, see `file::layer`.
+    pub(crate) fn merge(&mut self, other: Scene) {
+        self.world.merge(other.world);
+    }
+
+    /// The problems of the scene that loaded: the materials of the parameters out of their range
+    /// (the fuzz above 1, the index of refraction 0), the camera that looks at itself,
+    /// the errors would render NaNs, see `check`.
+    pub(crate) fn validate(&self) -> Vec<Diagnostic> {
+        check::check_scene(self)
+    }
+
+    /// The focus distance that makes sharp the surface seen at the point (u, v) of the image,
+    /// both 0..1 from the bottom-left corner. `None` when there is only the sky.
+    pub(crate) fn focus_distance_at(&self, u: f32, v: f32, aspect: f32) -> Option<f32> {
+        let setup = self.camera.setup();
+        // the ray through the center of the lens, the aperture would scatter it
+        let pinhole = CameraSetup { aperture: 0., ..setup.clone() }.build(aspect);
+        let hit = self.world.hit(&pinhole.get_ray(u, v), 0.001, f32::MAX)?;
+        Some(setup.distance_along_view(&hit.p))
+    }
+
+    /// The aspect ratio of the displayed image, the camera is built for it.
+    pub(crate) fn aspect(&self) -> f32 {
+        self.w as f32 * self.pixel_aspect / self.h as f32
+    }
 }
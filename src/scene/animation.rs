@@ -0,0 +1,354 @@
+/**
+The animations of the scene: the camera moves over the frames, and the objects
+with the `Motion` of the simulation (see `drop`) or the changes of the script.
+
+The turntable is the usual preview of an asset: the camera orbits the subject once
+around the up axis through `look_at`, so every side of it is seen.
+
+The flight (`--camera-path flight.toml`) moves the camera along a smooth curve
+while it keeps looking at the target, a point or a curve of its own:
+
+```toml
+frames = 240
+fps = 30                # optional, 24 by default
+# the positions of the camera, the curve goes through them (catmull-rom), or the Bezier
+# spans through every third one pulled by the two between (bezier): 4, 7, 10... points
+curve = "catmull-rom"   # optional
+positions = [[0, 1, 6], [5, 2, 2], [0, 3, -5], [-5, 2, 1]]
+# optional, the point the camera looks at, or the curve of the points it follows,
+# `look_at` of the camera of the scene by default
+look_at = [0, 0, -1]
+# optional, how the camera starts and stops: linear, ease-in, ease-out or ease-in-out
+easing = "ease-in-out"
+```
+
+The camera keeps the field of view and the aperture of the scene, its focus follows
+the target, so the target stays sharp.
+
+The shutter (`--shutter 0.5`) is open for the share of the time between the frames
+and blurs what moves meanwhile. The objects move straight from where they are when
+it opens to where they are when it closes, whatever moves them, the `Motion` or
+the script, and every ray sees them at a random moment, so the frame is rendered once
+and the blur is smooth, see `World::set_motion`. The camera on a path is blurred
+by the average of the images at the moments while the shutter is open instead, every one
+with its share of the samples. Without the shutter every frame is the sharp moment,
+and the fast motion strobes.
+
+LEARN:
+The curves are walked at the constant speed: the parameter of a spline runs faster
+where its points are far apart, so the table of the lengths along the curve maps
+the share of the way to the parameter, and only the easing changes the speed.
+*/
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::math::vec::Vec3;
+use crate::scene::arena::Id;
+use crate::scene::camera::{Camera, CameraSetup};
+use crate::scene::surfaces::Surface;
+#[cfg(feature = "script")]
+use crate::scene::script::Script;
+use crate::toml::{self, integer, optional, string, Table, Value};
+
+#[derive(Clone, Debug)]
+pub(crate) struct Animation {
+    pub(crate) frames: usize,
+    /// Frames per second of the video.
+    pub(crate) fps: usize,
+    pub(crate) path: CameraPath,
+    /// The share of the time between the frames the shutter is open, see the module,
+    /// `None` or 0 for the sharp frames.
+    pub(crate) shutter: Option<f32>,
+    /// Moves the objects of the scene over the frames.
+    pub(crate) motion: Option<Arc<Motion>>,
+    /// Changes the scene for every frame, see `script`.
+    #[cfg(feature = "script")]
+    pub(crate) script: Option<Arc<Script>>,
+}
+
+/// The objects that move over the animation: their offsets from where they are
+/// in the scene at every frame, in between the frames they move straight.
+#[derive(Debug)]
+pub(crate) struct Motion {
+    objects: Vec<Id<Surface>>,
+    /// The offsets of all the objects at every frame.
+    offsets: Vec<Vec<Vec3>>,
+}
+
+/// How the camera moves from the first frame to the last one.
+#[derive(Clone, Debug)]
+pub(crate) enum CameraPath {
+    /// The camera of the scene stays, e.g. for the script that moves the objects.
+    Still,
+    /// One full orbit around `look_at`, the last frame is followed by the first one
+    /// when the video loops.
+    Turntable,
+    /// Along the curve, from its start at the first frame to its end at the last one.
+    Flight(Flight),
+}
+
+/// The camera along the curve of the positions, looking at the target, see the module.
+#[derive(Clone, Debug)]
+pub(crate) struct Flight {
+    positions: Track,
+    /// The point or the curve the camera looks at, `look_at` of the scene without it.
+    look_at: Option<Track>,
+    easing: Easing,
+}
+
+/// How the camera speeds up and slows down along the flight.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Easing {
+    Linear,
+    /// Starts still and speeds up.
+    EaseIn,
+    /// Slows down to stop at the end.
+    EaseOut,
+    EaseInOut,
+}
+
+/// The spans of the cubic Bezier curves one after another, walked at the constant speed.
+#[derive(Clone, Debug)]
+struct Track {
+    spans: Vec<[Vec3; 4]>,
+    /// The length of the curve from its start to every step of every span, see `STEPS`.
+    lengths: Vec<f32>,
+}
+
+/// Every span is measured by this many straight steps.
+const STEPS: usize = 32;
+
+impl Animation {
+    pub(crate) const DEFAULT_FPS: usize = 24;
+
+    pub(crate) fn turntable(frames: usize) -> Animation {
+        Animation::new(frames, Animation::DEFAULT_FPS, CameraPath::Turntable)
+    }
+
+    pub(crate) fn still(frames: usize) -> Animation {
+        Animation::new(frames, Animation::DEFAULT_FPS, CameraPath::Still)
+    }
+
+    fn new(frames: usize, fps: usize, path: CameraPath) -> Animation {
+        Animation {
+            frames,
+            fps,
+            path,
+            shutter: None,
+            motion: None,
+            #[cfg(feature = "script")]
+            script: None,
+        }
+    }
+
+    /// The flight of the file, see the module.
+    pub(crate) fn flight(path: &Path) -> Result<Animation, String> {
+        let text = std::fs::read_to_string(path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+        let doc = toml::parse(&text).map_err(|why| format!("{}: syntax error at {}", path.display(), why))?;
+        parse_flight(&doc).map_err(|why| format!("{}: {}", path.display(), why))
+    }
+
+    /// Whether the path moves the camera over the frames (not the script, which may move it too).
+    pub(crate) fn moves_camera(&self) -> bool {
+        !matches!(self.path, CameraPath::Still)
+    }
+
+    /// Whether the animation changes the world of the scene, not only its camera.
+    pub(crate) fn moves_objects(&self) -> bool {
+        #[cfg(feature = "script")]
+        if self.script.is_some() {
+            return true;
+        }
+        self.motion.is_some()
+    }
+
+    /// The camera at the time in the frames, 0 is the camera of the scene for the turntable
+    /// and the start of the curve for the flight, the fractions are in between the frames.
+    pub(crate) fn camera(&self, camera: &Camera, frame: f32, aspect: f32) -> Camera {
+        let setup = camera.setup();
+        match &self.path {
+            CameraPath::Still => camera.with_aspect(aspect),
+            CameraPath::Turntable => {
+                let angle = 2. * PI * frame / self.frames.max(1) as f32;
+                let look_from = &setup.look_at + rotate(&(&setup.look_from - &setup.look_at), &setup.up.unit(), angle);
+                CameraSetup { look_from, ..setup }.build(aspect)
+            }
+            CameraPath::Flight(flight) => {
+                // the last frame is at the end of the curve, the flight does not loop
+                let share = flight.easing.apply(frame / (self.frames.max(2) - 1) as f32);
+                let look_from = flight.positions.at(share);
+                let look_at = flight.look_at.as_ref().map_or(setup.look_at.clone(), |track| track.at(share));
+                let focus_dist = (&look_at - &look_from).length();
+                CameraSetup { look_from, look_at, focus_dist, ..setup }.build(aspect)
+            }
+        }
+    }
+}
+
+impl Motion {
+    /// The motion of the objects by their offsets at every frame, each frame has
+    /// the offset of every object.
+    pub(crate) fn new(objects: Vec<Id<Surface>>, offsets: Vec<Vec<Vec3>>) -> Motion {
+        debug_assert!(offsets.iter().all(|frame| frame.len() == objects.len()));
+        Motion { objects, offsets }
+    }
+
+    /// The objects and their offsets at the time in the frames, the last frame stays.
+    pub(crate) fn at(&self, frame: f32) -> impl Iterator<Item = (Id<Surface>, Vec3)> + '_ {
+        let last = self.offsets.len().saturating_sub(1);
+        let before = (frame.max(0.) as usize).min(last);
+        let after = (before + 1).min(last);
+        let within = (frame - before as f32).clamp(0., 1.);
+        self.objects.iter().enumerate().map(move |(i, &id)| {
+            let (from, to) = (&self.offsets[before][i], &self.offsets[after][i]);
+            (id, from + &(within * &(to - from)))
+        })
+    }
+}
+
+fn parse_flight(doc: &Table) -> Result<Animation, String> {
+    if let Some(key) = doc.keys().find(|key| !["frames", "fps", "curve", "positions", "look_at", "easing"].contains(key)) {
+        return Err(format!("unknown key `{}`", key));
+    }
+    let frames = match integer(doc, "frames")? {
+        frames if frames >= 1 => frames as usize,
+        frames => return Err(format!("frames {} must be at least 1", frames)),
+    };
+    let fps = match optional(doc, "fps", integer)? {
+        Some(fps) if fps >= 1 => fps as usize,
+        Some(fps) => return Err(format!("fps {} must be at least 1", fps)),
+        None => Animation::DEFAULT_FPS,
+    };
+    let catmull_rom = match optional(doc, "curve", string)?.as_deref() {
+        None | Some("catmull-rom") => true,
+        Some("bezier") => false,
+        Some(other) => return Err(format!("unknown curve `{}`, catmull-rom or bezier", other)),
+    };
+    let easing = match optional(doc, "easing", string)? {
+        Some(name) => Easing::parse(&name).ok_or(format!("unknown easing `{}`, linear, ease-in, ease-out or ease-in-out", name))?,
+        None => Easing::EaseInOut,
+    };
+    let curve = |key: &str, value: &Value| -> Result<Track, String> {
+        let points = points(value).ok_or(format!("`{}` must be the arrays of 3 numbers", key))?;
+        match catmull_rom {
+            true if points.len() >= 2 => Ok(Track::catmull_rom(&points)),
+            false if points.len() >= 4 && (points.len() - 1).is_multiple_of(3) => Ok(Track::bezier(&points)),
+            true => Err(format!("`{}` must have at least 2 points", key)),
+            false => Err(format!("`{}` of the Bezier curve must have 4, 7, 10... points", key)),
+        }
+    };
+    let positions = curve("positions", doc.get("positions").ok_or("missing `positions`")?)?;
+    // a single point is the target that stands still
+    let look_at = match doc.get("look_at") {
+        Some(value) if value.as_array().is_some_and(|items| items.iter().all(|item| item.as_f64().is_some())) =>
+            Some(Track::still(point(value).ok_or("`look_at` must be the array of 3 numbers")?)),
+        Some(value) => Some(curve("look_at", value)?),
+        None => None,
+    };
+    Ok(Animation::new(frames, fps, CameraPath::Flight(Flight { positions, look_at, easing })))
+}
+
+/// The point of the array of 3 numbers.
+fn point(value: &Value) -> Option<Vec3> {
+    match value.as_array()?.iter().map(Value::as_f64).collect::<Option<Vec<f64>>>()?.as_slice() {
+        &[x, y, z] => Some(Vec3::new(x as f32, y as f32, z as f32)),
+        _ => None,
+    }
+}
+
+/// The points of the array of the arrays of 3 numbers.
+fn points(value: &Value) -> Option<Vec<Vec3>> {
+    value.as_array()?.iter().map(point).collect()
+}
+
+impl Easing {
+    fn parse(name: &str) -> Option<Easing> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease-in" => Some(Easing::EaseIn),
+            "ease-out" => Some(Easing::EaseOut),
+            "ease-in-out" => Some(Easing::EaseInOut),
+            _ => None,
+        }
+    }
+
+    /// The share of the way at the share of the time, both 0..1.
+    fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2. - t),
+            Easing::EaseInOut => t * t * (3. - 2. * t),
+        }
+    }
+}
+
+impl Track {
+    /// The point that stands still.
+    fn still(point: Vec3) -> Track {
+        Track::new(vec![[point.clone(), point.clone(), point.clone(), point]])
+    }
+
+    /// The curve through all the points, every one of them is passed in the direction
+    /// from the previous point to the next one (the uniform Catmull-Rom spline).
+    fn catmull_rom(points: &[Vec3]) -> Track {
+        let n = points.len();
+        // the ends are repeated, the curve starts and ends towards its neighbor
+        let point = |i: isize| &points[i.clamp(0, n as isize - 1) as usize];
+        let spans = (0..n as isize - 1)
+            .map(|i| {
+                let [p0, p1, p2, p3] = [point(i - 1), point(i), point(i + 1), point(i + 2)];
+                [p1.clone(), p1 + &((p2 - p0) / 6.), p2 - &((p3 - p1) / 6.), p2.clone()]
+            })
+            .collect();
+        Track::new(spans)
+    }
+
+    /// The Bezier spans of 4, 7, 10... points, the last point of a span is the first of the next.
+    fn bezier(points: &[Vec3]) -> Track {
+        Track::new(points.windows(4).step_by(3)
+            .map(|span| [span[0].clone(), span[1].clone(), span[2].clone(), span[3].clone()])
+            .collect())
+    }
+
+    fn new(spans: Vec<[Vec3; 4]>) -> Track {
+        let mut lengths = vec![0.];
+        let mut last = spans[0][0].clone();
+        for span in &spans {
+            for step in 1..=STEPS {
+                let point = bezier(span, step as f32 / STEPS as f32);
+                lengths.push(lengths[lengths.len() - 1] + (&point - &last).length());
+                last = point;
+            }
+        }
+        Track { spans, lengths }
+    }
+
+    /// The point at the share of the length of the curve, 0..1.
+    fn at(&self, share: f32) -> Vec3 {
+        let total = self.lengths[self.lengths.len() - 1];
+        let length = share.clamp(0., 1.) * total;
+        // the step the length falls in, and how far in it
+        let step = self.lengths.partition_point(|&l| l <= length).clamp(1, self.lengths.len() - 1);
+        let (from, to) = (self.lengths[step - 1], self.lengths[step]);
+        let within = if to > from { (length - from) / (to - from) } else { 0. };
+        let position = (step - 1) as f32 + within;
+        let span = ((position / STEPS as f32) as usize).min(self.spans.len() - 1);
+        bezier(&self.spans[span], position / STEPS as f32 - span as f32)
+    }
+}
+
+/// The point of the cubic Bezier span at 0..1.
+fn bezier([p0, p1, p2, p3]: &[Vec3; 4], s: f32) -> Vec3 {
+    let r = 1. - s;
+    r * r * r * p0 + 3. * r * r * s * p1 + 3. * r * s * s * p2 + s * s * s * p3
+}
+
+/// Rotates the vector around the unit axis by the angle in radians, counterclockwise
+/// when the axis points to the viewer (Rodrigues' rotation formula).
+fn rotate(v: &Vec3, axis: &Vec3, angle: f32) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    cos * v + sin * Vec3::cross(axis, v) + (1. - cos) * Vec3::dot(axis, v) * axis
+}
@@ -0,0 +1,101 @@
+/**
+A typed arena: the items of one kind live in a single vector and refer to each other by index.
+
+The big procedural scenes have millions of objects. With a separate heap allocation per object
+the allocator overhead and the fragmentation grow with the scene, and the objects end up
+scattered over the memory. In the arena they are packed together, and an index (4 bytes)
+is smaller than a pointer (8 bytes) or a `Box`.
+
+LEARN:
+The arena is also the common way in Rust to build the graphs of objects that refer to each other
+(e.g. many surfaces sharing one material) without the reference counting and the lifetimes.
+*/
+use std::marker::PhantomData;
+use std::mem::size_of;
+
+#[derive(Clone)]
+pub(crate) struct Arena<T> {
+    items: Vec<T>,
+}
+
+/// The index of an item in the `Arena<T>`.
+///
+/// LEARN:
+/// `PhantomData<fn() -> T>` makes the id typed, so the id of a material cannot be used
+/// to look up a surface, while the id stays `Copy`, `Send` and `Sync` whatever `T` is.
+pub(crate) struct Id<T> {
+    index: u32,
+    kind: PhantomData<fn() -> T>,
+}
+
+// LEARN:
+// `#[derive(Clone, Copy)]` would require `T: Copy`, although only the index is copied.
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Id<T> {}
+
+impl<T> std::fmt::Debug for Id<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+impl<T> Id<T> {
+    pub(crate) fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    /// The id of the item after the items of another arena are put before it, see `Arena::into_vec`.
+    pub(crate) fn shifted(&self, by: usize) -> Id<T> {
+        let index = u32::try_from(self.index() + by).expect("more than 4 billion items in the arena");
+        Id { index, kind: PhantomData }
+    }
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn new() -> Arena<T> {
+        Arena { items: Vec::new() }
+    }
+
+    /// Moves the item into the arena and returns its id.
+    pub(crate) fn alloc(&mut self, item: T) -> Id<T> {
+        let index = u32::try_from(self.items.len()).expect("more than 4 billion items in the arena");
+        self.items.push(item);
+        Id { index, kind: PhantomData }
+    }
+
+    pub(crate) fn get(&self, id: Id<T>) -> &T {
+        &self.items[id.index()]
+    }
+
+    pub(crate) fn get_mut(&mut self, id: Id<T>) -> &mut T {
+        &mut self.items[id.index()]
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub(crate) fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    /// The items in the order they were allocated, e.g. to move them into another arena.
+    pub(crate) fn into_vec(self) -> Vec<T> {
+        self.items
+    }
+
+    /// The ids of all the items, in the order they were allocated.
+    pub(crate) fn ids(&self) -> impl Iterator<Item = Id<T>> {
+        (0..self.items.len() as u32).map(|index| Id { index, kind: PhantomData })
+    }
+
+    /// The bytes held by the arena, including the reserved capacity.
+    pub(crate) fn memory(&self) -> usize {
+        self.items.capacity() * size_of::<T>()
+    }
+}
@@ -1,10 +1,21 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::scene::arena::Id;
 use crate::scene::camera::Camera;
 use crate::math::color::Color;
-use crate::math::rand::drand32;
+use crate::math::rand::{self, drand32};
+use crate::scene::environment::Environment;
+use crate::scene::fog::Fog;
 use crate::scene::material::Material;
 use crate::scene::Scene;
+use crate::scene::stars::Stars;
+use crate::scene::surfaces::mesh::Mesh;
+use crate::scene::surfaces::volume::Volume;
 use crate::scene::surfaces::Surface;
 use crate::scene::surfaces::world::World;
+use crate::scene::texture::procedural::Procedural;
+use crate::scene::texture::{ImageTexture, Texture};
 use crate::math::vec::Vec3;
 
 pub(crate) trait SceneBuilder {
@@ -13,14 +24,55 @@ pub(crate) trait SceneBuilder {
 
 pub(crate) enum BuiltIn {
     Default,
-    Random,
+    /// The spheres of the cover of the first book in the theme, placed by the seed text
+    /// when there is one, see `seeded`.
+    Random(Theme, Option<String>),
+    /// The textured globe of the second book.
+    Earth,
+    /// The cards labeled by their distances from the camera, for trying out the aperture
+    /// and the focus distance.
+    FocusChart,
+    /// The sphere of the material in the uniform white light, see `renderer::furnace`.
+    Furnace(Material),
+}
+
+/// The lighting and the atmosphere of the random scene, the spheres are the same.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Theme {
+    /// The cover of the first book under the blue sky.
+    Day,
+    /// The starry sky, some of the spheres glow like the lanterns and light the others.
+    Night,
+    /// The mist over the ground, a constant medium, and the haze that hides the far spheres.
+    Foggy,
+    /// The spheres on the checker floor before the cyclorama, lit by two softboxes, the glowing panels.
+    Studio,
+}
+
+impl Theme {
+    /// The name of the random scene of the theme for `--scene`.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Theme::Day => "random",
+            Theme::Night => "random-night",
+            Theme::Foggy => "random-foggy",
+            Theme::Studio => "random-studio",
+        }
+    }
+
+    pub(crate) fn parse(name: &str) -> Option<Theme> {
+        [Theme::Day, Theme::Night, Theme::Foggy, Theme::Studio].into_iter().find(|theme| theme.name() == name)
+    }
 }
 
 impl SceneBuilder for BuiltIn {
     fn build(&self) -> Scene {
         match self {
             BuiltIn::Default => self.buidl_default_scene(),
-            BuiltIn::Random => self.build_random_scene()
+            BuiltIn::Random(theme, seed) => self.build_random_scene(*theme, seed.as_deref()),
+            BuiltIn::Earth => self.build_earth_scene(),
+            BuiltIn::FocusChart => self.build_focus_chart(),
+            BuiltIn::Furnace(material) => self.build_furnace(material.clone()),
         }
     }
 }
@@ -30,28 +82,120 @@ impl BuiltIn {
     pub(crate) fn default() -> BuiltIn {
         BuiltIn::Default
     }
-    pub(crate) fn random() -> BuiltIn {
-        BuiltIn::Random
+    pub(crate) fn themed(theme: Theme) -> BuiltIn {
+        BuiltIn::Random(theme, None)
+    }
+    /// The random scene of the theme whose spheres are placed by the seed, any text,
+    /// e.g. `blue whale`. The same text makes the same scene on any machine,
+    /// and the image notes it, see `Scene::origin`.
+    pub(crate) fn seeded(theme: Theme, seed: &str) -> BuiltIn {
+        BuiltIn::Random(theme, Some(seed.to_string()))
+    }
+    pub(crate) fn earth() -> BuiltIn {
+        BuiltIn::Earth
+    }
+    pub(crate) fn focus_chart() -> BuiltIn {
+        BuiltIn::FocusChart
+    }
+    pub(crate) fn furnace(material: Material) -> BuiltIn {
+        BuiltIn::Furnace(material)
     }
 
     fn buidl_default_scene(&self) -> Scene {
         Scene {
-            camera: Camera::static_camera(),
+            camera: Camera::static_camera(2.),
             world: self.create_default_world(),
             w: 1024,
             h: 512,
+            pixel_aspect: 1.,
+            origin: None,
         }
     }
 
-    fn build_random_scene(&self) -> Scene {
+    fn build_random_scene(&self, theme: Theme, seed: Option<&str>) -> Scene {
         let w = 1024;
         let h = 512;
+        // without the seed the spheres are where the generator of the thread starts,
+        // the same for every run
+        if let Some(seed) = seed {
+            rand::seed(rand::text_seed(seed));
+        }
+        let (world, subject) = self.create_random_world(theme);
         Scene {
-            camera: self.positionable_camera(w, h),
-            world: self.create_random_world(),
-            w: w,
-            h: h,
+            camera: self.positionable_camera(w, h).focus_on(world.object(subject)),
+            world,
+            w,
+            h,
+            pixel_aspect: 1.,
+            origin: seed.map(|seed| format!("{}:{}", theme.name(), seed)),
+        }
+    }
+
+    /// The map is read from `textures/earthmap.ppm` in the working directory,
+    /// without it the sphere shows the grid of the latitudes and the longitudes.
+    fn build_earth_scene(&self) -> Scene {
+        let (w, h) = (1024, 512);
+        let map = Path::new("textures/earthmap.ppm");
+        let image = ImageTexture::load(map).unwrap_or_else(|why| {
+            eprintln!("Cannot read {} ({}), the grid is rendered instead.", map.display(), why);
+            ImageTexture::grid(720, 360)
+        });
+
+        let mut world = World::new();
+        let earth = world.add_material(Material::textured(Vec3::rgb(1., 1., 1.), Texture::Image(Arc::new(image))));
+        world.add(Surface::sphere(Vec3::new(0., 0., 0.), 2., earth));
+
+        let look_from = Vec3::new(0., 0., 12.);
+        let look_at = Vec3::new(0., 0., 0.);
+        let dist_to_focus = (&look_from - &look_at).length();
+        Scene {
+            camera: Camera::positionable(look_from, look_at, Vec3::new(0., 1., 0.), 20., w as f32 / h as f32, 0., dist_to_focus),
+            world,
+            w,
+            h,
+            pixel_aspect: 1.,
+            origin: None,
+        }
+    }
+
+    /// The cards at the distances 1 to 10 along the view, each labeled by its distance
+    /// and as large as the distance, so they all look the same size. They step from the left
+    /// to the right and up and down, none hides another. The camera is focused on the card 5,
+    /// the render notes the distance in focus in the image, so the sharp card can be checked.
+    fn build_focus_chart(&self) -> Scene {
+        let (w, h) = (1024, 512);
+        let mut world = World::new();
+        checker_ground(&mut world, 0., 0.5, [Vec3::rgb(0.6, 0.6, 0.6), Vec3::rgb(0.25, 0.25, 0.25)]);
+        let eye = Vec3::new(0., 1., 0.);
+        for distance in 1..=CHART_MARKERS {
+            let label = distance.to_string();
+            let card = ImageTexture::label(&label, 8);
+            let material = world.add_material(Material::textured(Vec3::rgb(1., 1., 1.), Texture::Image(Arc::new(card))));
+            // the directions to the centers of the cards, as seen from the camera
+            let across = -0.4 + 0.8 * (distance - 1) as f32 / (CHART_MARKERS - 1) as f32;
+            let above = if distance % 2 == 0 { 0.07 } else { -0.07 };
+            let d = distance as f32;
+            let height = 0.08 * d;
+            let width = height * (4 * label.len() + 1) as f32 / 7.;
+            let center = &eye + d * Vec3::new(across, above, -1.);
+            world.add(Surface::mesh(panel(center, Vec3::new(width, 0., 0.), Vec3::new(0., height, 0.)), material));
         }
+        let look_at = Vec3::new(0., 1., -1.);
+        let camera = Camera::positionable(eye, look_at, Vec3::new(0., 1., 0.), 30., w as f32 / h as f32, 0.1, CHART_FOCUS);
+        Scene { camera, world, w, h, pixel_aspect: 1., origin: None }
+    }
+
+    /// The sphere of the material lit by the environment of the radiance 1 from everywhere,
+    /// seen so close that it fills the whole image, of any aspect up to 2.
+    fn build_furnace(&self, material: Material) -> Scene {
+        let (w, h) = (128, 128);
+        let mut world = World::new();
+        let material = world.add_material(material);
+        world.add(Surface::sphere(Vec3::new(0., 0., 0.), 1., material));
+        world.set_environment(Environment::new(1, 1, vec![Vec3::rgb(1., 1., 1.)]));
+        let look_from = Vec3::new(0., 0., 4.);
+        let camera = Camera::positionable(look_from, Vec3::zero(), Vec3::new(0., 1., 0.), 10., w as f32 / h as f32, 0., 4.);
+        Scene { camera, world, w, h, pixel_aspect: 1., origin: None }
     }
 
     fn positionable_camera(&self, w: i32, h: i32) -> Camera {
@@ -60,6 +204,7 @@ impl BuiltIn {
         // Rust does not allow the implicit cast, so this is not a problem in the code review.
         let look_from = Vec3::new(3., 3., 2.);
         let look_at = Vec3::new(0., 0., -1.);
+        // refocused on the subject when the world is built
         let dist_to_focus = (&look_from - &look_at).length();
         let up = Vec3::new(0., 1., 0.);
         let fov = 20.;
@@ -80,59 +225,57 @@ impl BuiltIn {
 
         let mut world = World::new();
 
-        world.add(
-            Surface::sphere(
-                Vec3::new(0.0, 0.0, -1.0),
-                0.5,
-                Material::lambertian(Vec3::rgb(0.1, 0.2, 0.5))));
-        world.add(
-            Surface::sphere(
-                Vec3::new(0.0, -100.5, -1.0),
-                100.0,
-                Material::lambertian(Vec3::rgb(0.8, 0.8, 0.0))));
-        world.add(
-            Surface::sphere(
-                Vec3::new(1.0, 0.0, -1.0),
-                0.5,
-                Material::metal(Vec3::rgb(0.8, 0.6, 0.2), 0.2)));
-        world.add(
-            Surface::sphere(
-                Vec3::new(-1.0, 0.0, -1.0),
-                0.5,
-                Material::dielectric(1.5)));
+        let blue = world.add_material(Material::lambertian(Vec3::rgb(0.1, 0.2, 0.5)));
+        let ground = world.add_material(Material::lambertian(Vec3::rgb(0.8, 0.8, 0.0)));
+        let gold = world.add_material(Material::metal(Vec3::rgb(0.8, 0.6, 0.2), 0.2));
+        let glass = world.add_material(Material::dielectric(1.5));
+
+        world.add(Surface::sphere(Vec3::new(0.0, 0.0, -1.0), 0.5, blue));
+        world.add(Surface::sphere(Vec3::new(0.0, -100.5, -1.0), 100.0, ground));
+        world.add(Surface::sphere(Vec3::new(1.0, 0.0, -1.0), 0.5, gold));
+        world.add(Surface::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5, glass));
         // trick with negative radius does not affect the geometry and makes the sphere hollow
-        world.add(
-            Surface::sphere(
-                Vec3::new(-1.0, 0.0, -1.0),
-                -0.45,
-                Material::dielectric(1.5)));
+        world.add(Surface::sphere(Vec3::new(-1.0, 0.0, -1.0), -0.45, glass));
         world
     }
 
-    fn create_random_world(&self) -> World {
+    /// The world of the theme and the sphere in the middle the camera focuses on.
+    fn create_random_world(&self, theme: Theme) -> (World, Id<Surface>) {
 
         let mut world = World::new();
 
-        world.add(
-            Surface::sphere(
-                Vec3::new(0.0, -1000.0, 0.0),
-                1000.0,
-                Material::lambertian(Vec3::rgb(0.5, 0.5, 0.5))));
+        let ground = world.add_material(Material::lambertian(Vec3::rgb(0.5, 0.5, 0.5)));
+        // all the glass spheres share one material
+        let glass = world.add_material(Material::dielectric(1.5));
+
+        if theme == Theme::Studio {
+            // the backdrop rises behind the spheres, seen from the camera
+            let back = Vec3::new(-1., 0., -1.).unit();
+            checker_ground(&mut world, 0., 0.5, [Vec3::rgb(0.7, 0.7, 0.7), Vec3::rgb(0.3, 0.3, 0.3)]);
+            cyclorama(&mut world, &(4. * &back), &back, 12., 2., 5., ground);
+        } else {
+            world.add(Surface::sphere(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground));
+        }
 
         for a in -1..=1 {
             for b in -1..=1 {
                 let material = (drand32() * 100.) as i32;
-                assert!(material >= 0 && material < 100, "Material index out of range");
+                assert!((0..100).contains(&material), "Material index out of range");
                 let center = Vec3::new(a as f32 + 0.9 * drand32(), 0.2, b as f32 + 0.9 * drand32());
                 if (&center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
 
-                    match material {
+                    let material = match material {
+                        // a third of the spheres of the night glow in the warm colors of the lanterns
+                        0..=29 if theme == Theme::Night => {
+                            let warmth = drand32();
+                            world.add_material(Material::emissive(8. * Vec3::rgb(1., 0.5 + 0.4 * warmth, 0.2 + 0.4 * warmth)))
+                        }
                         // 80% is diffuse
                         0..=79 => {
                             let albedo = Vec3::rgb(drand32() * drand32(),
                                                    drand32() * drand32(),
                                                    drand32() * drand32());
-                            world.add(Surface::sphere(center, 0.2, Material::lambertian(albedo)));
+                            world.add_material(Material::lambertian(albedo))
                         }
                         // 15% is metal
                         80..=94 => {
@@ -140,41 +283,108 @@ impl BuiltIn {
                                 0.5 * (1. + drand32() * drand32()),
                                 0.5 * (1. + drand32() * drand32()),
                                 0.5 * (1. + drand32() * drand32()), );
-                            world.add(
-                                Surface::sphere(
-                                    center,
-                                    0.2,
-                                    Material::metal(albedo, 0.5 * drand32())));
+                            world.add_material(Material::metal(albedo, 0.5 * drand32()))
                         }
                         // 5% is glass
-                        _ => {
-                            world.add(
-                                Surface::sphere(
-                                    center,
-                                    0.2,
-                                    Material::dielectric(1.5)));
-                        }
-                    }
+                        _ => glass,
+                    };
+                    world.add(Surface::sphere(center, 0.2, material));
                 }
             }
         }
 
-        world.add(
-            Surface::sphere(
-                Vec3::new(0.0, 1.0, 0.0),
-                1.0,
-                Material::dielectric(1.5)));
-        world.add(
-            Surface::sphere(
-                Vec3::new(-4.0, 1.0, 0.0),
-                1.0,
-                Material::lambertian(Vec3::rgb(0.4, 0.2, 0.1))));
-        world.add(
-            Surface::sphere(
-                Vec3::new(4.0, 1.0, 0.0),
-                1.0,
-                Material::metal(Vec3::rgb(0.7, 0.6, 0.5), 0.0)));
+        // the big diffuse sphere of the night is the moon that rose over the ground
+        let brown = match theme {
+            Theme::Night => world.add_material(Material::emissive(Vec3::rgb(1.5, 1.6, 2.))),
+            _ => world.add_material(Material::lambertian(Vec3::rgb(0.4, 0.2, 0.1))),
+        };
+        let steel = world.add_material(Material::metal(Vec3::rgb(0.7, 0.6, 0.5), 0.0));
 
-        world
+        let subject = world.add(Surface::sphere(Vec3::new(0.0, 1.0, 0.0), 1.0, glass));
+        world.add(Surface::sphere(Vec3::new(-4.0, 1.0, 0.0), 1.0, brown));
+        world.add(Surface::sphere(Vec3::new(4.0, 1.0, 0.0), 1.0, steel));
+
+        match theme {
+            Theme::Day => {}
+            Theme::Night => world.set_stars(Stars::new(0, 0.15).with_milky_way()),
+            Theme::Foggy => {
+                // the mist lies knee-deep on the ground, the particles of it scatter the light around
+                let mist = world.add_material(Material::isotropic(Vec3::rgb(0.9, 0.9, 0.9)));
+                let layer = Volume::new(Vec3::new(-10., 0., -10.), Vec3::new(20., 0.4, 20.), [1, 1, 1], vec![1.], 1.5);
+                world.add(Surface::volume(layer, mist));
+                world.set_fog(Fog::new(Vec3::rgb(0.7, 0.75, 0.8), 0.15, 0., 0.5));
+            }
+            Theme::Studio => {
+                // the large panels light softly, the key one above and the fill one at the side
+                world.set_environment(Environment::new(1, 1, vec![Vec3::rgb(0.02, 0.02, 0.02)]));
+                let key = world.add_material(Material::emissive(Vec3::rgb(5., 5., 4.8)));
+                let fill = world.add_material(Material::emissive(Vec3::rgb(1.5, 1.6, 1.8)));
+                world.add(Surface::mesh(panel(Vec3::new(0., 5., 0.), Vec3::new(4., 0., 0.), Vec3::new(0., 0., 4.)), key));
+                world.add(Surface::mesh(panel(Vec3::new(2., 2., -5.), Vec3::new(3., 0., 3.), Vec3::new(0., 3., 0.)), fill));
+            }
+        }
+
+        (world, subject)
     }
-}
\ No newline at end of file
+}
+
+/// The cards of the focus chart, one at every unit of the distance.
+const CHART_MARKERS: usize = 10;
+
+/// The distance of the card the focus chart is focused on.
+const CHART_FOCUS: f32 = 5.;
+
+/// The rectangle around the center with the sides, a softbox or a card.
+fn panel(center: Vec3, a: Vec3, b: Vec3) -> Mesh {
+    let corner = |s: f32, t: f32| (&center + s / 2. * &a + t / 2. * &b, [(s + 1.) / 2., (t + 1.) / 2.]);
+    let points = vec![corner(-1., -1.), corner(1., -1.), corner(1., 1.), corner(-1., 1.)];
+    Mesh::new("panel", points, vec![([0, 1, 2], 0), ([0, 2, 3], 0)])
+}
+
+/// How far the ground plane reaches from the origin, the horizon of the scenes of a few units.
+const GROUND_EXTENT: f32 = 1000.;
+
+/// The flat ground at the height, as far as the eye sees, of the checker squares of the size
+/// in the two colors, the floor of the material tests. It stands in for the giant sphere
+/// of the book, whose curve shows under the wide views.
+pub(crate) fn checker_ground(world: &mut World, height: f32, square: f32, colors: [Vec3; 2]) -> Id<Surface> {
+    let checker = Texture::Procedural(Arc::new(Procedural::Checker { colors }));
+    let material = world.add_material(Material::textured(Vec3::rgb(1., 1., 1.), checker));
+    // the checker has two squares per unit of the texture coordinates
+    let corner = |x: f32, z: f32| (Vec3::new(x, height, z), [x / (2. * square), z / (2. * square)]);
+    let (near, far) = (-GROUND_EXTENT, GROUND_EXTENT);
+    let points = vec![corner(near, near), corner(near, far), corner(far, far), corner(far, near)];
+    world.add(Surface::mesh(Mesh::new("ground", points, vec![([0, 1, 2], 0), ([0, 2, 3], 0)]), material))
+}
+
+/// The cyclorama of the studio, the wall behind the subject that curves down into the floor
+/// without a corner the eye could tell the distance by. The curve of the radius starts
+/// on the ground at the foot and rises away along the horizontal direction `back`
+/// into the wall `height` high, and the whole is `width` wide.
+pub(crate) fn cyclorama(world: &mut World, foot: &Vec3, back: &Vec3, width: f32, radius: f32, height: f32, material: Id<Material>) -> Id<Surface> {
+    const STEPS: usize = 16;
+    let up = Vec3::new(0., 1., 0.);
+    let back = (back - Vec3::dot(back, &up) * &up).unit();
+    let side = Vec3::cross(&back, &up).unit();
+    // the profile, the distance along the back and the height, from the foot of the curve
+    let mut profile: Vec<(f32, f32)> = (0..STEPS).map(|i| {
+        let angle = std::f32::consts::FRAC_PI_2 * i as f32 / STEPS as f32;
+        (radius * angle.sin(), radius * (1. - angle.cos()))
+    }).collect();
+    profile.extend([(radius, radius), (radius, height.max(radius))]);
+
+    let points = profile.iter().enumerate()
+        .flat_map(|(i, (along, rise))| [-0.5, 0.5].map(|across| {
+            let p = foot + *along * &back + *rise * &up + across * width * &side;
+            (p, [across + 0.5, i as f32 / (profile.len() - 1) as f32])
+        }))
+        .collect();
+    // every step of the profile is a strip of two triangles facing the subject
+    let faces = (0..profile.len() - 1)
+        .flat_map(|i| {
+            let [a, b, c, d] = [2 * i, 2 * i + 1, 2 * i + 2, 2 * i + 3];
+            [([a, b, c], 0), ([b, d, c], 0)]
+        })
+        .collect();
+    world.add(Surface::mesh(Mesh::new("cyclorama", points, faces), material))
+}
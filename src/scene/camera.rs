@@ -1,5 +1,8 @@
 use crate::math::vec::{Ray, Vec3};
+use crate::scene::surfaces::hitable::Hitable;
+use crate::scene::surfaces::Surface;
 
+#[derive(Clone)]
 pub(crate) enum Camera {
     StaticCamera {
         origin: Vec3,
@@ -15,9 +18,44 @@ pub(crate) enum Camera {
         u: Vec3,
         v: Vec3,
         lens_radius: f32,
+        setup: CameraSetup,
     },
 }
 
+/// The parameters the positionable camera is built from.
+///
+/// The camera keeps them, so it can be adjusted and built again.
+#[derive(Clone, Debug)]
+pub(crate) struct CameraSetup {
+    pub(crate) look_from: Vec3,
+    pub(crate) look_at: Vec3,
+    pub(crate) up: Vec3,
+    /// Vertical field of view in degrees.
+    pub(crate) vfov: f32,
+    pub(crate) aperture: f32,
+    pub(crate) focus_dist: f32,
+}
+
+impl CameraSetup {
+    /// The distance from the camera to the plane through the point facing the camera,
+    /// the focus distance that makes the point sharp.
+    pub(crate) fn distance_along_view(&self, p: &Vec3) -> f32 {
+        let forward = (&self.look_at - &self.look_from).unit();
+        Vec3::dot(&(p - &self.look_from), &forward)
+    }
+
+    pub(crate) fn build(&self, aspect: f32) -> Camera {
+        Camera::positionable(
+            self.look_from.clone(),
+            self.look_at.clone(),
+            self.up.clone(),
+            self.vfov,
+            aspect,
+            self.aperture,
+            self.focus_dist)
+    }
+}
+
 pub(crate) trait RaySource {
     fn get_ray(&self, s: f32, t: f32) -> Ray;
 }
@@ -32,6 +70,15 @@ impl Camera {
                     aperture: f32,
                     focus_dist: f32) -> Camera {
 
+        let setup = CameraSetup {
+            look_from: look_from.clone(),
+            look_at: look_at.clone(),
+            up: up.clone(),
+            vfov,
+            aperture,
+            focus_dist,
+        };
+
         let theta = vfov.to_radians();
         let half_height = (theta / 2.0).tan();
         let half_width = aspect * half_height;
@@ -53,7 +100,8 @@ impl Camera {
         let u = Vec3::cross(&up, &w).unit();
         let v = Vec3::cross(&w, &u);
         Camera::PositionableCamera {
-            ll_corner: &look_from - half_width * focus_dist * &u - half_height * focus_dist * &v - &w,
+            // the image plane is the plane in focus, it is `focus_dist` away from the lens
+            ll_corner: &look_from - half_width * focus_dist * &u - half_height * focus_dist * &v - focus_dist * &w,
             horizontal: 2. * half_width * focus_dist * &u,
             vertical: 2. * half_height * focus_dist * &v,
             origin: look_from,
@@ -61,17 +109,139 @@ impl Camera {
             v,
             u,
             lens_radius: aperture / 2.,
+            setup,
+        }
+    }
+
+    /// The parameters of the camera. The static camera is described by the equivalent
+    /// pinhole camera: it looks from the origin down the -Z axis with 90 degrees vertical fov.
+    pub(crate) fn setup(&self) -> CameraSetup {
+        match self {
+            Camera::PositionableCamera { setup, .. } => setup.clone(),
+            Camera::StaticCamera { .. } => CameraSetup {
+                look_from: Vec3::zero(),
+                look_at: Vec3::new(0., 0., -1.),
+                up: Vec3::new(0., 1., 0.),
+                vfov: 90.,
+                aperture: 0.,
+                focus_dist: 1.,
+            },
         }
     }
 
-    pub(crate) fn static_camera() -> Camera {
+    /// The camera at the origin looking down the -Z axis at the image plane 2 units high
+    /// at the distance 1, the width of the plane follows the aspect ratio.
+    pub(crate) fn static_camera(aspect: f32) -> Camera {
         Camera::StaticCamera {
-            ll_corner: Vec3::new(-2., -1., -1.),
-            horizontal: Vec3::new(4., 0., 0.),
+            ll_corner: Vec3::new(-aspect, -1., -1.),
+            horizontal: Vec3::new(2. * aspect, 0., 0.),
             vertical: Vec3::new(0., 2., 0.),
             origin: Vec3::new(0., 0., 0.),
         }
     }
+
+    /// The same camera focused at the distance along the view direction.
+    pub(crate) fn with_focus_dist(&self, focus_dist: f32) -> Camera {
+        let setup = CameraSetup { focus_dist: focus_dist.max(1e-3), ..self.setup() };
+        setup.build(self.aspect())
+    }
+
+    /// The same camera with the pinhole instead of the lens, everything is sharp.
+    pub(crate) fn pinhole(&self) -> Camera {
+        match self {
+            Camera::StaticCamera { .. } => self.clone(),
+            Camera::PositionableCamera { setup, .. } => CameraSetup { aperture: 0., ..setup.clone() }.build(self.aspect()),
+        }
+    }
+
+    /// The same camera focused on the front of the surface.
+    ///
+    /// The ray from the camera to the center of the surface finds the visible side of it,
+    /// so the focus does not have to be computed by hand.
+    pub(crate) fn focus_on(&self, surface: &Surface) -> Camera {
+        let setup = self.setup();
+        let center = surface.center();
+        let ray = Ray::from(setup.look_from.clone(), &center - &setup.look_from);
+        let p = surface.hit(&ray, 0., f32::MAX).map_or(center, |hit| hit.p);
+        self.with_focus_dist(setup.distance_along_view(&p))
+    }
+
+    /// The angle a pixel of the image `h` pixels high takes, in radians.
+    /// This is the spread of the beams of the camera rays.
+    pub(crate) fn pixel_spread(&self, h: usize) -> f32 {
+        2. * (self.setup().vfov.to_radians() / 2.).tan() / h as f32
+    }
+
+    fn aspect(&self) -> f32 {
+        match self {
+            Camera::StaticCamera { horizontal, vertical, .. }
+            | Camera::PositionableCamera { horizontal, vertical, .. } => horizontal.length() / vertical.length(),
+        }
+    }
+
+    /// The lower left corner of the image plane relative to the center of the lens, and its sides.
+    /// The ray through the point (s, t) of the image goes along `corner + s * horizontal + t * vertical`.
+    pub(crate) fn image_plane(&self) -> (Vec3, &Vec3, &Vec3) {
+        match self {
+            Camera::StaticCamera { origin, ll_corner, horizontal, vertical }
+            | Camera::PositionableCamera { origin, ll_corner, horizontal, vertical, .. } =>
+                (ll_corner - origin, horizontal, vertical),
+        }
+    }
+
+    /// The point (s, t) of the image the point of the scene is seen at through the center
+    /// of the lens, as in `get_ray`, and its distance along the view. The point of the image
+    /// means nothing when the distance is not positive, the point is behind the camera.
+    pub(crate) fn project(&self, p: &Vec3) -> (f32, f32, f32) {
+        let (corner, horizontal, vertical) = self.image_plane();
+        // the sides of the image are at right angles to each other and to the view
+        let forward = Vec3::cross(vertical, horizontal).unit();
+        let relative = p - &self.setup().look_from;
+        let depth = Vec3::dot(&relative, &forward);
+        // the point where the ray to it crosses the image plane
+        let on_plane = Vec3::dot(&corner, &forward) / depth * &relative - &corner;
+        let s = Vec3::dot(&on_plane, horizontal) / horizontal.squared_length();
+        let t = Vec3::dot(&on_plane, vertical) / vertical.squared_length();
+        (s, t, depth)
+    }
+
+    /// The ray through the point of the image plane in the direction from the center of the lens,
+    /// from a random point of the lens when the camera has an aperture.
+    pub(crate) fn ray_along(&self, direction: Vec3) -> Ray {
+        match self {
+            Camera::StaticCamera { origin, .. } => Ray::from(origin.clone(), direction),
+            Camera::PositionableCamera { origin, u, v, lens_radius, .. } => {
+                let rand = *lens_radius * Vec3::random_in_unit_disk();
+                let offset = u * rand.x() + v * rand.y();
+                Ray::from(origin + &offset, direction - offset)
+            }
+        }
+    }
+
+    /// The camera moved to the right by the offset, the left for the negative one, looking
+    /// at the same image plane in focus, the eye of the stereo pair. The plane is seen
+    /// off the axis, so the views of the two eyes agree at the distance in focus.
+    pub(crate) fn eye(&self, offset: f32) -> Camera {
+        let mut camera = self.clone();
+        match &mut camera {
+            Camera::StaticCamera { origin, horizontal, .. } => *origin += offset * horizontal.unit(),
+            Camera::PositionableCamera { origin, u, setup, .. } => {
+                let shift = offset * &*u;
+                *origin += shift.clone();
+                setup.look_from += shift.clone();
+                setup.look_at += shift;
+            }
+        }
+        camera
+    }
+
+    /// The same camera for an image of another aspect ratio.
+    pub(crate) fn with_aspect(&self, aspect: f32) -> Camera {
+        match self {
+            Camera::StaticCamera { .. } => Camera::static_camera(aspect),
+            Camera::PositionableCamera { setup, .. } => setup.build(aspect),
+        }
+    }
 }
 
 impl RaySource for Camera {
@@ -80,10 +250,9 @@ impl RaySource for Camera {
     fn get_ray(&self, s: f32, t: f32) -> Ray {
         match self {
             Camera::StaticCamera { origin, ll_corner, horizontal, vertical } => {
-                let ray_origin:Vec3 = origin.clone();
-                Ray::from(ray_origin, ll_corner + s * horizontal + t * vertical - origin)
+                Ray::from(origin.clone(), ll_corner + s * horizontal + t * vertical - origin)
             }
-            Camera::PositionableCamera { origin, ll_corner, horizontal, vertical, u, v, lens_radius } => {
+            Camera::PositionableCamera { origin, ll_corner, horizontal, vertical, u, v, lens_radius, .. } => {
                 let rand = *lens_radius * Vec3::random_in_unit_disk();
                 let offset = u * rand.x() + v * rand.y();
                 let direction = ll_corner
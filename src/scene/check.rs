@@ -0,0 +1,231 @@
+/**
+The validation of the scene files without rendering them: `raytracer check <scene-file>`.
+
+The loader stops at the first error it cannot go past (a syntax error, a missing key).
+A scene that loads can still render garbage: a camera that looks at itself produces NaNs,
+a negative albedo makes black holes, an albedo above 1 makes the scene glow.
+These are the diagnostics of the scene that loaded, each with where it is in the file,
+so the asset pipelines can reject the broken scenes before they reach the render farm.
+*/
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::math::vec::Vec3;
+use crate::scene::camera::{CameraSetup, RaySource};
+use crate::scene::conductor::Conductor;
+use crate::scene::file;
+use crate::scene::material::{fuzz_to_roughness, Material};
+use crate::scene::surfaces::hitable::Hitable;
+use crate::scene::surfaces::Surface;
+use crate::scene::Scene;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Severity {
+    /// The scene renders, but probably not the way it was meant to.
+    Warning,
+    /// The scene cannot be loaded or renders wrong pixels.
+    Error,
+}
+
+pub(crate) struct Diagnostic {
+    pub(crate) severity: Severity,
+    /// Where in the scene the problem is, e.g. `sphere #2`.
+    pub(crate) location: String,
+    pub(crate) message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.location, self.message)
+    }
+}
+
+/// Loads the scene file and returns all the problems found in it,
+/// the warnings of the loading first: the old version, the keys that are ignored.
+///
+/// The files the scene names that are missing are all reported, each where it is named.
+/// The loading would stop at the first of them, so the rest of the scene is checked
+/// once they are found.
+pub(crate) fn check(path: &Path) -> Vec<Diagnostic> {
+    let missing = file::missing_files(path).unwrap_or_default();
+    if !missing.is_empty() {
+        return missing.into_iter()
+            .map(|(location, file)| Diagnostic {
+                severity: Severity::Error,
+                location,
+                message: format!("{} is not found next to the scene file or in the search path", file.display()),
+            })
+            .collect();
+    }
+    match file::load_with_warnings(path) {
+        Ok((scene, warnings)) => warnings.into_iter()
+            .map(|warning| Diagnostic { severity: Severity::Warning, location: warning.location, message: warning.message })
+            .chain(check_scene(&scene))
+            .collect(),
+        Err(why) => vec![Diagnostic { severity: Severity::Error, location: "file".to_string(), message: why.to_string() }],
+    }
+}
+
+pub(crate) fn check_scene(scene: &Scene) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut report = |severity, location: &str, message: String| {
+        diagnostics.push(Diagnostic { severity, location: location.to_string(), message })
+    };
+
+    let setup = scene.camera().setup();
+    let camera_ok = check_camera(&setup, &mut |severity, message| report(severity, "camera", message));
+
+    let objects = scene.world().objects();
+    if objects.is_empty() {
+        report(Severity::Warning, "scene", "there are no objects, the image is only the sky".to_string());
+    }
+    // the objects of each kind are numbered in the order of the file
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for surface in objects {
+        let count = counts.entry(surface.kind()).or_default();
+        *count += 1;
+        let location = format!("{} #{}", surface.kind(), count);
+        match surface {
+            Surface::Sphere { center, radius, .. } => {
+                if !finite(center) {
+                    report(Severity::Error, &location, format!("center {} is not a finite point", show(center)));
+                }
+                if *radius == 0. || !radius.is_finite() {
+                    report(Severity::Error, &location, format!("radius {} must be a finite non-zero number", radius));
+                }
+                if surface.contains(&setup.look_from) {
+                    report(Severity::Warning, &location, "the camera is inside the sphere, it sees only the inner side".to_string());
+                }
+            }
+            Surface::Mesh { mesh, .. } => {
+                if !mesh.vertices().all(|vertex| finite(&vertex.p)) {
+                    report(Severity::Error, &location, "some vertices are not finite points, check the displacement amount".to_string());
+                }
+            }
+            Surface::Curves { curves, .. } => {
+                if !curves.points().all(finite) {
+                    report(Severity::Error, &location, "some control points are not finite".to_string());
+                }
+            }
+            Surface::Points { points, .. } => {
+                if !points.positions().iter().all(finite) {
+                    report(Severity::Error, &location, "some points are not finite".to_string());
+                }
+            }
+            Surface::Volume { .. } => {}
+        }
+        for &material in surface.materials() {
+            check_material(scene.world().material(material), &mut |severity, message| report(severity, &location, message));
+        }
+    }
+
+    // LEARN:
+    // A coarse grid of the camera rays is enough to tell that the camera looks away
+    // from the scene, the rays of a real render would take much longer to say the same.
+    if camera_ok && !objects.is_empty() {
+        const GRID: usize = 16;
+        let pinhole = CameraSetup { aperture: 0., ..setup }.build(scene.aspect());
+        let sees_something = (0..GRID * GRID).any(|i| {
+            let (u, v) = ((i % GRID) as f32 + 0.5, (i / GRID) as f32 + 0.5);
+            let ray = pinhole.get_ray(u / GRID as f32, v / GRID as f32);
+            scene.world().hit(&ray, 0.001, f32::MAX).is_some()
+        });
+        if !sees_something {
+            report(Severity::Warning, "camera", "no object is in view, the image is only the sky".to_string());
+        }
+    }
+
+    diagnostics
+}
+
+/// Reports the problems of the camera, returns whether it can make the rays at all.
+fn check_camera(setup: &CameraSetup, report: &mut impl FnMut(Severity, String)) -> bool {
+    let mut ok = true;
+    let mut error = |message: String| {
+        ok = false;
+        report(Severity::Error, message)
+    };
+
+    let view = &setup.look_at - &setup.look_from;
+    if !finite(&setup.look_from) || !finite(&setup.look_at) || !finite(&setup.up) {
+        error("look_from, look_at and up must be finite".to_string());
+    } else if view.squared_length() == 0. {
+        error(format!("look_from and look_at are the same point {}", show(&setup.look_at)));
+    } else if Vec3::cross(&view, &setup.up).squared_length() == 0. {
+        error(format!("up {} is parallel to the view direction, the camera roll is undefined", show(&setup.up)));
+    }
+    if !(setup.vfov > 0. && setup.vfov < 180.) {
+        error(format!("fov {} must be between 0 and 180 degrees", setup.vfov));
+    }
+    if !(setup.aperture >= 0. && setup.aperture.is_finite()) {
+        error(format!("aperture {} must not be negative", setup.aperture));
+    }
+    if !(setup.focus_dist > 0. && setup.focus_dist.is_finite()) {
+        error(format!("focus_dist {} must be positive", setup.focus_dist));
+    }
+    ok
+}
+
+fn check_material(material: &Material, report: &mut impl FnMut(Severity, String)) {
+    let mut check_albedo = |albedo: &Vec3| {
+        if !finite(albedo) || albedo.x().min(albedo.y()).min(albedo.z()) < 0. {
+            report(Severity::Error, format!("albedo {} must be between 0 and 1", show(albedo)));
+        } else if albedo.x().max(albedo.y()).max(albedo.z()) > 1. {
+            report(Severity::Warning, format!("albedo {} above 1 reflects more light than it receives", show(albedo)));
+        }
+    };
+    match material {
+        Material::Lambertian { albedo, .. } => check_albedo(albedo),
+        Material::Metal { albedo, fuzz, conductor } => {
+            check_albedo(albedo);
+            if let Some(Conductor { n, k }) = conductor {
+                if !finite(n) || !finite(k) || n.x().min(n.y()).min(n.z()) <= 0. || k.x().min(k.y()).min(k.z()) < 0. {
+                    report(Severity::Error, format!("n {} must be positive and k {} not negative", show(n), show(k)));
+                }
+            }
+            if !fuzz.is_finite() {
+                report(Severity::Error, format!("fuzz {} must be a number between 0 and 1", fuzz));
+            } else if !(0. ..=1.).contains(fuzz) {
+                let clamped = fuzz.clamp(0., 1.);
+                report(Severity::Warning, format!("fuzz {} is outside 0..1, it is clamped to {}, the roughness {:.2}",
+                                                  fuzz, clamped, fuzz_to_roughness(clamped)));
+            }
+        }
+        Material::Dielectric { ref_idx, absorption, .. } => {
+            if !(*ref_idx > 0. && ref_idx.is_finite()) {
+                report(Severity::Error, format!("ior {} must be positive", ref_idx));
+            }
+            if !finite(absorption) || absorption.x().min(absorption.y()).min(absorption.z()) < 0. {
+                report(Severity::Error, format!("absorption {} must not be negative", show(absorption)));
+            }
+        }
+        Material::Isotropic { albedo } => check_albedo(albedo),
+        Material::Emissive { radiance } => {
+            if !finite(radiance) || radiance.x().min(radiance.y()).min(radiance.z()) < 0. {
+                report(Severity::Error, format!("radiance {} must not be negative", show(radiance)));
+            }
+        }
+        Material::Hair { color, roughness } => {
+            check_albedo(color);
+            if !roughness.is_finite() {
+                report(Severity::Error, format!("roughness {} must be a number between 0 and 1", roughness));
+            } else if !(0. ..=1.).contains(roughness) {
+                report(Severity::Warning, format!("roughness {} is outside 0..1, it is clamped to {}", roughness, roughness.clamp(0., 1.)));
+            }
+        }
+    }
+}
+
+fn finite(v: &Vec3) -> bool {
+    v.x().is_finite() && v.y().is_finite() && v.z().is_finite()
+}
+
+/// The vector the way it is written in the scene file.
+fn show(v: &Vec3) -> String {
+    format!("[{}, {}, {}]", v.x(), v.y(), v.z())
+}
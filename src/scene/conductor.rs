@@ -0,0 +1,71 @@
+/**
+The metals by their complex index of refraction: `{ type = "metal", preset = "gold" }`,
+or `n` and `k` of the red, the green and the blue light.
+
+A metal of the constant albedo reflects the same share of the light at every angle.
+A real one reflects more at the grazing angles, where every metal turns to the white
+of a mirror: the gold is deep yellow seen straight on and pale at its edges.
+The share follows the Fresnel equations of the conductor, from the index of refraction `n`
+and the extinction coefficient `k` of the metal, both differ for the colors of the light,
+which is all the color a metal has.
+
+LEARN:
+The light does not enter a metal, it is absorbed within a fraction of the wavelength,
+the `k` tells how fast. The larger it is, the more light is reflected instead, the silver
+and the aluminum have large `k` for all the colors and are almost white, the gold and the copper
+absorb the blue more (their `n` of the blue is higher) and reflect the yellow and the red.
+*/
+use crate::math::vec::Vec3;
+
+/// The complex index of refraction `n + ik` of the metal for the red, the green and the blue light.
+#[derive(Clone, Debug)]
+pub(crate) struct Conductor {
+    pub(crate) n: Vec3,
+    pub(crate) k: Vec3,
+}
+
+/// The measured metals at about 650, 550 and 450 nm.
+const PRESETS: &[(&str, [f32; 3], [f32; 3])] = &[
+    ("gold", [0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+    ("silver", [0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+    ("copper", [0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+    ("aluminum", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+];
+
+impl Conductor {
+    /// The metal of the name, e.g. `gold`, see `presets`.
+    pub(crate) fn preset(name: &str) -> Option<Conductor> {
+        PRESETS.iter()
+            .find(|(preset, _, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, [nr, ng, nb], [kr, kg, kb])| Conductor { n: Vec3::new(*nr, *ng, *nb), k: Vec3::new(*kr, *kg, *kb) })
+    }
+
+    /// The names of the preset metals.
+    pub(crate) fn presets() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|(name, _, _)| *name)
+    }
+
+    /// The share of the unpolarized light the metal reflects at the cosine of the angle
+    /// between the ray and the normal, for every color.
+    pub(crate) fn reflectance(&self, cosine: f32) -> Vec3 {
+        let reflect = |n: f32, k: f32| fresnel(cosine.clamp(0., 1.), n, k);
+        Vec3::new(reflect(self.n.x(), self.k.x()), reflect(self.n.y(), self.k.y()), reflect(self.n.z(), self.k.z()))
+    }
+}
+
+/// The exact Fresnel reflectance of the conductor, the average of both polarizations.
+fn fresnel(cos: f32, n: f32, k: f32) -> f32 {
+    let cos2 = cos * cos;
+    let sin2 = 1. - cos2;
+    let t0 = n * n - k * k - sin2;
+    // a² + b², the squared magnitude of the complex cosine of the refracted angle times the index
+    let a2b2 = (t0 * t0 + 4. * n * n * k * k).sqrt();
+    let a = (0.5 * (a2b2 + t0)).max(0.).sqrt();
+    let t1 = a2b2 + cos2;
+    let t2 = 2. * a * cos;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rs + rp)
+}
@@ -0,0 +1,138 @@
+/**
+The comparison of two versions of a scene file: `raytracer diff <old-scene> <new-scene>`.
+
+When a render changed and nobody knows why, the line diff of the scene files does not tell
+much: the objects are moved around the file, the numbers are written as `1` in one version
+and `1.0` in the other, and a change of the material is somewhere in a long inline table.
+This diff compares what the files describe instead of their text:
+
+```text
+camera: fov = 20 -> 30
+camera: + aperture = 0.1
+sphere "hero": material.type = "lambertian" -> "metal"
+sphere "hero": + material.fuzz = 0.2
+- sphere #3
++ light #2: position = [0, 4, 0], intensity = [20, 18, 15]
+```
+
+The tables of the file (`[camera]`, `[image]`, `[sky]`, ...) are compared key by key,
+the inline tables within them, like the materials, too. The objects of each kind
+(`[[sphere]]`, `[[mesh]]`, `[[light]]`, ...) that have a `name` are matched by it wherever
+they are in the file, the others by their order among the unnamed objects of the kind,
+numbered as in the old file, the added ones as in the new file. The files the scene
+refers to, the models and the textures, are compared only by their paths.
+*/
+use std::path::Path;
+
+use crate::toml::{self, Table, Value};
+
+/// The differences of the scene files, one line for each, the empty list when the scenes are the same.
+pub(crate) fn diff_files(old: &Path, new: &Path) -> Result<Vec<String>, String> {
+    let read = |path: &Path| -> Result<Table, String> {
+        let text = std::fs::read_to_string(path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+        toml::parse(&text).map_err(|why| format!("{}: syntax error at {}", path.display(), why))
+    };
+    Ok(diff(&read(old)?, &read(new)?))
+}
+
+fn diff(old: &Table, new: &Table) -> Vec<String> {
+    let mut changes = Vec::new();
+    let empty = Table::default();
+    for key in keys(old, new) {
+        let (a, b) = (old.get(key), new.get(key));
+        match (objects(a), objects(b), a, b) {
+            (Some(a), Some(b), _, _) => diff_objects(key, &a, &b, &mut changes),
+            // a table that is only in one of the files has all its keys added or removed
+            (_, _, Some(Value::Table(_)) | None, Some(Value::Table(_)) | None) => {
+                let (a, b) = (a.and_then(Value::as_table), b.and_then(Value::as_table));
+                diff_entries(key, "", a.unwrap_or(&empty), b.unwrap_or(&empty), &mut changes);
+            }
+            _ => diff_value("scene", key, a, b, &mut changes),
+        }
+    }
+    changes
+}
+
+/// The keys of both tables, those of the old one first.
+fn keys<'a>(old: &'a Table, new: &'a Table) -> Vec<&'a str> {
+    let mut keys: Vec<&str> = old.keys().collect();
+    keys.extend(new.keys().filter(|key| old.get(key).is_none()));
+    keys
+}
+
+/// The objects of an array of tables, none when the key is missing,
+/// `None` when the value is something else.
+fn objects(value: Option<&Value>) -> Option<Vec<&Table>> {
+    match value {
+        None => Some(Vec::new()),
+        Some(Value::Array(items)) => items.iter().map(Value::as_table).collect(),
+        Some(_) => None,
+    }
+}
+
+fn diff_objects(kind: &str, old: &[&Table], new: &[&Table], changes: &mut Vec<String>) {
+    let name = |object: &Table| object.get("name").and_then(Value::as_str).map(str::to_string);
+    let label = |object: &Table, i: usize| match name(object) {
+        Some(name) => format!("{} {}", kind, toml::quote(&name)),
+        None => format!("{} #{}", kind, i + 1),
+    };
+    let mut matched = vec![false; new.len()];
+    let mut unnamed = (0..new.len()).filter(|&j| name(new[j]).is_none());
+    for (i, a) in old.iter().enumerate() {
+        let found = match name(a) {
+            Some(wanted) => (0..new.len()).find(|&j| !matched[j] && name(new[j]).as_ref() == Some(&wanted)),
+            None => unnamed.next(),
+        };
+        match found {
+            Some(j) => {
+                matched[j] = true;
+                diff_entries(&label(a, i), "", a, new[j], changes);
+            }
+            None => changes.push(format!("- {}", label(a, i))),
+        }
+    }
+    for (j, b) in new.iter().enumerate().filter(|(j, _)| !matched[*j]) {
+        changes.push(format!("+ {}: {}", label(b, j), entries(b)));
+    }
+}
+
+/// The differences of the keys of the tables, the keys of the nested tables are prefixed by the path to them.
+fn diff_entries(location: &str, prefix: &str, old: &Table, new: &Table, changes: &mut Vec<String>) {
+    for key in keys(old, new) {
+        let path = format!("{}{}", prefix, key);
+        match (old.get(key), new.get(key)) {
+            (Some(Value::Table(a)), Some(Value::Table(b))) => diff_entries(location, &format!("{}.", path), a, b, changes),
+            (a, b) => diff_value(location, &path, a, b, changes),
+        }
+    }
+}
+
+fn diff_value(location: &str, key: &str, old: Option<&Value>, new: Option<&Value>, changes: &mut Vec<String>) {
+    match (old, new) {
+        (Some(a), Some(b)) if !same(a, b) => changes.push(format!("{}: {} = {} -> {}", location, key, a, b)),
+        (Some(a), None) => changes.push(format!("{}: - {} = {}", location, key, a)),
+        (None, Some(b)) => changes.push(format!("{}: + {} = {}", location, key, b)),
+        _ => {}
+    }
+}
+
+/// Whether the values describe the same, the numbers are equal whether written as integers or floats.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b)),
+        (Value::Table(a), Value::Table(b)) => keys(a, b).iter().all(|key| match (a.get(key), b.get(key)) {
+            (Some(a), Some(b)) => same(a, b),
+            _ => false,
+        }),
+        _ => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => a == b,
+            _ => a == b,
+        },
+    }
+}
+
+/// The keys and the values of the table on a line, without the braces.
+fn entries(table: &Table) -> String {
+    let inline = table.to_string();
+    inline.strip_prefix("{ ").and_then(|inline| inline.strip_suffix(" }")).unwrap_or(&inline).to_string()
+}
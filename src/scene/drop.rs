@@ -0,0 +1,160 @@
+/**
+The drop, the demo of the animations (`raytracer drop`): the spheres fall from above
+onto the checkered floor, bounce off it and off each other and settle, rendered
+with the shutter open, so the fast spheres blur and the ones at rest are sharp.
+
+The physics is the least that looks right: the gravity, the floor, the spheres that
+push each other apart, and the restitution, the share of the speed a sphere keeps
+when it bounces (1 bounces forever, 0 does not bounce at all). The spheres do not spin,
+they slide to a stop. The simulation runs once, before the render, and the frames
+only move the spheres to where it put them, see `Motion`.
+
+LEARN:
+The simulation takes many small steps between the frames (the semi-implicit Euler:
+the velocity changes first, then the position by the new velocity). The small steps
+keep the fast sphere from passing through the floor or another sphere within a step,
+the collisions are found after every step and resolved by pushing the spheres apart
+and reflecting the velocities they approach each other with.
+*/
+use std::sync::Arc;
+
+use crate::math::color::Color;
+use crate::math::rand::{self, drand32};
+use crate::math::vec::Vec3;
+use crate::scene::animation::{Animation, Motion};
+use crate::scene::builder::checker_ground;
+use crate::scene::camera::Camera;
+use crate::scene::material::Material;
+use crate::scene::surfaces::world::World;
+use crate::scene::surfaces::Surface;
+use crate::scene::Scene;
+
+/// The frames of the drop without `--frames`, 3 seconds at 24 frames per second,
+/// the spheres settle in about 2.
+pub(crate) const DEFAULT_FRAMES: usize = 72;
+
+pub(crate) const DEFAULT_RESTITUTION: f32 = 0.6;
+
+/// The shutter of the drop without `--shutter`, open half the time, the 180 degrees of the film cameras.
+pub(crate) const DEFAULT_SHUTTER: f32 = 0.5;
+
+const SPHERES: usize = 16;
+
+/// The gravity of the Earth, the scene is in meters.
+const GRAVITY: f32 = 9.81;
+
+/// The longest step of the simulation in seconds.
+const MAX_STEP: f32 = 1. / 960.;
+
+/// The sphere on the floor bouncing up slower than this (in meters per second) stays on it.
+const REST_SPEED: f32 = 0.1;
+
+/// How fast the sphere on the floor stops sliding, the share of its speed lost per second.
+const FRICTION: f32 = 1.5;
+
+struct Ball {
+    center: Vec3,
+    velocity: Vec3,
+    radius: f32,
+}
+
+/// The scene of the drop of the seed, the spheres fall for the frames of the animation
+/// and it moves them, with the shutter half open unless it says otherwise. The spheres
+/// and their colors are the same for the same seed, any text, the image notes it as `drop:<seed>`.
+pub(crate) fn scene(seed: &str, animation: &mut Animation, restitution: f32) -> Scene {
+    rand::seed(rand::text_seed(seed));
+
+    let mut world = World::new();
+    checker_ground(&mut world, 0., 0.5, [Vec3::rgb(0.7, 0.7, 0.7), Vec3::rgb(0.3, 0.3, 0.3)]);
+    let mut balls: Vec<Ball> = Vec::new();
+    while balls.len() < SPHERES {
+        let radius = 0.2 + 0.25 * drand32();
+        // spread over the floor and over the heights, so they land one after another
+        let center = Vec3::new(3. * drand32() - 1.5, 1. + 3. * drand32(), 3. * drand32() - 1.5);
+        if balls.iter().all(|ball| (&ball.center - &center).length() > ball.radius + radius) {
+            balls.push(Ball { center, velocity: Vec3::zero(), radius });
+        }
+    }
+    let objects = balls.iter()
+        .map(|ball| {
+            let color = Vec3::rgb(0.2 + 0.7 * drand32(), 0.2 + 0.7 * drand32(), 0.2 + 0.7 * drand32());
+            // a quarter of them are metal, the blur shows in their reflections too
+            let material = match drand32() < 0.25 {
+                true => Material::metal(color, 0.1),
+                false => Material::lambertian(color),
+            };
+            let material = world.add_material(material);
+            world.add(Surface::sphere(ball.center.clone(), ball.radius, material))
+        })
+        .collect();
+
+    let offsets = simulate(&mut balls, animation.frames, animation.fps, restitution);
+    animation.motion = Some(Arc::new(Motion::new(objects, offsets)));
+    animation.shutter.get_or_insert(DEFAULT_SHUTTER);
+
+    let (w, h) = (960, 540);
+    let look_from = Vec3::new(0., 3., 9.);
+    let look_at = Vec3::new(0., 1.2, 0.);
+    let focus = (&look_from - &look_at).length();
+    let camera = Camera::positionable(look_from, look_at, Vec3::new(0., 1., 0.), 40., w as f32 / h as f32, 0., focus);
+    Scene { camera, world, w, h, pixel_aspect: 1., origin: Some(format!("drop:{}", seed)) }
+}
+
+/// Simulates the balls over the frames at the frames per second, returns the offsets
+/// of every ball from where it starts at every frame.
+fn simulate(balls: &mut [Ball], frames: usize, fps: usize, restitution: f32) -> Vec<Vec<Vec3>> {
+    let steps = (1. / (fps as f32 * MAX_STEP)).ceil().max(1.) as usize;
+    let dt = 1. / (fps * steps) as f32;
+    let starts: Vec<Vec3> = balls.iter().map(|ball| ball.center.clone()).collect();
+    let mut offsets = Vec::with_capacity(frames);
+    for _ in 0..frames {
+        offsets.push(balls.iter().zip(&starts).map(|(ball, start)| &ball.center - start).collect());
+        for _ in 0..steps {
+            step(balls, dt, restitution);
+        }
+    }
+    offsets
+}
+
+fn step(balls: &mut [Ball], dt: f32, restitution: f32) {
+    for ball in balls.iter_mut() {
+        ball.velocity = &ball.velocity - &Vec3::new(0., GRAVITY * dt, 0.);
+        ball.center = &ball.center + &(dt * &ball.velocity);
+
+        if ball.center.y() < ball.radius {
+            let bounce = match -ball.velocity.y() * restitution {
+                up if up < REST_SPEED => 0.,
+                up => up,
+            };
+            let slide = (1. - FRICTION * dt).max(0.);
+            ball.center = Vec3::new(ball.center.x(), ball.radius, ball.center.z());
+            ball.velocity = Vec3::new(slide * ball.velocity.x(), bounce.max(ball.velocity.y()), slide * ball.velocity.z());
+        }
+    }
+
+    for i in 0..balls.len() {
+        let (before, after) = balls.split_at_mut(i + 1);
+        let a = &mut before[i];
+        for b in after.iter_mut() {
+            let between = &b.center - &a.center;
+            let distance = between.length();
+            let overlap = a.radius + b.radius - distance;
+            if overlap <= 0. || distance == 0. {
+                continue;
+            }
+            let normal = &between / distance;
+            // the masses of the spheres of the same density
+            let (mass_a, mass_b) = (a.radius.powi(3), b.radius.powi(3));
+            let share_a = mass_b / (mass_a + mass_b);
+            a.center = &a.center - &((overlap * share_a) * &normal);
+            b.center = &b.center + &((overlap * (1. - share_a)) * &normal);
+
+            let approach = Vec3::dot(&(&b.velocity - &a.velocity), &normal);
+            if approach < 0. {
+                let impulse = -(1. + restitution) * approach / (1. / mass_a + 1. / mass_b);
+                a.velocity = &a.velocity - &((impulse / mass_a) * &normal);
+                b.velocity = &b.velocity + &((impulse / mass_b) * &normal);
+            }
+        }
+    }
+}
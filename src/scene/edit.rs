@@ -0,0 +1,130 @@
+/**
+The changes of the named objects made to the loaded scene before the render, e.g.
+`--material hero='{ type = "metal", albedo = [0.9, 0.6, 0.2], fuzz = 0.1 }'` or `--move hero=0,0.5,0`.
+
+The objects are found by the names and the tags of the scene file (see `Scene::find`),
+so the variants of a scene are rendered from the one file, without its copies
+that differ in a line and drift apart.
+
+`raytracer variants <variants-file>` renders many variants in one run, each to its own image:
+
+```toml
+[[variant]]
+output = "gold.png"     # relative to the directory of --output
+material = { hero = { type = "metal", albedo = [1, 0.8, 0.3], fuzz = 0.1 } }
+move = { hero = [0, 0.5, 0] }
+
+[[variant]]
+output = "glass.png"
+material = { hero = { type = "dielectric", ior = 1.5 }, props = { type = "lambertian", albedo = [0.8, 0.1, 0.1] } }
+```
+
+The scene is loaded once, and every variant edits a copy of it. Loading a large scene
+is the slow part: reading the models and building the trees of their triangles.
+The copy takes the built trees along, so they are never built again.
+*/
+use std::path::{Path, PathBuf};
+
+use crate::math::vec::Vec3;
+use crate::scene::file::{self, LoadError};
+use crate::scene::material::Material;
+use crate::scene::Scene;
+use crate::toml::{self, string, table, tables};
+
+pub(crate) enum Edit {
+    /// The objects of the name are made of the material.
+    Material { name: String, material: Material },
+    /// The objects of the name move by the offset.
+    Move { name: String, offset: Vec3 },
+}
+
+impl Edit {
+    /// The edit of `--material`: the name, `=` and the material as in the scene file,
+    /// the paths of its textures are relative to the working directory.
+    pub(crate) fn material(value: &str) -> Result<Edit, String> {
+        let (name, material) = split(value)?;
+        let material = file::parse_inline_material(material, Path::new(""))
+            .map_err(|why| format!("the material of {}: {}", name, why))?;
+        Ok(Edit::Material { name: name.to_string(), material })
+    }
+
+    /// The edit of `--move`: the name, `=` and the offset, e.g. `hero=0,0.5,0`.
+    pub(crate) fn translation(value: &str) -> Result<Edit, String> {
+        let (name, numbers) = split(value)?;
+        let numbers: Option<Vec<f32>> = numbers.split(',').map(|n| n.trim().parse().ok()).collect();
+        match numbers.as_deref() {
+            Some(&[x, y, z]) => Ok(Edit::Move { name: name.to_string(), offset: Vec3::new(x, y, z) }),
+            _ => Err(format!("the offset of {} must be 3 numbers, e.g. 0,0.5,0", name)),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Edit::Material { name, .. } | Edit::Move { name, .. } => name,
+        }
+    }
+
+    /// Changes the objects of the name, there must be at least one, a misspelled name
+    /// would silently render the scene unchanged.
+    pub(crate) fn apply(&self, scene: &mut Scene) -> Result<(), String> {
+        let ids = scene.find(self.name());
+        if ids.is_empty() {
+            return Err(format!("no object is named or tagged `{}`", self.name()));
+        }
+        match self {
+            Edit::Material { material, .. } => {
+                // the objects of the name share the one new material
+                let material = scene.world.add_material(material.clone());
+                for id in ids {
+                    scene.world.set_material(id, material);
+                }
+            }
+            Edit::Move { offset, .. } => scene.world.translate(ids.into_iter().map(|id| (id, offset.clone()))),
+        }
+        Ok(())
+    }
+}
+
+/// A variant of the scene: the image it is rendered to and the edits of the scene, the materials first.
+pub(crate) struct Variant {
+    pub(crate) output: PathBuf,
+    pub(crate) edits: Vec<Edit>,
+}
+
+/// Reads the variants file, the paths of the textures are relative to it.
+pub(crate) fn load_variants(path: &Path) -> Result<Vec<Variant>, LoadError> {
+    let doc = toml::parse(&std::fs::read_to_string(path)?)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let variants = tables(&doc, "variant")?;
+    if variants.is_empty() {
+        return Err(LoadError::Invalid("there are no [[variant]] tables".to_string()));
+    }
+    let variants: Result<Vec<Variant>, String> = variants.into_iter()
+        .enumerate()
+        .map(|(i, variant)| parse_variant(variant, dir).map_err(|why| format!("variant #{}: {}", i + 1, why)))
+        .collect();
+    Ok(variants?)
+}
+
+fn parse_variant(variant: &toml::Table, dir: &Path) -> Result<Variant, String> {
+    let mut edits = Vec::new();
+    if let Some(materials) = table(variant, "material")? {
+        for name in materials.keys() {
+            let material = table(materials, name)?.expect("the key of the table");
+            let material = file::parse_material_table(material, dir).map_err(|why| format!("the material of {}: {}", name, why))?;
+            edits.push(Edit::Material { name: name.to_string(), material });
+        }
+    }
+    if let Some(moves) = table(variant, "move")? {
+        for name in moves.keys() {
+            edits.push(Edit::Move { name: name.to_string(), offset: file::vec3(moves, name)? });
+        }
+    }
+    Ok(Variant { output: PathBuf::from(string(variant, "output")?), edits })
+}
+
+fn split(value: &str) -> Result<(&str, &str), String> {
+    value.split_once('=')
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| format!("{} must be the name of the objects, = and the value", value))
+}
@@ -0,0 +1,137 @@
+/**
+The environment map: the light of the whole surroundings from an equirectangular image,
+usually the HDR panorama of a real sky or a studio, see `hdr`.
+
+The image wraps around the scene the way the maps of the Earth wrap around a globe:
+u is the longitude, the middle of the image faces +x, v the latitude from the bottom
+(straight down) to the top (straight up). The panorama can be turned around the vertical
+axis and made brighter or darker, so the sun of it can be put where the shot needs it
+without editing the image.
+
+The small and bright parts of the panorama, the sun or the softboxes of a studio,
+light almost the whole scene, and the diffuse rays scattered in all directions rarely
+hit them. The scene lit by them is covered with the noise, the fireflies where a ray
+did find the sun. Instead, every diffuse hit sends a ray to a direction picked by the
+brightness of the image, the sun far more often than the rest of it, and gathers
+the light from there the way it gathers a light. The diffuse rays that escape
+bring nothing, their sky is already counted.
+
+LEARN:
+A direction picked more often counts less: the light it brings is divided by the density
+of the picking (the importance sampling), so the average is the same as of the directions
+picked evenly, only with far less noise. The pixel is picked in two steps, its row by the
+brightness of the rows (the marginal distribution), then the column by the brightness
+of the pixels of the row (the conditional one), each by a binary search in the running
+sums of the brightness (the cumulative distributions). The rows near the poles cover
+a smaller part of the sphere, their pixels are squeezed, so their brightness is weighted
+by the sine of their angle from the pole.
+*/
+use std::f32::consts::PI;
+
+use crate::math::rand::drand32;
+use crate::math::vec::Vec3;
+
+pub(crate) struct Environment {
+    w: usize,
+    h: usize,
+    /// The linear radiance, row by row from the top-left corner.
+    pixels: Vec<Vec3>,
+    /// The running sums of the weights of the rows, from the top, the last one is the total.
+    rows: Vec<f32>,
+    /// The running sums of the weights of the pixels within their rows, row by row.
+    columns: Vec<f32>,
+    /// The turn of the panorama to the east, in the turns (1 is 360 degrees).
+    rotation: f32,
+    /// The scale of the radiance of the pixels.
+    intensity: f32,
+}
+
+impl Environment {
+    /// The environment of the image of the size, the pixels row by row from the top-left corner.
+    pub(crate) fn new(w: usize, h: usize, pixels: Vec<Vec3>) -> Environment {
+        let mut columns = Vec::with_capacity(w * h);
+        let mut rows = Vec::with_capacity(h);
+        let mut total = 0.;
+        for y in 0..h {
+            let sine = (PI * (y as f32 + 0.5) / h as f32).sin();
+            let mut sum = 0.;
+            for pixel in &pixels[y * w..(y + 1) * w] {
+                sum += brightness(pixel) * sine;
+                columns.push(sum);
+            }
+            total += sum;
+            rows.push(total);
+        }
+        Environment { w, h, pixels, rows, columns, rotation: 0., intensity: 1. }
+    }
+
+    /// The panorama turned by the degrees to the east, i.e. counterclockwise seen from above.
+    pub(crate) fn with_rotation(self, degrees: f32) -> Environment {
+        Environment { rotation: degrees / 360., ..self }
+    }
+
+    /// The panorama brighter by the stops, or darker by the negative ones.
+    pub(crate) fn with_exposure(self, stops: f32) -> Environment {
+        Environment { intensity: 2f32.powf(stops), ..self }
+    }
+
+    /// The light coming from the direction.
+    pub(crate) fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let (x, y) = self.pixel(direction);
+        self.intensity * &self.pixels[y * self.w + x]
+    }
+
+    /// A random direction towards the environment, the bright parts are picked more often,
+    /// and the light from there divided by the density of the picking.
+    /// `None` when the environment is black.
+    pub(crate) fn sample(&self) -> Option<(Vec3, Vec3)> {
+        let total = *self.rows.last()?;
+        if total <= 0. {
+            return None;
+        }
+        // the first running sum above the random share of the total is the one picked
+        let share = drand32() * total;
+        let y = self.rows.partition_point(|&sum| sum <= share).min(self.h - 1);
+        let row = &self.columns[y * self.w..(y + 1) * self.w];
+        let share = drand32() * row[self.w - 1];
+        let x = row.partition_point(|&sum| sum <= share).min(self.w - 1);
+
+        // a random point of the pixel, its directions are equally likely
+        let u = (x as f32 + drand32()) / self.w as f32;
+        let theta = PI * (y as f32 + drand32()) / self.h as f32;
+        let sine = theta.sin();
+        if sine <= 0. {
+            return None;
+        }
+        let weight = row[x] - if x == 0 { 0. } else { row[x - 1] };
+        if weight <= 0. {
+            return None;
+        }
+        // the pixel covers 2π/w by π/h of the angles, sin θ of them is the solid angle
+        let density = weight / total * (self.w * self.h) as f32 / (2. * PI * PI * sine);
+        Some((direction(u + self.rotation, theta), self.intensity * &self.pixels[y * self.w + x] / density))
+    }
+
+    /// The pixel seen in the direction, the column and the row from the top-left corner.
+    fn pixel(&self, direction: &Vec3) -> (usize, usize) {
+        let d = direction.unit();
+        let theta = d.y().clamp(-1., 1.).acos();
+        let phi = f32::atan2(-d.z(), d.x()) + PI;
+        let u = (phi / (2. * PI) - self.rotation).rem_euclid(1.);
+        let x = (u * self.w as f32) as usize;
+        let y = (theta / PI * self.h as f32) as usize;
+        (x.min(self.w - 1), y.min(self.h - 1))
+    }
+}
+
+/// The direction of the longitude (0..1 from -x) and the angle from straight up.
+fn direction(u: f32, theta: f32) -> Vec3 {
+    let phi = 2. * PI * u;
+    let (sin, cos) = theta.sin_cos();
+    Vec3::new(-sin * phi.cos(), cos, sin * phi.sin())
+}
+
+/// How bright the pixel is for the picking, any measure that is 0 only for black works.
+fn brightness(pixel: &Vec3) -> f32 {
+    (pixel.x() + pixel.y() + pixel.z()) / 3.
+}
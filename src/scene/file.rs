@@ -0,0 +1,1432 @@
+/**
+Loading of the scenes from the text files.
+
+The scene file is a TOML document, see `scenes/default.toml` for an example:
+
+```toml
+version = 2             # the version of the format, see `schema`
+# optional, the scene files this one is layered over, in the order, see below
+include = ["studio.toml", "lights/rig.toml"]
+# optional, how close the hits of the surfaces of different priorities are to be the same,
+# as the share of the distance, 1e-4 by default
+coincidence = 1e-4
+# optional, the length of the unit of the scene, the imported models are converted to it,
+# a name (meters, centimeters, millimeters, inches, feet) or the number of the meters, "meters" by default
+unit = "meters"
+
+[image]
+width = 1024
+height = 512
+pixel_aspect = 1        # optional, for the anamorphic images
+
+# without the [camera] table the static camera is used
+[camera]
+look_from = [3, 3, 2]   # optional, from the front and a little above, to see all the surfaces
+look_at = [0, 0, -1]    # optional, the center of the surfaces by default
+up = [0, 1, 0]          # optional
+fov = 20                # vertical, in degrees
+aperture = 0.1          # optional, 0 is a pinhole camera
+focus_dist = 5          # optional, the distance to look_at by default
+
+# optional, the sun and the sky at the place and the time, instead of the plain gradient
+[sky]
+latitude = 48.14        # degrees, the north is positive
+longitude = 11.58       # degrees, the east is positive
+date = "2024-06-21"
+time = "17:30"          # the local time
+timezone = 2            # optional, the hours ahead of UTC, by the longitude by default
+north = [0, 0, -1]      # optional, the direction of the north in the scene, -z by default
+sun_intensity = 2.5     # optional, how bright the sun is on the surfaces, 2.5 by default
+
+# optional, the light of the surroundings from an equirectangular panorama instead of the sky,
+# a Radiance HDR image (.hdr) or a PPM one, its middle faces +x
+[environment]
+image = "studio.hdr"
+rotation = 90           # optional, degrees to the east (counterclockwise seen from above), 0 by default
+exposure = -1           # optional, the stops brighter, or darker when negative, 0 by default
+
+# optional, the night sky of the generated stars instead of the sky or the panorama
+[stars]
+seed = 7                # optional, another seed is another sky, 0 by default
+density = 0.15          # optional, the stars per square degree, as many as the eye sees by default
+brightness = 1          # optional, multiplies the light of the stars
+milky_way = true        # optional, the band of the glow across the sky, false by default
+
+# optional, the haze that fades the far objects into its color, the densest at the base height
+[fog]
+color = [0.7, 0.8, 0.9]
+density = 0.05          # the fraction of the light scattered per unit of the length
+height = 0              # optional, the base height, the bottom of the surfaces by default
+falloff = 0.5           # optional, how fast it thins out above the base, 0 is the uniform fog
+# optional, the sunlight the fog scatters, the color on average, it glows around
+# the direction of the sun about ten times as bright
+sun = { direction = [1, 0.3, -1], color = [0.3, 0.2, 0.1] }
+# optional, the fog scatters the light of the lights and of the sun of the sky too,
+# with the shadows of the objects in it, the beams and the god rays, false by default
+lights = true
+steps = 32              # optional, the steps along every ray in the lit fog, the more the less noise
+
+[[sphere]]
+center = [0, 0, -1]
+radius = 0.5
+material = { type = "lambertian", albedo = [0.1, 0.2, 0.5] }
+# optional, which rays see the sphere, all are true by default
+visibility = { camera = true, shadow = true, reflection = true }
+# optional, every surface may have a name and the tags to find it by, e.g. to change it
+name = "hero"
+tags = ["props", "blue"]
+# optional, where the surface coincides with another one, e.g. the decal on the wall,
+# the one of the higher priority is seen instead of the speckle of both, 0 by default
+priority = 1
+
+[[sphere]]
+center = [0, 0, -3]
+radius = 1
+# the image wraps around the sphere, the path is relative to the scene file
+material = { type = "lambertian", texture = "textures/earthmap.ppm" }
+rotation = 90           # optional, turns the texture to the east, in degrees
+
+[[sphere]]
+center = [0, -100.5, -1]
+radius = 100
+# optional, the texture coordinates are scaled, then turned and shifted
+material = { type = "lambertian", texture = { image = "textures/tiles.ppm", scale = [400, 200], offset = [0.5, 0], rotation = 45 } }
+
+[[sphere]]
+center = [2, 0, -3]
+radius = 1
+# the procedural textures need no image
+material = { type = "lambertian", texture = { type = "bricks", brick = [0.6, 0.2, 0.1], mortar = [0.8, 0.8, 0.8], mortar_width = 0.1, scale = [12, 6] } }
+
+[[sphere]]
+center = [0, 3, -5]
+radius = 1
+material = { type = "lambertian", albedo = [0.5, 0.5, 0.5] }
+# optional, moves the surface out by the brightness of the texture times the amount,
+# the sphere becomes the mesh of `resolution` rows of the triangles (64 by default)
+displacement = { texture = "textures/craters.ppm", amount = 0.1, resolution = 128 }
+
+# the rectangle from the corner, size along x and z, displaced up,
+# the resolution is the number of the cells along each side (128 by default)
+[[heightfield]]
+corner = [-10, -1, -20]
+size = [20, 20]
+displacement = { texture = { type = "voronoi", size = 1, colors = [[0, 0, 0], [1, 1, 1]] }, amount = 0.5 }
+material = { type = "lambertian", albedo = [0.4, 0.5, 0.3] }
+
+# the triangles, from a Wavefront OBJ file (relative to the scene file),
+[[mesh]]
+obj = "models/lamp.obj"
+# optional, the unit of the lengths of the file, the one of the scene by default,
+# the up axis of the file, "y" or "z" (turned to the y of the scene), "y" by default,
+# and the factor of the size, 1 by default; also for the PLY files and the meshes in place
+unit = "millimeters"
+up = "z"
+scale = 2
+# the material of the faces the model does not assign one to
+material = { type = "lambertian", albedo = [0.5, 0.5, 0.5] }
+# optional, the model is the control mesh of the smooth surface, each step
+# of the subdivision splits every triangle into four
+subdivisions = 3
+# optional, the materials of the faces by the names the model uses (`usemtl`)
+materials = { glass = { type = "dielectric", ior = 1.5 }, brass = { type = "metal", albedo = [0.8, 0.6, 0.3], fuzz = 0.2 } }
+
+# or from a PLY file, e.g. of a scan, the colors of its vertices multiply the albedo
+[[mesh]]
+ply = "models/statue.ply"
+material = { type = "lambertian", albedo = [1, 1, 1] }
+
+# or written in place, the faces are counterclockwise seen from the outside
+[[mesh]]
+vertices = [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]]
+uvs = [[0, 0], [1, 0], [1, 1], [0, 1]]    # optional, for every vertex
+colors = [[1, 1, 1], [1, 0, 0], [0, 1, 0], [0, 0, 1]]   # optional, for every vertex
+faces = [[0, 1, 2, 3]]                    # the polygons are split into the triangles
+face_materials = ["red"]                  # optional, for every face
+materials = { red = { type = "lambertian", albedo = [0.8, 0.1, 0.1] } }
+
+# the thin fibers, e.g. the hair, the grass or the cables, every curve by its control points,
+# a smooth B-spline near them or, with `basis = "bezier"`, the Bezier spans through every third
+[[curves]]
+points = [[[0, 0, 0], [0, 0.3, 0], [0.1, 0.6, 0], [0.3, 0.8, 0]], [[0.2, 0, 0], [0.2, 0.3, 0], [0.3, 0.5, 0], [0.5, 0.6, 0]]]
+width = [0.02, 0.005]   # at the root and at the tip, or a single number
+material = { type = "hair", color = [0.6, 0.4, 0.2], roughness = 0.2 }
+
+# the point cloud, e.g. a scan, from the vertices of a PLY file and their colors
+[[points]]
+ply = "scans/room.ply"
+radius = 0.01
+shape = "disk"          # optional, the disks that face the rays or the spheres
+material = { type = "lambertian", albedo = [1, 1, 1] }
+
+# or written in place
+[[points]]
+positions = [[0, 2, 0], [0.1, 2, 0]]
+colors = [[1, 0, 0], [0, 1, 0]]   # optional, for every point
+radius = 0.05
+material = { type = "lambertian", albedo = [1, 1, 1] }
+
+# the smoke or the cloud of the densities on a grid, from a Mitsuba .vol file,
+# or the raw little-endian 32-bit floats with the `resolution = [x, y, z]` of the grid,
+# x changing the fastest; the grid is stretched over the box from the corner
+[[volume]]
+grid = "volumes/smoke.vol"
+corner = [-1, 0, -1]
+size = [2, 3, 2]
+density = 20            # optional, multiplies the densities, the particles per unit of the length
+material = { type = "isotropic", albedo = [0.8, 0.8, 0.8] }
+
+# the bulb, its intensity is the light per steradian, it falls with the square of the distance
+[[light]]
+position = [0, 4, 0]
+intensity = [20, 18, 15]
+direction = [0, -1, 0]  # optional, where the spot and the nadir of the profile point, down by default
+# optional, the spotlight within the half-angle in degrees around the direction,
+# the blend of the cone fades out towards its edge (0.2 by default)
+spot = { angle = 30, blend = 0.2 }
+# optional, the IES profile of the fixture, the intensity is the one of its brightest direction
+ies = "lights/downlight.ies"
+
+# the window the sky lights the room through, the rectangle from the corner along the two sides;
+# the sky seen through it is gathered at every diffuse hit, so the room is far less noisy
+[[portal]]
+corner = [-1, 1, -6]
+sides = [[2, 0, 0], [0, 1.5, 0]]
+
+# optional, cuts away everything on the side the normal points to
+[[clip_plane]]
+point = [0, 0, -1]
+normal = [0, 0, 1]
+cap = true              # optional, closes the cut solids with the plane
+```
+
+Materials: `lambertian` (albedo, texture), `metal` (albedo, fuzz or roughness, preset or n and k), `dielectric` (ior,
+absorption, waves), `water` (absorption, waves), the dielectric of the ior 1.33 that
+absorbs the red light first, `hair` (color, roughness), which reflects the light
+around the fibers of the curves, and `isotropic` (albedo), which scatters the light in all directions, for the volumes.
+The albedo of the textured lambertian is optional, it tints the image.
+The metal of the `preset` (gold, silver, copper or aluminum) or of the complex index
+of refraction `n` and `k` of the red, the green and the blue light, see `conductor`,
+reflects more at the grazing angles, its albedo is optional and tints it.
+The `fuzz` of a metal (0..1) blurs its reflection, the `roughness` (0..1) of the physically
+based renderers may be given instead, it is converted to the fuzz of about the same blur.
+The textures are the PPM images, the spheres that use the same file share it
+even when they transform it differently. The scale may be a single number for both axes.
+
+The optional `absorption` of a dielectric is the share of the light it absorbs per unit
+of the length inside, for every channel, e.g. `[0, 0.5, 0.5]` for the red glass,
+it needs a closed surface around the medium. The `waves` ripple its surface,
+`{ length = 1, height = 0.01 }` by default for the water: the longest wave and its height.
+The dielectrics may be nested, e.g. the glass of water with the bubbles of air in it,
+their solids overlap a little and the optional `priority` (0 by default) of each tells
+which medium fills the overlap, e.g. 2 for the glass, 1 for the water and 3 for the bubbles
+(the dielectric of the ior 1); the ray inside knows the medium it is in, so the surfaces
+between the media bend it by their ratio of the indices and every medium absorbs its share.
+
+Any material may have a `shader` that changes its light after the material, e.g.
+`shader = { type = "rim", color = [1, 0.6, 0.2], power = 3 }`: `rim` (color, power),
+`facing` and `bounces`, see `shader`.
+
+The procedural textures have a `type` instead of the `image`:
+`bricks` (brick, mortar, mortar_width), `stripes` (colors), `gradient` (from, to, colors)
+and `voronoi` (size, colors), where `colors` are two colors, e.g. `[[1, 1, 1], [0, 0, 0]]`.
+
+A scene file may be layered over another scene, e.g. the file of just the lights or just
+the camera, shared by many scenes, see `layer`. The file may name the files it is layered
+over itself with `include`, a path or the array of them, so the scene may be split into
+the files of the set, the lights and the camera: the first included file is the base,
+the others and then the file itself are layered over it, and the included files may
+include the others in turn.
+
+The relative paths of the included files and of the assets (the models, the textures,
+the panoramas, the volumes and the profiles of the lights) are relative to the file that
+names them. The ones not found there are looked for in the directories of the search path,
+`--search-path` and then `RAYTRACER_SEARCH_PATH`, e.g. the shared library of the models,
+so the scene moves to another machine with the library anywhere, see `locate`.
+
+LEARN:
+The search path is the setting of the whole process, like the current directory,
+so it does not have to be passed through every parser of the assets down to the texture.
+*/
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::math::vec::Vec3;
+use crate::scene::arena::Id;
+use crate::renderer::ppm;
+use crate::scene::camera::Camera;
+use crate::scene::conductor::Conductor;
+use crate::scene::environment::Environment;
+use crate::scene::fog::Fog;
+use crate::scene::hdr;
+use crate::scene::light::ies::Profile;
+use crate::scene::light::Light;
+use crate::scene::material::{roughness_to_fuzz, Material};
+use crate::scene::obj::{self, Model};
+use crate::scene::ply;
+use crate::scene::portal::Portal;
+use crate::scene::schema::{self, Warning};
+use crate::scene::shader::{self, Shader};
+use crate::scene::Scene;
+use crate::scene::sky::{Sky, SolarPosition};
+use crate::scene::stars::Stars;
+use crate::scene::surfaces::bvh::Bounds;
+use crate::scene::surfaces::clip::ClipPlane;
+use crate::scene::surfaces::curves::{Basis, Curves};
+use crate::scene::surfaces::displacement::{self, Displacement};
+use crate::scene::surfaces::mesh::Mesh;
+use crate::scene::surfaces::points::{Points, Shape};
+use crate::scene::surfaces::stream;
+use crate::scene::surfaces::subdivision;
+use crate::scene::surfaces::volume::{self, Volume};
+use crate::scene::surfaces::{Surface, Visibility};
+use crate::scene::surfaces::world::{Label, World};
+use crate::scene::texture::procedural::Procedural;
+use crate::scene::texture::{ImageTexture, Texture, UvTransform};
+use crate::scene::waves::Waves;
+use crate::toml::{self, boolean, integer, number, optional, string, table, tables, Table, Value};
+
+#[derive(Debug)]
+pub(crate) enum LoadError {
+    Io(std::io::Error),
+    Syntax(toml::ParseError),
+    /// The file is a valid TOML, but not a valid scene.
+    Invalid(String),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(why) => write!(f, "cannot read the scene: {}", why),
+            LoadError::Syntax(why) => write!(f, "syntax error at {}", why),
+            LoadError::Invalid(why) => write!(f, "invalid scene: {}", why),
+        }
+    }
+}
+
+// LEARN:
+// With the `From` implementations the `?` operator converts the errors automatically.
+impl From<std::io::Error> for LoadError {
+    fn from(why: std::io::Error) -> Self {
+        LoadError::Io(why)
+    }
+}
+
+impl From<toml::ParseError> for LoadError {
+    fn from(why: toml::ParseError) -> Self {
+        LoadError::Syntax(why)
+    }
+}
+
+impl From<String> for LoadError {
+    fn from(why: String) -> Self {
+        LoadError::Invalid(why)
+    }
+}
+
+/// Loads the scene and prints the warnings about the file to the standard error.
+pub(crate) fn load(path: &Path) -> Result<Scene, LoadError> {
+    let (scene, warnings) = load_with_warnings(path)?;
+    for warning in &warnings {
+        eprintln!("Warning: {}: {}", path.display(), warning);
+    }
+    Ok(scene)
+}
+
+/// Loads the scene and the warnings about the file: its version, the keys that are ignored.
+pub(crate) fn load_with_warnings(path: &Path) -> Result<(Scene, Vec<Warning>), LoadError> {
+    let text = std::fs::read_to_string(path)?;
+    parse(&text, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Parses the scene, the paths of the textures and of the included files are relative
+/// to the directory, see `locate`.
+pub(crate) fn parse(text: &str, dir: &Path) -> Result<(Scene, Vec<Warning>), LoadError> {
+    let (doc, mut warnings) = read_document(text)?;
+    let mut documents = included(doc, dir, &mut Vec::new(), &mut warnings)?.into_iter();
+    let (doc, dir) = documents.next().expect("the document itself is among the documents");
+    let mut scene = parse_document(&doc, &dir)?;
+    for (doc, dir) in documents {
+        layer_document(&mut scene, &doc, &dir)?;
+    }
+    Ok((scene, warnings))
+}
+
+/// The scene of the single document, without its includes.
+fn parse_document(doc: &Table, dir: &Path) -> Result<Scene, LoadError> {
+    let (mut world, (w, h, pixel_aspect)) = parse_world(doc, dir)?;
+    let aspect = w as f32 * pixel_aspect / h as f32;
+
+    // the camera and the fog fit the surfaces by default, they come after them
+    if let Some(fog) = table(doc, "fog")? {
+        let fog = parse_fog(fog, &world).map_err(|why| format!("fog: {}", why))?;
+        world.set_fog(fog);
+    }
+    let camera = match table(doc, "camera")? {
+        Some(camera) => parse_camera(camera, aspect, world.bounding_box())?,
+        None => Camera::static_camera(aspect),
+    };
+
+    Ok(Scene { camera, world, w, h, pixel_aspect, origin: None })
+}
+
+/// Layers the scene file over the scene, e.g. the rig of the lights or the camera shared
+/// by many scenes (`--layer`): its surfaces, materials and lights are added to the scene,
+/// see `Scene::merge`, and its [image], [camera] and [fog] replace the ones of the scene
+/// when it has them. Its camera and fog fit all the surfaces, of the scene and of the file,
+/// so the file of just the camera may frame whatever it is layered over.
+/// The files it includes are layered before it.
+/// Prints the warnings about the file to the standard error, as `load` does.
+pub(crate) fn layer(scene: &mut Scene, path: &Path) -> Result<(), LoadError> {
+    let text = std::fs::read_to_string(path)?;
+    let (doc, mut warnings) = read_document(&text)?;
+    let documents = included(doc, path.parent().unwrap_or(Path::new("")), &mut Vec::new(), &mut warnings);
+    for warning in &warnings {
+        eprintln!("Warning: {}: {}", path.display(), warning);
+    }
+    for (doc, dir) in documents? {
+        layer_document(scene, &doc, &dir)?;
+    }
+    Ok(())
+}
+
+/// Layers the single document over the scene, see `layer`.
+fn layer_document(scene: &mut Scene, doc: &Table, dir: &Path) -> Result<(), LoadError> {
+    let (world, (w, h, pixel_aspect)) = parse_world(doc, dir)?;
+    scene.merge(Scene { camera: scene.camera.clone(), world, w, h, pixel_aspect, origin: None });
+
+    // validated with the world
+    if let Some(coincidence) = optional(doc, "coincidence", number)? {
+        scene.world.set_coincidence(coincidence);
+    }
+    if table(doc, "image")?.is_some() {
+        (scene.w, scene.h, scene.pixel_aspect) = (w, h, pixel_aspect);
+    }
+    if let Some(fog) = table(doc, "fog")? {
+        let fog = parse_fog(fog, &scene.world).map_err(|why| format!("fog: {}", why))?;
+        scene.world.set_fog(fog);
+    }
+    if let Some(camera) = table(doc, "camera")? {
+        scene.camera = parse_camera(camera, scene.aspect(), scene.world.bounding_box())?;
+    }
+    Ok(())
+}
+
+/// The documents of the files the document includes, each one after the ones it includes
+/// in turn, and then the document itself, with their directories: the order they are
+/// layered in. `including` are the files on the way to the document, the file among them
+/// would include itself forever. The warnings about the included files are added
+/// with their paths.
+fn included(doc: Table, dir: &Path, including: &mut Vec<PathBuf>, warnings: &mut Vec<Warning>) -> Result<Vec<(Table, PathBuf)>, LoadError> {
+    let mut documents = Vec::new();
+    for path in includes(&doc)? {
+        let path = locate(dir, &path);
+        let identity = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if including.contains(&identity) {
+            return Err(LoadError::Invalid(format!("{} includes itself through the files it includes", path.display())));
+        }
+        // the errors of the included file name it, the ones of the file itself do not
+        let in_file = |why: LoadError| match why {
+            LoadError::Invalid(why) => LoadError::Invalid(format!("{}: {}", path.display(), why)),
+            other => LoadError::Invalid(format!("{}: {}", path.display(), other)),
+        };
+        let text = std::fs::read_to_string(&path).map_err(|why| in_file(why.into()))?;
+        let (included_doc, included_warnings) = read_document(&text).map_err(in_file)?;
+        warnings.extend(included_warnings.into_iter().map(|warning| Warning {
+            location: format!("{}: {}", path.display(), warning.location),
+            ..warning
+        }));
+        including.push(identity);
+        let included_dir = path.parent().unwrap_or(Path::new(""));
+        documents.extend(included(included_doc, included_dir, including, warnings).map_err(in_file)?);
+        including.pop();
+    }
+    documents.push((doc, dir.to_path_buf()));
+    Ok(documents)
+}
+
+/// The paths the document includes, `include` is a path or the array of them.
+fn includes(doc: &Table) -> Result<Vec<String>, LoadError> {
+    let Some(include) = doc.get("include") else {
+        return Ok(Vec::new());
+    };
+    let paths = match include.as_array() {
+        Some(items) => items.iter().map(|item| item.as_str().map(str::to_string)).collect(),
+        None => include.as_str().map(|path| vec![path.to_string()]),
+    };
+    paths.ok_or_else(|| LoadError::Invalid("include must be a path or an array of the paths".to_string()))
+}
+
+/// The directories set by `--search-path`, see `locate`.
+static SEARCH_PATH: RwLock<Vec<PathBuf>> = RwLock::new(Vec::new());
+
+/// The environment variable of the directories looked in after the ones of `--search-path`,
+/// separated as in `PATH`.
+const SEARCH_PATH_VARIABLE: &str = "RAYTRACER_SEARCH_PATH";
+
+/// Sets the directories the relative paths of the scene files are looked for in
+/// when they are not next to the file that names them (`--search-path`).
+pub(crate) fn set_search_path(dirs: Vec<PathBuf>) {
+    *SEARCH_PATH.write().unwrap_or_else(PoisonError::into_inner) = dirs;
+}
+
+/// The file of the path named by the scene file in the directory: the absolute path
+/// as it is, the file next to the scene file, or the file in the first directory
+/// of the search path that has it. When none has it, the path next to the scene file,
+/// so the error of reading it names the place the path is relative to.
+fn locate(dir: &Path, path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    let near = dir.join(path);
+    if path.is_absolute() || near.exists() {
+        return near;
+    }
+    let variable = std::env::var_os(SEARCH_PATH_VARIABLE).unwrap_or_default();
+    let set = SEARCH_PATH.read().unwrap_or_else(PoisonError::into_inner).clone();
+    set.into_iter()
+        .chain(std::env::split_paths(&variable))
+        .map(|search| search.join(path))
+        .find(|candidate| candidate.exists())
+        .unwrap_or(near)
+}
+
+/// The files named by the scene file and the files it includes that `locate` cannot find,
+/// each with where it is named, e.g. `mesh #2: obj`. The loading stops at the first one,
+/// these are all of them.
+pub(crate) fn missing_files(path: &Path) -> Result<Vec<(String, PathBuf)>, LoadError> {
+    let text = std::fs::read_to_string(path)?;
+    let (doc, mut warnings) = read_document(&text)?;
+    let documents = included(doc, path.parent().unwrap_or(Path::new("")), &mut Vec::new(), &mut warnings)?;
+    let mut missing = Vec::new();
+    for (doc, dir) in &documents {
+        let mut look_for = |location: String, name: &Value| {
+            let path = name.as_str().map(|name| locate(dir, name)).filter(|path| !path.exists());
+            missing.extend(path.map(|path| (location, path)));
+        };
+        if let Some(image) = doc.get("environment").and_then(Value::as_table).and_then(|environment| environment.get("image")) {
+            look_for("environment: image".to_string(), image);
+        }
+        for key in ["sphere", "heightfield", "mesh", "curves", "points", "volume", "light"] {
+            let items = doc.get(key).and_then(Value::as_array).unwrap_or_default();
+            for (i, item) in items.iter().filter_map(Value::as_table).enumerate() {
+                let location = |key_path: &str| format!("{} #{}: {}", key, i + 1, key_path);
+                for file in ["obj", "ply", "grid", "ies"] {
+                    if let Some(name) = item.get(file) {
+                        look_for(location(file), name);
+                    }
+                }
+                // the texture is the path or the table of the image, the procedural ones have a `type`
+                let mut texture_of = |key_path: String, owner: Option<&Value>| {
+                    match owner.and_then(Value::as_table).and_then(|owner| owner.get("texture")) {
+                        Some(Value::Table(texture)) if texture.get("type").is_none() => {
+                            if let Some(image) = texture.get("image") {
+                                look_for(format!("{}.texture.image", key_path), image);
+                            }
+                        }
+                        Some(Value::Table(_)) | None => {}
+                        Some(texture) => look_for(format!("{}.texture", key_path), texture),
+                    }
+                };
+                texture_of(location("material"), item.get("material"));
+                texture_of(location("displacement"), item.get("displacement"));
+                if let Some(named) = item.get("materials").and_then(Value::as_table) {
+                    for name in named.keys() {
+                        texture_of(location(&format!("materials.{}", name)), named.get(name));
+                    }
+                }
+            }
+        }
+    }
+    Ok(missing)
+}
+
+/// The document of the scene upgraded to the current version of the format,
+/// and the warnings about it: its version, the keys that are ignored.
+fn read_document(text: &str) -> Result<(Table, Vec<Warning>), LoadError> {
+    let mut doc = toml::parse(text)?;
+    let mut warnings = schema::upgrade(&mut doc)?;
+    warnings.extend(schema::unknown_keys(&doc));
+    Ok((doc, warnings))
+}
+
+/// The world of the document without the fog, and the width, the height and the pixel aspect
+/// of its image, 1024x512 of the square pixels without the [image].
+fn parse_world(doc: &Table, dir: &Path) -> Result<(World, (i32, i32, f32)), LoadError> {
+    let (w, h, pixel_aspect) = match table(doc, "image")? {
+        Some(image) => (
+            integer(image, "width")?,
+            integer(image, "height")?,
+            optional(image, "pixel_aspect", number)?.unwrap_or(1.)),
+        None => (1024, 512, 1.),
+    };
+    let (w, h) = validate_resolution(w, h)?;
+    validate_pixel_aspect(pixel_aspect)?;
+
+    let mut world = World::new();
+    if let Some(coincidence) = optional(doc, "coincidence", number)? {
+        if !(0. ..=0.1).contains(&coincidence) {
+            return Err(LoadError::Invalid(format!("the coincidence {} must be between 0 and 0.1", coincidence)));
+        }
+        world.set_coincidence(coincidence);
+    }
+    if let Some(sky) = table(doc, "sky")? {
+        world.set_sky(parse_sky(sky).map_err(|why| format!("sky: {}", why))?);
+    }
+    if let Some(environment) = table(doc, "environment")? {
+        if world.sky().is_some() {
+            return Err(LoadError::Invalid("the scene has either the [sky] or the [environment]".to_string()));
+        }
+        world.set_environment(parse_environment(environment, dir).map_err(|why| format!("environment: {}", why))?);
+    }
+    if let Some(stars) = table(doc, "stars")? {
+        if world.sky().is_some() || world.environment().is_some() {
+            return Err(LoadError::Invalid("the scene has only one of the [sky], the [environment] and the [stars]".to_string()));
+        }
+        world.set_stars(parse_stars(stars).map_err(|why| format!("stars: {}", why))?);
+    }
+    let unit = match doc.get("unit") {
+        Some(unit) => parse_unit(unit).map_err(|why| format!("unit: {}", why))?,
+        None => 1.,
+    };
+    let mut textures = Textures { dir, unit, loaded: HashMap::new() };
+    add_surfaces(doc, "sphere", &mut world, &mut textures, parse_sphere)?;
+    add_surfaces(doc, "heightfield", &mut world, &mut textures, parse_heightfield)?;
+    add_surfaces(doc, "mesh", &mut world, &mut textures, parse_mesh)?;
+    add_surfaces(doc, "curves", &mut world, &mut textures, parse_curves)?;
+    add_surfaces(doc, "points", &mut world, &mut textures, parse_points)?;
+    add_surfaces(doc, "volume", &mut world, &mut textures, parse_volume)?;
+
+    let mut profiles = HashMap::new();
+    for (i, light) in tables(doc, "light")?.into_iter().enumerate() {
+        let light = parse_light(light, dir, &mut profiles).map_err(|why| format!("light #{}: {}", i + 1, why))?;
+        world.add_light(light);
+    }
+
+    for (i, portal) in tables(doc, "portal")?.into_iter().enumerate() {
+        let portal = parse_portal(portal).map_err(|why| format!("portal #{}: {}", i + 1, why))?;
+        world.add_portal(portal);
+    }
+
+    for (i, plane) in tables(doc, "clip_plane")?.into_iter().enumerate() {
+        let plane = parse_clip_plane(plane).map_err(|why| format!("clip_plane #{}: {}", i + 1, why))?;
+        world.add_clip_plane(plane);
+    }
+
+    Ok((world, (w, h, pixel_aspect)))
+}
+
+/// The material written as in the scene file, e.g. `{ type = "metal", albedo = [1, 1, 1], fuzz = 0 }`,
+/// the paths of the textures are relative to the directory.
+pub(crate) fn parse_inline_material(text: &str, dir: &Path) -> Result<Material, String> {
+    let doc = toml::parse(&format!("material = {}", text)).map_err(|why| format!("syntax error at {}", why))?;
+    parse_material_of(&doc, &mut Textures { dir, unit: 1., loaded: HashMap::new() })
+}
+
+/// The material of the table as in the scene file, outside of a scene, e.g. of a variant.
+pub(crate) fn parse_material_table(material: &Table, dir: &Path) -> Result<Material, String> {
+    parse_material(material, &mut Textures { dir, unit: 1., loaded: HashMap::new() })
+}
+
+/// The largest width or height of the image.
+const MAX_SIZE: i64 = 1 << 16;
+
+pub(crate) fn validate_resolution(w: i64, h: i64) -> Result<(i32, i32), String> {
+    if w <= 0 || h <= 0 || w > MAX_SIZE || h > MAX_SIZE {
+        return Err(format!("image size {}x{} must be between 1 and {}", w, h, MAX_SIZE));
+    }
+    Ok((w as i32, h as i32))
+}
+
+pub(crate) fn validate_pixel_aspect(pixel_aspect: f32) -> Result<(), String> {
+    if pixel_aspect.is_finite() && pixel_aspect > 0. {
+        Ok(())
+    } else {
+        Err(format!("pixel aspect {} must be a positive number", pixel_aspect))
+    }
+}
+
+/// The camera of the table, without `look_at` it looks at the center of the surfaces,
+/// without `look_from` it looks from the front and a little above, as far as it needs
+/// to see all of them.
+fn parse_camera(camera: &Table, aspect: f32, bounds: Option<&Bounds>) -> Result<Camera, String> {
+    let up = optional(camera, "up", vec3)?.unwrap_or(Vec3::new(0., 1., 0.));
+    let fov = number(camera, "fov")?;
+    let framed = |key: &str| match bounds {
+        Some(bounds) => Ok(bounds.corners()),
+        None => Err(format!("missing `{}`, the scene has no surfaces to frame", key)),
+    };
+    let look_at = match optional(camera, "look_at", vec3)? {
+        Some(look_at) => look_at,
+        None => {
+            let (min, max) = framed("look_at")?;
+            0.5 * (min + max)
+        }
+    };
+    let look_from = match optional(camera, "look_from", vec3)? {
+        Some(look_from) => look_from,
+        None => {
+            let (min, max) = framed("look_from")?;
+            // the sphere around the box fits into the narrower of the two angles of the view
+            let center = 0.5 * (&min + &max);
+            let radius = 0.5 * (max - min).length();
+            let half_height = (fov.to_radians() / 2.).tan();
+            let half_angle = half_height.min(aspect * half_height).atan();
+            let distance = radius / half_angle.sin() + (&look_at - &center).length();
+            &look_at + distance * Vec3::new(0., 1., 3.).unit()
+        }
+    };
+    let aperture = optional(camera, "aperture", number)?.unwrap_or(0.);
+    let focus_dist = match optional(camera, "focus_dist", number)? {
+        Some(dist) => dist,
+        None => (&look_from - &look_at).length(),
+    };
+    Ok(Camera::positionable(look_from, look_at, up, fov, aspect, aperture, focus_dist))
+}
+
+fn parse_environment(environment: &Table, dir: &Path) -> Result<Environment, String> {
+    let path = locate(dir, string(environment, "image")?);
+    let loaded = if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hdr")) {
+        let image = hdr::load(&path)?;
+        Environment::new(image.w, image.h, image.pixels)
+    } else {
+        let image = ppm::read(&path).map_err(|why| why.to_string())?;
+        let decode = |c: u8| image.color_space.decode(c as f32 / 255.);
+        let pixels = image.pixels.iter().map(|&[r, g, b]| Vec3::new(decode(r), decode(g), decode(b))).collect();
+        Environment::new(image.w, image.h, pixels)
+    };
+    let exposure = optional(environment, "exposure", number)?.unwrap_or(0.);
+    if !exposure.is_finite() {
+        return Err(format!("exposure {} must be a number of stops", exposure));
+    }
+    Ok(loaded
+        .with_rotation(optional(environment, "rotation", number)?.unwrap_or(0.))
+        .with_exposure(exposure))
+}
+
+fn parse_stars(stars: &Table) -> Result<Stars, String> {
+    let seed = optional(stars, "seed", integer)?.unwrap_or(0);
+    let density = optional(stars, "density", number)?.unwrap_or(0.15);
+    if !(0. ..=100.).contains(&density) {
+        return Err(format!("density {} must be between 0 and 100 stars per square degree", density));
+    }
+    let brightness = optional(stars, "brightness", number)?.unwrap_or(1.);
+    if !(brightness >= 0. && brightness.is_finite()) {
+        return Err(format!("brightness {} must not be negative", brightness));
+    }
+    let sky = Stars::new(seed as u64, density).with_brightness(brightness);
+    Ok(if optional(stars, "milky_way", boolean)?.unwrap_or(false) { sky.with_milky_way() } else { sky })
+}
+
+fn parse_sky(sky: &Table) -> Result<Sky, String> {
+    let latitude = number(sky, "latitude")?;
+    if !(-90. ..=90.).contains(&latitude) {
+        return Err(format!("latitude {} must be between -90 and 90", latitude));
+    }
+    let longitude = number(sky, "longitude")?;
+    if !(-180. ..=180.).contains(&longitude) {
+        return Err(format!("longitude {} must be between -180 and 180", longitude));
+    }
+    let day = day_of_year(&string(sky, "date")?)?;
+    let time = string(sky, "time")?;
+    let hour = match time.split_once(':').map(|(h, m)| (h.parse::<u32>(), m.parse::<u32>())) {
+        Some((Ok(h), Ok(m))) if h < 24 && m < 60 => h as f32 + m as f32 / 60.,
+        _ => return Err(format!("time `{}` must be HH:MM", time)),
+    };
+    // the zone of the solar time, the real ones differ by the politics
+    let timezone = optional(sky, "timezone", number)?.unwrap_or((longitude / 15.).round());
+    let north = optional(sky, "north", vec3)?.unwrap_or(Vec3::new(0., 0., -1.));
+    if Vec3::cross(&north, &Vec3::new(0., 1., 0.)).squared_length() == 0. {
+        return Err("the north must point along the ground".to_string());
+    }
+    let strength = optional(sky, "sun_intensity", number)?.unwrap_or(2.5);
+    if strength < 0. || !strength.is_finite() {
+        return Err(format!("sun_intensity {} must not be negative", strength));
+    }
+    let position = SolarPosition::at(latitude, longitude, day, hour - timezone);
+    Ok(Sky::new(&position, &north, strength))
+}
+
+/// The day of the year of the date `YYYY-MM-DD`, 1 for the 1st of January.
+fn day_of_year(date: &str) -> Result<u32, String> {
+    let error = || format!("date `{}` must be YYYY-MM-DD", date);
+    let parts: Vec<u32> = date.split('-').map(|part| part.parse().map_err(|_| error())).collect::<Result<_, _>>()?;
+    let &[year, month, day] = parts.as_slice() else {
+        return Err(error());
+    };
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    let lengths = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if !(1..=12).contains(&month) || day == 0 || day > lengths[month as usize - 1] {
+        return Err(error());
+    }
+    Ok(lengths[..month as usize - 1].iter().sum::<u32>() + day)
+}
+
+/// The steps of the lit fog along every ray, by default and at most.
+const DEFAULT_FOG_STEPS: i64 = 32;
+const MAX_FOG_STEPS: i64 = 1024;
+
+/// The fog of the table, its base is at the bottom of the surfaces by default,
+/// the lit one scatters the light around the surfaces and the lights.
+fn parse_fog(fog: &Table, world: &World) -> Result<Fog, String> {
+    let bounds = world.bounding_box();
+    let density = number(fog, "density")?;
+    if density < 0. || !density.is_finite() {
+        return Err(format!("density {} must not be negative", density));
+    }
+    let falloff = optional(fog, "falloff", number)?.unwrap_or(0.);
+    if falloff < 0. || !falloff.is_finite() {
+        return Err(format!("falloff {} must not be negative", falloff));
+    }
+    let bottom = bounds.map_or(0., |bounds| bounds.corners().0.y());
+    let height = optional(fog, "height", number)?.unwrap_or(bottom);
+    let mut air = Fog::new(vec3(fog, "color")?, density, height, falloff);
+    if optional(fog, "lights", boolean)?.unwrap_or(false) {
+        let steps = optional(fog, "steps", integer)?.unwrap_or(DEFAULT_FOG_STEPS);
+        if !(1..=MAX_FOG_STEPS).contains(&steps) {
+            return Err(format!("steps {} must be between 1 and {}", steps, MAX_FOG_STEPS));
+        }
+        let lights = Bounds::around(world.lights().iter().map(Light::position));
+        let lit = bounds.cloned().unwrap_or(Bounds::empty()).union(&lights);
+        if lit.is_empty() {
+            return Err("the lit fog needs the surfaces or the lights".to_string());
+        }
+        air = air.with_lights(steps as usize, lit);
+    }
+    match table(fog, "sun")? {
+        Some(sun) => {
+            let direction = vec3(sun, "direction")?;
+            if direction.squared_length() == 0. {
+                return Err("the direction of the sun must not be zero".to_string());
+            }
+            Ok(air.with_sun(direction, vec3(sun, "color")?))
+        }
+        None => Ok(air),
+    }
+}
+
+/// Adds the surfaces of the array of the tables of the key, e.g. all the `[[sphere]]` ones,
+/// with their names and tags.
+fn add_surfaces(doc: &Table, key: &str, world: &mut World, textures: &mut Textures,
+                parse: fn(&Table, &mut World, &mut Textures) -> Result<Surface, String>) -> Result<(), String> {
+    for (i, table) in tables(doc, key)?.into_iter().enumerate() {
+        let (surface, label, priority) = parse(table, world, textures)
+            .and_then(|surface| Ok((surface, parse_label_of(table)?, parse_priority_of(table)?)))
+            .map_err(|why| format!("{} #{}: {}", key, i + 1, why))?;
+        let id = world.add(surface);
+        world.set_label(id, label);
+        if priority != 0 {
+            world.set_priority(id, priority);
+        }
+    }
+    Ok(())
+}
+
+fn parse_sphere(sphere: &Table, world: &mut World, textures: &mut Textures) -> Result<Surface, String> {
+    let center = vec3(sphere, "center")?;
+    let radius = number(sphere, "radius")?;
+    let material = add_material_of(sphere, world, textures)?;
+    let rotation = optional(sphere, "rotation", number)?.unwrap_or(0.);
+    let surface = match table(sphere, "displacement")? {
+        Some(displacement) => {
+            if radius <= 0. {
+                return Err(format!("the displaced sphere needs a positive radius, not {}", radius));
+            }
+            let (displacement, resolution) = parse_displacement(displacement, 64, textures)?;
+            Surface::mesh(displacement::sphere(&center, radius, rotation, resolution, &displacement), material)
+        }
+        None => Surface::sphere(center, radius, material).with_rotation(rotation),
+    };
+    Ok(surface.with_visibility(parse_visibility_of(sphere)?))
+}
+
+fn parse_heightfield(heightfield: &Table, world: &mut World, textures: &mut Textures) -> Result<Surface, String> {
+    let corner = vec3(heightfield, "corner")?;
+    let size = vec2(heightfield, "size")?;
+    if !(size[0] > 0. && size[1] > 0.) {
+        return Err(format!("size [{}, {}] must be positive", size[0], size[1]));
+    }
+    let material = add_material_of(heightfield, world, textures)?;
+    let displacement = table(heightfield, "displacement")?.ok_or("missing `displacement`")?;
+    let (displacement, resolution) = parse_displacement(displacement, 128, textures)?;
+    let mesh = displacement::heightfield(&corner, size, resolution, &displacement);
+    Ok(Surface::mesh(mesh, material).with_visibility(parse_visibility_of(heightfield)?))
+}
+
+fn parse_mesh(mesh: &Table, world: &mut World, textures: &mut Textures) -> Result<Surface, String> {
+    let model = match (optional(mesh, "obj", string)?, optional(mesh, "ply", string)?) {
+        (Some(_), Some(_)) => return Err("the mesh is either an `obj` or a `ply` file".to_string()),
+        (Some(path), None) => import(obj::load(&locate(textures.dir, path))?, mesh, textures.unit)?,
+        (None, Some(path)) => import(ply::load(&locate(textures.dir, path))?, mesh, textures.unit)?,
+        (None, None) => import(parse_model(mesh)?, mesh, textures.unit)?,
+    };
+    if model.faces.is_empty() {
+        return Err("the mesh has no faces".to_string());
+    }
+    let model = match optional(mesh, "subdivisions", integer)? {
+        Some(steps) if (0..=MAX_SUBDIVISIONS).contains(&steps) => subdivision::subdivide(model, steps as usize),
+        Some(steps) => return Err(format!("subdivisions {} must be between 0 and {}", steps, MAX_SUBDIVISIONS)),
+        None => model,
+    };
+
+    // the default material is the first one, the named ones follow in the order of the file
+    let mut materials = Vec::new();
+    let has_default = mesh.get("material").is_some();
+    if has_default {
+        materials.push(add_material_of(mesh, world, textures)?);
+    }
+    let mut names: Vec<&str> = Vec::new();
+    if let Some(named) = table(mesh, "materials")? {
+        for name in named.keys() {
+            let material = table(named, name)?.ok_or(format!("the material `{}` must be a table", name))?;
+            materials.push(add_material(material, world, textures).map_err(|why| format!("material `{}`: {}", name, why))?);
+            names.push(name);
+        }
+    }
+    let faces = model.faces.into_iter()
+        .map(|(face, name)| {
+            let index = match name.as_deref() {
+                Some(name) => names.iter().position(|known| *known == name)
+                    .map(|i| i + has_default as usize)
+                    .ok_or(format!("the faces use the material `{}` that is not in `materials`", name))?,
+                None if has_default => 0,
+                None => return Err("missing `material` of the faces without a material of their own".to_string()),
+            };
+            Ok((face, index))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut mesh_of = Mesh::new("mesh", model.points, faces);
+    if !model.colors.is_empty() {
+        mesh_of = mesh_of.with_colors(model.colors);
+    }
+    // the next mesh is loaded with this one on the disk, so the scene may be larger than the memory
+    if stream::is_enabled() && mesh_of.memory() > stream::MIN_STREAMED {
+        mesh_of = mesh_of.streamed().map_err(|why| format!("cannot write the mesh to the disk: {}", why))?;
+    }
+    Ok(Surface::mesh_of_materials(mesh_of, materials).with_visibility(parse_visibility_of(mesh)?))
+}
+
+/// The model converted to the scene of the unit of the length in meters:
+/// by the `unit` of the file, the `up` axis of the file turned to the y of the scene,
+/// and the `scale` of the table of the surface.
+fn import(mut model: Model, surface: &Table, scene_unit: f32) -> Result<Model, String> {
+    let unit = match surface.get("unit") {
+        Some(unit) => parse_unit(unit).map_err(|why| format!("unit: {}", why))?,
+        None => scene_unit,
+    };
+    let scale = optional(surface, "scale", number)?.unwrap_or(1.);
+    if scale <= 0. || !scale.is_finite() {
+        return Err(format!("scale {} must be positive", scale));
+    }
+    // the turn of the z up to the y up keeps the faces counterclockwise, the mirror would not
+    let z_up = match optional(surface, "up", string)?.as_deref() {
+        None | Some("y") => false,
+        Some("z") => true,
+        Some(other) => return Err(format!("up must be \"y\" or \"z\", not \"{}\"", other)),
+    };
+    let factor = scale * unit / scene_unit;
+    for (point, _) in model.points.iter_mut() {
+        let turned = if z_up { Vec3::new(point.x(), point.z(), -point.y()) } else { point.clone() };
+        *point = &turned * factor;
+    }
+    Ok(model)
+}
+
+/// The length of the unit in meters, by its name or the number of the meters.
+fn parse_unit(unit: &Value) -> Result<f32, String> {
+    let meters = match (unit.as_str(), unit.as_f64()) {
+        (Some("meters" | "m"), _) => 1.,
+        (Some("centimeters" | "cm"), _) => 0.01,
+        (Some("millimeters" | "mm"), _) => 0.001,
+        (Some("inches" | "in"), _) => 0.0254,
+        (Some("feet" | "ft"), _) => 0.3048,
+        (Some(other), _) => return Err(format!("unknown unit \"{}\", e.g. \"meters\" or \"millimeters\"", other)),
+        (None, Some(meters)) if meters > 0. && meters.is_finite() => meters as f32,
+        (None, _) => return Err("must be the name of the unit or the positive number of the meters".to_string()),
+    };
+    Ok(meters)
+}
+
+/// The model written in place in the scene file.
+fn parse_model(mesh: &Table) -> Result<Model, String> {
+    let array = |key: &str| mesh.get(key).and_then(Value::as_array);
+    let vertices = array("vertices").ok_or("the mesh needs an `obj` or a `ply` file or the `vertices` and the `faces`")?;
+    let vertices = vertices.iter()
+        .map(|vertex| rgb(vertex).ok_or("`vertices` must be the arrays of 3 numbers".to_string()))
+        .collect::<Result<Vec<Vec3>, String>>()?;
+    let uvs = match array("uvs") {
+        Some(uvs) if uvs.len() == vertices.len() => uvs.iter()
+            .map(|uv| numbers(uv).filter(|uv| uv.len() == 2).map(|uv| [uv[0], uv[1]]))
+            .collect::<Option<Vec<[f32; 2]>>>()
+            .ok_or("`uvs` must be the arrays of 2 numbers")?,
+        Some(_) => return Err(format!("`uvs` must have the coordinates of all the {} vertices", vertices.len())),
+        None => vec![[0., 0.]; vertices.len()],
+    };
+    let colors = match array("colors") {
+        Some(colors) if colors.len() == vertices.len() => colors.iter()
+            .map(rgb)
+            .collect::<Option<Vec<Vec3>>>()
+            .ok_or("`colors` must be the arrays of 3 numbers")?,
+        Some(_) => return Err(format!("`colors` must have the colors of all the {} vertices", vertices.len())),
+        None => Vec::new(),
+    };
+
+    let faces = array("faces").ok_or("missing `faces`")?;
+    let face_materials = match array("face_materials") {
+        Some(names) if names.len() == faces.len() => names.iter()
+            .map(|name| name.as_str().map(|name| Some(name.to_string())))
+            .collect::<Option<Vec<_>>>()
+            .ok_or("`face_materials` must be the names of the materials")?,
+        Some(_) => return Err(format!("`face_materials` must name the materials of all the {} faces", faces.len())),
+        None => vec![None; faces.len()],
+    };
+
+    let mut model = Model { points: vertices.into_iter().zip(uvs).collect(), colors, faces: Vec::new() };
+    for (face, material) in faces.iter().zip(face_materials) {
+        let corners = face.as_array()
+            .and_then(|corners| corners.iter().map(Value::as_integer).collect::<Option<Vec<i64>>>())
+            .filter(|corners| corners.len() >= 3)
+            .ok_or("`faces` must be the arrays of at least 3 indices of the vertices")?;
+        if let Some(corner) = corners.iter().find(|&&corner| !(0..model.points.len() as i64).contains(&corner)) {
+            return Err(format!("the face refers to the vertex {} of {}", corner, model.points.len()));
+        }
+        for i in 1..corners.len() - 1 {
+            model.faces.push(([corners[0], corners[i], corners[i + 1]].map(|corner| corner as usize), material.clone()));
+        }
+    }
+    Ok(model)
+}
+
+fn parse_curves(curves: &Table, world: &mut World, textures: &mut Textures) -> Result<Surface, String> {
+    let basis = match optional(curves, "basis", string)? {
+        Some(name) => Basis::parse(&name).ok_or(format!("unknown basis `{}`, expected bezier or bspline", name))?,
+        None => Basis::BSpline,
+    };
+    let points = curves.get("points").and_then(Value::as_array).ok_or("missing `points`, the control points of every curve")?;
+    let points = points.iter()
+        .map(|curve| {
+            let curve = curve.as_array()
+                .and_then(|curve| curve.iter().map(rgb).collect::<Option<Vec<Vec3>>>())
+                .ok_or("`points` must be the arrays of the points of 3 numbers")?;
+            if basis.fits(curve.len()) {
+                Ok(curve)
+            } else {
+                Err(format!("the curve has {} points, {}", curve.len(), match basis {
+                    Basis::Bezier => "the bezier one needs 4, 7, 10...",
+                    Basis::BSpline => "it needs at least 4",
+                }))
+            }
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let width = match curves.get("width").map(Value::as_f64) {
+        Some(Some(width)) => [width as f32; 2],
+        Some(None) => vec2(curves, "width")?,
+        None => return Err("missing `width`".to_string()),
+    };
+    if !(width[0] >= 0. && width[1] >= 0. && width[0] + width[1] > 0.) {
+        return Err(format!("width [{}, {}] must not be negative", width[0], width[1]));
+    }
+    let material = add_material_of(curves, world, textures)?;
+    Ok(Surface::curves(Curves::new(&points, basis, width), material).with_visibility(parse_visibility_of(curves)?))
+}
+
+fn parse_points(points: &Table, world: &mut World, textures: &mut Textures) -> Result<Surface, String> {
+    let (positions, colors) = match optional(points, "ply", string)? {
+        Some(path) => {
+            let model = import(ply::load(&locate(textures.dir, path))?, points, textures.unit)?;
+            (model.points.into_iter().map(|(p, _)| p).collect(), model.colors)
+        }
+        None => {
+            let array = |key: &str| points.get(key).and_then(Value::as_array);
+            let positions = array("positions").ok_or("the points need a `ply` file or the `positions`")?.iter()
+                .map(rgb)
+                .collect::<Option<Vec<Vec3>>>()
+                .ok_or("`positions` must be the arrays of 3 numbers")?;
+            let colors = match array("colors") {
+                Some(colors) if colors.len() == positions.len() => colors.iter()
+                    .map(rgb)
+                    .collect::<Option<Vec<Vec3>>>()
+                    .ok_or("`colors` must be the arrays of 3 numbers")?,
+                Some(_) => return Err(format!("`colors` must have the colors of all the {} points", positions.len())),
+                None => Vec::new(),
+            };
+            (positions, colors)
+        }
+    };
+    if positions.is_empty() {
+        return Err("there are no points".to_string());
+    }
+    let radius = number(points, "radius")?;
+    if radius <= 0. || !radius.is_finite() {
+        return Err(format!("radius {} must be positive", radius));
+    }
+    let shape = match optional(points, "shape", string)? {
+        Some(name) => Shape::parse(&name).ok_or(format!("unknown shape `{}`, expected disk or sphere", name))?,
+        None => Shape::Disk,
+    };
+    let material = add_material_of(points, world, textures)?;
+    Ok(Surface::points(Points::new(positions, colors, radius, shape), material).with_visibility(parse_visibility_of(points)?))
+}
+
+fn parse_volume(volume: &Table, world: &mut World, textures: &mut Textures) -> Result<Surface, String> {
+    let resolution = match optional(volume, "resolution", vec3)? {
+        Some(size) if [size.x(), size.y(), size.z()].iter().all(|&n| n >= 1. && n.fract() == 0.) =>
+            Some([size.x(), size.y(), size.z()].map(|n| n as usize)),
+        Some(size) => return Err(format!("resolution [{}, {}, {}] must be the whole numbers of the cells", size.x(), size.y(), size.z())),
+        None => None,
+    };
+    let (resolution, densities) = volume::load_grid(&locate(textures.dir, string(volume, "grid")?), resolution)?;
+    let size = vec3(volume, "size")?;
+    if size.x().min(size.y()).min(size.z()) <= 0. {
+        return Err(format!("size [{}, {}, {}] must be positive", size.x(), size.y(), size.z()));
+    }
+    let density = optional(volume, "density", number)?.unwrap_or(1.);
+    if density < 0. || !density.is_finite() {
+        return Err(format!("density {} must not be negative", density));
+    }
+    let material = add_material_of(volume, world, textures)?;
+    let volume_of = Volume::new(vec3(volume, "corner")?, size, resolution, densities, density);
+    Ok(Surface::volume(volume_of, material).with_visibility(parse_visibility_of(volume)?))
+}
+
+/// The displacement and the resolution of the mesh it needs.
+fn parse_displacement(displacement: &Table, resolution: usize, textures: &mut Textures) -> Result<(Displacement, usize), String> {
+    let texture = displacement.get("texture").ok_or("the displacement needs a `texture`")?;
+    let displacement_of = Displacement {
+        texture: parse_texture(texture, textures)?,
+        amount: number(displacement, "amount")?,
+    };
+    let resolution = match optional(displacement, "resolution", integer)? {
+        Some(n) => parse_resolution(n)?,
+        None => resolution,
+    };
+    Ok((displacement_of, resolution))
+}
+
+/// The largest number of the rows of the tessellated surfaces, 4096 rows of a sphere
+/// are 33 million triangles.
+const MAX_RESOLUTION: i64 = 4096;
+/// Every step quadruples the triangles, 6 turn a triangle into 4096.
+const MAX_SUBDIVISIONS: i64 = 6;
+
+fn parse_resolution(resolution: i64) -> Result<usize, String> {
+    if (1..=MAX_RESOLUTION).contains(&resolution) {
+        Ok(resolution as usize)
+    } else {
+        Err(format!("resolution {} must be between 1 and {}", resolution, MAX_RESOLUTION))
+    }
+}
+
+fn parse_material_of(surface: &Table, textures: &mut Textures) -> Result<Material, String> {
+    match surface.get("material") {
+        Some(Value::Table(material)) => parse_material(material, textures),
+        Some(other) => Err(format!("`material` must be a table, not {}", other.type_name())),
+        None => Err("missing `material`".to_string()),
+    }
+}
+
+/// Adds the material of the surface and its shader to the world.
+fn add_material_of(surface: &Table, world: &mut World, textures: &mut Textures) -> Result<Id<Material>, String> {
+    match surface.get("material") {
+        Some(Value::Table(material)) => add_material(material, world, textures),
+        _ => parse_material_of(surface, textures).map(|material| world.add_material(material)),
+    }
+}
+
+fn add_material(material: &Table, world: &mut World, textures: &mut Textures) -> Result<Id<Material>, String> {
+    let shader = table(material, "shader")?.map(parse_shader).transpose()?;
+    let id = world.add_material(parse_material(material, textures)?);
+    if let Some(shader) = shader {
+        world.set_shader(id, shader);
+    }
+    Ok(id)
+}
+
+fn parse_shader(shader: &Table) -> Result<Arc<dyn Shader>, String> {
+    let kind = string(shader, "type").map_err(|_| "the shader must have a `type`".to_string())?;
+    let power = optional(shader, "power", number)?;
+    if let Some(power) = power.filter(|power| !(*power > 0. && power.is_finite())) {
+        return Err(format!("the power of the shader {} must be positive", power));
+    }
+    shader::parse(&kind, optional(shader, "color", vec3)?, power)
+        .ok_or_else(|| format!("unknown shader type `{}`, the types are rim, facing, bounces", kind))
+}
+
+fn parse_visibility_of(surface: &Table) -> Result<Visibility, String> {
+    match table(surface, "visibility")? {
+        Some(visibility) => parse_visibility(visibility),
+        None => Ok(Visibility::ALL),
+    }
+}
+
+/// The optional name and tags of the surface, see `Scene::find`.
+fn parse_label_of(surface: &Table) -> Result<Label, String> {
+    let tags = match surface.get("tags") {
+        Some(tags) => tags.as_array()
+            .and_then(|tags| tags.iter().map(|tag| tag.as_str().map(str::to_string)).collect())
+            .ok_or("`tags` must be an array of strings")?,
+        None => Vec::new(),
+    };
+    Ok(Label { name: optional(surface, "name", string)?, tags })
+}
+
+fn parse_priority_of(surface: &Table) -> Result<i32, String> {
+    match optional(surface, "priority", integer)? {
+        Some(priority) => i32::try_from(priority).map_err(|_| format!("priority {} is out of range", priority)),
+        None => Ok(0),
+    }
+}
+
+fn parse_clip_plane(plane: &Table) -> Result<ClipPlane, String> {
+    let normal = vec3(plane, "normal")?;
+    if normal.squared_length() == 0. {
+        return Err("`normal` must not be zero".to_string());
+    }
+    let cap = optional(plane, "cap", boolean)?.unwrap_or(false);
+    Ok(ClipPlane::new(vec3(plane, "point")?, normal, cap))
+}
+
+/// The light, the IES profiles are read once for all the lights that use them.
+fn parse_light(light: &Table, dir: &Path, profiles: &mut HashMap<PathBuf, Arc<Profile>>) -> Result<Light, String> {
+    let intensity = vec3(light, "intensity")?;
+    if !(intensity.x() >= 0. && intensity.y() >= 0. && intensity.z() >= 0.) {
+        return Err(format!("intensity [{}, {}, {}] must not be negative", intensity.x(), intensity.y(), intensity.z()));
+    }
+    let mut result = Light::point(vec3(light, "position")?, intensity);
+    let aim = optional(light, "direction", vec3)?.unwrap_or(Vec3::new(0., -1., 0.));
+    if aim.squared_length() == 0. {
+        return Err("the direction must not be zero".to_string());
+    }
+    if let Some(spot) = table(light, "spot")? {
+        let angle = number(spot, "angle")?;
+        if !(angle > 0. && angle < 180.) {
+            return Err(format!("the angle of the spot {} must be between 0 and 180 degrees", angle));
+        }
+        let blend = optional(spot, "blend", number)?.unwrap_or(0.2);
+        if !(0. ..=1.).contains(&blend) {
+            return Err(format!("the blend of the spot {} must be between 0 and 1", blend));
+        }
+        result = result.spot(aim.clone(), angle, blend);
+    }
+    if let Some(path) = optional(light, "ies", string)? {
+        let path = locate(dir, path);
+        let profile = match profiles.get(&path) {
+            Some(profile) => profile.clone(),
+            None => {
+                let profile = Arc::new(Profile::load(&path)?);
+                profiles.insert(path, profile.clone());
+                profile
+            }
+        };
+        result = result.with_profile(aim, profile);
+    }
+    Ok(result)
+}
+
+fn parse_portal(portal: &Table) -> Result<Portal, String> {
+    let error = "`sides` must be an array of 2 vectors of 3 numbers";
+    let sides = portal.get("sides").and_then(Value::as_array).filter(|sides| sides.len() == 2).ok_or(error)?;
+    let (Some(first), Some(second)) = (rgb(&sides[0]), rgb(&sides[1])) else {
+        return Err(error.to_string());
+    };
+    let cross = Vec3::cross(&first, &second).length();
+    if cross == 0. || !cross.is_finite() {
+        return Err("the sides must be long and not parallel".to_string());
+    }
+    if Vec3::dot(&first, &second).abs() > 1e-3 * first.length() * second.length() {
+        return Err("the sides must be perpendicular".to_string());
+    }
+    Ok(Portal::new(vec3(portal, "corner")?, [first, second]))
+}
+
+fn parse_visibility(visibility: &Table) -> Result<Visibility, String> {
+    Ok(Visibility {
+        camera: optional(visibility, "camera", boolean)?.unwrap_or(true),
+        shadow: optional(visibility, "shadow", boolean)?.unwrap_or(true),
+        reflection: optional(visibility, "reflection", boolean)?.unwrap_or(true),
+    })
+}
+
+fn parse_material(material: &Table, textures: &mut Textures) -> Result<Material, String> {
+    let kind = material.get("type").and_then(Value::as_str)
+        .ok_or("material must have a `type`")?;
+    match kind {
+        "lambertian" => match material.get("texture") {
+            Some(texture) => {
+                let albedo = optional(material, "albedo", vec3)?.unwrap_or(Vec3::new(1., 1., 1.));
+                Ok(Material::textured(albedo, parse_texture(texture, textures)?))
+            }
+            None => Ok(Material::lambertian(vec3(material, "albedo")?)),
+        },
+        "metal" => match parse_conductor(material)? {
+            Some(conductor) => Ok(Material::conductor(
+                conductor,
+                optional(material, "albedo", vec3)?.unwrap_or(Vec3::new(1., 1., 1.)),
+                parse_fuzz(material)?)),
+            None => Ok(Material::metal(vec3(material, "albedo")?, parse_fuzz(material)?)),
+        },
+        "dielectric" => Ok(Material::absorbing(
+            number(material, "ior")?,
+            optional(material, "absorption", vec3)?.unwrap_or(Vec3::zero()),
+            table(material, "waves")?.map(parse_waves).transpose()?)
+            .with_priority(parse_priority_of(material)?)),
+        "water" => Ok(Material::water(
+            optional(material, "absorption", vec3)?,
+            Some(table(material, "waves")?.map(parse_waves).transpose()?.unwrap_or(DEFAULT_WAVES)))
+            .with_priority(parse_priority_of(material)?)),
+        "isotropic" => Ok(Material::isotropic(vec3(material, "albedo")?)),
+        "hair" => Ok(Material::hair(vec3(material, "color")?, optional(material, "roughness", number)?.unwrap_or(0.2))),
+        "emissive" => Ok(Material::emissive(vec3(material, "radiance")?)),
+        other => Err(format!("unknown material type `{}`", other)),
+    }
+}
+
+/// The fuzz of the metal, or the one of its physical `roughness`, as the other renderers have it.
+fn parse_fuzz(material: &Table) -> Result<f32, String> {
+    match (optional(material, "fuzz", number)?, optional(material, "roughness", number)?) {
+        (Some(fuzz), None) => Ok(fuzz),
+        (None, Some(roughness)) if (0. ..=1.).contains(&roughness) => Ok(roughness_to_fuzz(roughness)),
+        (None, Some(roughness)) => Err(format!("roughness {} must be between 0 and 1", roughness)),
+        (None, None) => Err("missing `fuzz` or `roughness`".to_string()),
+        (Some(_), Some(_)) => Err("the metal has either `fuzz` or `roughness`".to_string()),
+    }
+}
+
+/// The complex index of refraction of the metal, of the `preset` or the `n` and the `k`.
+fn parse_conductor(material: &Table) -> Result<Option<Conductor>, String> {
+    if let Some(name) = optional(material, "preset", string)? {
+        return Conductor::preset(&name).map(Some).ok_or_else(|| format!(
+            "unknown metal `{}`, the presets are {}", name, Conductor::presets().collect::<Vec<_>>().join(", ")));
+    }
+    match (optional(material, "n", vec3)?, optional(material, "k", vec3)?) {
+        (Some(n), Some(k)) => Ok(Some(Conductor { n, k })),
+        (None, None) => Ok(None),
+        _ => Err("the metal needs both `n` and `k`".to_string()),
+    }
+}
+
+/// The ripples of the water without the `waves` of its own.
+const DEFAULT_WAVES: Waves = Waves { length: 1., height: 0.01 };
+
+fn parse_waves(waves: &Table) -> Result<Waves, String> {
+    let length = number(waves, "length")?;
+    if length <= 0. || !length.is_finite() {
+        return Err(format!("the length of the waves {} must be positive", length));
+    }
+    let height = number(waves, "height")?;
+    if height < 0. || !height.is_finite() {
+        return Err(format!("the height of the waves {} must not be negative", height));
+    }
+    Ok(Waves { length, height })
+}
+
+/// The path of the image, or the table of the image or the procedural texture and its transform.
+fn parse_texture(texture: &Value, textures: &mut Textures) -> Result<Texture, String> {
+    let texture = match texture {
+        Value::String(path) => return textures.load(path),
+        Value::Table(texture) => texture,
+        other => return Err(format!("`texture` must be a path or a table, not {}", other.type_name())),
+    };
+    let source = match optional(texture, "type", string)? {
+        Some(kind) => Texture::Procedural(Arc::new(parse_procedural(&kind, texture)?)),
+        None => textures.load(&string(texture, "image")?)?,
+    };
+    let scale = match texture.get("scale").map(Value::as_f64) {
+        Some(Some(scale)) => [scale as f32; 2],
+        Some(None) => vec2(texture, "scale")?,
+        None => UvTransform::IDENTITY.scale,
+    };
+    let transform = UvTransform {
+        scale,
+        offset: optional(texture, "offset", vec2)?.unwrap_or(UvTransform::IDENTITY.offset),
+        rotation: optional(texture, "rotation", number)?.unwrap_or(0.),
+    };
+    Ok(source.with_transform(transform))
+}
+
+fn parse_procedural(kind: &str, texture: &Table) -> Result<Procedural, String> {
+    match kind {
+        "bricks" => Ok(Procedural::Bricks {
+            brick: vec3(texture, "brick")?,
+            mortar: vec3(texture, "mortar")?,
+            mortar_width: optional(texture, "mortar_width", number)?.unwrap_or(0.1),
+        }),
+        "stripes" => Ok(Procedural::Stripes { colors: colors(texture)? }),
+        "checker" => Ok(Procedural::Checker { colors: colors(texture)? }),
+        "gradient" => {
+            let (from, to) = (vec3(texture, "from")?, vec3(texture, "to")?);
+            if (&to - &from).squared_length() == 0. {
+                return Err("the gradient needs `from` and `to` apart".to_string());
+            }
+            Ok(Procedural::Gradient { from, to, colors: colors(texture)? })
+        }
+        "voronoi" => {
+            let size = number(texture, "size")?;
+            if size <= 0. {
+                return Err(format!("the size of the cells {} must be positive", size));
+            }
+            Ok(Procedural::Voronoi { size, colors: colors(texture)? })
+        }
+        other => Err(format!("unknown texture type `{}`, expected bricks, stripes, checker, gradient or voronoi", other)),
+    }
+}
+
+/// The two colors of the procedural texture.
+fn colors(texture: &Table) -> Result<[Vec3; 2], String> {
+    let colors = texture.get("colors").and_then(Value::as_array)
+        .filter(|colors| colors.len() == 2)
+        .ok_or("`colors` must be an array of 2 colors")?;
+    match (rgb(&colors[0]), rgb(&colors[1])) {
+        (Some(first), Some(second)) => Ok([first, second]),
+        _ => Err("`colors` must be an array of 2 colors of 3 numbers".to_string()),
+    }
+}
+
+fn vec2(table: &Table, key: &str) -> Result<[f32; 2], String> {
+    let value = table.get(key).ok_or(format!("missing `{}`", key))?;
+    match numbers(value).as_deref() {
+        Some(&[x, y]) => Ok([x, y]),
+        _ => Err(format!("`{}` must be an array of 2 numbers", key)),
+    }
+}
+
+pub(crate) fn vec3(table: &Table, key: &str) -> Result<Vec3, String> {
+    let value = table.get(key).ok_or(format!("missing `{}`", key))?;
+    rgb(value).ok_or(format!("`{}` must be an array of 3 numbers", key))
+}
+
+/// The array of 3 numbers, a color or a point.
+fn rgb(value: &Value) -> Option<Vec3> {
+    match numbers(value).as_deref() {
+        Some(&[x, y, z]) => Some(Vec3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+fn numbers(value: &Value) -> Option<Vec<f32>> {
+    value.as_array()
+        .map(|items| items.iter().map(|item| item.as_f64().map(|n| n as f32)).collect())
+        .unwrap_or(None)
+}
+
+/// The textures of the scene being parsed, every file is read once.
+struct Textures<'a> {
+    dir: &'a Path,
+    /// The length of the unit of the scene in meters, the imported models are converted to it.
+    unit: f32,
+    loaded: HashMap<PathBuf, Texture>,
+}
+
+impl Textures<'_> {
+    fn load(&mut self, path: &str) -> Result<Texture, String> {
+        let path = locate(self.dir, path);
+        if let Some(texture) = self.loaded.get(&path) {
+            return Ok(texture.clone());
+        }
+        let image = ImageTexture::load(&path).map_err(|why| format!("cannot read the texture {}: {}", path.display(), why))?;
+        let texture = Texture::Image(Arc::new(image));
+        self.loaded.insert(path, texture.clone());
+        Ok(texture)
+    }
+}
@@ -0,0 +1,191 @@
+/**
+The atmosphere of the outdoor scenes: the haze that hides the far hills
+and pools in the valleys.
+
+The air between the camera and a far object scatters some of its light away
+and some of the light of the sky towards the camera instead, the farther the object,
+the more it fades into the color of the fog. That is how the eye tells the distance,
+without it the outdoor scenes look flat. The fog is densest at its base height
+and thins out exponentially above it, like the real air.
+
+LEARN:
+Unlike the volumes, the fog is not tracked particle by particle. Its density along
+a straight ray has the closed-form integral, the optical depth, and the light that
+gets through is `exp(-depth)` of it, the rest is replaced by the light of the fog.
+It costs a single exponent per ray segment and adds no noise.
+
+The fog lit by the lights of the scene (`with_lights`) shows the beams of the lamps
+and the shafts of the sun between the shadows of the objects, the god rays. The shadows
+have no closed form, so the segment is marched in the steps instead: at every step
+a shadow ray goes to every light, and the light that reaches the step through
+the unblocked fog is scattered towards the camera by the density there. The start
+of the steps is random, so their aliasing turns into the noise the samples average out.
+*/
+use std::f32::consts::PI;
+
+use crate::math::rand::drand32;
+use crate::math::vec::{Ray, RayType, Vec3};
+use crate::scene::light::Light;
+use crate::scene::surfaces::bvh::Bounds;
+
+/// How much the fog scatters the light of the sun forward rather than in all directions,
+/// 0 is evenly, closer to 1 the tighter the glow around the sun.
+const SUN_ANISOTROPY: f32 = 0.6;
+
+/// The same of the light of the lights, less forward, the beams stay visible from the side.
+const LIGHT_ANISOTROPY: f32 = 0.3;
+
+/// The largest exponent of the density below the base height, the fog far below it
+/// is dense but not infinitely.
+const MAX_EXPONENT: f32 = 30.;
+
+#[derive(Clone)]
+pub(crate) struct Fog {
+    /// The color of the light the fog scatters towards the camera, the light of the sky in it.
+    color: Vec3,
+    /// The density at the base height, the fraction of the light scattered per unit of the length.
+    density: f32,
+    height: f32,
+    /// How fast the density falls with the height, 0 is the uniform fog.
+    falloff: f32,
+    sun: Option<Sun>,
+    lit: Option<Lit>,
+}
+
+/// The fog scatters the light of the lights within the box, see `with_lights`.
+#[derive(Clone)]
+struct Lit {
+    steps: usize,
+    bounds: Bounds,
+}
+
+/// The sunlight the fog scatters, it glows around the direction of the sun.
+#[derive(Clone)]
+pub(crate) struct Sun {
+    /// Towards the sun.
+    direction: Vec3,
+    color: Vec3,
+}
+
+impl Fog {
+    pub(crate) fn new(color: Vec3, density: f32, height: f32, falloff: f32) -> Fog {
+        Fog { color, density, height, falloff, sun: None, lit: None }
+    }
+
+    pub(crate) fn with_sun(self, direction: Vec3, color: Vec3) -> Fog {
+        Fog { sun: Some(Sun { direction: direction.unit(), color }), ..self }
+    }
+
+    /// The fog scatters the light of the lights and of the sun of the sky too, marched
+    /// in the steps across the box, e.g. around the surfaces and the lights.
+    pub(crate) fn with_lights(self, steps: usize, bounds: Bounds) -> Fog {
+        Fog { lit: Some(Lit { steps: steps.max(1), bounds }), ..self }
+    }
+
+    /// Whether the fog scatters the light of the lights, the shadow rays pass through it then.
+    pub(crate) fn is_lit(&self) -> bool {
+        self.lit.is_some()
+    }
+
+    /// The share of the light that passes through the segment of the ray up to `t`.
+    pub(crate) fn transmittance(&self, r: &Ray, t: f32) -> f32 {
+        (-self.depth(r, t)).exp()
+    }
+
+    /// The light of the `lights` and of the `sun` (towards it and its light) the fog
+    /// scatters towards the origin of the ray from the segment up to `t`, zero unless
+    /// the fog is lit. `visible` tells whether nothing blocks the shadow ray up to its reach.
+    ///
+    /// Only the camera rays and the mirrors and the glass see it, the diffuse bounces
+    /// would pay for the steps with the light too dim to see.
+    pub(crate) fn scattered(&self, r: &Ray, t: f32, lights: &[Light], sun: Option<(Vec3, Vec3)>,
+                            visible: impl Fn(&Ray, f32) -> bool) -> Vec3 {
+        let Some(lit) = self.lit.as_ref().filter(|_| r.kind() != RayType::Diffuse && self.density > 0.) else {
+            return Vec3::zero();
+        };
+        let Some((enter, exit)) = lit.bounds.interval(r, 0., t) else {
+            return Vec3::zero();
+        };
+        let step = (exit - enter) / lit.steps as f32;
+        let length = step * r.direction().length();
+        let from = r.direction().unit();
+        let offset = drand32();
+        let mut sum = Vec3::zero();
+        for i in 0..lit.steps {
+            let t = enter + (i as f32 + offset) * step;
+            let p = r.point_at(t);
+            let scattering = self.transmittance(r, t) * self.density_at(&p) * length;
+            // the light on the way to the lights is dimmed by the fog too
+            let shine = |to_light: Vec3, reach: f32, light: Vec3, dimmed: bool| {
+                let shadow = Ray::scattered(p.clone(), to_light, RayType::Shadow).at_time(r.time());
+                if !visible(&shadow, reach) {
+                    return Vec3::zero();
+                }
+                let cosine = Vec3::dot(&from, &shadow.direction().unit());
+                let transmittance = if dimmed { self.transmittance(&shadow, reach) } else { 1. };
+                transmittance * henyey_greenstein(LIGHT_ANISOTROPY, cosine) / (4. * PI) * light
+            };
+            let mut light = Vec3::zero();
+            for source in lights {
+                if let Some((to_light, arriving)) = source.illuminate(&p) {
+                    light += shine(to_light, 1., arriving, true);
+                }
+            }
+            // the sunlight of the sky is the one that reaches the ground, the fog does not dim it again
+            if let Some((to_sun, sunlight)) = sun.clone().filter(|(to_sun, _)| to_sun.y() > 0.) {
+                light += shine(to_sun, f32::MAX, sunlight, false);
+            }
+            sum += scattering * light;
+        }
+        sum
+    }
+
+    /// The light of the ray segment up to `t` that arrives through the fog,
+    /// the radiance from the end of it dimmed and the light of the fog added.
+    /// The escaping rays go up to `f32::MAX`.
+    pub(crate) fn apply(&self, r: &Ray, t: f32, radiance: Vec3) -> Vec3 {
+        let transmittance = (-self.depth(r, t)).exp();
+        if transmittance >= 1. {
+            return radiance;
+        }
+        transmittance * radiance + (1. - transmittance) * self.inscattered(r)
+    }
+
+    /// The optical depth of the segment, the integral of the density along it.
+    fn depth(&self, r: &Ray, t: f32) -> f32 {
+        if self.density <= 0. {
+            return 0.;
+        }
+        let scale = self.density * r.direction().length();
+        let exponent = -self.falloff * (r.origin().y() - self.height);
+        // the density changes along the ray by the rate it climbs
+        let rate = self.falloff * r.direction().y();
+        if rate.abs() < 1e-6 {
+            scale * exponent.min(MAX_EXPONENT).exp() * t
+        } else {
+            let [start, end] = [exponent, exponent - rate * t].map(|exponent| exponent.min(MAX_EXPONENT).exp());
+            scale * (start - end) / rate
+        }
+    }
+
+    /// The density at the point, the fraction of the light scattered per unit of the length.
+    fn density_at(&self, p: &Vec3) -> f32 {
+        self.density * (-self.falloff * (p.y() - self.height)).min(MAX_EXPONENT).exp()
+    }
+
+    fn inscattered(&self, r: &Ray) -> Vec3 {
+        match &self.sun {
+            Some(sun) => {
+                let cosine = Vec3::dot(&r.direction().unit(), &sun.direction);
+                &self.color + henyey_greenstein(SUN_ANISOTROPY, cosine) * &sun.color
+            }
+            None => self.color.clone(),
+        }
+    }
+}
+
+/// The Henyey-Greenstein phase function, 1 on average over the sphere, of the cosine
+/// of the angle between the ray and the light and the anisotropy `g`.
+fn henyey_greenstein(g: f32, cosine: f32) -> f32 {
+    (1. - g * g) / (1. + g * g - 2. * g * cosine).powf(1.5)
+}
@@ -0,0 +1,131 @@
+/**
+Reading of the Radiance HDR images (`.hdr`), the format the HDRI panoramas of the skies
+and the studios are shared in.
+
+The 8-bit images clip everything brighter than the white, the sun in them is as bright
+as a white cloud. An HDR image keeps the real radiance of every pixel, so the sun
+of the panorama lights the scene and casts the sharp shadows, and the clouds do not.
+
+LEARN:
+The pixels are stored as RGBE: three 8-bit mantissas and an exponent they share,
+the value of a channel is `mantissa * 2^(exponent - 136)`. Four bytes hold a range
+of the brightness no 8-bit channel can, at the precision of the brightest channel.
+The rows are usually compressed: every one of the four components of the row
+is stored separately as the runs of the same byte and the literal bytes.
+*/
+use std::path::Path;
+
+use crate::math::vec::Vec3;
+
+/// The linear radiance of the pixels, row by row from the top-left corner.
+pub(crate) struct HdrImage {
+    pub(crate) w: usize,
+    pub(crate) h: usize,
+    pub(crate) pixels: Vec<Vec3>,
+}
+
+pub(crate) fn load(path: &Path) -> Result<HdrImage, String> {
+    let bytes = std::fs::read(path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+    parse(&bytes).map_err(|why| format!("{} is not a Radiance HDR image: {}", path.display(), why))
+}
+
+pub(crate) fn parse(bytes: &[u8]) -> Result<HdrImage, String> {
+    // the header is the lines of the text up to an empty one, then the line of the size
+    let mut pos = 0;
+    let mut line = || -> Result<&str, String> {
+        let end = bytes[pos..].iter().position(|&b| b == b'\n').ok_or("the header is cut short")?;
+        let line = std::str::from_utf8(&bytes[pos..pos + end]).map_err(|_| "the header is not text")?;
+        pos += end + 1;
+        Ok(line.trim_end_matches('\r'))
+    };
+    let magic = line()?;
+    if !magic.starts_with("#?") {
+        return Err("no #? at the start".to_string());
+    }
+    loop {
+        match line()? {
+            "" => break,
+            format if format.starts_with("FORMAT=") && format != "FORMAT=32-bit_rle_rgbe" => {
+                return Err(format!("unsupported {}", format));
+            }
+            _ => {}
+        }
+    }
+    // only the usual orientation: the rows from the top, every row from the left
+    let size = line()?;
+    let (w, h) = match size.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => (
+            w.parse::<usize>().map_err(|_| format!("bad size {}", size))?,
+            h.parse::<usize>().map_err(|_| format!("bad size {}", size))?,
+        ),
+        _ => return Err(format!("unsupported orientation {}", size)),
+    };
+    if w == 0 || h == 0 {
+        return Err("the image is empty".to_string());
+    }
+
+    let mut data = &bytes[pos..];
+    let mut pixels = Vec::with_capacity(w * h);
+    let mut row = vec![[0u8; 4]; w];
+    for _ in 0..h {
+        data = read_row(data, &mut row)?;
+        pixels.extend(row.iter().map(|&rgbe| decode(rgbe)));
+    }
+    Ok(HdrImage { w, h, pixels })
+}
+
+/// Reads a row of the pixels, returns the rest of the data after it.
+fn read_row<'a>(data: &'a [u8], row: &mut [[u8; 4]]) -> Result<&'a [u8], String> {
+    let w = row.len();
+    let cut = || "the pixels are cut short".to_string();
+    // the compressed row starts with 2, 2 and the width, which no flat pixel does
+    let compressed = (8..0x8000).contains(&w) && data.len() >= 4 && data[0] == 2 && data[1] == 2 && data[2] & 0x80 == 0;
+    if !compressed {
+        let flat = data.get(..4 * w).ok_or_else(cut)?;
+        for (pixel, rgbe) in row.iter_mut().zip(flat.chunks_exact(4)) {
+            pixel.copy_from_slice(rgbe);
+        }
+        return Ok(&data[4 * w..]);
+    }
+    if ((data[2] as usize) << 8 | data[3] as usize) != w {
+        return Err("the width of a compressed row is wrong".to_string());
+    }
+    let mut pos = 4;
+    for component in 0..4 {
+        let mut x = 0;
+        while x < w {
+            let count = *data.get(pos).ok_or_else(cut)? as usize;
+            pos += 1;
+            if count > 128 {
+                // a run of the same byte
+                let count = count - 128;
+                let value = *data.get(pos).ok_or_else(cut)?;
+                pos += 1;
+                for pixel in row.get_mut(x..x + count).ok_or("a run is longer than the row")? {
+                    pixel[component] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 {
+                    return Err("an empty run".to_string());
+                }
+                let values = data.get(pos..pos + count).ok_or_else(cut)?;
+                pos += count;
+                for (pixel, &value) in row.get_mut(x..x + count).ok_or("a run is longer than the row")?.iter_mut().zip(values) {
+                    pixel[component] = value;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(&data[pos..])
+}
+
+fn decode([r, g, b, e]: [u8; 4]) -> Vec3 {
+    if e == 0 {
+        return Vec3::zero();
+    }
+    // the mantissas are 0..256 of the power of two, the middle of their step
+    let scale = 2f32.powi(e as i32 - 136);
+    Vec3::new((r as f32 + 0.5) * scale, (g as f32 + 0.5) * scale, (b as f32 + 0.5) * scale)
+}
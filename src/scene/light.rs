@@ -0,0 +1,99 @@
+/**
+The lights: the bulbs, the spotlights and the fixtures of the architectural scenes.
+
+The sky lights the scene by the rays that escape to it, but a bulb is a point,
+no scattered ray ever hits it. Its light is gathered at every diffuse hit instead:
+a shadow ray goes from the hit to the light, and when nothing blocks it, the light
+adds its intensity over the square of the distance (the inverse-square law, the same
+light spread over the sphere the larger the farther it goes).
+
+A spotlight shines within a cone, its edge fading out. The real fixtures are described
+by their photometric profiles, the IES files of the manufacturers, how bright the fixture
+is in every direction, see `ies`.
+*/
+use std::sync::Arc;
+
+use crate::math::vec::Vec3;
+use ies::Profile;
+
+pub(crate) mod ies;
+
+#[derive(Clone)]
+pub(crate) struct Light {
+    position: Vec3,
+    /// The light per steradian, in the brightest direction.
+    intensity: Vec3,
+    /// Where the spotlight points and where the nadir of the profile looks, the unit vector.
+    direction: Vec3,
+    /// The cosines of the half-angles where the spot starts to fade and where it ends,
+    /// `None` for the light in all directions.
+    cone: Option<[f32; 2]>,
+    profile: Option<Arc<Profile>>,
+}
+
+impl Light {
+    /// The point light, the same in all directions.
+    pub(crate) fn point(position: Vec3, intensity: Vec3) -> Light {
+        Light { position, intensity, direction: Vec3::new(0., -1., 0.), cone: None, profile: None }
+    }
+
+    /// The light is only within the half-angle (in degrees) around the direction,
+    /// `blend` of the cone fades out towards its edge.
+    pub(crate) fn spot(self, direction: Vec3, angle: f32, blend: f32) -> Light {
+        let outer = angle.to_radians();
+        let inner = outer * (1. - blend);
+        Light { direction: direction.unit(), cone: Some([inner.cos(), outer.cos()]), ..self }
+    }
+
+    /// The brightness in every direction is the one of the profile, its nadir
+    /// looks along the direction.
+    pub(crate) fn with_profile(self, direction: Vec3, profile: Arc<Profile>) -> Light {
+        Light { direction: direction.unit(), profile: Some(profile), ..self }
+    }
+
+    /// Multiplies the intensity by the factor, e.g. to dim or pulse the light over an animation.
+    #[cfg(feature = "script")]
+    pub(crate) fn scale(&mut self, factor: f32) {
+        self.intensity = &self.intensity * factor;
+    }
+
+    pub(crate) fn position(&self) -> &Vec3 {
+        &self.position
+    }
+
+    /// The way from the point to the light, its length is the distance, and the light
+    /// that arrives at the point, `None` when the light does not shine there.
+    pub(crate) fn illuminate(&self, p: &Vec3) -> Option<(Vec3, Vec3)> {
+        let to_light = &self.position - p;
+        let squared_distance = to_light.squared_length();
+        if squared_distance == 0. {
+            return None;
+        }
+        // from the light to the point
+        let out = -(&to_light / squared_distance.sqrt());
+        let axis = Vec3::dot(&out, &self.direction);
+        let mut strength = 1.;
+        if let Some([inner, outer]) = self.cone {
+            if axis <= outer {
+                return None;
+            }
+            if axis < inner {
+                // the smoothstep, the edge fades without a visible border
+                let x = (axis - outer) / (inner - outer);
+                strength *= x * x * (3. - 2. * x);
+            }
+        }
+        if let Some(profile) = &self.profile {
+            let helper = if self.direction.x().abs() < 0.9 { Vec3::new(1., 0., 0.) } else { Vec3::new(0., 1., 0.) };
+            let across = Vec3::cross(&self.direction, &helper).unit();
+            let side = Vec3::cross(&self.direction, &across);
+            let vertical = axis.clamp(-1., 1.).acos().to_degrees();
+            let horizontal = Vec3::dot(&out, &side).atan2(Vec3::dot(&out, &across)).to_degrees().rem_euclid(360.);
+            strength *= profile.intensity(vertical, horizontal);
+        }
+        if strength <= 0. {
+            return None;
+        }
+        Some((to_light, strength / squared_distance * &self.intensity))
+    }
+}
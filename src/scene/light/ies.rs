@@ -0,0 +1,126 @@
+/**
+The photometric profiles of the light fixtures, the IES LM-63 files.
+
+A fixture is not equally bright in all directions: the reflector throws the light
+down, the shade cuts it off above, the lens makes a narrow beam. The manufacturers
+measure the candelas of their fixtures on the grid of the directions and publish them
+as the IES files, so the renders of the buildings show the real pools of the light
+on the walls.
+
+The directions are the vertical angles from the nadir, 0 straight down and 180 straight up,
+and the horizontal angles around it. The file lists only the part of them the symmetry
+of the fixture needs, e.g. a single horizontal angle for a round one.
+Only the type C photometry, the one of the architectural fixtures, is read.
+*/
+use std::path::Path;
+
+pub(crate) struct Profile {
+    /// The vertical angles in degrees, ascending.
+    vertical: Vec<f32>,
+    /// The horizontal angles in degrees, ascending, from 0.
+    horizontal: Vec<f32>,
+    /// The candelas of every horizontal angle at every vertical one, divided by the largest,
+    /// so the profile only shapes the intensity of the light.
+    candelas: Vec<f32>,
+}
+
+impl Profile {
+    pub(crate) fn load(path: &Path) -> Result<Profile, String> {
+        let text = std::fs::read(path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+        // the files of the older tools are not always UTF-8, the numbers are ASCII anyway
+        Profile::parse(&String::from_utf8_lossy(&text)).map_err(|why| format!("{}: {}", path.display(), why))
+    }
+
+    fn parse(text: &str) -> Result<Profile, String> {
+        // the keywords of the header come before the TILT line, the numbers after it
+        let tilt = text.find("TILT=").ok_or("not an IES file, there is no TILT line")?;
+        let after = &text[tilt..];
+        let (tilt, data) = after.split_once('\n').unwrap_or((after, ""));
+        let mut numbers = data.split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse::<f32>().map_err(|_| format!("`{}` is not a number", token)));
+        let mut next = || numbers.next().unwrap_or(Err("the file ends too early".to_string()));
+
+        match tilt.trim_end() {
+            "TILT=NONE" => {}
+            // the change of the output with the tilt of the lamp, the fixtures are not tilted here
+            "TILT=INCLUDE" => {
+                next()?;
+                let pairs = next()? as usize;
+                for _ in 0..2 * pairs {
+                    next()?;
+                }
+            }
+            other => return Err(format!("only TILT=NONE or INCLUDE are supported, not {}", other)),
+        }
+
+        // the lamps, the lumens of a lamp, the multiplier of the candelas, the angles,
+        // the photometric type, the units, the size of the fixture, the ballast and the watts
+        let [_, _, multiplier, vertical_count, horizontal_count, photometry] = [(); 6].map(|_| next());
+        let (multiplier, photometry) = (multiplier?, photometry?);
+        let (vertical_count, horizontal_count) = (vertical_count? as usize, horizontal_count? as usize);
+        if photometry != 1. {
+            return Err(format!("only the type C photometry is supported, not type {}", photometry));
+        }
+        for _ in 0..7 {
+            next()?;
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err("the profile has no angles".to_string());
+        }
+        let vertical = (0..vertical_count).map(|_| next()).collect::<Result<Vec<f32>, String>>()?;
+        let horizontal = (0..horizontal_count).map(|_| next()).collect::<Result<Vec<f32>, String>>()?;
+        let candelas = (0..vertical_count * horizontal_count)
+            .map(|_| next().map(|candela| candela * multiplier))
+            .collect::<Result<Vec<f32>, String>>()?;
+        if !vertical.windows(2).all(|pair| pair[0] < pair[1]) || !horizontal.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err("the angles must be ascending".to_string());
+        }
+        let brightest = candelas.iter().fold(0., |max: f32, &candela| max.max(candela));
+        if brightest <= 0. || !brightest.is_finite() {
+            return Err("the fixture gives no light".to_string());
+        }
+        let candelas = candelas.into_iter().map(|candela| candela.max(0.) / brightest).collect();
+        Ok(Profile { vertical, horizontal, candelas })
+    }
+
+    /// The relative intensity in the direction, 1 in the brightest one.
+    pub(crate) fn intensity(&self, vertical: f32, horizontal: f32) -> f32 {
+        // the other horizontal angles mirror the ones in the file
+        let horizontal = match self.horizontal.last() {
+            Some(&last) if last <= 0. => 0.,
+            Some(&last) if last <= 90. => {
+                let h = horizontal % 180.;
+                if h > 90. { 180. - h } else { h }
+            }
+            Some(&last) if last <= 180. => if horizontal > 180. { 360. - horizontal } else { horizontal },
+            _ => horizontal,
+        };
+        let Some((v, fv)) = between(&self.vertical, vertical) else {
+            return 0.;
+        };
+        let (h, fh) = between(&self.horizontal, horizontal).unwrap_or((0, 0.));
+        let count = self.vertical.len();
+        let candela = |h: usize, v: usize| {
+            let (h, v) = (h.min(self.horizontal.len() - 1), v.min(count - 1));
+            self.candelas[h * count + v]
+        };
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        lerp(lerp(candela(h, v), candela(h, v + 1), fv), lerp(candela(h + 1, v), candela(h + 1, v + 1), fv), fh)
+    }
+}
+
+/// The index of the angle at or before the value and how far the value is to the next one,
+/// `None` outside of the angles.
+fn between(angles: &[f32], value: f32) -> Option<(usize, f32)> {
+    let (first, last) = (*angles.first()?, *angles.last()?);
+    if value < first || value > last {
+        return None;
+    }
+    let next = angles.iter().position(|&angle| angle > value).unwrap_or(angles.len() - 1).max(1);
+    if angles.len() == 1 {
+        return Some((0, 0.));
+    }
+    let (a, b) = (angles[next - 1], angles[next]);
+    Some((next - 1, ((value - a) / (b - a)).clamp(0., 1.)))
+}
@@ -1,16 +1,27 @@
+use std::f32::consts::PI;
+
 use crate::math::rand::drand32;
+use crate::scene::conductor::Conductor;
 use crate::scene::surfaces::hitable::HitRecord;
-use crate::math::vec::{Ray, Vec3};
+use crate::math::vec::{Ray, RayType, Vec3};
+use crate::scene::texture::Texture;
+use crate::scene::waves::Waves;
 
+#[derive(Clone)]
 pub(crate) enum Material {
     // randomly diffuses the light
     Lambertian {
         albedo: Vec3,
+        /// Multiplies the albedo, so the same image can be tinted.
+        texture: Option<Texture>,
     },
     // not transparent material that reflects the light
     Metal {
         albedo: Vec3,
         fuzz: f32,
+        /// The complex index of refraction of the metal, its reflectance changes with the angle
+        /// and the albedo only tints it, `None` reflects the albedo at every angle.
+        conductor: Option<Conductor>,
     },
     // transparent material that refracts and also reflects the light
     Dielectric {
@@ -20,32 +31,196 @@ pub(crate) enum Material {
         // We could also make it a constant, but we may want to experiment
         // with transparency.
         attenuation: Vec3,
-    }
+        /// The share of the light absorbed per unit of the length inside, for every channel,
+        /// e.g. the water swallows the red first and looks blue the deeper it is.
+        absorption: Vec3,
+        /// The ripples of the surface, `None` keeps it smooth.
+        waves: Option<Waves>,
+        /// Where the solids of the dielectrics overlap, e.g. the water poured into the glass,
+        /// the one of the higher priority fills the overlap, see `renderer::media`.
+        priority: i32,
+    },
+    // the fibers of the hair and the fur, they scatter the light around themselves
+    Hair {
+        /// The pigment, the color of the light that passes through the fibers.
+        color: Vec3,
+        /// How far the light strays from the cone around the fiber, 0..1.
+        roughness: f32,
+    },
+    // the particles of the smoke and the clouds scatter the light equally in all directions
+    Isotropic {
+        albedo: Vec3,
+    },
+    // the glowing surface of a lamp, it gives the light and scatters none
+    Emissive {
+        /// The light it gives off in every direction, above 1 for anything that lights a scene.
+        radiance: Vec3,
+    },
+}
+
+/// The index of refraction of the water.
+const WATER_IOR: f32 = 1.33;
+
+/// The absorption of the clear water per meter (the unit of the length), the red is gone
+/// a few meters deep, the blue goes on for dozens.
+const WATER_ABSORPTION: [f32; 3] = [0.45, 0.07, 0.02];
+
+/// The share of the light the surface of a hair reflects, the rest passes through
+/// the fiber and takes its color.
+const HAIR_HIGHLIGHT: f32 = 0.2;
+
+/// The fuzz of the metal that blurs its reflection about as much as the roughness
+/// of the physically based renderers, the square root of the GGX alpha, 0..1.
+///
+/// LEARN:
+/// The fuzz moves the reflected direction to a random point of the ball of its radius,
+/// half of the directions end within 0.61 of it. The GGX microfacets tilt half of their
+/// normals within the alpha, and the reflection turns twice as far as the normal,
+/// so the blur of the fuzz is the one of the alpha of 0.3 fuzz, at the small angles.
+pub(crate) fn roughness_to_fuzz(roughness: f32) -> f32 {
+    (roughness * roughness / FUZZ_PER_ALPHA).min(1.)
 }
 
+/// The roughness of the physically based renderers that blurs the reflection
+/// as much as the fuzz of the metal, see `roughness_to_fuzz`.
+pub(crate) fn fuzz_to_roughness(fuzz: f32) -> f32 {
+    (fuzz.max(0.) * FUZZ_PER_ALPHA).sqrt()
+}
+
+/// The alpha of GGX per the fuzz that blurs the reflection the same.
+const FUZZ_PER_ALPHA: f32 = 0.304;
+
 /// LEARN:
 /// In C++ the scatter function is an abstract method of the base class `Material`.
 /// In Rust we use traits for that.
 ///
 /// Different materials reflect or refract the light in different ways.
 /// This trait will implement the scatter function for each material.
+///
+/// `min_roughness` is the least fuzz of the reflected and refracted rays, 0 keeps the
+/// mirrors and the glass perfectly smooth. The renderer raises it after a few bounces
+/// to trade a little blur in the reflections for much less noise.
 pub(crate) trait Scatterable {
     fn scatter(&self,
                r_in: &Ray,
-               rec: HitRecord) -> Option<(Ray, &Vec3)>;
+               rec: HitRecord,
+               min_roughness: f32) -> Option<(Ray, Vec3)>;
 }
 
 impl Material {
     pub(crate) fn lambertian(albedo: Vec3) -> Material {
-        Material::Lambertian { albedo }
+        Material::Lambertian { albedo, texture: None }
+    }
+    pub(crate) fn textured(albedo: Vec3, texture: Texture) -> Material {
+        Material::Lambertian { albedo, texture: Some(texture) }
     }
     pub(crate) fn metal(albedo: Vec3, fuzz: f32) -> Material {
-        Material::Metal { albedo, fuzz }
+        Material::Metal { albedo, fuzz, conductor: None }
+    }
+    /// The metal of the complex index of refraction, e.g. the gold of `Conductor::preset`.
+    pub(crate) fn conductor(conductor: Conductor, albedo: Vec3, fuzz: f32) -> Material {
+        Material::Metal { albedo, fuzz, conductor: Some(conductor) }
     }
     pub(crate) fn dielectric(ref_idx: f32) -> Material {
+        Material::absorbing(ref_idx, Vec3::zero(), None)
+    }
+    /// The dielectric that absorbs the light passing through it, e.g. the colored glass.
+    pub(crate) fn absorbing(ref_idx: f32, absorption: Vec3, waves: Option<Waves>) -> Material {
         Material::Dielectric {
             ref_idx,
-            attenuation: Vec3::new(1.0, 1.0, 1.0)
+            attenuation: Vec3::new(1.0, 1.0, 1.0),
+            absorption,
+            waves,
+            priority: 0,
+        }
+    }
+    /// The same dielectric of the priority in the overlaps with the other ones.
+    pub(crate) fn with_priority(self, priority: i32) -> Material {
+        match self {
+            Material::Dielectric { ref_idx, attenuation, absorption, waves, .. } =>
+                Material::Dielectric { ref_idx, attenuation, absorption, waves, priority },
+            other => other,
+        }
+    }
+    /// The water, rippled by the waves, the absorption may override the one of the clear water.
+    pub(crate) fn water(absorption: Option<Vec3>, waves: Option<Waves>) -> Material {
+        let [r, g, b] = WATER_ABSORPTION;
+        Material::absorbing(WATER_IOR, absorption.unwrap_or(Vec3::new(r, g, b)), waves)
+    }
+    pub(crate) fn hair(color: Vec3, roughness: f32) -> Material {
+        Material::Hair { color, roughness }
+    }
+    pub(crate) fn isotropic(albedo: Vec3) -> Material {
+        Material::Isotropic { albedo }
+    }
+    pub(crate) fn emissive(radiance: Vec3) -> Material {
+        Material::Emissive { radiance }
+    }
+
+    /// Scatters the ray at the surface of the dielectric between two media, whose indices
+    /// of refraction relate as `ref_idx`, the inside one over the outside one, instead of
+    /// the air and the dielectric. The light is absorbed on the way by the tracker of the media.
+    pub(crate) fn scatter_between(&self, r_in: &Ray, rec: HitRecord, min_roughness: f32, ref_idx: f32) -> Option<(Ray, Vec3)> {
+        match self {
+            Material::Dielectric { attenuation, waves, priority, .. } => {
+                let between = Material::Dielectric { ref_idx, attenuation: attenuation.clone(), absorption: Vec3::zero(), waves: waves.clone(), priority: *priority };
+                between.scatter(r_in, rec, min_roughness)
+            }
+            _ => self.scatter(r_in, rec, min_roughness),
+        }
+    }
+
+    /// The light the surface gives off towards the ray that hit it.
+    ///
+    /// LEARN:
+    /// The lights are not sampled at the glowing surfaces, only the rays that happen
+    /// to hit them bring their light, so the small bright ones are noisy.
+    /// The large soft ones, like the softboxes of a studio, are hit often enough.
+    pub(crate) fn emitted(&self) -> Vec3 {
+        match self {
+            Material::Emissive { radiance } => radiance.clone(),
+            _ => Vec3::zero(),
+        }
+    }
+
+    /// The color of the surface at the hit without the light, the albedo pass of the compositors,
+    /// the textures are sampled at their finest. The glass is white, the glowing surfaces black.
+    pub(crate) fn albedo(&self, rec: &HitRecord) -> Vec3 {
+        let albedo = match self {
+            Material::Lambertian { albedo, texture: Some(texture) } => albedo * texture.value(rec.u, rec.v, &rec.p, [0., 0.]),
+            Material::Lambertian { albedo, texture: None } | Material::Metal { albedo, .. } | Material::Isotropic { albedo } => albedo.clone(),
+            Material::Dielectric { attenuation, .. } => attenuation.clone(),
+            Material::Hair { color, .. } => color.clone(),
+            Material::Emissive { .. } => return Vec3::zero(),
+        };
+        match &rec.color {
+            Some(color) => &albedo * color,
+            None => albedo,
+        }
+    }
+
+    /// The share of the light arriving from `to_light` that the material scatters
+    /// along the ray back to where it came from, for the lights no scattered ray can hit.
+    /// It multiplies the attenuation of the diffuse scattering, whose own color it already has.
+    pub(crate) fn diffuse_response(&self, r_in: &Ray, normal: &Vec3, to_light: &Vec3) -> f32 {
+        match self {
+            // LEARN:
+            // The lambertian scattering aims at a random point of the unit ball on the normal,
+            // the directions it picks have the density 2cos³/π (the chord of the ball from the hit
+            // is 2cos long, and the ball holds the cube of it), not the cos/π of the ideal diffuser.
+            // The light gathered directly must follow the same law, or the light the scattered
+            // rays would bring from the same directions would differ.
+            // The light must be on the side the ray came from.
+            Material::Lambertian { .. } => {
+                let cosine = Vec3::dot(&to_light.unit(), normal);
+                if cosine * Vec3::dot(r_in.direction(), normal) < 0. { 2. * cosine.abs().powi(3) / PI } else { 0. }
+            }
+            // the particles and the fibers scatter the light all around, 1/4π per steradian
+            Material::Isotropic { .. } | Material::Hair { .. } => 1. / (4. * PI),
+            // the mirrors and the glass reflect a point light in a single direction only
+            Material::Metal { .. } | Material::Dielectric { .. } => 0.,
+            // the glowing surfaces scatter nothing
+            Material::Emissive { .. } => 0.,
         }
     }
 }
@@ -56,10 +231,12 @@ impl Scatterable for Material {
     // LEARN:
     // In the book the scatter accepts the hit_record as a mutable reference and returns bool
     // In Rust the idiomatic way is to return an Option<(ray: Ray, attenuation:Vec3)> instead.
+    // The attenuation is owned, the textured materials compute it at the hit point.
     // Note that the HitRecord is consumed by this function.
     fn scatter(&self,
                r_in: &Ray,
-               rec: HitRecord) -> Option<(Ray, &Vec3)> {
+               rec: HitRecord,
+               min_roughness: f32) -> Option<(Ray, Vec3)> {
 
         // LEARN:
         // The `match` must be exhaustive. We need to handle all variants of the enum.
@@ -95,33 +272,92 @@ impl Scatterable for Material {
         match self {
             // LEARN:
             // the enum is destructed her and the structs fields are accessed by ref.
-            Material::Lambertian { albedo } => {
+            Material::Lambertian { albedo, texture } => {
                 let target = &rec.p + &rec.normal + Vec3::random_in_unit_sphere();
                 let direction = target - &rec.p;
-                let scattered = Ray::from(rec.p, direction);
-                let attenuation = albedo;
+                let attenuation = match texture {
+                    Some(texture) => {
+                        // the beam is stretched along the surface when it hits it at a grazing angle
+                        let cosine = Vec3::dot(&r_in.direction().unit(), &rec.normal).abs().max(0.05);
+                        let width = r_in.footprint_at(rec.t) / cosine;
+                        let footprint = [width * rec.uv_rate[0], width * rec.uv_rate[1]];
+                        albedo * texture.value(rec.u, rec.v, &rec.p, footprint)
+                    }
+                    None => albedo.clone(),
+                };
+                let attenuation = match &rec.color {
+                    Some(color) => &attenuation * color,
+                    None => attenuation,
+                };
+                let scattered = Ray::scattered(rec.p, direction, RayType::Diffuse);
                 Some((scattered, attenuation))
             }
-            Material::Metal { albedo, fuzz } => {
+            Material::Metal { albedo, fuzz, conductor } => {
 
                 let reflected = reflect(&r_in.direction().unit(), &rec.normal);
-                let direction = reflected + *fuzz * Vec3::random_in_unit_sphere();
-                let scattered = Ray::from(rec.p, direction);
-                let attenuation = albedo;
+                let direction = reflected + fuzz.clamp(0., 1.).max(min_roughness) * Vec3::random_in_unit_sphere();
+                let scattered = Ray::scattered(rec.p, direction, RayType::Reflection);
                 if Vec3::dot(scattered.direction(), &rec.normal) > 0.0 {
+                    // the real metal reflects more the more grazing the ray is
+                    let albedo = match conductor {
+                        Some(conductor) => albedo * conductor.reflectance(-Vec3::dot(&r_in.direction().unit(), &rec.normal)),
+                        None => albedo.clone(),
+                    };
+                    let attenuation = match rec.color {
+                        Some(color) => &albedo * color,
+                        None => albedo,
+                    };
                     Some((scattered, attenuation))
                 } else {
                     None
                 }
             }
-            Material::Dielectric {ref_idx, attenuation} => {
+            Material::Dielectric { ref_idx, attenuation, absorption, waves, .. } => {
 
+                // the side is the one of the flat surface, the waves only tilt the normal
+                let inside = Vec3::dot(r_in.direction(), &rec.normal) > 0.0;
+                // LEARN:
+                // The light loses the same share of itself on every unit of its way
+                // through the medium (the Beer-Lambert law), so the part that is left
+                // falls exponentially with the distance. The ray that hits the surface
+                // from inside has just crossed the medium from where it entered.
+                let attenuation = if inside {
+                    let distance = rec.t * r_in.direction().length();
+                    let absorbed = |a: f32| (-a * distance).exp();
+                    attenuation * Vec3::new(absorbed(absorption.x()), absorbed(absorption.y()), absorbed(absorption.z()))
+                } else {
+                    attenuation.clone()
+                };
+                // the flat normal is kept aside only when the waves tilt it
+                let (rec, geometric) = match waves {
+                    Some(waves) => {
+                        let normal = waves.normal(&rec.p, &rec.normal);
+                        let geometric = rec.normal;
+                        (HitRecord { normal, ..rec }, Some(geometric))
+                    }
+                    None => (rec, None),
+                };
+                let geometric = geometric.as_ref().unwrap_or(&rec.normal);
                 let outward_normal: Vec3;
-                let reflected = reflect(&r_in.direction(), &rec.normal);
+                // the glass is smooth, the regularization is the only source of its fuzz
+                let roughen = |direction: Vec3| if min_roughness > 0. {
+                    direction.unit() + min_roughness * Vec3::random_in_unit_sphere()
+                } else {
+                    direction
+                };
+                let reflected = reflect(r_in.direction(), &rec.normal);
+                // a tilted normal may reflect the ray into the surface, it stays on its side
+                let across = Vec3::dot(&reflected, geometric) * Vec3::dot(r_in.direction(), geometric);
+                let reflected = if across > 0.0 {
+                    reflect(&reflected, geometric)
+                } else {
+                    reflected
+                };
+                let reflected = roughen(reflected);
                 let ni_over_nt: f32;
                 let cosine: f32;
 
-                if Vec3::dot(r_in.direction(), &rec.normal) > 0.0 {
+                if inside {
                     ni_over_nt = *ref_idx;
                     cosine = *ref_idx * Vec3::dot(r_in.direction(), &rec.normal) / r_in.direction().length();
                     outward_normal = -&rec.normal;
@@ -137,19 +373,53 @@ impl Scatterable for Material {
                 // that the ownership of the variables is clear and the destructing or consuming
                 // operations move toward to the tail of the scope of the variables.
 
-                match refract(&r_in.direction(), &outward_normal, ni_over_nt) {
-                    Some(refracted) => {
-                        // some rays are reflected and some are refracted
-                        // depends on the angle of view
-                        if drand32() >= schlick(cosine, *ref_idx) {
-                            Some((Ray::from(rec.p, refracted), attenuation))
-                        } else {
-                            Some((Ray::from(rec.p, reflected), attenuation))
-                        }
-                    },
-                    None => Some((Ray::from(rec.p, reflected), attenuation)),
-                }
+                let (direction, kind) = match refract(r_in.direction(), &outward_normal, ni_over_nt) {
+                    // some rays are reflected and some are refracted
+                    // depends on the angle of view
+                    Some(refracted) if drand32() >= schlick(cosine, *ref_idx) => (roughen(refracted), RayType::Refraction),
+                    _ => (reflected, RayType::Reflection),
+                };
+                Some((Ray::scattered(rec.p, direction, kind), attenuation))
+            }
+            Material::Hair { color, roughness } => {
+                let d = r_in.direction().unit();
+                // without a fiber, e.g. on a sphere, the fibers run across the normal
+                let tangent = rec.tangent.clone().unwrap_or_else(|| {
+                    let helper = if rec.normal.x().abs() < 0.9 { Vec3::new(1., 0., 0.) } else { Vec3::new(0., 1., 0.) };
+                    Vec3::cross(&rec.normal, &helper).unit()
+                });
+                // LEARN:
+                // The light leaves a fiber at the same angle to it as it came, so the directions
+                // lie on a cone around the fiber. The highlights of the hair are the bands
+                // across it, not the spots, because of that.
+                let along = Vec3::dot(&d, &tangent);
+                let (direction, attenuation, kind) = if drand32() < HAIR_HIGHLIGHT {
+                    // reflected by the surface of the fiber, it keeps the color of the light
+                    (reflect(&d, &rec.normal), Vec3::new(1., 1., 1.), RayType::Reflection)
+                } else {
+                    // through the fiber and out of it at any side, colored by the pigment
+                    let across = &d - along * &tangent;
+                    let across = if across.squared_length() > 0. { across.unit() } else { rec.normal.clone() };
+                    let side = Vec3::cross(&tangent, &across);
+                    let angle = 2. * PI * drand32();
+                    let around = angle.cos() * across + angle.sin() * side;
+                    let attenuation = match &rec.color {
+                        Some(tint) => color * tint,
+                        None => color.clone(),
+                    };
+                    (along * &tangent + (1. - along * along).max(0.).sqrt() * around, attenuation, RayType::Diffuse)
+                };
+                let direction = direction + roughness.clamp(0., 1.).max(min_roughness) * Vec3::random_in_unit_sphere();
+                Some((Ray::scattered(rec.p, direction, kind), attenuation))
+            }
+            Material::Isotropic { albedo } => {
+                // a zero direction would be a ray that goes nowhere
+                let direction = std::iter::repeat_with(Vec3::random_in_unit_sphere)
+                    .find(|direction| direction.squared_length() > 1e-6)
+                    .expect("the random points are not all at the center");
+                Some((Ray::scattered(rec.p, direction, RayType::Diffuse), albedo.clone()))
             }
+            Material::Emissive { .. } => None,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,116 @@
+/**
+Reading of the Wavefront OBJ models, the text format most modeling tools export.
+
+Only the geometry is read: the vertices (`v`), the texture coordinates (`vt`),
+the faces (`f`) and the names of the materials the faces use (`usemtl`).
+The colors some tools append to the vertices (`v x y z r g b`, e.g. of a scan)
+are read too.
+The polygons are split into the fans of the triangles. The normals (`vn`) are ignored,
+the mesh computes the smooth ones, and the `.mtl` files are not read:
+the scene file defines the materials for the names the model uses.
+*/
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::math::color::ColorSpace;
+use crate::math::vec::Vec3;
+
+/// The triangles of the model and the names of their materials.
+pub(crate) struct Model {
+    pub(crate) points: Vec<(Vec3, [f32; 2])>,
+    /// The linear colors of the points, empty when the model has none.
+    pub(crate) colors: Vec<Vec3>,
+    /// The vertices of the triangle and the material it uses, `None` before any `usemtl`.
+    pub(crate) faces: Vec<([usize; 3], Option<String>)>,
+}
+
+pub(crate) fn load(path: &Path) -> Result<Model, String> {
+    let text = std::fs::read_to_string(path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+    parse(&text).map_err(|why| format!("{}: {}", path.display(), why))
+}
+
+pub(crate) fn parse(text: &str) -> Result<Model, String> {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut position_colors: Vec<Option<Vec3>> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut model = Model { points: Vec::new(), colors: Vec::new(), faces: Vec::new() };
+    let mut point_positions: Vec<usize> = Vec::new();
+    // the vertex of the mesh for every pair of the position and the texture coordinates
+    let mut points: HashMap<(usize, Option<usize>), usize> = HashMap::new();
+    let mut material: Option<String> = None;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let error = |message: String| format!("line {}: {}", line_number + 1, message);
+        let mut words = line.split_whitespace();
+        let numbers = |words: std::str::SplitWhitespace| words
+            .map(|word| word.parse::<f32>().map_err(|_| error(format!("`{}` is not a number", word))))
+            .collect::<Result<Vec<f32>, String>>();
+        match words.next() {
+            Some("v") => match *numbers(words)?.as_slice() {
+                // the color is written as sRGB, 0..1
+                [x, y, z, r, g, b, ..] => {
+                    positions.push(Vec3::new(x, y, z));
+                    let [r, g, b] = [r, g, b].map(|c| ColorSpace::Srgb.decode(c.clamp(0., 1.)));
+                    position_colors.push(Some(Vec3::new(r, g, b)));
+                }
+                [x, y, z, ..] => {
+                    positions.push(Vec3::new(x, y, z));
+                    position_colors.push(None);
+                }
+                _ => return Err(error("the vertex needs 3 coordinates".to_string())),
+            },
+            Some("vt") => match *numbers(words)?.as_slice() {
+                [u, v, ..] => uvs.push([u, v]),
+                [u] => uvs.push([u, 0.]),
+                _ => return Err(error("the texture coordinates need 1 or 2 numbers".to_string())),
+            },
+            Some("f") => {
+                let mut corners = Vec::new();
+                for word in words {
+                    // `v`, `v/vt`, `v//vn` or `v/vt/vn`
+                    let mut indices = word.split('/');
+                    let position = resolve(indices.next().unwrap_or(""), positions.len()).map_err(&error)?;
+                    let uv = match indices.next() {
+                        Some("") | None => None,
+                        Some(index) => Some(resolve(index, uvs.len()).map_err(&error)?),
+                    };
+                    let next = model.points.len();
+                    let point = *points.entry((position, uv)).or_insert(next);
+                    if point == next {
+                        model.points.push((positions[position].clone(), uv.map_or([0., 0.], |uv| uvs[uv])));
+                        point_positions.push(position);
+                    }
+                    corners.push(point);
+                }
+                if corners.len() < 3 {
+                    return Err(error("the face needs at least 3 vertices".to_string()));
+                }
+                for i in 1..corners.len() - 1 {
+                    model.faces.push(([corners[0], corners[i], corners[i + 1]], material.clone()));
+                }
+            }
+            Some("usemtl") => material = words.next().map(str::to_string),
+            // the comments, the normals, the groups, the smoothing and the rest
+            _ => {}
+        }
+    }
+    // the vertices without a color are white, they show the material as it is
+    if position_colors.iter().any(Option::is_some) {
+        model.colors = point_positions.iter()
+            .map(|&position| position_colors[position].clone().unwrap_or(Vec3::new(1., 1., 1.)))
+            .collect();
+    }
+    Ok(model)
+}
+
+/// The index from 0 of the element the OBJ index refers to, the OBJ indices start from 1
+/// and the negative ones count back from the last element read so far.
+fn resolve(index: &str, count: usize) -> Result<usize, String> {
+    let index: i64 = index.parse().map_err(|_| format!("`{}` is not an index", index))?;
+    let resolved = if index < 0 { count as i64 + index } else { index - 1 };
+    if (0..count as i64).contains(&resolved) {
+        Ok(resolved as usize)
+    } else {
+        Err(format!("index {} is out of the {} elements read so far", index, count))
+    }
+}
@@ -0,0 +1,274 @@
+/**
+Reading and writing of the PLY models, the format the 3D scanners and the point cloud tools write.
+
+The file starts with the text header that declares the elements, e.g. the vertices
+and the faces, and the properties every one of them has. The values follow
+as the text or as the binary numbers of either byte order. The positions, the colors
+and the texture coordinates of the vertices are read with the faces,
+the rest of the properties and of the elements is skipped.
+The models are written as the text, e.g. the meshes with the baked colors of their vertices.
+*/
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::math::color::ColorSpace;
+use crate::math::vec::Vec3;
+use crate::scene::obj::Model;
+
+#[derive(Clone, Copy)]
+enum Scalar { I8, U8, I16, U16, I32, U32, F32, F64 }
+
+enum Kind {
+    Scalar(Scalar),
+    /// The type of the length of the list and the type of its items.
+    List(Scalar, Scalar),
+}
+
+struct Property {
+    name: String,
+    kind: Kind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// The value of the white of the colors stored in this type.
+    fn white(self) -> f64 {
+        match self {
+            Scalar::U8 => 255.,
+            Scalar::U16 => 65535.,
+            _ => 1.,
+        }
+    }
+}
+
+/// The values of the body of the file, one at a time.
+enum Reader<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl Reader<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+        match self {
+            Reader::Ascii(words) => {
+                let word = words.next().ok_or("the file ends before all the elements")?;
+                word.parse().map_err(|_| format!("`{}` is not a number", word))
+            }
+            Reader::Binary { bytes, big_endian } => {
+                let size = scalar.size();
+                let (value, rest) = bytes.split_at_checked(size).ok_or("the file ends before all the elements")?;
+                *bytes = rest;
+                let mut raw = [0u8; 8];
+                raw[..size].copy_from_slice(value);
+                if *big_endian {
+                    raw[..size].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = raw;
+                Ok(match scalar {
+                    Scalar::I8 => b0 as i8 as f64,
+                    Scalar::U8 => b0 as f64,
+                    Scalar::I16 => i16::from_le_bytes([b0, b1]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([b0, b1]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(raw),
+                })
+            }
+        }
+    }
+}
+
+pub(crate) fn load(path: &Path) -> Result<Model, String> {
+    let bytes = std::fs::read(path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+    parse(&bytes).map_err(|why| format!("{}: {}", path.display(), why))
+}
+
+pub(crate) fn parse(bytes: &[u8]) -> Result<Model, String> {
+    let (elements, mut reader) = header(bytes)?;
+    let mut model = Model { points: Vec::new(), colors: Vec::new(), faces: Vec::new() };
+    // the values of the properties of the current item, the lists are read into `list`
+    let mut values = Vec::new();
+    let mut list = Vec::new();
+
+    for element in &elements {
+        let find = |names: &[&str]| element.properties.iter()
+            .position(|property| names.contains(&property.name.as_str()));
+        let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let color = [find(&["red", "r", "diffuse_red"]), find(&["green", "g", "diffuse_green"]), find(&["blue", "b", "diffuse_blue"])];
+        let uv = [find(&["u", "s", "texture_u"]), find(&["v", "t", "texture_v"])];
+        let indices = find(&["vertex_indices", "vertex_index"]);
+        if element.name == "vertex" && position.contains(&None) {
+            return Err("the vertices need x, y and z".to_string());
+        }
+        if element.name == "face" && indices.is_none() {
+            return Err("the faces need the `vertex_indices`".to_string());
+        }
+
+        for _ in 0..element.count {
+            values.clear();
+            for (i, property) in element.properties.iter().enumerate() {
+                match property.kind {
+                    Kind::Scalar(scalar) => values.push(reader.read(scalar)?),
+                    Kind::List(length, item) => {
+                        values.push(0.);
+                        let length = reader.read(length)? as usize;
+                        if Some(i) == indices {
+                            list.clear();
+                        }
+                        for _ in 0..length {
+                            let value = reader.read(item)?;
+                            if Some(i) == indices {
+                                list.push(value);
+                            }
+                        }
+                    }
+                }
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let [x, y, z] = position.map(|i| values[i.unwrap()] as f32);
+                    let uv = match uv {
+                        [Some(u), Some(v)] => [values[u] as f32, values[v] as f32],
+                        _ => [0., 0.],
+                    };
+                    model.points.push((Vec3::new(x, y, z), uv));
+                    if let [Some(r), Some(g), Some(b)] = color {
+                        let [r, g, b] = [r, g, b].map(|i| {
+                            let white = match element.properties[i].kind {
+                                Kind::Scalar(scalar) => scalar.white(),
+                                Kind::List(..) => 1.,
+                            };
+                            ColorSpace::Srgb.decode((values[i] / white).clamp(0., 1.) as f32)
+                        });
+                        model.colors.push(Vec3::new(r, g, b));
+                    }
+                }
+                "face" => {
+                    if list.len() < 3 {
+                        return Err("the face needs at least 3 vertices".to_string());
+                    }
+                    for i in 1..list.len() - 1 {
+                        model.faces.push(([list[0], list[i], list[i + 1]].map(|index| index as usize), None));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if let Some((face, _)) = model.faces.iter().find(|(face, _)| face.iter().any(|&i| i >= model.points.len())) {
+        return Err(format!("the face {:?} refers to a vertex out of the {}", face, model.points.len()));
+    }
+    Ok(model)
+}
+
+/// Writes the model as the text PLY file, the colors as 8-bit sRGB, as they are read,
+/// returns the size of the file. The names of the materials of the faces are not kept.
+pub(crate) fn write(path: &Path, model: &Model) -> std::io::Result<u64> {
+    let mut text = String::new();
+    let colored = model.colors.len() == model.points.len();
+    let _ = writeln!(text, "ply\nformat ascii 1.0\nelement vertex {}", model.points.len());
+    text.push_str("property float x\nproperty float y\nproperty float z\nproperty float u\nproperty float v\n");
+    if colored {
+        text.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\n");
+    }
+    let _ = writeln!(text, "element face {}\nproperty list uchar int vertex_indices\nend_header", model.faces.len());
+    for (i, (p, [u, v])) in model.points.iter().enumerate() {
+        let _ = write!(text, "{} {} {} {} {}", p.x(), p.y(), p.z(), u, v);
+        if colored {
+            let color = &model.colors[i];
+            for channel in [color.x(), color.y(), color.z()] {
+                let _ = write!(text, " {}", (255. * ColorSpace::Srgb.encode(channel) + 0.5).clamp(0., 255.) as u8);
+            }
+        }
+        text.push('\n');
+    }
+    for ([a, b, c], _) in &model.faces {
+        let _ = writeln!(text, "3 {} {} {}", a, b, c);
+    }
+    std::fs::write(path, &text)?;
+    Ok(text.len() as u64)
+}
+
+/// The elements the header declares and the reader of the values that follow it.
+fn header(bytes: &[u8]) -> Result<(Vec<Element>, Reader<'_>), String> {
+    let mut rest = bytes;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut format = None;
+    for line_number in 1.. {
+        let end = rest.iter().position(|&b| b == b'\n').ok_or("the header has no `end_header`")?;
+        let line = std::str::from_utf8(&rest[..end]).map_err(|_| format!("line {} of the header is not text", line_number))?;
+        rest = &rest[end + 1..];
+        let error = |message: &str| format!("line {}: {}", line_number, message);
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["ply"] if line_number == 1 => {}
+            _ if line_number == 1 => return Err("not a PLY file".to_string()),
+            ["format", name, _] => format = Some(*name),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| error("the count of the element is not a number"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", length, item, name] => {
+                let kind = match (Scalar::parse(length), Scalar::parse(item)) {
+                    (Some(length), Some(item)) => Kind::List(length, item),
+                    _ => return Err(error("unknown type of the list")),
+                };
+                let element = elements.last_mut().ok_or_else(|| error("the property is before any element"))?;
+                element.properties.push(Property { name: name.to_string(), kind });
+            }
+            ["property", scalar, name] => {
+                let kind = Kind::Scalar(Scalar::parse(scalar).ok_or_else(|| error("unknown type of the property"))?);
+                let element = elements.last_mut().ok_or_else(|| error("the property is before any element"))?;
+                element.properties.push(Property { name: name.to_string(), kind });
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(error("not a line of the header")),
+        }
+    }
+
+    let reader = match format {
+        Some("ascii") => {
+            let text = std::str::from_utf8(rest).map_err(|_| "the values of the ascii file are not text")?;
+            Reader::Ascii(text.split_ascii_whitespace())
+        }
+        Some("binary_little_endian") => Reader::Binary { bytes: rest, big_endian: false },
+        Some("binary_big_endian") => Reader::Binary { bytes: rest, big_endian: true },
+        Some(format) => return Err(format!("unknown format `{}`", format)),
+        None => return Err("the header has no `format`".to_string()),
+    };
+    Ok((elements, reader))
+}
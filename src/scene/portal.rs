@@ -0,0 +1,65 @@
+/**
+The light portals: the rectangles of the windows the sky lights a room through.
+
+Inside a room the sky is seen only through the windows, a small part of all the directions.
+The diffuse rays scattered in all directions rarely find them, so the room lit by the sky
+is covered with the noise, and it stays noisy for thousands of samples.
+
+A portal tells the renderer where the window is. Every diffuse hit sends a ray through
+a random point of the portal and gathers the sky it sees, the way it gathers a light,
+and the diffuse rays that leave through the portal bring nothing, their sky is already
+counted. The portals are not surfaces, they neither block nor reflect the light.
+The portals must not overlap as seen from the room, the sky behind both would count twice.
+*/
+use crate::math::rand::drand32;
+use crate::math::vec::{Ray, Vec3};
+
+#[derive(Clone)]
+pub(crate) struct Portal {
+    corner: Vec3,
+    /// The two sides of the rectangle from the corner.
+    sides: [Vec3; 2],
+    /// The unit normal of the rectangle, either side.
+    normal: Vec3,
+    area: f32,
+}
+
+impl Portal {
+    /// The rectangle from the corner along the two perpendicular sides.
+    pub(crate) fn new(corner: Vec3, sides: [Vec3; 2]) -> Portal {
+        let cross = Vec3::cross(&sides[0], &sides[1]);
+        let area = cross.length();
+        Portal { corner, normal: cross / area, sides, area }
+    }
+
+    pub(crate) fn area(&self) -> f32 {
+        self.area
+    }
+
+    /// The random point of the rectangle, all of them equally likely.
+    pub(crate) fn sample(&self) -> Vec3 {
+        &self.corner + drand32() * &self.sides[0] + drand32() * &self.sides[1]
+    }
+
+    /// The cosine of the angle between the direction and the normal, either side.
+    pub(crate) fn cosine(&self, direction: &Vec3) -> f32 {
+        Vec3::dot(&direction.unit(), &self.normal).abs()
+    }
+
+    /// Whether the ray goes through the rectangle.
+    pub(crate) fn crosses(&self, r: &Ray) -> bool {
+        let denominator = Vec3::dot(r.direction(), &self.normal);
+        if denominator == 0. {
+            return false;
+        }
+        let t = Vec3::dot(&(&self.corner - r.origin()), &self.normal) / denominator;
+        if t <= 0. {
+            return false;
+        }
+        let local = r.point_at(t) - &self.corner;
+        self.sides.iter().all(|side| {
+            let along = Vec3::dot(&local, side) / side.squared_length();
+            (0. ..=1.).contains(&along)
+        })
+    }
+}
@@ -0,0 +1,295 @@
+/**
+The versions of the scene format and the keys of every table of the current one.
+
+Every scene file starts with the `version` of the format it is written for, so the format
+can change without breaking the scenes already written:
+
+- a file of an older version is upgraded in memory when it is loaded, with a warning,
+  and `raytracer migrate <scene-file>` rewrites it in the current version once for all;
+- a file of a newer version, written for a newer build, is read as far as this build
+  understands it, with a warning, instead of being refused;
+- a key this build does not know, a newer one or a typo, is ignored with a warning
+  that names it, and `raytracer check --strict` fails on it.
+
+The changes of the format are kept in `MIGRATIONS`, each with the version it made.
+The version 2 renamed the brightness of the sun of the `[sky]` to `sun_intensity`:
+the `sun` of the `[fog]` is the table of the sunlight, and the same name
+for a number in one table and a table in the other was a trap.
+
+LEARN:
+The migration rewrites the file line by line instead of writing the parsed document back,
+so the comments and the layout of the file are kept. The rewritten text is parsed again
+and compared with the document migrated in memory, a file the lines cannot be rewritten
+for (e.g. the key written in an inline table) is left as it is.
+*/
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::toml::{self, Table, Value};
+
+/// The version of the format the files are written in.
+pub(crate) const VERSION: i64 = 2;
+
+/// A key renamed by a version of the format.
+struct Migration {
+    /// The version that renamed it.
+    version: i64,
+    table: &'static str,
+    from: &'static str,
+    to: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 2, table: "sky", from: "sun", to: "sun_intensity" },
+];
+
+/// A problem of the file that does not stop it from loading.
+pub(crate) struct Warning {
+    /// Where in the scene the problem is, e.g. `sphere #2`.
+    pub(crate) location: String,
+    pub(crate) message: String,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Upgrades the document of an older version to the current one,
+/// returns the warnings about its version.
+pub(crate) fn upgrade(doc: &mut Table) -> Result<Vec<Warning>, String> {
+    let version = version_of(doc)?;
+    let warning = |message: String| vec![Warning { location: "version".to_string(), message }];
+    if version > VERSION {
+        return Ok(warning(format!(
+            "the file is written for the version {} of the format, this build reads the version {}, \
+             the keys it does not know are ignored", version, VERSION)));
+    }
+    if version < VERSION {
+        migrate(doc, version);
+        return Ok(warning(format!(
+            "the file is written for the old version {} of the format, `raytracer migrate` upgrades it to the version {}",
+            version, VERSION)));
+    }
+    Ok(Vec::new())
+}
+
+fn version_of(doc: &Table) -> Result<i64, String> {
+    let version = doc.get("version").ok_or(format!("missing `version`, the current version is {}", VERSION))?;
+    match version.as_integer() {
+        Some(version) if version >= 1 => Ok(version),
+        _ => Err(format!("unsupported version {}", version)),
+    }
+}
+
+/// Applies the changes of the versions after the version, returns what they changed.
+fn migrate(doc: &mut Table, version: i64) -> Vec<String> {
+    let mut changes = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > version) {
+        let Some(table) = doc.table_mut(migration.table) else {
+            continue;
+        };
+        if table.get(migration.to).is_none() && table.rename(migration.from, migration.to) {
+            changes.push(format!("[{}] {} is renamed to {}", migration.table, migration.from, migration.to));
+        }
+    }
+    doc.set("version", Value::Integer(VERSION));
+    changes
+}
+
+/// Rewrites the scene file of an older version in the current one, keeping its comments.
+/// Returns what was changed, `None` when the file is already of the current version.
+pub(crate) fn migrate_file(path: &Path) -> Result<Option<Vec<String>>, String> {
+    let text = std::fs::read_to_string(path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+    let mut doc = toml::parse(&text).map_err(|why| format!("syntax error at {}", why))?;
+    let version = version_of(&doc)?;
+    if version > VERSION {
+        return Err(format!("the version {} is newer than the version {} of this build", version, VERSION));
+    }
+    if version == VERSION {
+        return Ok(None);
+    }
+    let changes = migrate(&mut doc, version);
+    let migrated = migrate_text(&text, version);
+    if toml::parse(&migrated).ok().as_ref() != Some(&doc) {
+        return Err(format!("cannot be rewritten line by line, change it by hand: {}", changes.join(", ")));
+    }
+    std::fs::write(path, migrated).map_err(|why| format!("cannot write {}: {}", path.display(), why))?;
+    Ok(Some(changes))
+}
+
+/// The text with the keys renamed by the versions after the version and the new version.
+fn migrate_text(text: &str, version: i64) -> String {
+    let mut table = String::new();
+    let mut migrated = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') {
+            table = trimmed.trim_start_matches('[').split(']').next().unwrap_or_default().trim().to_string();
+        }
+        let is_key = |key: &str| trimmed.strip_prefix(key).is_some_and(|rest| rest.trim_start().starts_with('='));
+        if table.is_empty() && is_key("version") {
+            // the value is replaced, the comment after it is kept
+            let (key, value) = line.split_once('=').unwrap_or((line, ""));
+            let value = value.trim_start();
+            let end = value.find(|c: char| c.is_whitespace() || c == '#').unwrap_or(value.len());
+            migrated.push_str(&format!("{}= {}{}", key, VERSION, &value[end..]));
+            continue;
+        }
+        let renamed = MIGRATIONS.iter()
+            .find(|migration| migration.version > version && migration.table == table && is_key(migration.from));
+        match renamed {
+            Some(migration) => migrated.push_str(&line.replacen(migration.from, migration.to, 1)),
+            None => migrated.push_str(line),
+        }
+    }
+    migrated
+}
+
+/// The keys of the texture tables that set the transform of the texture coordinates.
+const TRANSFORM_KEYS: &[&str] = &["scale", "offset", "rotation"];
+
+/// The keys every surface may have.
+const SURFACE_KEYS: &[&str] = &["material", "visibility", "name", "tags", "priority"];
+
+/// The warnings about the keys the current version does not know, they are ignored.
+pub(crate) fn unknown_keys(doc: &Table) -> Vec<Warning> {
+    let mut unknown = Unknown(Vec::new());
+    unknown.check("scene", "", doc, &[&[
+        "version", "include", "coincidence", "unit", "image", "camera", "sky", "environment", "stars", "fog",
+        "sphere", "heightfield", "mesh", "curves", "points", "volume", "light", "portal", "clip_plane",
+    ]]);
+    let tables: [(&str, &[&str]); 6] = [
+        ("image", &["width", "height", "pixel_aspect"]),
+        ("camera", &["look_from", "look_at", "up", "fov", "aperture", "focus_dist"]),
+        ("sky", &["latitude", "longitude", "date", "time", "timezone", "north", "sun_intensity"]),
+        ("environment", &["image", "rotation", "exposure"]),
+        ("stars", &["seed", "density", "brightness", "milky_way"]),
+        ("fog", &["color", "density", "height", "falloff", "sun", "lights", "steps"]),
+    ];
+    for (key, known) in tables {
+        if let Some(table) = doc.get(key).and_then(Value::as_table) {
+            unknown.check(key, "", table, &[known]);
+        }
+    }
+    if let Some(sun) = doc.get("fog").and_then(Value::as_table).and_then(|fog| fog.get("sun")).and_then(Value::as_table) {
+        unknown.check("fog", "sun.", sun, &[&["direction", "color"]]);
+    }
+
+    let surfaces: [(&str, &[&str]); 6] = [
+        ("sphere", &["center", "radius", "rotation", "displacement"]),
+        ("heightfield", &["corner", "size", "displacement"]),
+        ("mesh", &["obj", "ply", "unit", "up", "scale", "subdivisions", "materials", "vertices", "uvs", "colors", "faces", "face_materials"]),
+        ("curves", &["basis", "points", "width"]),
+        ("points", &["ply", "unit", "up", "scale", "positions", "colors", "radius", "shape"]),
+        ("volume", &["grid", "resolution", "corner", "size", "density"]),
+    ];
+    for (kind, known) in surfaces {
+        for (i, surface) in objects(doc, kind).enumerate() {
+            let location = format!("{} #{}", kind, i + 1);
+            unknown.check(&location, "", surface, &[known, SURFACE_KEYS]);
+            if let Some(material) = surface.get("material") {
+                unknown.material(&location, "material.", material);
+            }
+            for (name, material) in surface.get("materials").and_then(Value::as_table).into_iter().flat_map(named) {
+                unknown.material(&location, &format!("materials.{}.", name), material);
+            }
+            if let Some(visibility) = surface.get("visibility").and_then(Value::as_table) {
+                unknown.check(&location, "visibility.", visibility, &[&["camera", "shadow", "reflection"]]);
+            }
+            if let Some(displacement) = surface.get("displacement").and_then(Value::as_table) {
+                unknown.check(&location, "displacement.", displacement, &[&["texture", "amount", "resolution"]]);
+                if let Some(texture) = displacement.get("texture") {
+                    unknown.texture(&location, "displacement.texture.", texture);
+                }
+            }
+        }
+    }
+
+    for (i, light) in objects(doc, "light").enumerate() {
+        let location = format!("light #{}", i + 1);
+        unknown.check(&location, "", light, &[&["position", "intensity", "direction", "spot", "ies"]]);
+        if let Some(spot) = light.get("spot").and_then(Value::as_table) {
+            unknown.check(&location, "spot.", spot, &[&["angle", "blend"]]);
+        }
+    }
+    for (i, portal) in objects(doc, "portal").enumerate() {
+        unknown.check(&format!("portal #{}", i + 1), "", portal, &[&["corner", "sides"]]);
+    }
+    for (i, plane) in objects(doc, "clip_plane").enumerate() {
+        unknown.check(&format!("clip_plane #{}", i + 1), "", plane, &[&["point", "normal", "cap"]]);
+    }
+    unknown.0
+}
+
+/// The tables of the array of tables of the key, the wrong values are left to the loader.
+fn objects<'a>(doc: &'a Table, key: &str) -> impl Iterator<Item = &'a Table> {
+    doc.get(key).and_then(Value::as_array).unwrap_or_default().iter().filter_map(Value::as_table)
+}
+
+/// The entries of the table of the named materials.
+fn named(table: &Table) -> impl Iterator<Item = (&str, &Value)> {
+    table.keys().filter_map(|name| table.get(name).map(|value| (name, value)))
+}
+
+struct Unknown(Vec<Warning>);
+
+impl Unknown {
+    /// Reports the keys of the table that are in none of the lists, the prefix is the path to the table.
+    fn check(&mut self, location: &str, prefix: &str, table: &Table, known: &[&[&str]]) {
+        for key in table.keys().filter(|key| !known.iter().any(|known| known.contains(key))) {
+            self.0.push(Warning {
+                location: location.to_string(),
+                message: format!("unknown key `{}{}`, ignored", prefix, key),
+            });
+        }
+    }
+
+    fn material(&mut self, location: &str, prefix: &str, material: &Value) {
+        let Some(material) = material.as_table() else {
+            return;
+        };
+        let known: &[&str] = match material.get("type").and_then(Value::as_str) {
+            Some("lambertian") => &["albedo", "texture"],
+            Some("metal") => &["albedo", "fuzz", "roughness", "preset", "n", "k"],
+            Some("dielectric") => &["ior", "absorption", "waves", "priority"],
+            Some("water") => &["absorption", "waves", "priority"],
+            Some("isotropic") => &["albedo"],
+            Some("hair") => &["color", "roughness"],
+            Some("emissive") => &["radiance"],
+            // the loader reports the unknown types
+            _ => return,
+        };
+        self.check(location, prefix, material, &[&["type", "shader"], known]);
+        if let Some(shader) = material.get("shader").and_then(Value::as_table) {
+            let known: &[&str] = match shader.get("type").and_then(Value::as_str) {
+                Some("rim") => &["color", "power"],
+                Some("facing") | Some("bounces") => &[],
+                _ => &["color", "power"],
+            };
+            self.check(location, &format!("{}shader.", prefix), shader, &[&["type"], known]);
+        }
+        if let Some(waves) = material.get("waves").and_then(Value::as_table) {
+            self.check(location, &format!("{}waves.", prefix), waves, &[&["length", "height"]]);
+        }
+        if let Some(texture) = material.get("texture") {
+            self.texture(location, &format!("{}texture.", prefix), texture);
+        }
+    }
+
+    fn texture(&mut self, location: &str, prefix: &str, texture: &Value) {
+        let Some(texture) = texture.as_table() else {
+            return;
+        };
+        let known: &[&str] = match texture.get("type").and_then(Value::as_str) {
+            None => &["image"],
+            Some("bricks") => &["type", "brick", "mortar", "mortar_width"],
+            Some("stripes") | Some("checker") => &["type", "colors"],
+            Some("gradient") => &["type", "from", "to", "colors"],
+            Some("voronoi") => &["type", "size", "colors"],
+            Some(_) => return,
+        };
+        self.check(location, prefix, texture, &[known, TRANSFORM_KEYS]);
+    }
+}
@@ -0,0 +1,183 @@
+/**
+The scripts that change the scene for every frame of an animation (`--script bounce.rhai`),
+the procedural animations without a new build of the renderer: the bouncing ball,
+the pulsing lamp, the material that changes halfway. The scripts are written in Rhai,
+the small language of the Rust programs, it lives behind the `script` feature.
+
+Every frame starts from the loaded scene and its camera of the animation, see
+`Animation::camera`, then the script runs and its changes are applied. So the script
+says where the things are at the time, not how they moved since the previous frame:
+
+```text
+// the ball bounces once a second, the lamp pulses, the ball turns to gold halfway
+let t = time % 1.0;
+translate("ball", 0, 4 * t * (1 - t), 0);
+scale_light(1, 1 + 0.5 * sin(time * 2 * PI()));
+if frame >= frames / 2 {
+    material("ball", `{ type = "metal", albedo = [1, 0.8, 0.3], fuzz = 0.1 }`);
+}
+look_at(0, 1, -1);
+```
+
+The script sees the `frame` from 0, the number of the `frames` and the `time` in seconds
+at the frames per second of the animation, which goes on in between the frames while
+the shutter is open. It may call:
+
+- `translate(name, x, y, z)`: moves the objects of the name or the tag by the offset,
+  as `--move` does;
+- `material(name, material)`: makes the objects of the material written as in the scene
+  file, as `--material` does, the paths of its textures are relative to the script;
+- `scale_light(number, factor)`: multiplies the intensity of the light, numbered
+  from 1 in the order of the scene file;
+- `look_from(x, y, z)`, `look_at(x, y, z)`, `fov(degrees)`, `aperture(size)`
+  and `focus_dist(distance)`: change the camera, as the tweaker does.
+
+LEARN:
+The functions of the script do not change the scene themselves: they only note
+the changes in the state they share, and the changes are applied after the script ends.
+So the closures given to the engine hold no reference to the scene, which the engine
+would require to live forever.
+*/
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rhai::{Dynamic, Engine, EvalAltResult, Scope};
+
+use crate::math::vec::Vec3;
+use crate::scene::camera::CameraSetup;
+use crate::scene::edit::Edit;
+use crate::scene::file;
+use crate::scene::Scene;
+
+/// The script, run for every frame. It is kept as the text and compiled for every frame,
+/// which costs nothing next to the render: the compiled one may not cross the threads
+/// the settings are shared by.
+#[derive(Debug)]
+pub(crate) struct Script {
+    path: PathBuf,
+    source: String,
+}
+
+/// The changes the script made, see the module.
+struct Changes {
+    edits: Vec<Edit>,
+    /// The number of the light from 1 and the factor of its intensity.
+    lights: Vec<(i64, f32)>,
+    /// The camera changed by the script, `None` when it is not.
+    camera: Option<CameraSetup>,
+}
+
+impl Changes {
+    /// Changes the camera, the one of the scene at the first change.
+    fn camera(&mut self, scene_camera: &CameraSetup, change: impl FnOnce(&mut CameraSetup)) {
+        change(self.camera.get_or_insert_with(|| scene_camera.clone()));
+    }
+}
+
+impl Script {
+    /// Reads and compiles the script, the syntax errors are found before the first frame.
+    pub(crate) fn load(path: &Path) -> Result<Script, String> {
+        let text = std::fs::read_to_string(path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+        Engine::new().compile(&text).map_err(|why| format!("{}: {}", path.display(), why))?;
+        Ok(Script { path: path.to_path_buf(), source: text })
+    }
+
+    /// Runs the script for the frame of the animation of the frames at the time in seconds
+    /// (in between the frames while the shutter is open) and applies its changes to the scene.
+    pub(crate) fn apply(&self, scene: &mut Scene, frame: usize, frames: usize, time: f64) -> Result<(), String> {
+        let changes = Rc::new(RefCell::new(Changes { edits: Vec::new(), lights: Vec::new(), camera: None }));
+        let engine = self.engine(&changes, scene.camera.setup());
+        let mut scope = Scope::new();
+        scope.push_constant("frame", frame as i64);
+        scope.push_constant("frames", frames as i64);
+        scope.push_constant("time", time);
+        engine.run_with_scope(&mut scope, &self.source)
+            .map_err(|why| format!("{}: frame {}: {}", self.path.display(), frame + 1, why))?;
+        drop(engine);
+
+        let Changes { edits, lights, camera } = Rc::into_inner(changes).expect("the engine is dropped").into_inner();
+        for edit in &edits {
+            edit.apply(scene).map_err(|why| format!("{}: {}", self.path.display(), why))?;
+        }
+        let count = scene.world.lights().len();
+        for (number, factor) in lights {
+            let light = usize::try_from(number).ok()
+                .and_then(|number| number.checked_sub(1))
+                .and_then(|index| scene.world.lights_mut().get_mut(index))
+                .ok_or_else(|| format!("{}: there is no light {}, the scene has {}", self.path.display(), number, count))?;
+            light.scale(factor);
+        }
+        if let Some(camera) = camera {
+            scene.camera = camera.build(scene.aspect());
+        }
+        Ok(())
+    }
+
+    /// The engine with the functions of the script, see the module, noting the changes.
+    fn engine(&self, changes: &Rc<RefCell<Changes>>, camera: CameraSetup) -> Engine {
+        let mut engine = Engine::new();
+        let dir = self.path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        let state = changes.clone();
+        engine.register_fn("translate", move |name: &str, x: Dynamic, y: Dynamic, z: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let offset = Vec3::new(number(x)?, number(y)?, number(z)?);
+            state.borrow_mut().edits.push(Edit::Move { name: name.to_string(), offset });
+            Ok(())
+        });
+        let state = changes.clone();
+        engine.register_fn("material", move |name: &str, material: &str| -> Result<(), Box<EvalAltResult>> {
+            let material = file::parse_inline_material(material, &dir)
+                .map_err(|why| format!("the material of {}: {}", name, why))?;
+            state.borrow_mut().edits.push(Edit::Material { name: name.to_string(), material });
+            Ok(())
+        });
+        let state = changes.clone();
+        engine.register_fn("scale_light", move |light: i64, factor: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            state.borrow_mut().lights.push((light, number(factor)?));
+            Ok(())
+        });
+
+        let (state, scene_camera) = (changes.clone(), camera.clone());
+        engine.register_fn("look_from", move |x: Dynamic, y: Dynamic, z: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let value = Vec3::new(number(x)?, number(y)?, number(z)?);
+            state.borrow_mut().camera(&scene_camera, |camera| camera.look_from = value);
+            Ok(())
+        });
+        let (state, scene_camera) = (changes.clone(), camera.clone());
+        engine.register_fn("look_at", move |x: Dynamic, y: Dynamic, z: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let value = Vec3::new(number(x)?, number(y)?, number(z)?);
+            state.borrow_mut().camera(&scene_camera, |camera| camera.look_at = value);
+            Ok(())
+        });
+        let (state, scene_camera) = (changes.clone(), camera.clone());
+        engine.register_fn("fov", move |degrees: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let value = number(degrees)?;
+            state.borrow_mut().camera(&scene_camera, |camera| camera.vfov = value);
+            Ok(())
+        });
+        let (state, scene_camera) = (changes.clone(), camera.clone());
+        engine.register_fn("aperture", move |size: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let value = number(size)?;
+            state.borrow_mut().camera(&scene_camera, |camera| camera.aperture = value);
+            Ok(())
+        });
+        let (state, scene_camera) = (changes.clone(), camera.clone());
+        engine.register_fn("focus_dist", move |distance: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            let value = number(distance)?;
+            state.borrow_mut().camera(&scene_camera, |camera| camera.focus_dist = value);
+            Ok(())
+        });
+        engine
+    }
+}
+
+/// The number of the script, either an integer or a floating-point one,
+/// so `translate("ball", 0, 1, 0)` needs no decimal points.
+fn number(value: Dynamic) -> Result<f32, Box<EvalAltResult>> {
+    let type_name = value.type_name();
+    value.as_float()
+        .or_else(|_| value.as_int().map(|n| n as f64))
+        .map(|n| n as f32)
+        .map_err(|_| format!("a number is expected, not {}", type_name).into())
+}
@@ -0,0 +1,80 @@
+/**
+The shaders of the materials: `shader = { type = "rim", color = [1, 0.6, 0.2] }` in the material.
+
+A shader changes the light a material sends from the hit back along the ray, after
+the material has computed it, without being a material of its own. It sees the whole
+record of the hit and the bounce of the path, enough for the effects the physics
+does not give: the glowing rim of the objects seen edge-on, the shading by the angle
+to the camera of the technical drawings, or the debugging colors of the bounces.
+
+- `rim` adds the `color` where the surface turns away from the ray, `power` (3) makes
+  the rim narrower,
+- `facing` darkens the surface by the angle between it and the ray, the facing ratio,
+- `bounces` replaces the light by the color of the bounce that hit the surface: red for
+  the camera rays, green for the first bounce, blue for the second, and so on.
+
+LEARN:
+Any type that implements the trait is a shader, and so is a closure of the same
+signature, by the blanket `impl` of the trait for all the functions that match:
+a shader that needs no parameters is written as a closure in place.
+The shaders are shared by the threads and the copies of the world in an `Arc`,
+so they are `Send + Sync`, like the observers of the render.
+*/
+use std::sync::Arc;
+
+use crate::math::vec::{Ray, Vec3};
+use crate::scene::surfaces::hitable::HitRecord;
+
+pub(crate) trait Shader: Send + Sync {
+    /// The light that leaves the hit towards the origin of the ray at the bounce of the path,
+    /// 0 for the camera rays, from the `radiance` the material computed.
+    fn shade(&self, hit: &HitRecord, r: &Ray, bounce: usize, radiance: Vec3) -> Vec3;
+}
+
+impl<F> Shader for F where F: Fn(&HitRecord, &Ray, usize, Vec3) -> Vec3 + Send + Sync {
+    fn shade(&self, hit: &HitRecord, r: &Ray, bounce: usize, radiance: Vec3) -> Vec3 {
+        self(hit, r, bounce, radiance)
+    }
+}
+
+/// The glow of the edges, the stronger the more the surface turns away from the ray.
+pub(crate) struct Rim {
+    pub(crate) color: Vec3,
+    pub(crate) power: f32,
+}
+
+impl Shader for Rim {
+    fn shade(&self, hit: &HitRecord, r: &Ray, _bounce: usize, radiance: Vec3) -> Vec3 {
+        radiance + (1. - facing(hit, r)).powf(self.power) * self.color.clone()
+    }
+}
+
+/// The colors of the bounces, they repeat after the last one.
+const BOUNCE_COLORS: [[f32; 3]; 6] = [
+    [1., 0., 0.],
+    [0., 1., 0.],
+    [0., 0., 1.],
+    [1., 1., 0.],
+    [0., 1., 1.],
+    [1., 0., 1.],
+];
+
+/// The shader of the type, `rim` with its color and power, `facing` or `bounces`.
+pub(crate) fn parse(kind: &str, color: Option<Vec3>, power: Option<f32>) -> Option<Arc<dyn Shader>> {
+    match kind {
+        "rim" => Some(Arc::new(Rim { color: color.unwrap_or(Vec3::new(1., 1., 1.)), power: power.unwrap_or(3.) })),
+        "facing" => Some(Arc::new(|hit: &HitRecord, r: &Ray, _bounce: usize, radiance: Vec3| facing(hit, r) * radiance)),
+        "bounces" => Some(Arc::new(|hit: &HitRecord, r: &Ray, bounce: usize, _radiance: Vec3| {
+            let [red, green, blue] = BOUNCE_COLORS[bounce % BOUNCE_COLORS.len()];
+            // the shape stays visible in the flat color
+            facing(hit, r) * Vec3::new(red, green, blue)
+        })),
+        _ => None,
+    }
+}
+
+/// The cosine of the angle between the ray and the surface, 1 where the ray hits it straight on,
+/// on either side of it.
+fn facing(hit: &HitRecord, r: &Ray) -> f32 {
+    Vec3::dot(&r.direction().unit(), &hit.normal).abs().min(1.)
+}
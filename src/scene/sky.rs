@@ -0,0 +1,128 @@
+/**
+The sky of the sun at the place and the time: the architects want to see how
+the building looks at 9 in the morning in December, not under the sun put anywhere.
+
+The position of the sun is computed from the latitude, the longitude, the date
+and the time (the NOAA approximation, good to a fraction of a degree), and both
+the sun and the sky follow it: the midday sun is white and high, the sky deep blue,
+the evening sun is low and orange, the sky fades from the blue to the yellow at the horizon.
+
+LEARN:
+The air scatters the blue light far more than the red one (the Rayleigh scattering,
+by the fourth power of the frequency). The sky is blue because the blue light of the sun
+is scattered towards the eye from everywhere, the setting sun is red because its light
+crosses so much air near the horizon that the blue is scattered away before it arrives.
+Both follow from the length of the way through the air, the air mass.
+*/
+use std::f32::consts::PI;
+
+use crate::math::vec::Vec3;
+
+/// The optical depth of the air at the zenith for the red, the green and the blue light.
+const RAYLEIGH: [f32; 3] = [0.06, 0.13, 0.31];
+
+/// The scale of the light scattered by the air to the light of the sun.
+const SKY_BRIGHTNESS: f32 = 3.;
+
+/// The haze that makes the glow around the sun, how forward it scatters and how much.
+const MIE_ANISOTROPY: f32 = 0.8;
+const MIE_BRIGHTNESS: f32 = 0.02;
+
+#[derive(Clone)]
+pub(crate) struct Sky {
+    /// Towards the sun, the unit vector.
+    sun: Vec3,
+    /// The share of the light of the sun that arrives through the air, of every channel.
+    sunlight: Vec3,
+    /// How bright the sun is above the air, the sky is as bright at any strength.
+    strength: f32,
+}
+
+/// Where the sun is in the sky, in degrees: above the horizon, and the compass direction,
+/// clockwise from the north.
+pub(crate) struct SolarPosition {
+    pub(crate) elevation: f32,
+    pub(crate) azimuth: f32,
+}
+
+impl Sky {
+    /// The sky of the sun at the position, the north of the scene is the direction
+    /// along the ground, the up is +y. `strength` scales the light of the sun on the surfaces.
+    pub(crate) fn new(position: &SolarPosition, north: &Vec3, strength: f32) -> Sky {
+        let up = Vec3::new(0., 1., 0.);
+        let north = (north - Vec3::dot(north, &up) * &up).unit();
+        let east = Vec3::cross(&north, &up);
+        let (elevation, azimuth) = (position.elevation.to_radians(), position.azimuth.to_radians());
+        let sun = elevation.cos() * (azimuth.cos() * &north + azimuth.sin() * &east) + elevation.sin() * &up;
+
+        let mass = air_mass(sun.y());
+        // the twilight: the sun under the horizon still lights the sky for a while
+        let twilight = ((sun.y() + 0.1) / 0.12).clamp(0., 1.);
+        let [r, g, b] = RAYLEIGH.map(|depth| twilight * (-depth * mass).exp());
+        Sky { sun, sunlight: Vec3::new(r, g, b), strength }
+    }
+
+    /// The direction to the sun and the light of it, for the diffuse surfaces lit by it directly.
+    pub(crate) fn sun(&self) -> (&Vec3, Vec3) {
+        (&self.sun, self.strength * &self.sunlight)
+    }
+
+    /// The light of the sky in the direction, without the disk of the sun, which lights
+    /// the surfaces directly.
+    pub(crate) fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let d = direction.unit();
+        // below the horizon the ground would be, the haze of the horizon stands for it
+        let mass = air_mass(d.y());
+        let cosine = Vec3::dot(&d, &self.sun);
+        let rayleigh = 0.75 * (1. + cosine * cosine);
+        let g = MIE_ANISOTROPY;
+        let mie = MIE_BRIGHTNESS * (1. - g * g) / (1. + g * g - 2. * g * cosine).powf(1.5);
+        let channel = |depth: f32, sunlight: f32| {
+            let scattered = 1. - (-depth * mass).exp();
+            sunlight * (SKY_BRIGHTNESS * scattered * rayleigh + mie * mass.min(10.))
+        };
+        Vec3::new(
+            channel(RAYLEIGH[0], self.sunlight.x()),
+            channel(RAYLEIGH[1], self.sunlight.y()),
+            channel(RAYLEIGH[2], self.sunlight.z()),
+        )
+    }
+}
+
+/// How much air the light crosses from the direction of the height (the cosine
+/// of the angle from the zenith) relative to the zenith, the Kasten-Young formula
+/// that accounts for the curvature of the atmosphere, about 38 at the horizon.
+fn air_mass(height: f32) -> f32 {
+    let cosine = height.clamp(0., 1.);
+    let zenith = cosine.acos().to_degrees();
+    1. / (cosine + 0.50572 * (96.07995 - zenith).powf(-1.6364))
+}
+
+impl SolarPosition {
+    /// The sun seen from the latitude and the longitude (degrees, the north and the east
+    /// are positive) on the day of the year (1 is the 1st of January) at the hour of UTC.
+    pub(crate) fn at(latitude: f32, longitude: f32, day: u32, hour: f32) -> SolarPosition {
+        // the fractional year in radians
+        let y = 2. * PI / 365. * (day as f32 - 1. + (hour - 12.) / 24.);
+        // how much the solar noon runs ahead of the clock, in minutes
+        let equation_of_time = 229.18 * (0.000075 + 0.001868 * y.cos() - 0.032077 * y.sin()
+            - 0.014615 * (2. * y).cos() - 0.040849 * (2. * y).sin());
+        // the latitude the sun is over
+        let declination = 0.006918 - 0.399912 * y.cos() + 0.070257 * y.sin() - 0.006758 * (2. * y).cos()
+            + 0.000907 * (2. * y).sin() - 0.002697 * (3. * y).cos() + 0.00148 * (3. * y).sin();
+        let solar_minutes = hour * 60. + equation_of_time + 4. * longitude;
+        // how far the sun has turned from the noon
+        let hour_angle = (solar_minutes / 4. - 180.).to_radians();
+        let latitude = latitude.to_radians();
+
+        let elevation = (latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos())
+            .clamp(-1., 1.)
+            .asin();
+        let azimuth = hour_angle.sin().atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos());
+        SolarPosition {
+            elevation: elevation.to_degrees(),
+            // the formula counts from the south
+            azimuth: (azimuth.to_degrees() + 180.).rem_euclid(360.),
+        }
+    }
+}
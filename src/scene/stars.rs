@@ -0,0 +1,155 @@
+/**
+The night sky of the stars, generated instead of read from an HDR panorama:
+`[stars]` with the `seed`, the `density` of the stars and an optional milky way.
+
+The stars are spread evenly over the sky, a few bright ones and many faint ones,
+white, a little bluish or yellowish, and the same seed always makes the same sky.
+The milky way is the band of the faint glow across the sky, where the stars are also
+more numerous, tilted to the horizon the way it is seen from the mid latitudes.
+
+LEARN:
+The sky is not stored anywhere. It is divided into the cells of a cube around the scene,
+and every cell has at most one star, whose place, brightness and color come from the hash
+of the seed and the cell. A ray finds the cell it points to and looks at the one star
+that may be there, so the sky of millions of stars costs the same as the empty one.
+*/
+use std::f32::consts::PI;
+
+use crate::math::rand::splitmix64;
+use crate::math::vec::Vec3;
+
+/// The angular radius of a star, in radians, the size of a pixel of a wide view.
+const RADIUS: f32 = 0.0012;
+
+/// The light of the star of the brightness 1, the radiance times the solid angle of its disk.
+const FLUX: f32 = 2e-6;
+
+/// The brightest star to the faintest one.
+const MAX_BRIGHTNESS: f32 = 500.;
+
+/// The dark blue of the sky between the stars.
+const NIGHT: [f32; 3] = [0.0008, 0.001, 0.002];
+
+/// The glow of the milky way at its middle line and its half width, in radians.
+const BAND_GLOW: f32 = 0.006;
+const BAND_WIDTH: f32 = 0.2;
+
+/// The square degrees of the whole sky.
+const SKY_AREA: f32 = 41_253.;
+
+#[derive(Clone)]
+pub(crate) struct Stars {
+    seed: u64,
+    /// The cells along a side of a face of the cube.
+    cells: usize,
+    /// The expected number of the stars in the whole sky.
+    count: f32,
+    /// The scale of the light of the stars and the band.
+    brightness: f32,
+    /// The pole of the milky way, the band runs around the sky at the right angle to it.
+    band: Option<Vec3>,
+}
+
+impl Stars {
+    /// The sky of `density` stars per square degree on average, about 0.15 are seen
+    /// by the naked eye far from the city lights.
+    pub(crate) fn new(seed: u64, density: f32) -> Stars {
+        let count = density * SKY_AREA;
+        // a star in every fourth cell keeps the stars in the largest cells at the middle
+        // of the faces below one per cell
+        let cells = ((4. * count / 6.).sqrt().ceil() as usize).max(1);
+        Stars { seed, cells, count, brightness: 1., band: None }
+    }
+
+    pub(crate) fn with_brightness(self, brightness: f32) -> Stars {
+        Stars { brightness, ..self }
+    }
+
+    /// The same sky with the milky way across it.
+    pub(crate) fn with_milky_way(self) -> Stars {
+        Stars { band: Some(Vec3::new(0.5, 0.6, -0.62).unit()), ..self }
+    }
+
+    /// The light of the sky from the direction.
+    pub(crate) fn radiance(&self, direction: &Vec3) -> Vec3 {
+        let d = direction.unit();
+        let [r, g, b] = NIGHT;
+        let band = self.band(&d);
+        let sky = Vec3::new(r, g, b) + BAND_GLOW * band * Vec3::new(0.9, 0.95, 1.);
+        let star = self.star(&d, band).unwrap_or(Vec3::zero());
+        self.brightness * (sky + star)
+    }
+
+    /// How close the direction is to the middle of the milky way, 1 on its middle line.
+    fn band(&self, d: &Vec3) -> f32 {
+        let Some(pole) = &self.band else {
+            return 0.;
+        };
+        let latitude = Vec3::dot(d, pole).clamp(-1., 1.).asin();
+        // the clouds of the stars and the dark lanes of the dust along the band
+        let around = Vec3::dot(d, &Vec3::cross(pole, &Vec3::new(0., 1., 0.)).unit()).atan2(d.y());
+        let clouds = 0.65 + 0.35 * (3. * around).sin() * (7. * around + 1.).cos();
+        let lane = 1. - 0.6 * (-((latitude - 0.03) / 0.03).powi(2)).exp();
+        (-(latitude / BAND_WIDTH).powi(2)).exp() * clouds * lane
+    }
+
+    /// The light of the star of the cell the direction points to, if the direction hits it.
+    fn star(&self, d: &Vec3, band: f32) -> Option<Vec3> {
+        let n = self.cells;
+        let (face, u, v) = cube_face(d);
+        let cell = |x: f32| (((x + 1.) / 2. * n as f32) as usize).min(n - 1);
+        let (i, j) = (cell(u), cell(v));
+        let hash = splitmix64(self.seed ^ splitmix64(((face * n + i) * n + j) as u64));
+        let random = |bits: u32| ((hash >> bits) & 0xffff) as f32 / 65536.;
+
+        // the cell takes the share of the stars of its solid angle, the cells near
+        // the corners of the cube look smaller from the center
+        let size = 2. / n as f32;
+        let (cu, cv) = (-1. + (i as f32 + 0.5) * size, -1. + (j as f32 + 0.5) * size);
+        let solid_angle = size * size / (1. + cu * cu + cv * cv).powf(1.5);
+        let chance = self.count * solid_angle / (4. * PI) * (1. + 3. * band);
+        if random(0) >= chance {
+            return None;
+        }
+        let center = from_cube_face(face, -1. + (i as f32 + random(16)) * size, -1. + (j as f32 + random(32)) * size);
+        let distance = (d - center).length();
+        if distance >= RADIUS {
+            return None;
+        }
+        // LEARN:
+        // In a space evenly filled with the stars of the same kind, the number of the stars
+        // brighter than some brightness falls with the power -3/2 of it: twice as far away
+        // there are eight times as many stars, a quarter as bright. The brightness
+        // of the power of the uniform random number follows this law.
+        let brightness = (1. - random(48)).powf(-2. / 3.).min(MAX_BRIGHTNESS);
+        // the hot stars are bluish, the cool ones yellowish
+        let temperature = (splitmix64(hash) & 0xffff) as f32 / 65536.;
+        let color = (1. - temperature) * Vec3::new(1., 0.75, 0.55) + temperature * Vec3::new(0.75, 0.85, 1.);
+        // brightest at the middle of the disk, fading to its edge
+        let profile = 2. * (1. - (distance / RADIUS).powi(2));
+        Some(brightness * FLUX / (PI * RADIUS * RADIUS) * profile * color)
+    }
+}
+
+/// The face of the cube the direction points to (0..6) and the point of it, both -1..1.
+fn cube_face(d: &Vec3) -> (usize, f32, f32) {
+    let (x, y, z) = (d.x(), d.y(), d.z());
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    if ax >= ay && ax >= az {
+        (if x > 0. { 0 } else { 1 }, y / ax, z / ax)
+    } else if ay >= az {
+        (if y > 0. { 2 } else { 3 }, x / ay, z / ay)
+    } else {
+        (if z > 0. { 4 } else { 5 }, x / az, y / az)
+    }
+}
+
+/// The direction to the point of the face of the cube, the inverse of `cube_face`.
+fn from_cube_face(face: usize, u: f32, v: f32) -> Vec3 {
+    let sign = if face.is_multiple_of(2) { 1. } else { -1. };
+    match face / 2 {
+        0 => Vec3::new(sign, u, v),
+        1 => Vec3::new(u, sign, v),
+        _ => Vec3::new(u, v, sign),
+    }.unit()
+}
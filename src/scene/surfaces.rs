@@ -1,21 +1,268 @@
+pub(crate) mod bvh;
+pub(crate) mod clip;
+pub(crate) mod curves;
+pub(crate) mod displacement;
 pub(crate) mod hitable;
+pub(crate) mod mesh;
+pub(crate) mod points;
+pub(crate) mod stream;
 pub(crate) mod sphere;
+pub(crate) mod subdivision;
+pub(crate) mod volume;
 pub(crate) mod world;
 
-use crate::math::vec::Vec3;
+use crate::math::vec::{RayType, Vec3};
+use crate::scene::arena::Id;
 use crate::scene::material::Material;
+use crate::scene::surfaces::bvh::Bounds;
+use crate::scene::surfaces::curves::Curves;
+use crate::scene::surfaces::mesh::Mesh;
+use crate::scene::surfaces::points::Points;
+use crate::scene::surfaces::volume::Volume;
 use crate::scene::surfaces::Surface::Sphere;
 
+#[derive(Clone)]
 pub(crate) enum Surface {
     Sphere {
         center: Vec3,
         radius: f32,
-        material: Material,
+        material: Id<Material>,
+        visibility: Visibility,
+        /// Turns the texture around the vertical axis, in degrees, e.g. to face
+        /// the other side of the globe to the camera.
+        rotation: f32,
+    },
+    /// The triangles, e.g. of a displaced sphere or an imported model.
+    Mesh {
+        mesh: Mesh,
+        /// The triangles refer to these by their index, e.g. the faces of a model
+        /// keep the materials they were assigned.
+        materials: Vec<Id<Material>>,
+        visibility: Visibility,
+    },
+    /// The thin fibers, e.g. the hair or the grass.
+    Curves {
+        curves: Curves,
+        material: Id<Material>,
+        visibility: Visibility,
+    },
+    /// The point cloud, e.g. of a scan.
+    Points {
+        points: Points,
+        material: Id<Material>,
+        visibility: Visibility,
+    },
+    /// The smoke or the cloud, the material scatters the light at the particles inside.
+    Volume {
+        volume: Volume,
+        material: Id<Material>,
+        visibility: Visibility,
+    },
+}
+
+/// Which rays see the surface.
+///
+/// These are the usual controls of a lighting artist, e.g. a floor that catches
+/// the reflections and the shadows but is not seen by the camera, or a fill card
+/// that brightens a side of an object without showing up in the mirrors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Visibility {
+    /// Seen directly by the camera.
+    pub(crate) camera: bool,
+    /// Blocks the shadow rays towards the lights, i.e. casts the shadows.
+    /// The light bounced off the other surfaces still sees it, as the diffuse rays do.
+    pub(crate) shadow: bool,
+    /// Appears in the reflections and the refractions.
+    pub(crate) reflection: bool,
+}
+
+impl Visibility {
+    pub(crate) const ALL: Visibility = Visibility { camera: true, shadow: true, reflection: true };
+
+    pub(crate) fn sees(&self, kind: RayType) -> bool {
+        match kind {
+            RayType::Camera => self.camera,
+            RayType::Diffuse => true,
+            RayType::Shadow => self.shadow,
+            RayType::Reflection | RayType::Refraction => self.reflection,
+        }
     }
 }
 
 impl Surface {
-    pub(crate) fn sphere(center: Vec3, radius: f32, material: Material) -> Surface {
-        Sphere { center, radius, material }
+    pub(crate) fn sphere(center: Vec3, radius: f32, material: Id<Material>) -> Surface {
+        Sphere { center, radius, material, visibility: Visibility::ALL, rotation: 0. }
+    }
+
+    /// The mesh whose triangles all have the same material.
+    pub(crate) fn mesh(mesh: Mesh, material: Id<Material>) -> Surface {
+        Surface::Mesh { mesh, materials: vec![material], visibility: Visibility::ALL }
+    }
+
+    /// The mesh whose triangles refer to the materials by their index.
+    pub(crate) fn mesh_of_materials(mesh: Mesh, materials: Vec<Id<Material>>) -> Surface {
+        assert!(mesh.material_count() <= materials.len(), "the triangles refer to the missing materials");
+        Surface::Mesh { mesh, materials, visibility: Visibility::ALL }
+    }
+
+    pub(crate) fn curves(curves: Curves, material: Id<Material>) -> Surface {
+        Surface::Curves { curves, material, visibility: Visibility::ALL }
+    }
+
+    pub(crate) fn points(points: Points, material: Id<Material>) -> Surface {
+        Surface::Points { points, material, visibility: Visibility::ALL }
+    }
+
+    pub(crate) fn volume(volume: Volume, material: Id<Material>) -> Surface {
+        Surface::Volume { volume, material, visibility: Visibility::ALL }
+    }
+
+    pub(crate) fn with_visibility(self, visibility: Visibility) -> Surface {
+        match self {
+            Sphere { center, radius, material, rotation, .. } => Sphere { center, radius, material, visibility, rotation },
+            Surface::Mesh { mesh, materials, .. } => Surface::Mesh { mesh, materials, visibility },
+            Surface::Curves { curves, material, .. } => Surface::Curves { curves, material, visibility },
+            Surface::Points { points, material, .. } => Surface::Points { points, material, visibility },
+            Surface::Volume { volume, material, .. } => Surface::Volume { volume, material, visibility },
+        }
+    }
+
+    /// Turns the texture of the sphere, the other surfaces carry their texture coordinates.
+    pub(crate) fn with_rotation(self, rotation: f32) -> Surface {
+        match self {
+            Sphere { center, radius, material, visibility, .. } => Sphere { center, radius, material, visibility, rotation },
+            other => other,
+        }
+    }
+
+    /// The point and the normal of the surface at the centers of the texels of the map
+    /// of the size in its texture coordinates, see `Mesh::texels`, `None` for the surfaces
+    /// that have no texture coordinates of their own.
+    pub(crate) fn texels(&self, w: usize, h: usize) -> Option<Vec<Option<(Vec3, Vec3)>>> {
+        match self {
+            Sphere { center, radius, rotation, .. } => Some((0..w * h)
+                .map(|i| {
+                    let (u, v) = ((i % w) as f32 + 0.5, (i / w) as f32 + 0.5);
+                    let d = sphere::sphere_point(u / w as f32, 1. - v / h as f32, *rotation);
+                    // the hollow sphere faces inward
+                    Some((center + radius.abs() * &d, radius.signum() * d))
+                })
+                .collect()),
+            Surface::Mesh { mesh, .. } => Some(mesh.texels(w, h)),
+            Surface::Curves { .. } | Surface::Points { .. } | Surface::Volume { .. } => None,
+        }
+    }
+
+    pub(crate) fn center(&self) -> Vec3 {
+        match self {
+            Sphere { center, .. } => center.clone(),
+            Surface::Mesh { mesh, .. } => mesh.center(),
+            Surface::Curves { curves, .. } => curves.center(),
+            Surface::Points { points, .. } => points.center(),
+            Surface::Volume { volume, .. } => volume.center(),
+        }
+    }
+
+    /// The box around the surface, empty of the surface of nothing, e.g. of an empty mesh.
+    pub(crate) fn bounding_box(&self) -> Bounds {
+        match self {
+            Sphere { center, radius, .. } => Bounds::around([center]).padded(radius.abs()),
+            Surface::Mesh { mesh, .. } => mesh.bounding_box(),
+            Surface::Curves { curves, .. } => curves.bounding_box(),
+            Surface::Points { points, .. } => points.bounding_box(),
+            Surface::Volume { volume, .. } => volume.bounding_box(),
+        }
+    }
+
+    /// The material of the whole surface, of a mesh it is the first one.
+    pub(crate) fn material(&self) -> Id<Material> {
+        self.materials()[0]
+    }
+
+    pub(crate) fn materials(&self) -> &[Id<Material>] {
+        match self {
+            Sphere { material, .. }
+            | Surface::Curves { material, .. }
+            | Surface::Points { material, .. }
+            | Surface::Volume { material, .. } => std::slice::from_ref(material),
+            Surface::Mesh { materials, .. } => materials,
+        }
+    }
+
+    /// Makes the whole surface of the material, all the faces of a mesh.
+    pub(crate) fn set_material(&mut self, material: Id<Material>) {
+        match self {
+            Sphere { material: old, .. }
+            | Surface::Curves { material: old, .. }
+            | Surface::Points { material: old, .. }
+            | Surface::Volume { material: old, .. } => *old = material,
+            // the triangles keep their indices, all of them lead to the one material
+            Surface::Mesh { materials, .. } => materials.fill(material),
+        }
+    }
+
+    /// Refers to the materials `by` places further, when the materials of its world
+    /// are moved after the ones of another, see `World::merge`.
+    pub(crate) fn shift_materials(&mut self, by: usize) {
+        match self {
+            Sphere { material, .. }
+            | Surface::Curves { material, .. }
+            | Surface::Points { material, .. }
+            | Surface::Volume { material, .. } => *material = material.shifted(by),
+            Surface::Mesh { materials, .. } => materials.iter_mut().for_each(|material| *material = material.shifted(by)),
+        }
     }
-}
\ No newline at end of file
+
+    /// Moves the surface by the offset.
+    pub(crate) fn translate(&mut self, offset: &Vec3) {
+        match self {
+            Sphere { center, .. } => *center = &*center + offset,
+            Surface::Mesh { mesh, .. } => mesh.translate(offset),
+            Surface::Curves { curves, .. } => curves.translate(offset),
+            Surface::Points { points, .. } => points.translate(offset),
+            Surface::Volume { volume, .. } => volume.translate(offset),
+        }
+    }
+
+    /// Whether the point is inside the solid bounded by the surface.
+    /// The spheres with the negative radius (the inner side of the hollow glass)
+    /// do not bound anything, neither do the other surfaces, e.g. the meshes need not be closed.
+    pub(crate) fn contains(&self, p: &Vec3) -> bool {
+        match self {
+            Sphere { center, radius, .. } => *radius > 0. && (p - center).squared_length() < radius * radius,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn visibility(&self) -> Visibility {
+        match self {
+            Sphere { visibility, .. }
+            | Surface::Mesh { visibility, .. }
+            | Surface::Curves { visibility, .. }
+            | Surface::Points { visibility, .. }
+            | Surface::Volume { visibility, .. } => *visibility,
+        }
+    }
+
+    /// What the surface is, as the diagnostics call it.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Sphere { .. } => "sphere",
+            Surface::Mesh { mesh, .. } => mesh.kind,
+            Surface::Curves { .. } => "curves",
+            Surface::Points { .. } => "points",
+            Surface::Volume { .. } => "volume",
+        }
+    }
+
+    /// The bytes the surface holds outside of the world, e.g. the triangles of a mesh.
+    pub(crate) fn memory(&self) -> usize {
+        match self {
+            Sphere { .. } => 0,
+            Surface::Mesh { mesh, .. } => mesh.memory(),
+            Surface::Curves { curves, .. } => curves.memory(),
+            Surface::Points { points, .. } => points.memory(),
+            Surface::Volume { volume, .. } => volume.memory(),
+        }
+    }
+}
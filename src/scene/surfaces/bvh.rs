@@ -0,0 +1,270 @@
+/**
+The bounding volume hierarchy (BVH): the tree of the boxes around the groups
+of the primitives of a surface, e.g. the triangles of a mesh.
+
+Testing all the primitives for every ray would take forever, a surface may have
+many thousands of them. The ray skips a whole group when it misses its box,
+and it visits only a few dozen primitives instead of all of them.
+The tree only knows the boxes, the surface tests its own primitives.
+*/
+use std::mem::size_of;
+
+use crate::math::vec::{Ray, Vec3};
+
+/// The most primitives the ray tests in a leaf of the tree.
+const LEAF_SIZE: usize = 4;
+
+#[derive(Clone)]
+pub(crate) struct Bvh {
+    /// The root is the first one.
+    nodes: Vec<Node>,
+}
+
+#[derive(Clone)]
+struct Node {
+    bounds: Bounds,
+    /// The primitives of a leaf, or the index of the second child of an inner node,
+    /// the first child follows the node.
+    content: Content,
+}
+
+#[derive(Clone)]
+enum Content {
+    Leaf { start: usize, count: usize },
+    Inner { second: usize },
+}
+
+/// The axis-aligned box.
+#[derive(Clone)]
+pub(crate) struct Bounds {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Bvh {
+    /// The tree over the primitives, and the primitives reordered so every node
+    /// holds a contiguous range of them.
+    ///
+    /// The primitives are split in the middle of the longest side of the box around
+    /// the centers of their boxes, half of them on each side.
+    pub(crate) fn build<T>(primitives: Vec<T>, bounds: impl Fn(&T) -> Bounds) -> (Bvh, Vec<T>) {
+        let mut boxes: Vec<(Bounds, usize)> = primitives.iter().map(&bounds).zip(0..).collect();
+        let mut nodes = Vec::new();
+        let count = boxes.len();
+        build(&mut nodes, &mut boxes, 0, count);
+
+        let mut slots: Vec<Option<T>> = primitives.into_iter().map(Some).collect();
+        let primitives = boxes.iter().map(|&(_, i)| slots[i].take().expect("every primitive once")).collect();
+        (Bvh { nodes }, primitives)
+    }
+
+    /// The box around all the primitives.
+    pub(crate) fn bounds(&self) -> Option<&Bounds> {
+        self.nodes.first().map(|root| &root.bounds)
+    }
+
+    pub(crate) fn memory(&self) -> usize {
+        self.nodes.capacity() * size_of::<Node>()
+    }
+
+    /// About the memory the tree over the count of the primitives takes, before it is built:
+    /// the halves split down to the leaves of about `LEAF_SIZE` primitives.
+    pub(crate) fn estimate_memory(count: usize) -> usize {
+        2 * count.div_ceil(LEAF_SIZE) * size_of::<Node>()
+    }
+
+    /// Moves the boxes with the primitives, the tree stays the same.
+    pub(crate) fn translate(&mut self, offset: &Vec3) {
+        for node in &mut self.nodes {
+            node.bounds = node.bounds.translated(offset);
+        }
+    }
+
+    /// The nearest hit of the primitives in the boxes the ray enters.
+    ///
+    /// `intersect` tests the primitive of the index against the ray closer than
+    /// the distance, and returns the distance to the hit and whatever the surface
+    /// needs to complete it.
+    pub(crate) fn hit<H>(&self, r: &Ray, t_min: f32, t_max: f32,
+                         mut intersect: impl FnMut(usize, f32) -> Option<(f32, H)>) -> Option<(f32, H)> {
+        let inverse = [r.direction().x(), r.direction().y(), r.direction().z()].map(|d| 1. / d);
+        let origin = [r.origin().x(), r.origin().y(), r.origin().z()];
+        let mut nearest: Option<(f32, H)> = None;
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let t_far = nearest.as_ref().map_or(t_max, |(t, _)| *t);
+            if !node.bounds.hit(&origin, &inverse, t_min, t_far) {
+                continue;
+            }
+            match node.content {
+                Content::Leaf { start, count } => {
+                    for i in start..start + count {
+                        let t_far = nearest.as_ref().map_or(t_max, |(t, _)| *t);
+                        if let Some(hit) = intersect(i, t_far) {
+                            nearest = Some(hit);
+                        }
+                    }
+                }
+                Content::Inner { second } => {
+                    stack.push(second);
+                    stack.push(index + 1);
+                }
+            }
+        }
+        nearest
+    }
+
+    /// Whether the ray hits any of the primitives between the distances, it stops
+    /// at the first one found, whichever it is.
+    ///
+    /// `hits` tests the primitive of the index against the ray.
+    pub(crate) fn any_hit(&self, r: &Ray, t_min: f32, t_max: f32, mut hits: impl FnMut(usize) -> bool) -> bool {
+        let inverse = [r.direction().x(), r.direction().y(), r.direction().z()].map(|d| 1. / d);
+        let origin = [r.origin().x(), r.origin().y(), r.origin().z()];
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bounds.hit(&origin, &inverse, t_min, t_max) {
+                continue;
+            }
+            match node.content {
+                Content::Leaf { start, count } => {
+                    if (start..start + count).any(&mut hits) {
+                        return true;
+                    }
+                }
+                Content::Inner { second } => {
+                    stack.push(second);
+                    stack.push(index + 1);
+                }
+            }
+        }
+        false
+    }
+}
+
+fn build(nodes: &mut Vec<Node>, boxes: &mut [(Bounds, usize)], start: usize, end: usize) {
+    let bounds = boxes[start..end].iter()
+        .fold(Bounds::empty(), |bounds, (other, _)| bounds.union(other));
+
+    let index = nodes.len();
+    nodes.push(Node { bounds, content: Content::Leaf { start, count: end - start } });
+    if end - start <= LEAF_SIZE {
+        return;
+    }
+
+    let centers = boxes[start..end].iter()
+        .fold(Bounds::empty(), |bounds, (other, _)| bounds.with(&other.center()));
+    let axis = (0..3)
+        .max_by(|&a, &b| centers.extent(a).total_cmp(&centers.extent(b)))
+        .unwrap_or(0);
+    let middle = (start + end) / 2;
+    // LEARN:
+    // `select_nth_unstable_by` puts the median in its place, the smaller ones before it
+    // and the larger ones after, in linear time, a full sort is not needed.
+    boxes[start..end].select_nth_unstable_by(middle - start, |(a, _), (b, _)| a.center()[axis].total_cmp(&b.center()[axis]));
+
+    build(nodes, boxes, start, middle);
+    let second = nodes.len();
+    build(nodes, boxes, middle, end);
+    nodes[index].content = Content::Inner { second };
+}
+
+impl Bounds {
+    pub(crate) fn empty() -> Bounds {
+        Bounds { min: [f32::MAX; 3], max: [f32::MIN; 3] }
+    }
+
+    /// The box around the points.
+    pub(crate) fn around<'a>(points: impl IntoIterator<Item = &'a Vec3>) -> Bounds {
+        points.into_iter().fold(Bounds::empty(), |bounds, p| bounds.with(&[p.x(), p.y(), p.z()]))
+    }
+
+    /// The box grown by the distance on every side, e.g. around the points of a thick curve.
+    pub(crate) fn padded(self, distance: f32) -> Bounds {
+        Bounds { min: self.min.map(|min| min - distance), max: self.max.map(|max| max + distance) }
+    }
+
+    pub(crate) fn translated(&self, offset: &Vec3) -> Bounds {
+        Bounds {
+            min: [0, 1, 2].map(|axis| self.min[axis] + offset[axis]),
+            max: [0, 1, 2].map(|axis| self.max[axis] + offset[axis]),
+        }
+    }
+
+    fn with(self, p: &[f32; 3]) -> Bounds {
+        Bounds {
+            min: [0, 1, 2].map(|axis| self.min[axis].min(p[axis])),
+            max: [0, 1, 2].map(|axis| self.max[axis].max(p[axis])),
+        }
+    }
+
+    pub(crate) fn union(self, other: &Bounds) -> Bounds {
+        if other.is_empty() {
+            return self;
+        }
+        self.with(&other.min).with(&other.max)
+    }
+
+    /// Whether the box holds nothing, e.g. of the world without surfaces.
+    pub(crate) fn is_empty(&self) -> bool {
+        (0..3).any(|axis| self.min[axis] > self.max[axis])
+    }
+
+    /// The corner of the smallest coordinates and the one of the largest.
+    pub(crate) fn corners(&self) -> (Vec3, Vec3) {
+        let [min, max] = [self.min, self.max].map(|[x, y, z]| Vec3::new(x, y, z));
+        (min, max)
+    }
+
+    fn extent(&self, axis: usize) -> f32 {
+        self.max[axis] - self.min[axis]
+    }
+
+    pub(crate) fn center(&self) -> [f32; 3] {
+        [0, 1, 2].map(|axis| (self.min[axis] + self.max[axis]) / 2.)
+    }
+
+    /// Where the ray enters the box and where it leaves it, within `t_min` and `t_max`,
+    /// none when it misses the box.
+    pub(crate) fn interval(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        if self.is_empty() {
+            return None;
+        }
+        let (mut enter, mut exit) = (t_min, t_max);
+        for axis in 0..3 {
+            let inverse = 1. / r.direction()[axis];
+            let t0 = (self.min[axis] - r.origin()[axis]) * inverse;
+            let t1 = (self.max[axis] - r.origin()[axis]) * inverse;
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            enter = enter.max(t0);
+            exit = exit.min(t1);
+            if exit < enter {
+                return None;
+            }
+        }
+        Some((enter, exit))
+    }
+
+    /// Whether the ray enters the box between `t_min` and `t_max`, the slab test.
+    fn hit(&self, origin: &[f32; 3], inverse: &[f32; 3], mut t_min: f32, mut t_max: f32) -> bool {
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - origin[axis]) * inverse[axis];
+            let t1 = (self.max[axis] - origin[axis]) * inverse[axis];
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
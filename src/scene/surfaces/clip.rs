@@ -0,0 +1,38 @@
+use crate::math::vec::{Ray, Vec3};
+
+/// A plane that cuts away the geometry on one side, for the cutaway and the sectional renders.
+///
+/// Cutting a solid object leaves it open, e.g. a glass sphere becomes a thin bowl.
+/// With `cap` the cut is closed by the plane, so the object stays a solid.
+#[derive(Clone)]
+pub(crate) struct ClipPlane {
+    point: Vec3,
+    /// Points to the side that is cut away.
+    normal: Vec3,
+    pub(crate) cap: bool,
+}
+
+impl ClipPlane {
+    pub(crate) fn new(point: Vec3, normal: Vec3, cap: bool) -> ClipPlane {
+        ClipPlane { point, normal: normal.unit(), cap }
+    }
+
+    /// Whether the point is cut away.
+    pub(crate) fn clips(&self, p: &Vec3) -> bool {
+        Vec3::dot(&(p - &self.point), &self.normal) > 0.
+    }
+
+    /// The distance along the ray to the plane, `None` when the ray is parallel to it.
+    pub(crate) fn intersect(&self, r: &Ray) -> Option<f32> {
+        let denominator = Vec3::dot(r.direction(), &self.normal);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+        Some(Vec3::dot(&(&self.point - r.origin()), &self.normal) / denominator)
+    }
+
+    /// The normal of the cap, it looks out of the remaining part of the solid.
+    pub(crate) fn normal(&self) -> &Vec3 {
+        &self.normal
+    }
+}
@@ -0,0 +1,225 @@
+/**
+The curves: the thin fibers, e.g. the hair, the fur, the grass and the cables.
+
+A fiber is far thinner than it is long, as the triangles it would take thousands
+of them to look round. A curve is a cubic Bezier or B-spline through a few control
+points instead, with the width that may taper from the root to the tip.
+
+The curves are cut into the short straight pieces when the scene is built,
+and a piece is hit where the ray passes closer to its axis than the radius,
+like a ribbon that always faces the ray. The normal turns across the ribbon
+the way it turns across a cylinder, so the fibers are shaded as round.
+*/
+use std::mem::size_of;
+
+use crate::math::vec::{Ray, Vec3};
+use crate::scene::surfaces::bvh::{Bounds, Bvh};
+use crate::scene::surfaces::hitable::Intersection;
+
+/// The straight pieces of every cubic span of a curve.
+const PIECES: usize = 8;
+
+/// How the control points define the curve.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Basis {
+    /// Every span goes through its first and last points, the inner two pull it,
+    /// the last point of a span is the first one of the next: 4, 7, 10... points.
+    Bezier,
+    /// The smooth curve near the points, through none of them, every point after the third
+    /// adds a span.
+    BSpline,
+}
+
+#[derive(Clone)]
+pub(crate) struct Curves {
+    pieces: Vec<Piece>,
+    bvh: Bvh,
+}
+
+#[derive(Clone)]
+struct Piece {
+    from: Vec3,
+    to: Vec3,
+    radius: [f32; 2],
+    /// How far along the whole curve the ends are, 0 at the root and 1 at the tip.
+    v: [f32; 2],
+}
+
+pub(crate) struct CurveHit {
+    pub(crate) t: f32,
+    pub(crate) p: Vec3,
+    pub(crate) normal: Vec3,
+    /// The direction of the fiber, from the root to the tip.
+    pub(crate) tangent: Vec3,
+    /// u goes across the fiber, v along it from the root to the tip.
+    pub(crate) uv: [f32; 2],
+}
+
+impl Basis {
+    pub(crate) fn parse(name: &str) -> Option<Basis> {
+        match name {
+            "bezier" => Some(Basis::Bezier),
+            "bspline" => Some(Basis::BSpline),
+            _ => None,
+        }
+    }
+
+    /// Whether the number of the control points makes a whole curve.
+    pub(crate) fn fits(&self, points: usize) -> bool {
+        match self {
+            Basis::Bezier => points >= 4 && (points - 1).is_multiple_of(3),
+            Basis::BSpline => points >= 4,
+        }
+    }
+
+    /// The Bezier control points of every span of the curve.
+    fn spans(&self, points: &[Vec3]) -> Vec<[Vec3; 4]> {
+        match self {
+            Basis::Bezier => points.windows(4).step_by(3)
+                .map(|span| [span[0].clone(), span[1].clone(), span[2].clone(), span[3].clone()])
+                .collect(),
+            // the same cubic polynomial written in the other basis
+            Basis::BSpline => points.windows(4)
+                .map(|span| {
+                    let [p0, p1, p2, p3] = [&span[0], &span[1], &span[2], &span[3]];
+                    [
+                        (p0 + 4. * p1 + p2) / 6.,
+                        (4. * p1 + 2. * p2) / 6.,
+                        (2. * p1 + 4. * p2) / 6.,
+                        (p1 + 4. * p2 + p3) / 6.,
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The point of the cubic Bezier span at 0..1.
+fn bezier([p0, p1, p2, p3]: &[Vec3; 4], s: f32) -> Vec3 {
+    let r = 1. - s;
+    r * r * r * p0 + 3. * r * r * s * p1 + 3. * r * s * s * p2 + s * s * s * p3
+}
+
+impl Curves {
+    /// The curves of the control points, each tapering from the root width to the tip width.
+    pub(crate) fn new(curves: &[Vec<Vec3>], basis: Basis, width: [f32; 2]) -> Curves {
+        let mut pieces = Vec::new();
+        for points in curves {
+            let spans = basis.spans(points);
+            let count = (spans.len() * PIECES) as f32;
+            let points: Vec<Vec3> = spans.iter()
+                .enumerate()
+                .flat_map(|(i, span)| (usize::from(i > 0)..=PIECES).map(move |k| bezier(span, k as f32 / PIECES as f32)))
+                .collect();
+            for (k, ends) in points.windows(2).enumerate() {
+                if (&ends[1] - &ends[0]).squared_length() == 0. {
+                    continue;
+                }
+                let v = [k as f32 / count, (k + 1) as f32 / count];
+                let radius = v.map(|v| ((1. - v) * width[0] + v * width[1]) / 2.);
+                pieces.push(Piece { from: ends[0].clone(), to: ends[1].clone(), radius, v });
+            }
+        }
+        let (bvh, pieces) = Bvh::build(pieces, |piece| {
+            Bounds::around([&piece.from, &piece.to]).padded(piece.radius[0].max(piece.radius[1]))
+        });
+        Curves { pieces, bvh }
+    }
+
+    /// The number of the pieces, the tweaker lists it.
+    #[cfg(feature = "tweaker")]
+    pub(crate) fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub(crate) fn points(&self) -> impl Iterator<Item = &Vec3> {
+        self.pieces.iter().flat_map(|piece| [&piece.from, &piece.to])
+    }
+
+    pub(crate) fn translate(&mut self, offset: &Vec3) {
+        for piece in &mut self.pieces {
+            piece.from = &piece.from + offset;
+            piece.to = &piece.to + offset;
+        }
+        self.bvh.translate(offset);
+    }
+
+    /// The box around the curves.
+    pub(crate) fn bounding_box(&self) -> Bounds {
+        self.bvh.bounds().cloned().unwrap_or_else(Bounds::empty)
+    }
+
+    /// The center of the box around the curves.
+    pub(crate) fn center(&self) -> Vec3 {
+        match self.bvh.bounds() {
+            Some(bounds) => {
+                let [x, y, z] = bounds.center();
+                Vec3::new(x, y, z)
+            }
+            None => Vec3::zero(),
+        }
+    }
+
+    pub(crate) fn memory(&self) -> usize {
+        self.pieces.capacity() * size_of::<Piece>() + self.bvh.memory()
+    }
+
+    /// The nearest piece the ray passes through, and how far along and across it.
+    pub(crate) fn intersect(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Intersection> {
+        let (t, (i, s, offset)) = self.bvh.hit(r, t_min, t_max, |i, t_far| {
+            self.intersect_piece(i, r, t_min, t_far).map(|(t, s, offset)| (t, (i, s, offset)))
+        })?;
+        Some(Intersection { t, primitive: i, coordinates: [s, offset] })
+    }
+
+    /// Whether the ray passes through any piece between the distances.
+    pub(crate) fn occluded(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.bvh.any_hit(r, t_min, t_max, |i| self.intersect_piece(i, r, t_min, t_max).is_some())
+    }
+
+    pub(crate) fn shade(&self, r: &Ray, hit: &Intersection) -> CurveHit {
+        let Intersection { t, primitive: i, coordinates: [s, offset] } = *hit;
+        let piece = &self.pieces[i];
+        let tangent = (&piece.to - &piece.from).unit();
+        // towards the ray, across the fiber, and the normal of the cylinder in between
+        let d = r.direction();
+        let facing = -(d - Vec3::dot(d, &tangent) * &tangent).unit();
+        let side = Vec3::cross(&facing, &tangent);
+        let normal = offset * &side + (1. - offset * offset).max(0.).sqrt() * &facing;
+        let v = (1. - s) * piece.v[0] + s * piece.v[1];
+        CurveHit { t, p: r.point_at(t), normal, tangent, uv: [(offset + 1.) / 2., v] }
+    }
+
+    /// The distance to the closest approach of the ray to the axis of the piece, when it passes
+    /// within the radius, how far along the piece it is and how far across, -1..1.
+    ///
+    /// The ray that starts inside the piece, e.g. scattered by the fiber, does not hit it again.
+    fn intersect_piece(&self, piece: usize, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+        let Piece { from, to, radius, .. } = &self.pieces[piece];
+        let (d, e) = (r.direction(), to - from);
+        let w = r.origin() - from;
+        let (a, b, c) = (Vec3::dot(d, d), Vec3::dot(d, &e), Vec3::dot(&e, &e));
+        let (dw, ew) = (Vec3::dot(d, &w), Vec3::dot(&e, &w));
+        let denominator = a * c - b * b;
+        // parallel lines are closest anywhere, the start of the piece will do
+        let s = if denominator > 1e-12 * a * c { (a * ew - b * dw) / denominator } else { 0. };
+        let s = s.clamp(0., 1.);
+        let axis = from + s * &e;
+        let t = Vec3::dot(d, &(&axis - r.origin())) / a;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let radius = (1. - s) * radius[0] + s * radius[1];
+        let across = r.point_at(t) - &axis;
+        if across.squared_length() >= radius * radius {
+            return None;
+        }
+        let start = from + (ew / c).clamp(0., 1.) * &e;
+        if (r.origin() - start).squared_length() < radius * radius {
+            return None;
+        }
+        let side = Vec3::cross(&e, d);
+        let offset = Vec3::dot(&across, &side) / (side.length() * radius);
+        Some((t, s, offset.clamp(-1., 1.)))
+    }
+}
@@ -0,0 +1,119 @@
+/**
+The displacement: the surfaces moved along their normals by the height read from a texture.
+
+A texture on a flat surface only colors it, a displaced surface really has the bumps:
+they cast the shadows, change the silhouette and occlude each other.
+The surface is tessellated into a fine mesh when the scene is built, and every vertex
+is moved by the brightness of the texture at its coordinates times the amount,
+so the mesh needs as many vertices as the details of the texture the render should show.
+*/
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use crate::math::vec::Vec3;
+use crate::scene::surfaces::mesh::Mesh;
+use crate::scene::texture::Texture;
+
+pub(crate) struct Displacement {
+    /// The black of the texture keeps the surface, the white moves it by the amount.
+    pub(crate) texture: Texture,
+    /// The distance, outwards; a negative amount carves the surface in.
+    pub(crate) amount: f32,
+}
+
+impl Displacement {
+    /// How far the point of the surface moves.
+    fn height(&self, p: &Vec3, [u, v]: [f32; 2]) -> f32 {
+        let c = self.texture.value(u, v, p, [0., 0.]);
+        // the luminance of the linear color
+        self.amount * (0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z())
+    }
+
+    /// Moves every vertex along the normal.
+    ///
+    /// The copies of a vertex along the seam of the texture coordinates (and at the poles)
+    /// look the texture up at the different coordinates, e.g. u = 0 and u = 1.
+    /// All of them move like the first one, or the mesh would crack along the seam.
+    fn displace(&self, points: Vec<(Vec3, Vec3, [f32; 2])>) -> Vec<(Vec3, [f32; 2])> {
+        let mut moved: HashMap<[u32; 3], Vec3> = HashMap::new();
+        points.into_iter()
+            .map(|(p, normal, uv)| {
+                let key = [p.x(), p.y(), p.z()].map(f32::to_bits);
+                let displaced = moved.entry(key).or_insert_with(|| &p + self.height(&p, uv) * &normal).clone();
+                (displaced, uv)
+            })
+            .collect()
+    }
+}
+
+/// The sphere as the grid of `rows` latitudes and twice as many longitudes, displaced.
+///
+/// The texture coordinates are the ones the sphere itself has, so a texture
+/// of the displacement lines up with the same texture of the color.
+pub(crate) fn sphere(center: &Vec3, radius: f32, rotation: f32, rows: usize, displacement: &Displacement) -> Mesh {
+    let columns = 2 * rows;
+    let mut points: Vec<(Vec3, Vec3, [f32; 2])> = Vec::with_capacity((rows + 1) * (columns + 1));
+    for i in 0..=rows {
+        let v = i as f32 / rows as f32;
+        let theta = PI * v;
+        for j in 0..=columns {
+            let u = j as f32 / columns as f32;
+            // the inverse of the mapping of the sphere, u = 0.5 faces +x without the rotation
+            let phi = 2. * PI * (u + rotation / 360.);
+            let normal = Vec3::new(-phi.cos() * theta.sin(), -theta.cos(), phi.sin() * theta.sin());
+            // the poles and the seam are exactly where their copies are
+            let normal = match (i, j) {
+                (0, _) => Vec3::new(0., -1., 0.),
+                (i, _) if i == rows => Vec3::new(0., 1., 0.),
+                (_, j) if j == columns => points[i * (columns + 1)].1.clone(),
+                _ => normal,
+            };
+            points.push((center + radius * &normal, normal, [u, v]));
+        }
+    }
+
+    let index = |i: usize, j: usize| i * (columns + 1) + j;
+    let mut faces = Vec::with_capacity(2 * rows * columns);
+    for i in 0..rows {
+        for j in 0..columns {
+            let (a, b, c, d) = (index(i, j), index(i, j + 1), index(i + 1, j + 1), index(i + 1, j));
+            // the triangles at the poles would have two vertices in the same point
+            if i > 0 {
+                faces.push(([a, b, c], 0));
+            }
+            if i < rows - 1 {
+                faces.push(([a, c, d], 0));
+            }
+        }
+    }
+    Mesh::new("sphere", displacement.displace(points), faces)
+}
+
+/// The rectangle on the XZ plane from the corner, `size` along x and z,
+/// split into the grid of `resolution` cells along each side and displaced up.
+///
+/// u goes along x, v against z, so an image seen from above with -z up is not mirrored.
+pub(crate) fn heightfield(corner: &Vec3, size: [f32; 2], resolution: usize, displacement: &Displacement) -> Mesh {
+    let up = Vec3::new(0., 1., 0.);
+    let mut points = Vec::with_capacity((resolution + 1) * (resolution + 1));
+    for i in 0..=resolution {
+        let v = i as f32 / resolution as f32;
+        for j in 0..=resolution {
+            let u = j as f32 / resolution as f32;
+            let p = corner + Vec3::new(u * size[0], 0., (1. - v) * size[1]);
+            points.push((p, up.clone(), [u, v]));
+        }
+    }
+
+    let index = |i: usize, j: usize| i * (resolution + 1) + j;
+    let mut faces = Vec::with_capacity(2 * resolution * resolution);
+    for i in 0..resolution {
+        for j in 0..resolution {
+            // v grows against z, so the rows go towards -z and this order faces up
+            let (a, b, c, d) = (index(i, j), index(i, j + 1), index(i + 1, j + 1), index(i + 1, j));
+            faces.push(([a, b, c], 0));
+            faces.push(([a, c, d], 0));
+        }
+    }
+    Mesh::new("heightfield", displacement.displace(points), faces)
+}
@@ -1,19 +1,94 @@
+use crate::scene::arena::Id;
 use crate::scene::material::Material;
 use crate::math::vec::{Ray, Vec3};
 
-pub(crate) struct HitRecord<'a> {
+#[derive(Clone)]
+pub(crate) struct HitRecord {
     // LEARN:
     // It is completely normal and common to have public fields in Rust
     // Although in some places getters can be useful.
     pub(crate) t: f32,
     pub(crate) p: Vec3,
     pub(crate) normal: Vec3,
-    pub(crate) material: &'a Material
+    /// The texture coordinates of the hit point, both 0..1.
+    pub(crate) u: f32,
+    pub(crate) v: f32,
+    /// How much u and v change per unit of the distance on the surface,
+    /// the width of a beam in the texture is its width on the surface times these.
+    pub(crate) uv_rate: [f32; 2],
+    /// The color of the surface itself, e.g. of the vertices of a scanned mesh,
+    /// the materials multiply their albedo by it.
+    pub(crate) color: Option<Vec3>,
+    /// The direction of the fiber of a curve, the hair reflects the light around it.
+    pub(crate) tangent: Option<Vec3>,
+    /// The material is looked up in the world, the record stays free of references.
+    pub(crate) material: Id<Material>,
 }
 
+/// The hit found along the ray before it is shaded: the distance to it, and where
+/// it is on the surface, enough to compute the rest of the record when it is needed.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Intersection {
+    pub(crate) t: f32,
+    /// The part of the surface that was hit, e.g. the triangle of a mesh.
+    pub(crate) primitive: usize,
+    /// Where on the part, e.g. the barycentric coordinates of the triangle.
+    pub(crate) coordinates: [f32; 2],
+}
+
+impl Intersection {
+    /// The hit of the surface of a single part that needs nothing but the distance.
+    pub(crate) fn at(t: f32) -> Intersection {
+        Intersection { t, primitive: 0, coordinates: [0., 0.] }
+    }
+}
+
+/// The part of the ray inside a solid, from where it enters the solid to where it leaves it.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Interval {
+    pub(crate) enter: f32,
+    pub(crate) exit: f32,
+}
+
+impl Interval {
+    pub(crate) fn contains(&self, t: f32) -> bool {
+        self.enter < t && t < self.exit
+    }
+}
+
+/// LEARN:
+/// The nearest hit is searched in two steps. Finding it takes only the distances:
+/// a ray crosses many surfaces (and the boxes of the tree) on the way to the nearest one,
+/// and most of the hits it finds are replaced by closer ones, while the point, the normal
+/// and the texture coordinates are needed only of the last one. The shadow rays need
+/// none of them at all, only whether anything is in the way, see `occluded`.
 pub(crate) trait Hitable {
+    /// What the surface needs to shade its hit, e.g. `Intersection`.
+    type Intersection;
+
+    /// The nearest hit closer than `t_max`, without the shading.
+    fn intersect(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Self::Intersection>;
+
+    /// The record of the hit the ray found by `intersect`.
+    fn shade(&self, r: &Ray, hit: &Self::Intersection) -> HitRecord;
+
+    /// Whether anything is hit between the distances, e.g. between the point
+    /// and the light. Any hit will do, so the search stops at the first one found.
+    fn occluded(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.intersect(r, t_min, t_max).is_some()
+    }
+
+    /// All the parts of the ray inside the solid the surface bounds, in the order along
+    /// the ray and cut to `t_min..t_max`, e.g. to tell how far the light goes through it.
+    /// None of the surfaces that bound no solid, e.g. of the meshes that need not be closed.
+    fn intervals(&self, _r: &Ray, _t_min: f32, _t_max: f32) -> Option<Vec<Interval>> {
+        None
+    }
+
     /// LEARN:
     /// The original book uses a mutable reference to HitRecord.
     /// This is not idiomatic Rust. We use an Option of HitRecord instead.
-    fn hit<'a>(&'a self, r: &'a Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.intersect(r, t_min, t_max).map(|hit| self.shade(r, &hit))
+    }
 }
@@ -0,0 +1,472 @@
+/**
+The triangle meshes: the surfaces that are not spheres.
+
+The meshes are built from the other surfaces when the scene is loaded,
+e.g. a displaced sphere is tessellated into the triangles and the triangles are moved.
+A mesh may have many thousands of triangles, testing all of them for every ray
+would take forever, so they are kept in a bounding volume hierarchy (BVH):
+the tree of the boxes around the groups of the triangles. The ray skips a whole group
+when it misses the box, and it visits only a few dozen triangles instead of all of them.
+
+The heavy meshes of a scene larger than the memory stay in the memory-mapped files,
+and their trees are built when the rays need them, see `stream`.
+*/
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{Error, Write};
+use std::mem::size_of;
+use std::sync::Arc;
+
+use crate::math::vec::{Ray, Vec3};
+use crate::scene::surfaces::bvh::{Bounds, Bvh};
+use crate::scene::surfaces::hitable::Intersection;
+use crate::scene::surfaces::stream::{self, Blas, Mapping, Slot};
+
+/// The cosine of the largest angle between the faces that are shaded as one smooth surface,
+/// 60 degrees. The sharper edges, e.g. of a cube, stay sharp.
+const SMOOTH_COSINE: f32 = 0.5;
+
+#[derive(Clone)]
+pub(crate) struct Mesh {
+    /// What the mesh was built from, e.g. `sphere`, the diagnostics refer to it.
+    pub(crate) kind: &'static str,
+    geometry: Geometry,
+}
+
+#[derive(Clone)]
+enum Geometry {
+    /// The triangles in the order of their tree.
+    Resident {
+        vertices: Vec<Vertex>,
+        /// The linear colors of the vertices, e.g. of a scan, empty when the mesh has none.
+        colors: Vec<Vec3>,
+        triangles: Vec<Triangle>,
+        bvh: Bvh,
+    },
+    /// The vertices, their colors and the triangles written one after another
+    /// into the mapped file, see `stream`.
+    Streamed {
+        file: Arc<Mapping>,
+        vertices: usize,
+        triangles: usize,
+        colored: bool,
+        /// How far the mesh moved since it was written, the file keeps the positions
+        /// and the tree is built of them.
+        offset: Vec3,
+        /// The box around the mesh where it is now.
+        bounds: Bounds,
+        tree: Arc<Slot>,
+    },
+}
+
+/// The bytes of a vertex in the file: the position and the texture coordinates.
+const VERTEX_BYTES: usize = 20;
+/// The bytes of a color in the file.
+const COLOR_BYTES: usize = 12;
+/// The bytes of a triangle in the file: the vertices, the normals, the material and the rate.
+const TRIANGLE_BYTES: usize = 56;
+
+#[derive(Clone)]
+pub(crate) struct Vertex {
+    pub(crate) p: Vec3,
+    pub(crate) uv: [f32; 2],
+}
+
+#[derive(Clone)]
+struct Triangle {
+    vertices: [usize; 3],
+    /// The normals of the smooth surface the mesh approximates at the corners,
+    /// computed when the mesh is built.
+    normals: [Vec3; 3],
+    /// The index in the materials of the surface.
+    material: usize,
+    /// How fast the texture coordinates change over the triangle, the same for u and v.
+    uv_rate: f32,
+}
+
+/// The part of the hit the mesh knows, the surface adds the material.
+pub(crate) struct MeshHit {
+    pub(crate) t: f32,
+    pub(crate) p: Vec3,
+    pub(crate) normal: Vec3,
+    pub(crate) uv: [f32; 2],
+    pub(crate) uv_rate: f32,
+    /// The index in the materials of the surface.
+    pub(crate) material: usize,
+    /// The color interpolated from the colors of the vertices.
+    pub(crate) color: Option<Vec3>,
+}
+
+impl Mesh {
+    /// The mesh of the triangles, counterclockwise seen from the outside,
+    /// each with the index of its material.
+    ///
+    /// The normal at a corner is the average of the normals of the triangles around it,
+    /// weighted by their areas, except for the triangles at a sharp angle to the one
+    /// of the corner. The vertices at the same position are one corner, so the seams
+    /// of the texture coordinates do not show in the shading.
+    pub(crate) fn new(kind: &'static str, points: Vec<(Vec3, [f32; 2])>, faces: Vec<([usize; 3], usize)>) -> Mesh {
+        let welded = weld(points.iter().map(|(p, _)| p));
+        // the length of the cross product is twice the area
+        let face_normals: Vec<Vec3> = faces.iter()
+            .map(|(face, _)| {
+                let [a, b, c] = face.map(|i| &points[i].0);
+                Vec3::cross(&(b - a), &(c - a))
+            })
+            .collect();
+        let unit = |n: &Vec3| if n.squared_length() > 0. { n.unit() } else { Vec3::zero() };
+        let mut around = vec![Vec::new(); points.len()];
+        for (i, (face, _)) in faces.iter().enumerate() {
+            for &corner in face {
+                around[welded[corner]].push(i);
+            }
+        }
+        let corner_normal = |face: usize, corner: usize| {
+            let own = unit(&face_normals[face]);
+            let sum = around[welded[corner]].iter()
+                .map(|&other| &face_normals[other])
+                .filter(|normal| Vec3::dot(&unit(normal), &own) >= SMOOTH_COSINE)
+                .fold(Vec3::zero(), |sum, normal| sum + normal);
+            if sum.squared_length() > 0. { sum.unit() } else { Vec3::new(0., 1., 0.) }
+        };
+
+        let triangles: Vec<Triangle> = faces.iter()
+            .enumerate()
+            .map(|(i, &(face, material))| {
+                let [a, b, c] = face.map(|corner| &points[corner]);
+                let area = face_normals[i].length();
+                let uv_area = ((b.1[0] - a.1[0]) * (c.1[1] - a.1[1]) - (c.1[0] - a.1[0]) * (b.1[1] - a.1[1])).abs();
+                let uv_rate = if area > 0. { (uv_area / area).sqrt() } else { 0. };
+                Triangle { vertices: face, normals: face.map(|corner| corner_normal(i, corner)), material, uv_rate }
+            })
+            .collect();
+        let vertices: Vec<Vertex> = points.into_iter().map(|(p, uv)| Vertex { p, uv }).collect();
+
+        let (bvh, triangles) = Bvh::build(triangles, |triangle| Bounds::around(triangle.vertices.map(|v| &vertices[v].p)));
+        Mesh { kind, geometry: Geometry::Resident { vertices, colors: Vec::new(), triangles, bvh } }
+    }
+
+    /// The same mesh written into the memory-mapped file, see `stream`.
+    pub(crate) fn streamed(self) -> Result<Mesh, Error> {
+        let bounds = self.bounding_box();
+        let Geometry::Resident { vertices, colors, triangles, .. } = self.geometry else {
+            return Ok(self);
+        };
+        let file = Mapping::write(|out: &mut dyn Write| {
+            for vertex in &vertices {
+                stream::write_vec3(out, &vertex.p)?;
+                out.write_all(&vertex.uv[0].to_le_bytes())?;
+                out.write_all(&vertex.uv[1].to_le_bytes())?;
+            }
+            for color in &colors {
+                stream::write_vec3(out, color)?;
+            }
+            for triangle in &triangles {
+                for v in triangle.vertices {
+                    out.write_all(&(v as u32).to_le_bytes())?;
+                }
+                for normal in &triangle.normals {
+                    stream::write_vec3(out, normal)?;
+                }
+                out.write_all(&(triangle.material as u32).to_le_bytes())?;
+                out.write_all(&triangle.uv_rate.to_le_bytes())?;
+            }
+            Ok(())
+        })?;
+        Ok(Mesh {
+            kind: self.kind,
+            geometry: Geometry::Streamed {
+                file: Arc::new(file),
+                vertices: vertices.len(),
+                triangles: triangles.len(),
+                colored: !colors.is_empty(),
+                offset: Vec3::zero(),
+                bounds,
+                tree: Arc::default(),
+            },
+        })
+    }
+
+    /// The number of the materials the triangles refer to.
+    pub(crate) fn material_count(&self) -> usize {
+        (0..self.triangle_count()).map(|i| self.triangle(i).material + 1).max().unwrap_or(0)
+    }
+
+    /// The mesh with a color for every vertex, in the order of the points it was built from.
+    pub(crate) fn with_colors(self, colors: Vec<Vec3>) -> Mesh {
+        let Geometry::Resident { vertices, triangles, bvh, .. } = self.geometry else {
+            panic!("the colors are added before the mesh is streamed");
+        };
+        assert_eq!(colors.len(), vertices.len(), "every vertex needs a color");
+        Mesh { kind: self.kind, geometry: Geometry::Resident { vertices, colors, triangles, bvh } }
+    }
+
+    pub(crate) fn vertex_count(&self) -> usize {
+        match &self.geometry {
+            Geometry::Resident { vertices, .. } => vertices.len(),
+            Geometry::Streamed { vertices, .. } => *vertices,
+        }
+    }
+
+    pub(crate) fn vertices(&self) -> impl Iterator<Item = Cow<'_, Vertex>> {
+        (0..self.vertex_count()).map(|i| self.vertex(i))
+    }
+
+    /// The corners of the triangles, the indices of their vertices.
+    pub(crate) fn faces(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        (0..self.triangle_count()).map(|i| self.triangle(i).vertices)
+    }
+
+    /// The normal of the smooth surface at every vertex, the average of the normals
+    /// of the corners of the triangles at it.
+    pub(crate) fn vertex_normals(&self) -> Vec<Vec3> {
+        let mut sums = vec![Vec3::zero(); self.vertex_count()];
+        for i in 0..self.triangle_count() {
+            let triangle = self.triangle(i);
+            for (&vertex, normal) in triangle.vertices.iter().zip(&triangle.normals) {
+                sums[vertex] = &sums[vertex] + normal;
+            }
+        }
+        sums.into_iter()
+            .map(|sum| if sum.squared_length() > 0. { sum.unit() } else { Vec3::new(0., 1., 0.) })
+            .collect()
+    }
+
+    /// Moves the mesh, the normals and the tree of the triangles stay valid.
+    pub(crate) fn translate(&mut self, by: &Vec3) {
+        match &mut self.geometry {
+            Geometry::Resident { vertices, bvh, .. } => {
+                for vertex in vertices {
+                    vertex.p = &vertex.p + by;
+                }
+                bvh.translate(by);
+            }
+            Geometry::Streamed { offset, bounds, .. } => {
+                *offset = &*offset + by;
+                *bounds = bounds.translated(by);
+            }
+        }
+    }
+
+    /// The box around the mesh.
+    pub(crate) fn bounding_box(&self) -> Bounds {
+        match &self.geometry {
+            Geometry::Resident { bvh, .. } => bvh.bounds().cloned().unwrap_or_else(Bounds::empty),
+            Geometry::Streamed { bounds, .. } => bounds.clone(),
+        }
+    }
+
+    /// The center of the box around the mesh.
+    pub(crate) fn center(&self) -> Vec3 {
+        match self.bounding_box() {
+            bounds if bounds.is_empty() => Vec3::zero(),
+            bounds => {
+                let [x, y, z] = bounds.center();
+                Vec3::new(x, y, z)
+            }
+        }
+    }
+
+    /// The bytes held by the vertices, the triangles and the tree. The streamed mesh holds
+    /// none of them, its file is paged by the system and its tree is counted by the cache.
+    pub(crate) fn memory(&self) -> usize {
+        match &self.geometry {
+            Geometry::Resident { vertices, colors, triangles, bvh } =>
+                vertices.capacity() * size_of::<Vertex>()
+                    + colors.capacity() * size_of::<Vec3>()
+                    + triangles.capacity() * size_of::<Triangle>()
+                    + bvh.memory(),
+            Geometry::Streamed { .. } => 0,
+        }
+    }
+
+    /// About the memory the tree of the streamed mesh takes in the cache, before it is built,
+    /// none for the resident mesh, its tree is a part of `memory`.
+    pub(crate) fn tree_memory(&self) -> usize {
+        match &self.geometry {
+            Geometry::Resident { .. } => 0,
+            Geometry::Streamed { triangles, .. } => Bvh::estimate_memory(*triangles) + triangles * size_of::<u32>(),
+        }
+    }
+
+    /// The nearest triangle the ray hits, and the barycentric coordinates of the hit.
+    pub(crate) fn intersect(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Intersection> {
+        let (t, (i, b1, b2)) = match &self.geometry {
+            Geometry::Resident { bvh, .. } => bvh.hit(r, t_min, t_max, |i, t_far| {
+                self.intersect_triangle(i, r, t_min, t_far).map(|(t, b1, b2)| (t, (i, b1, b2)))
+            })?,
+            Geometry::Streamed { offset, .. } => {
+                // the tree is of the positions in the file, the ray moves back there to walk it
+                let blas = self.blas();
+                blas.bvh.hit(&r.translated(&-offset), t_min, t_max, |i, t_far| {
+                    let i = blas.order[i] as usize;
+                    self.intersect_triangle(i, r, t_min, t_far).map(|(t, b1, b2)| (t, (i, b1, b2)))
+                })?
+            }
+        };
+        Some(Intersection { t, primitive: i, coordinates: [b1, b2] })
+    }
+
+    /// Whether the ray hits any triangle between the distances.
+    pub(crate) fn occluded(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        match &self.geometry {
+            Geometry::Resident { bvh, .. } =>
+                bvh.any_hit(r, t_min, t_max, |i| self.intersect_triangle(i, r, t_min, t_max).is_some()),
+            Geometry::Streamed { offset, .. } => {
+                let blas = self.blas();
+                blas.bvh.any_hit(&r.translated(&-offset), t_min, t_max, |i| {
+                    self.intersect_triangle(blas.order[i] as usize, r, t_min, t_max).is_some()
+                })
+            }
+        }
+    }
+
+    pub(crate) fn shade(&self, r: &Ray, hit: &Intersection) -> MeshHit {
+        let Intersection { t, primitive: i, coordinates: [b1, b2] } = *hit;
+        let triangle = self.triangle(i);
+        let [a, b, c] = triangle.vertices.map(|v| self.vertex(v));
+        let [na, nb, nc] = &triangle.normals;
+        let b0 = 1. - b1 - b2;
+        let normal = (b0 * na + b1 * nb + b2 * nc).unit();
+        let uv = [0, 1].map(|k| b0 * a.uv[k] + b1 * b.uv[k] + b2 * c.uv[k]);
+        let color = match triangle.vertices.map(|v| self.color(v)) {
+            [Some(a), Some(b), Some(c)] => Some(b0 * &*a + b1 * &*b + b2 * &*c),
+            _ => None,
+        };
+        let Triangle { uv_rate, material, .. } = *triangle;
+        MeshHit { t, p: r.point_at(t), normal, uv, uv_rate, material, color }
+    }
+
+    /// The point and the normal of the surface at the centers of the texels of the map
+    /// of the size in the texture coordinates, by the rows from the top (v = 1), as the images
+    /// of the textures are read; `None` where no triangle covers the texel. Where the triangles
+    /// overlap in the texture, the first one covers the texel.
+    pub(crate) fn texels(&self, w: usize, h: usize) -> Vec<Option<(Vec3, Vec3)>> {
+        let mut texels = vec![None; w * h];
+        for i in 0..self.triangle_count() {
+            let triangle = self.triangle(i);
+            let [a, b, c] = triangle.vertices.map(|v| self.vertex(v));
+            // the corners in the texels, the centers of the texels are at the halves
+            let [(ax, ay), (bx, by), (cx, cy)] = [&a, &b, &c].map(|vertex| (vertex.uv[0] * w as f32, (1. - vertex.uv[1]) * h as f32));
+            let det = (bx - ax) * (cy - ay) - (cx - ax) * (by - ay);
+            if det == 0. {
+                continue;
+            }
+            let range = |min: f32, max: f32, size: usize| {
+                let from = (min - 0.5).ceil().max(0.) as usize;
+                let to = ((max - 0.5).floor() + 1.).clamp(0., size as f32) as usize;
+                from..to
+            };
+            for y in range(ay.min(by).min(cy), ay.max(by).max(cy), h) {
+                for x in range(ax.min(bx).min(cx), ax.max(bx).max(cx), w) {
+                    let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                    let b1 = ((px - ax) * (cy - ay) - (cx - ax) * (py - ay)) / det;
+                    let b2 = ((bx - ax) * (py - ay) - (px - ax) * (by - ay)) / det;
+                    let b0 = 1. - b1 - b2;
+                    if b0 < 0. || b1 < 0. || b2 < 0. || texels[y * w + x].is_some() {
+                        continue;
+                    }
+                    let [na, nb, nc] = &triangle.normals;
+                    let p = b0 * &a.p + b1 * &b.p + b2 * &c.p;
+                    texels[y * w + x] = Some((p, (b0 * na + b1 * nb + b2 * nc).unit()));
+                }
+            }
+        }
+        texels
+    }
+
+    /// The distance to the triangle and the barycentric coordinates of the hit
+    /// of the second and the third vertices (the Möller–Trumbore algorithm).
+    fn intersect_triangle(&self, triangle: usize, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+        let [a, b, c] = self.triangle(triangle).vertices.map(|v| self.vertex(v));
+        let [a, b, c] = [&a.p, &b.p, &c.p];
+        let (e1, e2) = (b - a, c - a);
+        let pv = Vec3::cross(r.direction(), &e2);
+        let det = Vec3::dot(&e1, &pv);
+        // the ray runs along the plane of the triangle, otherwise both of its sides are hit
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let tv = r.origin() - a;
+        let b1 = Vec3::dot(&tv, &pv) / det;
+        if !(0. ..=1.).contains(&b1) {
+            return None;
+        }
+        let qv = Vec3::cross(&tv, &e1);
+        let b2 = Vec3::dot(r.direction(), &qv) / det;
+        if b2 < 0. || b1 + b2 > 1. {
+            return None;
+        }
+        let t = Vec3::dot(&e2, &qv) / det;
+        (t > t_min && t < t_max).then_some((t, b1, b2))
+    }
+
+    fn triangle_count(&self) -> usize {
+        match &self.geometry {
+            Geometry::Resident { triangles, .. } => triangles.len(),
+            Geometry::Streamed { triangles, .. } => *triangles,
+        }
+    }
+
+    fn vertex(&self, i: usize) -> Cow<'_, Vertex> {
+        match &self.geometry {
+            Geometry::Resident { vertices, .. } => Cow::Borrowed(&vertices[i]),
+            Geometry::Streamed { file, offset, .. } => {
+                let (bytes, at) = (file.bytes(), i * VERTEX_BYTES);
+                Cow::Owned(Vertex {
+                    p: stream::vec3_at(bytes, at) + offset,
+                    uv: [stream::f32_at(bytes, at + 12), stream::f32_at(bytes, at + 16)],
+                })
+            }
+        }
+    }
+
+    fn color(&self, i: usize) -> Option<Cow<'_, Vec3>> {
+        match &self.geometry {
+            Geometry::Resident { colors, .. } => colors.get(i).map(Cow::Borrowed),
+            Geometry::Streamed { file, vertices, colored, .. } => colored
+                .then(|| Cow::Owned(stream::vec3_at(file.bytes(), vertices * VERTEX_BYTES + i * COLOR_BYTES))),
+        }
+    }
+
+    fn triangle(&self, i: usize) -> Cow<'_, Triangle> {
+        match &self.geometry {
+            Geometry::Resident { triangles, .. } => Cow::Borrowed(&triangles[i]),
+            Geometry::Streamed { file, vertices, colored, .. } => {
+                let colors = if *colored { vertices * COLOR_BYTES } else { 0 };
+                let (bytes, at) = (file.bytes(), vertices * VERTEX_BYTES + colors + i * TRIANGLE_BYTES);
+                Cow::Owned(Triangle {
+                    vertices: [0, 1, 2].map(|k| stream::u32_at(bytes, at + 4 * k) as usize),
+                    normals: [0, 1, 2].map(|k| stream::vec3_at(bytes, at + 12 + 12 * k)),
+                    material: stream::u32_at(bytes, at + 48) as usize,
+                    uv_rate: stream::f32_at(bytes, at + 52),
+                })
+            }
+        }
+    }
+
+    /// The tree of the streamed mesh, built of the positions in the file unless it is cached.
+    fn blas(&self) -> Arc<Blas> {
+        let Geometry::Streamed { file, vertices, triangles, colored, tree, .. } = &self.geometry else {
+            unreachable!("only the streamed meshes build their trees on demand");
+        };
+        tree.tree(|| {
+            let bytes = file.bytes();
+            let first = vertices * VERTEX_BYTES + if *colored { vertices * COLOR_BYTES } else { 0 };
+            let (bvh, order) = Bvh::build((0..*triangles as u32).collect(), |&i| {
+                let at = first + i as usize * TRIANGLE_BYTES;
+                let [a, b, c] = [0, 1, 2].map(|k| stream::vec3_at(bytes, stream::u32_at(bytes, at + 4 * k) as usize * VERTEX_BYTES));
+                Bounds::around([&a, &b, &c])
+            });
+            Blas { bvh, order }
+        })
+    }
+}
+
+/// The index of the first vertex at the same position for every vertex.
+pub(crate) fn weld<'a>(points: impl Iterator<Item = &'a Vec3>) -> Vec<usize> {
+    let mut first: HashMap<[u32; 3], usize> = HashMap::new();
+    points.enumerate()
+        .map(|(i, p)| *first.entry([p.x(), p.y(), p.z()].map(f32::to_bits)).or_insert(i))
+        .collect()
+}
@@ -0,0 +1,139 @@
+/**
+The point clouds: the scans of the laser scanners and the particles of the simulations.
+
+The points have no surface between them, each is drawn as a small disk that faces
+the ray or as a tiny sphere. A scan has millions of them, so the positions
+and the colors are kept in the flat arrays, without the materials or the radii
+of their own, and in the tree of the boxes around the groups of them.
+*/
+use std::mem::size_of;
+
+use crate::math::vec::{Ray, Vec3};
+use crate::scene::surfaces::bvh::{Bounds, Bvh};
+use crate::scene::surfaces::hitable::Intersection;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Shape {
+    /// Flat and always facing the ray, the cheapest, the points look like the splats.
+    Disk,
+    /// Round and shaded, e.g. for the particles seen up close.
+    Sphere,
+}
+
+#[derive(Clone)]
+pub(crate) struct Points {
+    positions: Vec<Vec3>,
+    /// The linear colors of the points, empty when they have none.
+    colors: Vec<Vec3>,
+    radius: f32,
+    shape: Shape,
+    bvh: Bvh,
+}
+
+pub(crate) struct PointHit {
+    pub(crate) t: f32,
+    pub(crate) p: Vec3,
+    pub(crate) normal: Vec3,
+    pub(crate) color: Option<Vec3>,
+}
+
+impl Shape {
+    pub(crate) fn parse(name: &str) -> Option<Shape> {
+        match name {
+            "disk" => Some(Shape::Disk),
+            "sphere" => Some(Shape::Sphere),
+            _ => None,
+        }
+    }
+}
+
+impl Points {
+    /// The points of the same radius, with a color each or none at all.
+    pub(crate) fn new(positions: Vec<Vec3>, colors: Vec<Vec3>, radius: f32, shape: Shape) -> Points {
+        assert!(colors.is_empty() || colors.len() == positions.len(), "every point needs a color");
+        let (bvh, order) = Bvh::build((0..positions.len()).collect(), |&i| Bounds::around([&positions[i]]).padded(radius));
+        let positions = order.iter().map(|&i| positions[i].clone()).collect();
+        let colors = if colors.is_empty() { colors } else { order.iter().map(|&i| colors[i].clone()).collect() };
+        Points { positions, colors, radius, shape, bvh }
+    }
+
+    pub(crate) fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    pub(crate) fn translate(&mut self, offset: &Vec3) {
+        for p in &mut self.positions {
+            *p = &*p + offset;
+        }
+        self.bvh.translate(offset);
+    }
+
+    /// The box around the points.
+    pub(crate) fn bounding_box(&self) -> Bounds {
+        self.bvh.bounds().cloned().unwrap_or_else(Bounds::empty)
+    }
+
+    /// The center of the box around the points.
+    pub(crate) fn center(&self) -> Vec3 {
+        match self.bvh.bounds() {
+            Some(bounds) => {
+                let [x, y, z] = bounds.center();
+                Vec3::new(x, y, z)
+            }
+            None => Vec3::zero(),
+        }
+    }
+
+    pub(crate) fn memory(&self) -> usize {
+        (self.positions.capacity() + self.colors.capacity()) * size_of::<Vec3>() + self.bvh.memory()
+    }
+
+    /// The nearest point the ray hits.
+    pub(crate) fn intersect(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Intersection> {
+        let (t, i) = self.bvh.hit(r, t_min, t_max, |i, t_far| self.intersect_point(i, r, t_min, t_far).map(|t| (t, i)))?;
+        Some(Intersection { t, primitive: i, coordinates: [0., 0.] })
+    }
+
+    /// Whether the ray hits any point between the distances.
+    pub(crate) fn occluded(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.bvh.any_hit(r, t_min, t_max, |i| self.intersect_point(i, r, t_min, t_max).is_some())
+    }
+
+    pub(crate) fn shade(&self, r: &Ray, hit: &Intersection) -> PointHit {
+        let (t, i) = (hit.t, hit.primitive);
+        let p = r.point_at(t);
+        let normal = match self.shape {
+            Shape::Disk => -r.direction().unit(),
+            Shape::Sphere => (&p - &self.positions[i]) / self.radius,
+        };
+        PointHit { t, p, normal, color: self.colors.get(i).cloned() }
+    }
+
+    fn intersect_point(&self, point: usize, r: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        let oc = r.origin() - &self.positions[point];
+        let a = r.direction().squared_length();
+        let b = Vec3::dot(&oc, r.direction());
+        let c = oc.squared_length() - self.radius * self.radius;
+        let t = match self.shape {
+            // the ray scattered by the disk would hit the disk that faces it at the same point
+            Shape::Disk if c < 0. => return None,
+            Shape::Disk => {
+                let t = -b / a;
+                let closest = (&oc + t * r.direction()).squared_length();
+                if closest >= self.radius * self.radius {
+                    return None;
+                }
+                t
+            }
+            Shape::Sphere => {
+                let discriminant = b * b - a * c;
+                if discriminant <= 0. {
+                    return None;
+                }
+                let near = (-b - discriminant.sqrt()) / a;
+                if near > t_min { near } else { (-b + discriminant.sqrt()) / a }
+            }
+        };
+        (t > t_min && t < t_max).then_some(t)
+    }
+}
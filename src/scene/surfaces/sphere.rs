@@ -1,40 +1,157 @@
+use std::f32::consts::PI;
+
+use crate::scene::arena::Id;
 use crate::scene::material::Material;
+use crate::math::sphere::hit_sphere;
 use crate::math::vec::{Ray, Vec3};
-use crate::scene::surfaces::hitable::{Hitable, HitRecord};
+use crate::scene::surfaces::hitable::{Hitable, HitRecord, Intersection, Interval};
 use crate::scene::surfaces::Surface;
 use crate::scene::surfaces::Surface::{Sphere};
 
 impl Hitable for Surface {
-    fn hit<'a>(&'a self, r: &'a Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    type Intersection = Intersection;
+
+    fn intersect(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Intersection> {
         match self {
-            Sphere { center, radius, material } =>
-                hit_sphere(r, t_min, t_max, center, *radius, material)
+            Sphere { center, radius, .. } => hit_sphere(r, t_min, t_max, center, *radius).map(Intersection::at),
+            Surface::Mesh { mesh, .. } => mesh.intersect(r, t_min, t_max),
+            Surface::Curves { curves, .. } => curves.intersect(r, t_min, t_max),
+            Surface::Points { points, .. } => points.intersect(r, t_min, t_max),
+            Surface::Volume { volume, .. } => volume.intersect(r, t_min, t_max).map(Intersection::at),
+        }
+    }
+
+    fn occluded(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        match self {
+            Sphere { center, radius, .. } => hit_sphere(r, t_min, t_max, center, *radius).is_some(),
+            Surface::Mesh { mesh, .. } => mesh.occluded(r, t_min, t_max),
+            Surface::Curves { curves, .. } => curves.occluded(r, t_min, t_max),
+            Surface::Points { points, .. } => points.occluded(r, t_min, t_max),
+            // the ray passes through the smoke unless it meets a particle on the way
+            Surface::Volume { volume, .. } => volume.intersect(r, t_min, t_max).is_some(),
         }
     }
-}
 
-// LEARN: In the book the hit_sphere accepts the hit_record as a mutable reference and returns bool
-// In Rust the idiomatic way is to return an Option<HitRecord> instead.
-fn hit_sphere<'a>(r: &'a Ray, t_min: f32, t_max: f32, center: &'a Vec3, radius: f32, material: &'a Material) -> Option<HitRecord<'a>> {
+    fn intervals(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Vec<Interval>> {
+        let interval = match self {
+            // the inner side of the hollow glass bounds the air, not a solid
+            Sphere { center, radius, .. } if *radius > 0. => sphere_interval(r, center, *radius),
+            Surface::Volume { volume, .. } => volume.enter_exit(r).map(|(enter, exit)| Interval { enter, exit }),
+            _ => return None,
+        };
+        let cut = interval
+            .map(|Interval { enter, exit }| Interval { enter: enter.max(t_min), exit: exit.min(t_max) })
+            .filter(|interval| interval.enter < interval.exit);
+        Some(cut.into_iter().collect())
+    }
 
+    fn shade(&self, r: &Ray, hit: &Intersection) -> HitRecord {
+        match self {
+            Sphere { center, radius, material, rotation, .. } =>
+                sphere_record(r, hit.t, center, *radius, *material, *rotation),
+            Surface::Mesh { mesh, materials, .. } => {
+                let hit = mesh.shade(r, hit);
+                HitRecord {
+                    t: hit.t,
+                    p: hit.p,
+                    normal: hit.normal,
+                    u: hit.uv[0],
+                    v: hit.uv[1],
+                    uv_rate: [hit.uv_rate; 2],
+                    color: hit.color,
+                    tangent: None,
+                    material: materials[hit.material],
+                }
+            }
+            Surface::Curves { curves, material, .. } => {
+                let hit = curves.shade(r, hit);
+                HitRecord {
+                    t: hit.t,
+                    p: hit.p,
+                    normal: hit.normal,
+                    u: hit.uv[0],
+                    v: hit.uv[1],
+                    uv_rate: [0., 0.],
+                    color: None,
+                    tangent: Some(hit.tangent),
+                    material: *material,
+                }
+            }
+            Surface::Points { points, material, .. } => {
+                let hit = points.shade(r, hit);
+                HitRecord {
+                    t: hit.t,
+                    p: hit.p,
+                    normal: hit.normal,
+                    u: 0.,
+                    v: 0.,
+                    uv_rate: [0., 0.],
+                    color: hit.color,
+                    tangent: None,
+                    material: *material,
+                }
+            }
+            // the particle has no side, it faces the ray
+            Surface::Volume { material, .. } => HitRecord {
+                t: hit.t,
+                p: r.point_at(hit.t),
+                normal: -r.direction().unit(),
+                u: 0.,
+                v: 0.,
+                uv_rate: [0., 0.],
+                color: None,
+                tangent: None,
+                material: *material,
+            },
+        }
+    }
+}
+
+/// Where the ray enters the ball and where it leaves it, anywhere along the line of the ray.
+fn sphere_interval(r: &Ray, center: &Vec3, radius: f32) -> Option<Interval> {
     let oc = r.origin() - center;
     let a = Vec3::dot(r.direction(), r.direction());
     let b = Vec3::dot(&oc, r.direction());
     let c = Vec3::dot(&oc, &oc) - radius * radius;
     let discriminant = b * b - a * c;
-    if discriminant > 0.0 {
-        let mut t = (-b - discriminant.sqrt()) / a;
-        if t < t_max && t > t_min {
-            let p = r.point_at(t);
-            let normal = (&p - center) / radius;
-            return Some(HitRecord { t, p, normal, material });
-        }
-        t = (-b + discriminant.sqrt()) / a;
-        if t < t_max && t > t_min {
-            let p = r.point_at(t);
-            let normal = (&p - center) / radius;
-            return Some(HitRecord { t, p, normal, material });
-        }
-    }
-    None
-}
\ No newline at end of file
+    (discriminant > 0.0).then(|| Interval {
+        enter: (-b - discriminant.sqrt()) / a,
+        exit: (-b + discriminant.sqrt()) / a,
+    })
+}
+
+fn sphere_record(r: &Ray, t: f32, center: &Vec3, radius: f32, material: Id<Material>, rotation: f32) -> HitRecord {
+    let p = r.point_at(t);
+    let normal = (&p - center) / radius;
+    let (u, v, uv_rate) = sphere_uv(&((&p - center) / radius.abs()), radius.abs(), rotation);
+    HitRecord { t, p, normal, u, v, uv_rate, color: None, tangent: None, material }
+}
+
+/// The texture coordinates of the point on the unit sphere around the origin,
+/// and how fast they change on the sphere of the radius.
+///
+/// v is the latitude from the south pole (0) to the north one (1), u the longitude
+/// around the y axis from -x (0) through +z, +x (0.5) and -z back to -x,
+/// so the middle of an equirectangular map faces +x. v runs along a half of the great circle,
+/// u along the whole parallel, which shrinks to a point at the poles.
+/// The rotation in degrees turns the texture to the east, i.e. counterclockwise seen from above.
+///
+/// LEARN:
+/// `atan2` is defined everywhere, even at the poles where x and z are both 0,
+/// so there is no division by zero and no NaN. The clamp keeps `acos` away from NaN
+/// when the rounding puts y a tiny bit outside -1..1.
+fn sphere_uv(d: &Vec3, radius: f32, rotation: f32) -> (f32, f32, [f32; 2]) {
+    let theta = (-d.y()).clamp(-1., 1.).acos();
+    let phi = f32::atan2(-d.z(), d.x()) + PI;
+    let u = (phi / (2. * PI) - rotation / 360.).rem_euclid(1.);
+    let parallel = 2. * PI * radius * theta.sin();
+    (u, theta / PI, [1. / parallel, 1. / (PI * radius)])
+}
+
+/// The point of the unit sphere around the origin at the texture coordinates, the inverse
+/// of `sphere_uv`.
+pub(crate) fn sphere_point(u: f32, v: f32, rotation: f32) -> Vec3 {
+    let theta = v * PI;
+    let phi = 2. * PI * (u + rotation / 360.);
+    Vec3::new(-theta.sin() * phi.cos(), -theta.cos(), theta.sin() * phi.sin())
+}
@@ -0,0 +1,291 @@
+/**
+The streamed geometry, for the scenes larger than the memory (`--memory-budget`).
+
+With a memory budget the heavy meshes of the OBJ and the PLY files are not kept
+in the memory. Every one is written to a file of its own as soon as it is loaded,
+and the file is memory-mapped: the operating system reads the pages of the vertices
+and the triangles when the rays need them, and drops them again when the memory runs
+short, the pages can always be read again from the file. The files are made
+in the temporary directory (`TMPDIR`), which should be on a disk, not in the memory.
+A single mesh still has to fit in the memory while it is loaded.
+
+The tree of the triangles of a streamed mesh (its BLAS, the bottom level of the two levels
+of the trees, the world keeps the top one) is built when a ray first comes near the mesh.
+The trees are cached under the budget left by the rest of the render, and when a new one
+does not fit, the trees of the meshes used the longest ago are evicted, to be built
+again if a ray comes back to them.
+
+LEARN:
+A tree may be evicted while the rays of the other threads still walk it. The cache hands
+out `Arc`s of the trees, so the evicted tree is only taken out of the cache, and it is freed
+when the last ray walking it lets go of its `Arc`. The memory may exceed the budget
+by the trees in use for that moment, never by the trees nobody uses.
+
+LEARN:
+The memory map needs the `mmap` of the C library. As with the pinning of the threads,
+the standard library links it anyway, so it is declared and called directly.
+The other systems read the file into the memory instead.
+*/
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+
+use crate::math::vec::Vec3;
+use crate::scene::surfaces::bvh::Bvh;
+
+/// The meshes that take less than this stay in the memory even with a budget,
+/// the few bytes they would save are not worth the files and the builds of their trees.
+pub(crate) const MIN_STREAMED: usize = 1 << 20;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// How many files were made, the names of the files of the process differ by it.
+static FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts the builds of the trees, every tree remembers the count at its last use.
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// A tree built this many times is evicted while the rays still need it, see `Slot::tree`.
+const THRASHING: usize = 4;
+
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache { limit: None, used: 0, trees: Vec::new(), built: 0, evicted: 0 });
+
+/// Streams the heavy meshes loaded from now on, the setting of the process.
+pub(crate) fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Sets the bytes the cached trees may take, `None` keeps all of them. The trees built
+/// before, e.g. by the rays of the check of the scene, are evicted down to the limit at once.
+pub(crate) fn set_cache_limit(limit: Option<usize>) {
+    let mut cache = CACHE.lock().unwrap();
+    cache.limit = limit;
+    let evicted = cache.shrink(None);
+    drop(cache);
+    drop(evicted);
+}
+
+/// How many trees the process built and how many of them it evicted.
+pub(crate) fn activity() -> (usize, usize) {
+    let cache = CACHE.lock().unwrap();
+    (cache.built, cache.evicted)
+}
+
+/// The read-only memory map of a file.
+pub(crate) struct Mapping {
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    start: *const u8,
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    len: usize,
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    bytes: Vec<u8>,
+}
+
+// SAFETY: the mapping is never written, the threads only read it
+#[cfg(all(unix, target_pointer_width = "64"))]
+unsafe impl Send for Mapping {}
+#[cfg(all(unix, target_pointer_width = "64"))]
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    /// Writes the bytes of `write` into a new file of the temporary directory and maps it.
+    /// The file is removed at once, the system keeps it while it is mapped.
+    pub(crate) fn write(write: impl FnOnce(&mut dyn Write) -> Result<(), Error>) -> Result<Mapping, Error> {
+        let path = std::env::temp_dir()
+            .join(format!("raytracer-{}-{}.mesh", std::process::id(), FILES.fetch_add(1, Ordering::Relaxed)));
+        let mapped = write_file(&path, write).and_then(|()| Mapping::open(&path));
+        // the file of the other systems cannot be removed while it is open, it is read by now
+        let _ = std::fs::remove_file(&path);
+        mapped
+    }
+
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    fn open(path: &PathBuf) -> Result<Mapping, Error> {
+        use std::ffi::c_void;
+        use std::os::fd::AsRawFd;
+
+        extern "C" {
+            fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+        }
+        const PROT_READ: i32 = 1;
+        const MAP_PRIVATE: i32 = 2;
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Err(Error::other("the file of the mesh is empty"));
+        }
+        // SAFETY: the file was written by this process, it is removed right away and never changed,
+        // so the bytes stay what they were; the mapping stays valid after the file is closed
+        let start = unsafe { mmap(std::ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if start as isize == -1 {
+            return Err(Error::last_os_error());
+        }
+        Ok(Mapping { start: start as *const u8, len })
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    fn open(path: &PathBuf) -> Result<Mapping, Error> {
+        Ok(Mapping { bytes: std::fs::read(path)? })
+    }
+
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    pub(crate) fn bytes(&self) -> &[u8] {
+        // SAFETY: the mapping of the length is valid until it is dropped
+        unsafe { std::slice::from_raw_parts(self.start, self.len) }
+    }
+
+    #[cfg(not(all(unix, target_pointer_width = "64")))]
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        extern "C" {
+            fn munmap(addr: *mut std::ffi::c_void, len: usize) -> i32;
+        }
+        // SAFETY: the mapping is not used after it is dropped
+        unsafe { munmap(self.start as *mut std::ffi::c_void, self.len) };
+    }
+}
+
+fn write_file(path: &PathBuf, write: impl FnOnce(&mut dyn Write) -> Result<(), Error>) -> Result<(), Error> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write(&mut out)?;
+    out.flush()
+}
+
+/// The number of the little-endian bytes at the offset.
+pub(crate) fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("4 bytes"))
+}
+
+pub(crate) fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("4 bytes"))
+}
+
+pub(crate) fn vec3_at(bytes: &[u8], offset: usize) -> Vec3 {
+    Vec3::new(f32_at(bytes, offset), f32_at(bytes, offset + 4), f32_at(bytes, offset + 8))
+}
+
+pub(crate) fn write_vec3(out: &mut dyn Write, v: &Vec3) -> Result<(), Error> {
+    for c in [v.x(), v.y(), v.z()] {
+        out.write_all(&c.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// The tree of the triangles of a streamed mesh, over their indices in the file.
+pub(crate) struct Blas {
+    pub(crate) bvh: Bvh,
+    /// The triangles in the order of the tree.
+    pub(crate) order: Vec<u32>,
+}
+
+impl Blas {
+    fn memory(&self) -> usize {
+        self.bvh.memory() + self.order.capacity() * size_of::<u32>()
+    }
+}
+
+/// The place of the tree of a mesh in the cache, shared by the copies of the mesh.
+#[derive(Default)]
+pub(crate) struct Slot {
+    tree: RwLock<Option<Arc<Blas>>>,
+    /// Held while the tree is built, the other threads wait for it instead of building it too.
+    building: Mutex<()>,
+    last_used: AtomicU64,
+    /// How many times the tree was built.
+    builds: AtomicUsize,
+}
+
+impl Slot {
+    /// The tree of the slot, built by `build` unless it is cached.
+    pub(crate) fn tree(self: &Arc<Slot>, build: impl FnOnce() -> Blas) -> Arc<Blas> {
+        let epoch = EPOCH.load(Ordering::Relaxed);
+        // most rays find the epoch stored already, so they only read the shared memory
+        if self.last_used.load(Ordering::Relaxed) != epoch {
+            self.last_used.store(epoch, Ordering::Relaxed);
+        }
+        if let Some(tree) = self.tree.read().unwrap().as_ref() {
+            return Arc::clone(tree);
+        }
+        let _building = self.building.lock().unwrap();
+        // another thread may have built it while this one waited
+        if let Some(tree) = self.tree.read().unwrap().as_ref() {
+            return Arc::clone(tree);
+        }
+        let tree = Arc::new(build());
+        *self.tree.write().unwrap() = Some(Arc::clone(&tree));
+        admit(self, tree.memory());
+        // the tree the rays keep coming back to does not fit next to the others,
+        // the render spends its time building the trees instead of tracing the rays
+        if self.builds.fetch_add(1, Ordering::Relaxed) + 1 == THRASHING && !WARNED.swap(true, Ordering::Relaxed) {
+            eprintln!("Warning: the trees of the streamed meshes the rays need do not fit into the memory budget together, \
+                       they are built again and again and the render is slow, a larger --memory-budget would help.");
+        }
+        tree
+    }
+}
+
+/// The trees in the memory and the bytes they take.
+struct Cache {
+    limit: Option<usize>,
+    used: usize,
+    trees: Vec<(Weak<Slot>, usize)>,
+    built: usize,
+    evicted: usize,
+}
+
+/// Counts the new tree of the slot in the cache and evicts the trees used the longest ago
+/// until the cache fits its limit again, or only the new one is left.
+fn admit(slot: &Arc<Slot>, bytes: usize) {
+    let mut cache = CACHE.lock().unwrap();
+    EPOCH.fetch_add(1, Ordering::Relaxed);
+    cache.built += 1;
+    cache.used += bytes;
+    cache.trees.push((Arc::downgrade(slot), bytes));
+    let evicted = cache.shrink(Some(slot));
+    // the evicted slots are dropped after the lock, the last copy of a mesh may go with them
+    drop(cache);
+    drop(evicted);
+}
+
+impl Cache {
+    /// Evicts the trees used the longest ago, all but the one of `keep`, until the cache fits
+    /// its limit, and gives back their slots.
+    fn shrink(&mut self, keep: Option<&Arc<Slot>>) -> Vec<Arc<Slot>> {
+        // the trees of the meshes dropped since, e.g. of the scene of a previous frame
+        let (kept, gone): (Vec<_>, Vec<_>) = std::mem::take(&mut self.trees).into_iter().partition(|(slot, _)| slot.strong_count() > 0);
+        self.used -= gone.iter().map(|(_, bytes)| bytes).sum::<usize>();
+        self.trees = kept;
+
+        let mut evicted = Vec::new();
+        while self.limit.is_some_and(|limit| self.used > limit) {
+            let oldest = self.trees.iter()
+                .enumerate()
+                .filter_map(|(i, (other, _))| Some((i, other.upgrade()?)))
+                .filter(|(_, other)| keep.is_none_or(|slot| !Arc::ptr_eq(other, slot)))
+                .min_by_key(|(_, other)| other.last_used.load(Ordering::Relaxed));
+            let Some((i, other)) = oldest else { break };
+            other.tree.write().unwrap().take();
+            let (_, bytes) = self.trees.remove(i);
+            self.used -= bytes;
+            self.evicted += 1;
+            evicted.push(other);
+        }
+        evicted
+    }
+}
@@ -0,0 +1,113 @@
+/**
+The subdivision surfaces: the smooth shapes described by the coarse control meshes.
+
+Every step of the Loop subdivision splits each triangle into four, with the new
+vertices in the middles of the edges, and moves every vertex towards the weighted
+average of its neighbors. After a few steps the mesh converges to a smooth surface
+that follows the control mesh, so a rounded shape takes a few dozen control points
+instead of thousands of imported triangles. Each step quadruples the triangles.
+
+The open edges, the borders of a mesh that is not closed, stay on the curves
+through their own vertices, the surface does not shrink away from them.
+*/
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use crate::math::vec::Vec3;
+use crate::scene::obj::Model;
+use crate::scene::surfaces::mesh::weld;
+
+/// The control mesh subdivided `steps` times.
+pub(crate) fn subdivide(model: Model, steps: usize) -> Model {
+    (0..steps).fold(model, |model, _| step(model))
+}
+
+/// The edge between two vertices, by the lower index first.
+fn edge(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+fn step(model: Model) -> Model {
+    // the copies of a vertex along a seam of the texture coordinates are one vertex of the surface
+    let welded = weld(model.points.iter().map(|(p, _)| p));
+    let position = |i: usize| &model.points[i].0;
+
+    // the vertices opposite to every edge, one for each triangle the edge belongs to
+    let mut opposite: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (face, _) in &model.faces {
+        let [a, b, c] = face.map(|i| welded[i]);
+        for (from, to, other) in [(a, b, c), (b, c, a), (c, a, b)] {
+            opposite.entry(edge(from, to)).or_default().push(other);
+        }
+    }
+
+    // the neighbors of every vertex, and the ones along the open edges
+    let mut neighbors: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut border: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (&(a, b), others) in &opposite {
+        neighbors.entry(a).or_default().push(b);
+        neighbors.entry(b).or_default().push(a);
+        if others.len() == 1 {
+            border.entry(a).or_default().push(b);
+            border.entry(b).or_default().push(a);
+        }
+    }
+
+    // the old vertices move towards their neighbors
+    let moved: HashMap<usize, Vec3> = neighbors.iter()
+        .map(|(&v, around)| {
+            let p = position(v);
+            let p = match border.get(&v).map(Vec::as_slice) {
+                Some(&[a, b]) => 0.75 * p + 0.125 * (position(a) + position(b)),
+                // a corner where several open edges meet stays in place
+                Some(_) => p.clone(),
+                None => {
+                    // the weights of Loop, e.g. 3/16 of each of the three neighbors
+                    let n = around.len() as f32;
+                    let beta = (0.625 - (0.375 + 0.25 * (2. * PI / n).cos()).powi(2)) / n;
+                    let sum = around.iter().fold(Vec3::zero(), |sum, &u| sum + position(u));
+                    (1. - n * beta) * p + beta * sum
+                }
+            };
+            (v, p)
+        })
+        .collect();
+
+    // the new vertices in the middles of the edges
+    let middle = |a: usize, b: usize| {
+        let (wa, wb) = (welded[a], welded[b]);
+        let ends = position(wa) + position(wb);
+        match opposite[&edge(wa, wb)].as_slice() {
+            &[c, d] => 0.375 * ends + 0.125 * (position(c) + position(d)),
+            // the open edges, and the ones of more than two triangles
+            _ => 0.5 * ends,
+        }
+    };
+
+    let mut points: Vec<(Vec3, [f32; 2])> = model.points.iter()
+        .enumerate()
+        .map(|(i, (p, uv))| (moved.get(&welded[i]).unwrap_or(p).clone(), *uv))
+        .collect();
+    let mut colors = model.colors.clone();
+    // the triangles that share both vertices of an edge share its middle too
+    let mut middles: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut faces = Vec::with_capacity(4 * model.faces.len());
+    for (face, material) in &model.faces {
+        let [a, b, c] = *face;
+        let [ab, bc, ca] = [(a, b), (b, c), (c, a)].map(|(from, to)| {
+            *middles.entry(edge(from, to)).or_insert_with(|| {
+                let (p, q) = (&model.points[from], &model.points[to]);
+                points.push((middle(from, to), [(p.1[0] + q.1[0]) / 2., (p.1[1] + q.1[1]) / 2.]));
+                if !model.colors.is_empty() {
+                    colors.push(0.5 * (&model.colors[from] + &model.colors[to]));
+                }
+                points.len() - 1
+            })
+        });
+        faces.push(([a, ab, ca], material.clone()));
+        faces.push(([ab, b, bc], material.clone()));
+        faces.push(([ca, bc, c], material.clone()));
+        faces.push(([ab, bc, ca], material.clone()));
+    }
+    Model { points, colors, faces }
+}
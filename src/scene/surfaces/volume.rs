@@ -0,0 +1,160 @@
+/**
+The volumes: the smoke, the clouds and the fog whose density varies from place to place.
+
+A volume has no surface, the light is scattered by the particles inside of it.
+The density is sampled on a 3D grid in a box, e.g. exported from a simulation,
+and interpolated between the samples. The denser the place, the more likely a ray
+meets a particle there, so the ray goes through the thin wisps and stops in the thick core.
+
+LEARN:
+The distance to the next particle in a uniform medium is random, with the probability
+falling exponentially. In a varying one the delta (Woodcock) tracking makes it uniform:
+it steps through the volume as if it were everywhere as dense as its densest sample,
+and accepts a step as a real collision with the probability of the density there
+over the largest one. The rejected steps are the collisions with the imaginary particles
+that fill the difference, and the result is exactly the distance of the varying medium.
+*/
+use std::mem::size_of;
+use std::path::Path;
+
+use crate::math::rand::drand32;
+use crate::math::vec::{Ray, Vec3};
+use crate::scene::surfaces::bvh::Bounds;
+
+#[derive(Clone)]
+pub(crate) struct Volume {
+    corner: Vec3,
+    size: Vec3,
+    resolution: [usize; 3],
+    /// The densities, the particles per unit of the length, x changes the fastest, then y, then z.
+    densities: Vec<f32>,
+    /// The largest density, the one the tracking steps by.
+    majorant: f32,
+}
+
+impl Volume {
+    /// The grid of the densities stretched over the box from the corner, the densities
+    /// are multiplied by the scale.
+    pub(crate) fn new(corner: Vec3, size: Vec3, resolution: [usize; 3], densities: Vec<f32>, scale: f32) -> Volume {
+        assert_eq!(densities.len(), resolution.iter().product(), "every cell of the grid needs a density");
+        let densities: Vec<f32> = densities.into_iter().map(|density| density.max(0.) * scale).collect();
+        let majorant = densities.iter().fold(0., |max: f32, &density| max.max(density));
+        Volume { corner, size, resolution, densities, majorant }
+    }
+
+    /// The cells of the grid along x, y and z, the tweaker lists them.
+    #[cfg(feature = "tweaker")]
+    pub(crate) fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    pub(crate) fn bounding_box(&self) -> Bounds {
+        Bounds::around([&self.corner, &(&self.corner + &self.size)])
+    }
+
+    pub(crate) fn center(&self) -> Vec3 {
+        &self.corner + 0.5 * &self.size
+    }
+
+    pub(crate) fn translate(&mut self, offset: &Vec3) {
+        self.corner = &self.corner + offset;
+    }
+
+    pub(crate) fn memory(&self) -> usize {
+        self.densities.capacity() * size_of::<f32>()
+    }
+
+    /// The trilinear interpolation of the samples, which are in the centers of the cells.
+    fn density(&self, p: &Vec3) -> f32 {
+        let local = [p.x() - self.corner.x(), p.y() - self.corner.y(), p.z() - self.corner.z()];
+        let extent = [self.size.x(), self.size.y(), self.size.z()];
+        let mut cell = [0; 3];
+        let mut fraction = [0.; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let x = (local[axis] / extent[axis] * n as f32 - 0.5).clamp(0., (n - 1) as f32);
+            cell[axis] = (x as usize).min(n.saturating_sub(2));
+            fraction[axis] = x - cell[axis] as f32;
+        }
+        // a grid of a single cell along an axis has no neighbor there
+        let sample = |dx: usize, dy: usize, dz: usize| {
+            let [x, y, z] = [0, 1, 2].map(|axis| (cell[axis] + [dx, dy, dz][axis]).min(self.resolution[axis] - 1));
+            self.densities[(z * self.resolution[1] + y) * self.resolution[0] + x]
+        };
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let [fx, fy, fz] = fraction;
+        let y0 = lerp(lerp(sample(0, 0, 0), sample(1, 0, 0), fx), lerp(sample(0, 1, 0), sample(1, 1, 0), fx), fy);
+        let y1 = lerp(lerp(sample(0, 0, 1), sample(1, 0, 1), fx), lerp(sample(0, 1, 1), sample(1, 1, 1), fx), fy);
+        lerp(y0, y1, fz)
+    }
+
+    /// The distance to the particle the ray meets, the delta tracking through the box.
+    pub(crate) fn intersect(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<f32> {
+        if self.majorant <= 0. {
+            return None;
+        }
+        let (enter, exit) = self.enter_exit(r)?;
+        let (mut t, exit) = (enter.max(t_min), exit.min(t_max));
+        let speed = r.direction().length();
+        while t < exit {
+            t -= (1. - drand32()).ln() / (self.majorant * speed);
+            if t >= exit {
+                return None;
+            }
+            if drand32() * self.majorant < self.density(&r.point_at(t)) {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    /// Where the ray enters the box and where it leaves it, the slab test.
+    pub(crate) fn enter_exit(&self, r: &Ray) -> Option<(f32, f32)> {
+        let far = &self.corner + &self.size;
+        let (mut enter, mut exit) = (f32::MIN, f32::MAX);
+        for axis in 0..3 {
+            let inverse = 1. / r.direction()[axis];
+            let t0 = (self.corner[axis] - r.origin()[axis]) * inverse;
+            let t1 = (far[axis] - r.origin()[axis]) * inverse;
+            enter = enter.max(t0.min(t1));
+            exit = exit.min(t0.max(t1));
+        }
+        (enter < exit).then_some((enter, exit))
+    }
+}
+
+/// The densities of the grid and its resolution from a file, either the Mitsuba `.vol`
+/// that carries its resolution, or the raw little-endian 32-bit floats of the given one.
+pub(crate) fn load_grid(path: &Path, resolution: Option<[usize; 3]>) -> Result<([usize; 3], Vec<f32>), String> {
+    let bytes = std::fs::read(path).map_err(|why| format!("cannot read {}: {}", path.display(), why))?;
+    let error = |message: String| format!("{}: {}", path.display(), message);
+    let float = |chunk: &[u8]| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    let integer = |at: usize| i32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+
+    let (resolution, data) = if bytes.starts_with(b"VOL") {
+        // the magic, the version, the encoding (1 is float32), the resolution, the channels
+        // and the bounding box, which the scene sets instead
+        const HEADER: usize = 48;
+        if bytes.len() < HEADER || bytes[3] != 3 || integer(4) != 1 {
+            return Err(error("only the version 3 .vol of the 32-bit floats is supported".to_string()));
+        }
+        let [x, y, z] = [8, 12, 16].map(|at| integer(at).max(0) as usize);
+        if integer(20) != 1 {
+            return Err(error(format!("the grid has {} channels, the density needs 1", integer(20))));
+        }
+        ([x, y, z], &bytes[HEADER..])
+    } else {
+        let resolution = resolution.ok_or_else(|| error("the raw grid needs the `resolution`".to_string()))?;
+        (resolution, &bytes[..])
+    };
+    let count: usize = resolution.iter().product();
+    if count == 0 || data.len() != 4 * count {
+        return Err(error(format!("the grid of {}x{}x{} needs {} bytes of the densities, there are {}",
+                                 resolution[0], resolution[1], resolution[2], 4 * count, data.len())));
+    }
+    let densities: Vec<f32> = data.chunks_exact(4).map(float).collect();
+    if densities.iter().any(|density| !density.is_finite()) {
+        return Err(error("some densities are not finite".to_string()));
+    }
+    Ok((resolution, densities))
+}
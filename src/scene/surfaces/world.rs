@@ -1,43 +1,481 @@
-use crate::scene::surfaces::hitable::{Hitable, HitRecord};
+use std::sync::Arc;
+
+use crate::scene::arena::{Arena, Id};
+use crate::scene::environment::Environment;
+use crate::scene::fog::Fog;
+use crate::scene::light::Light;
+use crate::scene::portal::Portal;
+use crate::scene::sky::Sky;
+use crate::scene::stars::Stars;
+use crate::scene::material::Material;
+use crate::scene::shader::Shader;
+use crate::scene::surfaces::hitable::{Hitable, HitRecord, Intersection};
+use crate::scene::surfaces::bvh::Bounds;
+use crate::scene::surfaces::clip::ClipPlane;
 use crate::scene::surfaces::Surface;
-use crate::math::vec::Ray;
+use crate::math::vec::{Ray, Vec3};
 
+/// All the surfaces of the scene, the materials they are made of, the lights
+/// and the air between them.
+#[derive(Clone)]
 pub(crate) struct World {
-    objects: Vec<Surface>,
-    size: usize,
+    objects: Arena<Surface>,
+    /// The names of the objects, by the index of the object.
+    labels: Vec<Label>,
+    /// The priorities of the objects at the coincident hits, by the index of the object,
+    /// and whether any of them is not 0.
+    priorities: Vec<i32>,
+    prioritized: bool,
+    /// The hits closer to each other than this share of the distance along the ray
+    /// are coincident, the one of the higher priority wins.
+    coincidence: f32,
+    materials: Arena<Material>,
+    /// The shaders of the materials, by the index of the material, the ones after the last are none.
+    shaders: Vec<Option<Arc<dyn Shader>>>,
+    clip_planes: Vec<ClipPlane>,
+    lights: Vec<Light>,
+    portals: Vec<Portal>,
+    sky: Option<Sky>,
+    stars: Option<Stars>,
+    /// The image is shared by the copies of the world, like the textures.
+    environment: Option<Arc<Environment>>,
+    fog: Option<Fog>,
+    /// How far the objects move while the shutter is open, by the index of the object,
+    /// the ones after the last stand still, see `set_motion`.
+    motions: Vec<Vec3>,
+    /// The box around all the surfaces, and the same box a little larger,
+    /// the rays search the hits only as far as they stay within it.
+    bounds: Bounds,
+    reach: Bounds,
 }
 
 impl World {
-    pub(crate) fn new<'a>() -> World {
+    /// A little more than the rounding errors of the hits of the same plane by different surfaces.
+    pub(crate) const DEFAULT_COINCIDENCE: f32 = 1e-4;
+
+    pub(crate) fn new() -> World {
         World {
-            objects: Vec::new(),
-            size: 0,
+            objects: Arena::new(),
+            labels: Vec::new(),
+            priorities: Vec::new(),
+            prioritized: false,
+            coincidence: World::DEFAULT_COINCIDENCE,
+            materials: Arena::new(),
+            shaders: Vec::new(),
+            clip_planes: Vec::new(),
+            lights: Vec::new(),
+            portals: Vec::new(),
+            sky: None,
+            stars: None,
+            environment: None,
+            fog: None,
+            motions: Vec::new(),
+            bounds: Bounds::empty(),
+            reach: Bounds::empty(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, object: Surface) -> Id<Surface> {
+        self.bounds = self.bounds.clone().union(&object.bounding_box());
+        self.update_reach();
+        self.labels.push(Label::default());
+        self.priorities.push(0);
+        self.objects.alloc(object)
+    }
+
+    fn update_reach(&mut self) {
+        // the hits at the very side of the box must not fall out of it by the rounding
+        let (min, max) = self.bounds.corners();
+        self.reach = self.bounds.clone().padded(1e-4 * (1. + (max - min).length()));
+    }
+
+    /// Names the object, or tags it, so it can be found by the name, see `Scene::find`.
+    pub(crate) fn set_label(&mut self, id: Id<Surface>, label: Label) {
+        self.labels[id.index()] = label;
+    }
+
+    pub(crate) fn label(&self, id: Id<Surface>) -> &Label {
+        &self.labels[id.index()]
+    }
+
+    /// Makes the object win over the objects of the lower priority where their surfaces coincide,
+    /// e.g. the decal on the wall, instead of the speckle of both where the rounding decides.
+    pub(crate) fn set_priority(&mut self, id: Id<Surface>, priority: i32) {
+        self.priorities[id.index()] = priority;
+        self.prioritized = self.priorities.iter().any(|&priority| priority != 0);
+    }
+
+    /// Sets how close the hits are to be coincident, as the share of the distance along the ray.
+    pub(crate) fn set_coincidence(&mut self, coincidence: f32) {
+        self.coincidence = coincidence;
+    }
+
+    /// The objects named so or tagged so, in the order they were added.
+    pub(crate) fn find(&self, name: &str) -> Vec<Id<Surface>> {
+        self.objects.ids().filter(|&id| self.label(id).matches(name)).collect()
+    }
+
+    /// Makes the whole object of the material, e.g. of a new one for another variant of the scene.
+    pub(crate) fn set_material(&mut self, id: Id<Surface>, material: Id<Material>) {
+        self.objects.get_mut(id).set_material(material);
+    }
+
+    /// Moves the objects by their offsets, the box of the world follows them.
+    pub(crate) fn translate(&mut self, moves: impl IntoIterator<Item = (Id<Surface>, Vec3)>) {
+        for (id, offset) in moves {
+            self.objects.get_mut(id).translate(&offset);
+        }
+        // the box may also shrink, it is computed again once for all the moves
+        self.bounds = (0..self.size()).fold(Bounds::empty(), |bounds, object| bounds.union(&self.swept_box(object)));
+        self.update_reach();
+    }
+
+    /// Moves the object by the offset while the shutter is open: the ray of the time
+    /// sees it that share of the way along, see `Ray::time`, so it is blurred along its way.
+    pub(crate) fn set_motion(&mut self, id: Id<Surface>, motion: Vec3) {
+        if self.motions.len() <= id.index() {
+            self.motions.resize(id.index() + 1, Vec3::zero());
+        }
+        self.motions[id.index()] = motion;
+        self.bounds = self.bounds.clone().union(&self.swept_box(id.index()));
+        self.update_reach();
+    }
+
+    /// Whether any object moves while the shutter is open.
+    pub(crate) fn is_moving(&self) -> bool {
+        !self.motions.is_empty()
+    }
+
+    /// The box around the object all the way it moves while the shutter is open.
+    fn swept_box(&self, object: usize) -> Bounds {
+        let bounds = self.objects()[object].bounding_box();
+        match self.motions.get(object) {
+            Some(motion) => bounds.translated(motion).union(&bounds),
+            None => bounds,
+        }
+    }
+
+    /// The ray as the object that moves sees it from where it was when the shutter opened,
+    /// `None` when the object stands still.
+    fn seen_by(&self, object: usize, r: &Ray) -> Option<Ray> {
+        self.motions.get(object).map(|motion| r.translated(&(-r.time() * motion)))
+    }
+
+    /// Adds everything of the other world to this one: its objects with their names
+    /// and priorities, its materials with their shaders, its lights, portals and clip planes
+    /// (which cut all the objects). The sky, the environment or the stars of the other world
+    /// replace the ones of this one, as its fog does. The coincidence of this one stays.
+    pub(crate) fn merge(&mut self, other: World) {
+        let World { objects, labels, priorities, materials, shaders, clip_planes, lights, portals, sky, stars, environment, fog, .. } = other;
+        let offset = self.materials.len();
+        for material in materials.into_vec() {
+            self.materials.alloc(material);
+        }
+        // the shaders go by the index of the material, the materials of this one may have none
+        self.shaders.resize(offset, None);
+        self.shaders.extend(shaders);
+        for ((mut object, label), priority) in objects.into_vec().into_iter().zip(labels).zip(priorities) {
+            object.shift_materials(offset);
+            let id = self.add(object);
+            self.set_label(id, label);
+            if priority != 0 {
+                self.set_priority(id, priority);
+            }
+        }
+        self.clip_planes.extend(clip_planes);
+        self.lights.extend(lights);
+        self.portals.extend(portals);
+        if sky.is_some() || stars.is_some() || environment.is_some() {
+            (self.sky, self.stars, self.environment) = (sky, stars, environment);
+        }
+        if fog.is_some() {
+            self.fog = fog;
+        }
+    }
+
+    /// The box around all the surfaces, none when there are none.
+    pub(crate) fn bounding_box(&self) -> Option<&Bounds> {
+        (!self.bounds.is_empty()).then_some(&self.bounds)
+    }
+
+    /// Adds the material the surfaces can refer to, many surfaces may share one material.
+    pub(crate) fn add_material(&mut self, material: Material) -> Id<Material> {
+        self.materials.alloc(material)
+    }
+
+    /// Makes the shader change the light of the material, see `shader`.
+    pub(crate) fn set_shader(&mut self, id: Id<Material>, shader: Arc<dyn Shader>) {
+        if self.shaders.len() <= id.index() {
+            self.shaders.resize(id.index() + 1, None);
+        }
+        self.shaders[id.index()] = Some(shader);
+    }
+
+    pub(crate) fn shader(&self, id: Id<Material>) -> Option<&Arc<dyn Shader>> {
+        self.shaders.get(id.index()).and_then(Option::as_ref)
+    }
+
+    /// Cuts away the geometry on the side of the plane the normal points to.
+    pub(crate) fn add_clip_plane(&mut self, plane: ClipPlane) {
+        self.clip_planes.push(plane);
+    }
+
+    pub(crate) fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    pub(crate) fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// The lights to change, e.g. by the script of an animation.
+    #[cfg(feature = "script")]
+    pub(crate) fn lights_mut(&mut self) -> &mut [Light] {
+        &mut self.lights
+    }
+
+    /// Marks the window the sky lights the interior through.
+    pub(crate) fn add_portal(&mut self, portal: Portal) {
+        self.portals.push(portal);
+    }
+
+    pub(crate) fn portals(&self) -> &[Portal] {
+        &self.portals
+    }
+
+    pub(crate) fn set_sky(&mut self, sky: Sky) {
+        self.sky = Some(sky);
+    }
+
+    /// The sky of the sun at the place and the time, `None` for the plain gradient.
+    pub(crate) fn sky(&self) -> Option<&Sky> {
+        self.sky.as_ref()
+    }
+
+    pub(crate) fn set_stars(&mut self, stars: Stars) {
+        self.stars = Some(stars);
+    }
+
+    /// The starry night sky, `None` for the environment map, the sky of the sun or the gradient.
+    pub(crate) fn stars(&self) -> Option<&Stars> {
+        self.stars.as_ref()
+    }
+
+    pub(crate) fn set_environment(&mut self, environment: Environment) {
+        self.environment = Some(Arc::new(environment));
+    }
+
+    /// The environment map around the scene, `None` for the sky or the plain gradient.
+    pub(crate) fn environment(&self) -> Option<&Environment> {
+        self.environment.as_deref()
+    }
+
+    pub(crate) fn set_fog(&mut self, fog: Fog) {
+        self.fog = Some(fog);
+    }
+
+    /// The fog every ray passes through, `None` in the clear air.
+    pub(crate) fn fog(&self) -> Option<&Fog> {
+        self.fog.as_ref()
+    }
+
+    pub(crate) fn material(&self, id: Id<Material>) -> &Material {
+        self.materials.get(id)
+    }
+
+    /// Whether any material samples a texture, the only lookups the footprints of the rays filter.
+    pub(crate) fn has_textures(&self) -> bool {
+        self.materials.as_slice().iter().any(|material| matches!(material, Material::Lambertian { texture: Some(_), .. }))
+    }
+
+    /// The material to change, e.g. by the sliders of the tweaker.
+    #[cfg(feature = "tweaker")]
+    pub(crate) fn material_mut(&mut self, id: Id<Material>) -> &mut Material {
+        self.materials.get_mut(id)
+    }
+
+    pub(crate) fn object(&self, id: Id<Surface>) -> &Surface {
+        self.objects.get(id)
+    }
+
+    pub(crate) fn objects(&self) -> &[Surface] {
+        self.objects.as_slice()
+    }
+
+    pub(crate) fn ids(&self) -> impl Iterator<Item = Id<Surface>> + '_ {
+        self.objects.ids()
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// The bytes held by the surfaces and the materials.
+    pub(crate) fn memory(&self) -> usize {
+        self.objects.memory()
+            + self.objects().iter().map(Surface::memory).sum::<usize>()
+            + self.materials.memory()
+    }
+}
+
+/// The name of an object and its tags, all optional.
+///
+/// The name is meant for a single object (`hero`), the tags for the groups (`glass`, `background`),
+/// but both are searched the same way, an edit of a name changes all the objects of that name.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Label {
+    pub(crate) name: Option<String>,
+    pub(crate) tags: Vec<String>,
+}
+
+impl Label {
+    /// Whether the name or one of the tags is the one searched for.
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        self.name.as_deref() == Some(name) || self.tags.iter().any(|tag| tag == name)
+    }
+}
+
+/// The nearest hit of the world: the surface that was hit, and where.
+pub(crate) struct WorldIntersection {
+    pub(crate) t: f32,
+    /// The index of the object.
+    object: usize,
+    part: Part,
+}
+
+impl WorldIntersection {
+    /// The index of the object that was hit, in `World::objects`.
+    pub(crate) fn object(&self) -> usize {
+        self.object
+    }
+}
+
+enum Part {
+    Surface(Intersection),
+    /// The cap of the clip plane of the index.
+    Cap(usize),
+}
+
+impl World {
+    /// How far apart the coincident hits may be at the distance.
+    fn tie(&self, t: f32) -> f32 {
+        self.coincidence * t.abs().max(1.)
+    }
+
+    /// Whether the hit is taken instead of the nearest one so far: it is nearer,
+    /// or the two coincide and its object has the higher priority.
+    fn wins(&self, hit: &WorldIntersection, nearest: &WorldIntersection) -> bool {
+        let (priority, nearest_priority) = (self.priorities[hit.object], self.priorities[nearest.object]);
+        if priority == nearest_priority || (hit.t - nearest.t).abs() > self.tie(hit.t.min(nearest.t)) {
+            return hit.t < nearest.t;
         }
+        priority > nearest_priority
     }
 
-    pub(crate) fn add(&mut self, object: Surface) {
-        self.objects.push(object);
-        self.size += 1;
+    /// The nearest hit of the object that is not cut away by the clip planes, or of its caps.
+    fn intersect_clipped(&self, object: usize, surface: &Surface, r: &Ray, t_min: f32, t_max: f32) -> Option<WorldIntersection> {
+        let on_surface = |hit: Intersection| WorldIntersection { t: hit.t, object, part: Part::Surface(hit) };
+        // the moving surface is hit where it is at the time, the clip planes stay
+        let seen = self.seen_by(object, r);
+        let local = seen.as_ref().unwrap_or(r);
+        if self.clip_planes.is_empty() {
+            return surface.intersect(local, t_min, t_max).map(on_surface);
+        }
+        let clipped_by_others = |p: &Vec3, skip: Option<&ClipPlane>| self.clip_planes.iter()
+            .filter(|plane| !skip.is_some_and(|skip| std::ptr::eq(*plane, skip)))
+            .any(|plane| plane.clips(p));
+
+        // skip the hits in the removed part, the next one along the ray may be kept
+        let mut t_from = t_min;
+        let mut hit = loop {
+            match surface.intersect(local, t_from, t_max) {
+                Some(hit) if clipped_by_others(&r.point_at(hit.t), None) => t_from = hit.t,
+                other => break other.map(on_surface),
+            }
+        };
+
+        // the ray crosses the cap where the plane cuts through the inside of the solid,
+        // the smoke is cut without any, it has no surface to close
+        let capped = !matches!(surface, Surface::Volume { .. });
+        for (index, plane) in self.clip_planes.iter().enumerate().filter(|(_, plane)| capped && plane.cap) {
+            let t_nearest = hit.as_ref().map_or(t_max, |hit| hit.t);
+            if let Some(t) = plane.intersect(r).filter(|t| *t > t_min && *t < t_nearest) {
+                let p = r.point_at(t);
+                // the cap is where the ray is inside the solid, the point is on the plane itself,
+                // so only the other planes can clip it
+                let inside = surface.intervals(local, t_min, t_nearest)
+                    .is_some_and(|intervals| intervals.iter().any(|interval| interval.contains(t)));
+                if inside && !clipped_by_others(&p, Some(plane)) {
+                    hit = Some(WorldIntersection { t, object, part: Part::Cap(index) });
+                }
+            }
+        }
+        hit
     }
 }
 
 impl Hitable for World {
-    fn hit<'a>(&'a self, r: &'a Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let mut temp_rec = None;
+    type Intersection = WorldIntersection;
+
+    fn intersect(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<WorldIntersection> {
+        // nothing is hit beyond the box of the world, the ray that misses it misses everything
+        let (_, exit) = self.reach.interval(r, t_min, t_max)?;
+        let mut nearest = None;
 
-        let mut closest_so_far = t_max;
+        let mut closest_so_far = exit;
 
         // We cannot use the monadic behavior here as we need to update the closest value.
-        for hitable in self.objects.iter() {
-            match hitable.hit(r, t_min, closest_so_far) {
-                Some(rec) => {
-                    closest_so_far = rec.t;
-                    temp_rec = Some(rec);
-                },
-                None => {},
+        let visible = self.objects().iter().enumerate().filter(|(_, surface)| surface.visibility().sees(r.kind()));
+        for (object, surface) in visible {
+            // the hit a little behind the closest one may still win by its priority
+            let reach = match nearest {
+                Some(_) if self.prioritized => (closest_so_far + self.tie(closest_so_far)).min(exit),
+                _ => closest_so_far,
+            };
+            if let Some(hit) = self.intersect_clipped(object, surface, r, t_min, reach) {
+                if nearest.as_ref().is_none_or(|nearest| self.wins(&hit, nearest)) {
+                    closest_so_far = hit.t;
+                    nearest = Some(hit);
+                }
             }
         }
 
-        temp_rec
+        nearest
     }
-}
\ No newline at end of file
+
+    fn occluded(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        let Some((_, t_max)) = self.reach.interval(r, t_min, t_max) else {
+            return false;
+        };
+        let mut visible = self.objects().iter().enumerate().filter(|(_, surface)| surface.visibility().sees(r.kind()));
+        // the clip planes may have cut away the hit, the cut solid needs its nearest hits
+        if self.clip_planes.is_empty() {
+            visible.any(|(object, surface)| surface.occluded(self.seen_by(object, r).as_ref().unwrap_or(r), t_min, t_max))
+        } else {
+            visible.any(|(object, surface)| self.intersect_clipped(object, surface, r, t_min, t_max).is_some())
+        }
+    }
+
+    fn shade(&self, r: &Ray, hit: &WorldIntersection) -> HitRecord {
+        let surface = &self.objects()[hit.object];
+        match hit.part {
+            Part::Surface(intersection) => match self.seen_by(hit.object, r) {
+                // the point is where the surface is at the time of the ray
+                Some(seen) => HitRecord { p: r.point_at(hit.t), ..surface.shade(&seen, &intersection) },
+                None => surface.shade(r, &intersection),
+            },
+            // the caps are not textured, the coordinates are the same all over them
+            Part::Cap(plane) => HitRecord {
+                t: hit.t,
+                p: r.point_at(hit.t),
+                normal: self.clip_planes[plane].normal().clone(),
+                u: 0.,
+                v: 0.,
+                uv_rate: [0., 0.],
+                color: None,
+                tangent: None,
+                material: surface.material(),
+            },
+        }
+    }
+}
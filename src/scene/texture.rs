@@ -0,0 +1,249 @@
+/**
+The textures: the colors that vary over the surface.
+
+A texture is looked up by the (u, v) coordinates of the hit, both 0..1.
+For the spheres u goes around the vertical axis and v from the south pole to the north,
+so the equirectangular images (e.g. the maps of the Earth) wrap around them.
+
+The coordinates can be transformed before the lookup, so one image is tiled
+over a large surface or shifted and turned on another one without editing it.
+*/
+use std::io::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::math::vec::Vec3;
+use crate::renderer::output::Image;
+use crate::renderer::ppm;
+use crate::scene::texture::procedural::Procedural;
+
+pub(crate) mod procedural;
+
+#[derive(Clone)]
+pub(crate) enum Texture {
+    /// The image is shared by all the materials that use it, it is loaded once.
+    Image(Arc<ImageTexture>),
+    Procedural(Arc<Procedural>),
+    /// The texture looked up at the transformed coordinates.
+    Transformed(Box<Texture>, UvTransform),
+}
+
+impl Texture {
+    /// The linear color at the texture coordinates and the point `p` of the surface,
+    /// averaged over the footprint: the size of the area seen by the pixel along u and v.
+    pub(crate) fn value(&self, u: f32, v: f32, p: &Vec3, footprint: [f32; 2]) -> Vec3 {
+        match self {
+            Texture::Image(image) => image.value(u, v, footprint),
+            Texture::Procedural(procedural) => procedural.value(u, v, p, footprint),
+            Texture::Transformed(texture, transform) => {
+                let (u, v) = transform.apply(u, v);
+                texture.value(u, v, p, transform.footprint(footprint))
+            }
+        }
+    }
+
+    pub(crate) fn with_transform(self, transform: UvTransform) -> Texture {
+        if transform == UvTransform::IDENTITY {
+            self
+        } else {
+            Texture::Transformed(Box::new(self), transform)
+        }
+    }
+}
+
+/// Where the texture coordinates of the surface land on the texture.
+///
+/// The coordinates are scaled first, e.g. the scale 4 repeats the texture 4 times,
+/// then turned counterclockwise around (0, 0) and shifted by the offset,
+/// which is in the units of the texture, 0.5 moves it by a half.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct UvTransform {
+    pub(crate) scale: [f32; 2],
+    pub(crate) offset: [f32; 2],
+    /// In degrees.
+    pub(crate) rotation: f32,
+}
+
+impl UvTransform {
+    pub(crate) const IDENTITY: UvTransform = UvTransform { scale: [1., 1.], offset: [0., 0.], rotation: 0. };
+
+    fn apply(&self, u: f32, v: f32) -> (f32, f32) {
+        let (u, v) = (u * self.scale[0], v * self.scale[1]);
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        (cos * u - sin * v + self.offset[0], sin * u + cos * v + self.offset[1])
+    }
+
+    /// The size of the footprint after the transform, the bounding box of the turned one.
+    fn footprint(&self, [du, dv]: [f32; 2]) -> [f32; 2] {
+        let (du, dv) = (du * self.scale[0].abs(), dv * self.scale[1].abs());
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (sin, cos) = (sin.abs(), cos.abs());
+        [cos * du + sin * dv, sin * du + cos * dv]
+    }
+}
+
+/// The image with its colors converted to the linear values the renderer works with,
+/// and its mipmaps.
+///
+/// A distant textured surface fits many pixels of the image into a pixel of the render.
+/// A few samples of them pick the pixels at random, and the texture sparkles and shows
+/// the moire. The mipmaps are the copies of the image, each half the size of the previous one,
+/// so every pixel of a level is the average of four of the level above. The lookup picks
+/// the levels whose pixels are about as large as the footprint of the pixel of the render.
+pub(crate) struct ImageTexture {
+    /// From the full size down to 1x1.
+    levels: Vec<Level>,
+}
+
+struct Level {
+    w: usize,
+    h: usize,
+    /// Row by row from the top-left corner, the top row is at v = 1.
+    pixels: Vec<Vec3>,
+    /// The average colors of the bottom and the top rows.
+    poles: [Vec3; 2],
+}
+
+impl ImageTexture {
+    pub(crate) fn load(path: &Path) -> Result<ImageTexture, Error> {
+        Ok(ImageTexture::from_image(&ppm::read(path)?))
+    }
+
+    pub(crate) fn from_image(image: &Image) -> ImageTexture {
+        let decode = |c: u8| image.color_space.decode(c as f32 / 255.);
+        let pixels = image.pixels.iter()
+            .map(|&[r, g, b]| Vec3::new(decode(r), decode(g), decode(b)))
+            .collect();
+        let mut levels = vec![Level::new(image.w, image.h, pixels)];
+        while let Some(last) = levels.last().filter(|level| level.w > 1 || level.h > 1) {
+            levels.push(last.half());
+        }
+        ImageTexture { levels }
+    }
+
+    /// Blends the two levels around the footprint, so there is no visible line
+    /// where the lookup moves from one level to the next. The longer side of the footprint
+    /// picks the levels, the texture is rather blurred than sparkling.
+    fn value(&self, u: f32, v: f32, [du, dv]: [f32; 2]) -> Vec3 {
+        let texels = (du * self.levels[0].w as f32).max(dv * self.levels[0].h as f32);
+        let lod = texels.max(1.).log2().min((self.levels.len() - 1) as f32);
+        let (lower, t) = (lod.floor() as usize, lod.fract());
+        let color = self.levels[lower].value(u, v);
+        if t > 0. {
+            (1. - t) * color + t * self.levels[lower + 1].value(u, v)
+        } else {
+            color
+        }
+    }
+
+    /// The latitude and longitude grid, every 15 degrees, with the prime meridian (the middle
+    /// of the image, as on the world maps) in red and the equator in green.
+    /// It shows how the texture is mapped without an image file.
+    pub(crate) fn grid(w: usize, h: usize) -> ImageTexture {
+        let pixels = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let (lon, lat) = (x * 24 / w, y * 12 / h);
+                if x == w / 2 {
+                    [200, 30, 30]
+                } else if y == h / 2 {
+                    [30, 160, 30]
+                } else if (lon + lat) % 2 == 0 {
+                    [40, 70, 140]
+                } else {
+                    [200, 200, 210]
+                }
+            })
+            .collect();
+        ImageTexture::from_image(&Image { w, h, color_space: Default::default(), pixels, biases: Vec::new(), notes: Vec::new() })
+    }
+
+    /// The number in black on the white card with a black border, the label of a marker.
+    /// The digits are 3 by 5 cells of the size in texels, the other characters are left blank.
+    pub(crate) fn label(text: &str, cell: usize) -> ImageTexture {
+        let chars: Vec<char> = text.chars().collect();
+        // a cell of the margin around the text and one between the digits
+        let (w, h) = ((4 * chars.len() + 1) * cell, 7 * cell);
+        let pixels = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let border = x < cell / 4 || y < cell / 4 || x >= w - cell / 4 || y >= h - cell / 4;
+                let (column, row) = (x / cell, y / cell);
+                let ink = (1..=5).contains(&row) && column >= 1 && column % 4 != 0 && chars
+                    .get((column - 1) / 4)
+                    .and_then(|c| c.to_digit(10))
+                    .is_some_and(|digit| DIGITS[digit as usize][row - 1] >> (2 - (column - 1) % 4) & 1 == 1);
+                if border || ink { [20, 20, 20] } else { [235, 235, 235] }
+            })
+            .collect();
+        ImageTexture::from_image(&Image { w, h, color_space: Default::default(), pixels, biases: Vec::new(), notes: Vec::new() })
+    }
+}
+
+/// The digits of the labels, 5 rows of 3 bits from the top, the highest bit on the left.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+impl Level {
+    fn new(w: usize, h: usize, pixels: Vec<Vec3>) -> Level {
+        let average = |row: &[Vec3]| row.iter().fold(Vec3::zero(), |sum, c| sum + c) / row.len() as f32;
+        let poles = [average(&pixels[(h - 1) * w..]), average(&pixels[..w])];
+        Level { w, h, pixels, poles }
+    }
+
+    /// The next smaller level, the odd last row or column is averaged in twice.
+    fn half(&self) -> Level {
+        let (w, h) = ((self.w / 2).max(1), (self.h / 2).max(1));
+        let pixel = |x: usize, y: usize| &self.pixels[y.min(self.h - 1) * self.w + x.min(self.w - 1)];
+        let pixels = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w * 2, i / w * 2);
+                (pixel(x, y) + pixel(x + 1, y) + pixel(x, y + 1) + pixel(x + 1, y + 1)) / 4.
+            })
+            .collect();
+        Level::new(w, h, pixels)
+    }
+
+    /// The bilinear interpolation of the four nearest pixels.
+    ///
+    /// The image wraps around horizontally, so there is no seam where its left and right
+    /// edges meet. The whole top (or bottom) row maps to a single point, the pole,
+    /// so the color fades to the average of the row there instead of pinching
+    /// into a star of the pixels of the row. Outside 0..1 the image repeats vertically
+    /// too, as the tiled textures expect.
+    fn value(&self, u: f32, v: f32) -> Vec3 {
+        let v = if (0. ..=1.).contains(&v) { v } else { v.rem_euclid(1.) };
+        let x = u.rem_euclid(1.) * self.w as f32 - 0.5;
+        let y = (1. - v) * self.h as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let column = |x: f32| (x as isize).rem_euclid(self.w as isize) as usize;
+        let row = |y: f32| (y as isize).clamp(0, self.h as isize - 1) as usize;
+        let pixel = |x: f32, y: f32| &self.pixels[row(y) * self.w + column(x)];
+        let top = (1. - fx) * pixel(x0, y0) + fx * pixel(x0 + 1., y0);
+        let bottom = (1. - fx) * pixel(x0, y0 + 1.) + fx * pixel(x0 + 1., y0 + 1.);
+        let color = (1. - fy) * top + fy * bottom;
+
+        // within the half of the pixel from the pole
+        if y < 0. {
+            let t = -2. * y;
+            (1. - t) * color + t * &self.poles[1]
+        } else if y > self.h as f32 - 1. {
+            let t = 2. * (y - (self.h as f32 - 1.));
+            (1. - t) * color + t * &self.poles[0]
+        } else {
+            color
+        }
+    }
+}
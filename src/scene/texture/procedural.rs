@@ -0,0 +1,116 @@
+/**
+The textures computed from the coordinates instead of read from an image.
+
+The bricks, the stripes and the checker are laid out in the texture coordinates, one brick
+(half a unit high), one pair of the stripes or two by two squares per unit, so the scale of the texture transform
+sets how many of them cover the surface. The gradient and the Voronoi cells are
+solid textures: they are defined everywhere in the space, and the surface shows
+the slice of them it cuts through, so there are no seams and no stretching at the poles.
+The texture transform does not move them.
+
+The pattern is no better than an image when the pixel covers many bricks, stripes or squares,
+so those fade to their average color as the footprint grows, like the mipmaps do.
+*/
+use crate::math::rand::splitmix64;
+use crate::math::vec::Vec3;
+
+pub(crate) enum Procedural {
+    /// Every other row is shifted by half a brick.
+    Bricks {
+        brick: Vec3,
+        mortar: Vec3,
+        /// The width of the mortar joints relative to the height of a brick.
+        mortar_width: f32,
+    },
+    /// The stripes along v, the first color from u = 0 to 0.5, the second one to 1.
+    Stripes {
+        colors: [Vec3; 2],
+    },
+    /// The squares half a unit wide, the first color in the one at the origin.
+    Checker {
+        colors: [Vec3; 2],
+    },
+    /// Changes from the first color at the point `from` to the second one at `to`,
+    /// the colors continue beyond the points.
+    Gradient {
+        from: Vec3,
+        to: Vec3,
+        colors: [Vec3; 2],
+    },
+    /// The cells around the random points, one in every cube of the size.
+    /// Every cell has its own color between the two colors.
+    Voronoi {
+        size: f32,
+        colors: [Vec3; 2],
+    },
+}
+
+impl Procedural {
+    pub(crate) fn value(&self, u: f32, v: f32, p: &Vec3, [du, dv]: [f32; 2]) -> Vec3 {
+        let footprint = du.max(dv);
+        match self {
+            Procedural::Bricks { brick, mortar, mortar_width } => {
+                let row = (2. * v).floor();
+                // in the lengths and the heights of a brick, it is twice as long as high
+                let x = (u + 0.5 * row).rem_euclid(1.);
+                let y = 2. * v - row;
+                // half of every joint is on each side of it
+                let joint = mortar_width / 2.;
+                let in_mortar = y < joint || y > 1. - joint || x < joint / 2. || x > 1. - joint / 2.;
+                let color = if in_mortar { mortar } else { brick };
+                let mortar_area = (mortar_width + mortar_width / 2. - mortar_width * mortar_width / 2.).min(1.);
+                let average = (1. - mortar_area) * brick + mortar_area * mortar;
+                fade(color.clone(), average, footprint)
+            }
+            Procedural::Stripes { colors } => {
+                let color = &colors[(u.rem_euclid(1.) >= 0.5) as usize];
+                fade(color.clone(), (&colors[0] + &colors[1]) / 2., footprint)
+            }
+            Procedural::Checker { colors } => {
+                let odd = ((2. * u).floor() + (2. * v).floor()).rem_euclid(2.) >= 1.;
+                fade(colors[odd as usize].clone(), (&colors[0] + &colors[1]) / 2., footprint)
+            }
+            Procedural::Gradient { from, to, colors } => {
+                let axis = to - from;
+                let t = (Vec3::dot(&(p - from), &axis) / axis.squared_length()).clamp(0., 1.);
+                (1. - t) * &colors[0] + t * &colors[1]
+            }
+            Procedural::Voronoi { size, colors } => {
+                let t = voronoi(&(p / *size));
+                (1. - t) * &colors[0] + t * &colors[1]
+            }
+        }
+    }
+}
+
+/// Fades the color to the average as the footprint grows from a quarter of the pattern
+/// to the whole of it.
+fn fade(color: Vec3, average: Vec3, footprint: f32) -> Vec3 {
+    let t = ((footprint - 0.25) / 0.75).clamp(0., 1.);
+    (1. - t) * color + t * average
+}
+
+/// The random value 0..1 of the cell of the point, in the units of the cell size.
+///
+/// Every unit cube has one random point in it, and the cell of a point is the one
+/// of the nearest of those. The nearest point is always in the cube of the point
+/// or in one of its 26 neighbours.
+fn voronoi(p: &Vec3) -> f32 {
+    let cube = [p.x().floor(), p.y().floor(), p.z().floor()];
+    let mut nearest = (f32::MAX, 0);
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            for dz in -1..=1 {
+                let corner = [cube[0] + dx as f32, cube[1] + dy as f32, cube[2] + dz as f32];
+                let hash = corner.iter().fold(0u64, |hash, c| splitmix64(hash ^ *c as i64 as u64));
+                let random = |bits: u32| ((hash >> bits) & 0xffff) as f32 / 65536.;
+                let point = Vec3::new(corner[0] + random(0), corner[1] + random(16), corner[2] + random(32));
+                let distance = (&point - p).squared_length();
+                if distance < nearest.0 {
+                    nearest = (distance, hash);
+                }
+            }
+        }
+    }
+    (nearest.1 >> 48) as f32 / 65536.
+}
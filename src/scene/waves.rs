@@ -0,0 +1,55 @@
+/**
+The waves: the ripples of the water surface, without the geometry of them.
+
+A calm sea would need millions of tiny triangles to show its ripples, but from
+any distance only the way they bend the reflections and the refractions is seen.
+The waves tilt the normal the material sees at the hit point instead, the surface
+stays flat (the normal mapping), so they cost nothing to build and nothing to store.
+
+The tilt is the slope of the sum of a few sine waves, each shorter than the one before,
+running in the different directions, mostly along the horizontal, like the wind waves.
+Together they look irregular enough, on any surface, not only on the level one.
+*/
+use std::f32::consts::PI;
+
+use crate::math::vec::Vec3;
+
+/// The number of the sine waves, every one is shorter than the one before by `SHORTER`.
+const WAVES: usize = 8;
+const SHORTER: f32 = 0.7;
+
+/// The turn between the directions of the consecutive waves, the golden angle,
+/// so no two of them run the same way.
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+#[derive(Clone)]
+pub(crate) struct Waves {
+    /// The longest wave, from a crest to the next one.
+    pub(crate) length: f32,
+    /// Half the height of the longest wave, from a trough to a crest, the shorter ones
+    /// are as low as they are short, so all of them are equally steep.
+    pub(crate) height: f32,
+}
+
+impl Waves {
+    /// The normal of the rippled surface at the point, tilted from the normal of the flat one.
+    pub(crate) fn normal(&self, p: &Vec3, normal: &Vec3) -> Vec3 {
+        let mut slope = Vec3::zero();
+        let mut length = self.length;
+        // a * k, the steepest slope of the sine of the amplitude a and the wave number k
+        let steepness = self.height * 2. * PI / self.length;
+        for i in 0..WAVES {
+            let angle = i as f32 * GOLDEN_ANGLE;
+            let rise = (i % 3) as f32 * 0.25 - 0.25;
+            let direction = Vec3::new(angle.cos(), rise, angle.sin()).unit();
+            let k = 2. * PI / length;
+            // the phases are scattered too, or all the crests would meet at the origin
+            let phase = 3. * angle;
+            slope += steepness * (k * Vec3::dot(&direction, p) + phase).cos() * direction;
+            length *= SHORTER;
+        }
+        // only the slope along the surface tilts it
+        let along = &slope - Vec3::dot(&slope, normal) * normal;
+        (normal - along).unit()
+    }
+}
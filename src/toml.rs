@@ -0,0 +1,553 @@
+/**
+A small parser of the subset of TOML used by the scene and configuration files.
+
+Supported: comments, `[table]` and `[[array.of.tables]]` headers (dotted paths),
+`key = value` pairs, strings, integers, floats, booleans, arrays (may span lines)
+and inline tables. Not supported: dates, multi-line strings and dotted keys.
+
+LEARN:
+Writing a parser by hand is a good exercise on ownership: the parser owns the input,
+the values own their data, and the navigation through the nested tables
+has to convince the borrow checker that only one mutable reference is alive at a time.
+*/
+use std::fmt::{Display, Formatter};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+/// Key/value pairs in the order they appear in the file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Table {
+    entries: Vec<(String, Value)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ParseError {
+    pub(crate) line: usize,
+    pub(crate) message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Value {
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+
+    /// Integers are accepted where floats are expected, `fov = 20` reads better than `fov = 20.0`.
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_table(&self) -> Option<&Table> {
+        match self {
+            Value::Table(table) => Some(table),
+            _ => None,
+        }
+    }
+}
+
+/// The value written the way the parser reads it back, the tables inline.
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Integer(i) => write!(f, "{}", i),
+            // the debug format keeps the fraction of the whole floats, 1.0 stays a float
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", quote(s)),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Table(table) => write!(f, "{}", table),
+        }
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.entries.is_empty() {
+            return write!(f, "{{}}");
+        }
+        let entries: Vec<String> = self.entries.iter().map(|(key, value)| format!("{} = {}", quote_key(key), value)).collect();
+        write!(f, "{{ {} }}", entries.join(", "))
+    }
+}
+
+impl Table {
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(k, _)| k.as_str())
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.entries.iter_mut().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// The table of the key to change it, `None` when the value is not a table.
+    pub(crate) fn table_mut(&mut self, key: &str) -> Option<&mut Table> {
+        match self.get_mut(key) {
+            Some(Value::Table(table)) => Some(table),
+            _ => None,
+        }
+    }
+
+    /// Replaces the value of the key, or adds the key at the end.
+    pub(crate) fn set(&mut self, key: &str, value: Value) {
+        match self.get_mut(key) {
+            Some(old) => *old = value,
+            None => self.entries.push((key.to_string(), value)),
+        }
+    }
+
+    /// Renames the key where it is, returns whether the table has it.
+    pub(crate) fn rename(&mut self, from: &str, to: &str) -> bool {
+        match self.entries.iter_mut().find(|(k, _)| k == from) {
+            Some((key, _)) => {
+                *key = to.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, key: String, value: Value) -> Result<(), String> {
+        if self.get(&key).is_some() {
+            return Err(format!("duplicate key `{}`", key));
+        }
+        self.entries.push((key, value));
+        Ok(())
+    }
+}
+
+pub(crate) fn parse(text: &str) -> Result<Table, ParseError> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0, line: 1 };
+    parser.document().map_err(|message| ParseError { line: parser.line, message })
+}
+
+/// The string as a TOML value, with the quotes and the escapes the parser reads back.
+pub(crate) fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The key as it is written, quoted unless it is a bare key.
+fn quote_key(key: &str) -> String {
+    let bare = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare { key.to_string() } else { quote(key) }
+}
+
+// The typed accessors of the values with the errors that name the key.
+
+/// Reads the key with `read` if it is present.
+pub(crate) fn optional<T>(table: &Table, key: &str, read: fn(&Table, &str) -> Result<T, String>) -> Result<Option<T>, String> {
+    match table.get(key) {
+        Some(_) => read(table, key).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn table<'a>(doc: &'a Table, key: &str) -> Result<Option<&'a Table>, String> {
+    match doc.get(key) {
+        Some(Value::Table(table)) => Ok(Some(table)),
+        Some(other) => Err(format!("`{}` must be a table, not {}", key, other.type_name())),
+        None => Ok(None),
+    }
+}
+
+/// The tables of an array of tables, e.g. all `[[sphere]]` entries.
+pub(crate) fn tables<'a>(doc: &'a Table, key: &str) -> Result<Vec<&'a Table>, String> {
+    match doc.get(key) {
+        Some(Value::Array(items)) => items.iter()
+            .map(|item| item.as_table().ok_or(format!("`{}` must be an array of tables", key)))
+            .collect(),
+        Some(other) => Err(format!("`{}` must be an array of tables, not {}", key, other.type_name())),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub(crate) fn integer(table: &Table, key: &str) -> Result<i64, String> {
+    match table.get(key) {
+        Some(value) => value.as_integer()
+            .ok_or(format!("`{}` must be an integer, not {}", key, value.type_name())),
+        None => Err(format!("missing `{}`", key)),
+    }
+}
+
+pub(crate) fn boolean(table: &Table, key: &str) -> Result<bool, String> {
+    match table.get(key) {
+        Some(value) => value.as_bool()
+            .ok_or(format!("`{}` must be a boolean, not {}", key, value.type_name())),
+        None => Err(format!("missing `{}`", key)),
+    }
+}
+
+pub(crate) fn string(table: &Table, key: &str) -> Result<String, String> {
+    match table.get(key) {
+        Some(value) => value.as_str()
+            .map(str::to_string)
+            .ok_or(format!("`{}` must be a string, not {}", key, value.type_name())),
+        None => Err(format!("missing `{}`", key)),
+    }
+}
+
+pub(crate) fn number(table: &Table, key: &str) -> Result<f32, String> {
+    match table.get(key) {
+        Some(value) => value.as_f64()
+            .map(|n| n as f32)
+            .ok_or(format!("`{}` must be a number, not {}", key, value.type_name())),
+        None => Err(format!("missing `{}`", key)),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        self.pos += 1;
+        c
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some('\n') => Err(format!("expected `{}`, found the end of the line", expected)),
+            Some(c) => Err(format!("expected `{}`, found `{}`", expected, c)),
+            None => Err(format!("expected `{}`, found the end of the file", expected)),
+        }
+    }
+
+    /// Skips spaces and tabs, and optionally the new lines and the comments.
+    fn skip_whitespace(&mut self, new_lines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => { self.bump(); }
+                '\n' if new_lines => { self.bump(); }
+                '#' if new_lines => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.bump();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// After a value or a header only a comment may follow on the same line.
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_whitespace(false);
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some('#') => {
+                self.skip_whitespace(true);
+                Ok(())
+            }
+            Some(c) => Err(format!("unexpected `{}` after the value", c)),
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, String> {
+        let mut root = Table::default();
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_whitespace(true);
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.bump();
+                    let array = self.peek() == Some('[');
+                    if array {
+                        self.bump();
+                    }
+                    let path = self.key_path()?;
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                        let (last, parent) = path.split_last().expect("key path is never empty");
+                        let parent = navigate(&mut root, parent)?;
+                        match parent.get_mut(last) {
+                            Some(Value::Array(items)) => items.push(Value::Table(Table::default())),
+                            Some(_) => return Err(format!("`{}` is not an array of tables", last)),
+                            None => parent.insert(last.clone(), Value::Array(vec![Value::Table(Table::default())]))?,
+                        }
+                    } else {
+                        navigate(&mut root, &path)?;
+                    }
+                    self.end_of_line()?;
+                    current = path;
+                }
+                Some(_) => {
+                    let key = self.key()?;
+                    self.skip_whitespace(false);
+                    self.expect('=')?;
+                    self.skip_whitespace(false);
+                    let value = self.value()?;
+                    navigate(&mut root, &current)?.insert(key, value)?;
+                    self.end_of_line()?;
+                }
+            }
+        }
+    }
+
+    fn key_path(&mut self) -> Result<Vec<String>, String> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_whitespace(false);
+            path.push(self.key()?);
+            self.skip_whitespace(false);
+            if self.peek() == Some('.') {
+                self.bump();
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            self.bump();
+        }
+        if start == self.pos {
+            return Err(match self.peek() {
+                Some(c) => format!("expected a key, found `{}`", c),
+                None => "expected a key, found the end of the file".to_string(),
+            });
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some('t') | Some('f') => {
+                let word = self.key()?;
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => Err(format!("unknown value `{}`, strings must be quoted", word)),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => self.number(),
+            Some(c) => Err(format!("unexpected `{}`, expected a value", c)),
+            None => Err("expected a value, found the end of the file".to_string()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.bump() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some(c) => return Err(format!("unknown escape sequence `\\{}`", c)),
+                    None => return Err("unterminated string".to_string()),
+                },
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || "+-._".contains(c)) {
+            self.bump();
+        }
+        let text: String = self.chars[start..self.pos].iter().filter(|&&c| c != '_').collect();
+        if let Ok(i) = text.parse::<i64>() {
+            return Ok(Value::Integer(i));
+        }
+        text.parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| format!("invalid number `{}`", text))
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace(true);
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_whitespace(true);
+            match self.peek() {
+                Some(',') => { self.bump(); }
+                Some(']') => {}
+                _ => return Err("expected `,` or `]` in the array".to_string()),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut table = Table::default();
+        loop {
+            self.skip_whitespace(true);
+            if self.peek() == Some('}') {
+                self.bump();
+                return Ok(Value::Table(table));
+            }
+            let key = self.key()?;
+            self.skip_whitespace(false);
+            self.expect('=')?;
+            self.skip_whitespace(false);
+            let value = self.value()?;
+            table.insert(key, value)?;
+            self.skip_whitespace(true);
+            match self.peek() {
+                Some(',') => { self.bump(); }
+                Some('}') => {}
+                _ => return Err("expected `,` or `}` in the inline table".to_string()),
+            }
+        }
+    }
+}
+
+/// Finds (or creates) the table at the path. When a part of the path is an array
+/// of tables, the last table of the array is used, as the TOML specification says.
+fn navigate<'a>(mut table: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String> {
+    for key in path {
+        if table.get(key).is_none() {
+            table.insert(key.clone(), Value::Table(Table::default()))?;
+        }
+        // LEARN:
+        // `table` is reassigned with a reference derived from itself.
+        // The borrow checker allows that because the old reference is never used again.
+        table = match table.get_mut(key) {
+            Some(Value::Table(t)) => t,
+            Some(Value::Array(items)) => match items.last_mut() {
+                Some(Value::Table(t)) => t,
+                _ => return Err(format!("`{}` is not a table", key)),
+            },
+            _ => return Err(format!("`{}` is not a table", key)),
+        };
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Value};
+
+    #[test]
+    fn parses_what_display_writes() {
+        let text = r#"
+            a = 1
+            b = -2.0
+            c = "quoted \"name\" and \\ back"
+            d = [true, false]
+            e = { x = [1, 2.5], "key with spaces" = { nested = "yes" } }
+            f = []
+            g = {}
+        "#;
+        let doc = parse(text).unwrap();
+        for key in doc.keys() {
+            let value = doc.get(key).unwrap();
+            let written = format!("value = {}", value);
+            let read = parse(&written).unwrap_or_else(|why| panic!("{}: {}", written, why));
+            assert_eq!(read.get("value"), Some(value), "{}", written);
+        }
+    }
+
+    #[test]
+    fn keeps_the_floats_floats() {
+        let doc = parse("whole = 1.0").unwrap();
+        let written = format!("whole = {}", doc.get("whole").unwrap());
+        assert_eq!(written, "whole = 1.0");
+        assert!(matches!(parse(&written).unwrap().get("whole"), Some(Value::Float(_))));
+    }
+}
@@ -0,0 +1,274 @@
+/**
+The interactive scene tweaker (`--tweak`): a window with the sliders of the camera,
+the sampling and the materials next to the image, which is rendered again on every change.
+
+Every change renders the quick preview first and then refines it to the full quality
+pass by pass, so it works as a playground to learn how the parameters affect the image.
+The finished image is written to the output, as the render without the window would write it.
+It lives behind the `tweaker` feature, off by default, so the builds for the render nodes
+leave out the window and its dependencies.
+
+LEARN:
+The window must answer the mouse while the image renders, so the render runs on a thread
+of its own. Every change sends the scene and the settings to that thread and bumps
+the generation, a counter shared by both: the render checks it after every pass
+and drops the stale image as soon as the generation is not the one it renders.
+The thread gives the images back through a `Mutex`, the window takes the latest one
+when it repaints, and the thread asks it to repaint after every image.
+*/
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use eframe::egui;
+
+use crate::math::vec::Vec3;
+use crate::renderer;
+use crate::renderer::framebuffer::FrameBuffer;
+use crate::renderer::output;
+use crate::renderer::settings::RenderSettings;
+use crate::scene::material::Material;
+use crate::scene::surfaces::Surface;
+use crate::scene::Scene;
+
+/// The passes the full quality image is refined in at least, the image shows after each.
+const PASSES: usize = 8;
+
+/// The scene and the settings to render, and the generation of the change that made them.
+struct Job {
+    scene: Scene,
+    settings: RenderSettings,
+    generation: usize,
+}
+
+/// The latest image of the render and what it is, e.g. `Pass 3 of 8...`.
+struct Shown {
+    image: egui::ColorImage,
+    status: String,
+}
+
+struct Tweaker {
+    scene: Scene,
+    settings: RenderSettings,
+    /// The object whose material the sliders edit.
+    selected: usize,
+    jobs: Sender<Job>,
+    generation: Arc<AtomicUsize>,
+    shown: Arc<Mutex<Option<Shown>>>,
+    texture: Option<egui::TextureHandle>,
+    status: String,
+}
+
+/// Opens the window of the scene, returns when it is closed.
+pub(crate) fn run(scene: Scene, settings: &RenderSettings, output: &Path) -> Result<(), String> {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_title("raytracer tweaker").with_inner_size([1280., 720.]),
+        ..Default::default()
+    };
+    let settings = settings.clone();
+    let output = output.to_path_buf();
+    eframe::run_native("raytracer tweaker", options, Box::new(move |creation| {
+        let (jobs, received) = mpsc::channel();
+        let generation = Arc::new(AtomicUsize::new(0));
+        let shown = Arc::new(Mutex::new(None));
+        let (latest, rendered, ctx) = (Arc::clone(&generation), Arc::clone(&shown), creation.egui_ctx.clone());
+        std::thread::spawn(move || render_changes(received, latest, rendered, ctx, output));
+        let tweaker = Tweaker { scene, settings, selected: 0, jobs, generation, shown, texture: None, status: String::new() };
+        tweaker.render();
+        Ok(Box::new(tweaker))
+    })).map_err(|why| format!("cannot open the window of the tweaker: {}", why))
+}
+
+impl eframe::App for Tweaker {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(shown) = self.shown.lock().unwrap().take() {
+            match &mut self.texture {
+                Some(texture) => texture.set(shown.image, egui::TextureOptions::LINEAR),
+                None => self.texture = Some(ctx.load_texture("render", shown.image, egui::TextureOptions::LINEAR)),
+            }
+            self.status = shown.status;
+        }
+
+        let mut changed = false;
+        egui::SidePanel::left("controls").default_width(280.).show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                changed |= self.camera_controls(ui);
+                ui.separator();
+                changed |= ui.add(egui::Slider::new(&mut self.settings.samples, 1..=4096).logarithmic(true).text("samples")).changed();
+                ui.separator();
+                changed |= self.material_controls(ui);
+                ui.separator();
+                ui.label(&self.status);
+            });
+        });
+        egui::CentralPanel::default().show(ctx, |ui| match &self.texture {
+            Some(texture) => {
+                ui.centered_and_justified(|ui| ui.add(egui::Image::new(texture).shrink_to_fit()));
+            }
+            None => {
+                ui.label("Rendering the preview...");
+            }
+        });
+        if changed {
+            self.render();
+        }
+    }
+}
+
+impl Tweaker {
+    /// Starts rendering the scene as it is now, the render of the previous change stops.
+    fn render(&self) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        // the thread is gone only when the window closes
+        let _ = self.jobs.send(Job { scene: self.scene.clone(), settings: self.settings.clone(), generation });
+    }
+
+    /// The sliders of the camera, true when any of them changed.
+    fn camera_controls(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut setup = self.scene.camera.setup();
+        ui.heading("Camera");
+        let changed = drag_vec3(ui, "look from", &mut setup.look_from)
+            | drag_vec3(ui, "look at", &mut setup.look_at)
+            | ui.add(egui::Slider::new(&mut setup.vfov, 1. ..=150.).text("field of view")).changed()
+            | ui.add(egui::Slider::new(&mut setup.aperture, 0. ..=2.).text("aperture")).changed()
+            | ui.add(egui::Slider::new(&mut setup.focus_dist, 0.01..=1000.).logarithmic(true).text("focus distance")).changed();
+        if changed {
+            self.scene.camera = setup.build(self.scene.aspect());
+        }
+        changed
+    }
+
+    /// The object to edit and the sliders of its material, true when any of them changed.
+    /// The objects sharing the material change together.
+    fn material_controls(&mut self, ui: &mut egui::Ui) -> bool {
+        let world = &mut self.scene.world;
+        ui.heading("Material");
+        if world.size() == 0 {
+            ui.label("The scene has no objects.");
+            return false;
+        }
+        egui::ComboBox::from_label("object")
+            .selected_text(format!("#{} {}", self.selected, kind(&world.objects()[self.selected])))
+            .show_ui(ui, |ui| {
+                for (i, surface) in world.objects().iter().enumerate() {
+                    ui.selectable_value(&mut self.selected, i, format!("#{} {}", i, kind(surface)));
+                }
+            });
+        let material = world.objects()[self.selected].material();
+        // LEARN:
+        // Matching on a mutable reference gives mutable references to the fields,
+        // so the sliders change the material in place.
+        match world.material_mut(material) {
+            Material::Lambertian { albedo, .. } | Material::Isotropic { albedo } => color(ui, "albedo", albedo),
+            Material::Metal { albedo, fuzz, .. } =>
+                color(ui, "albedo", albedo) | ui.add(egui::Slider::new(fuzz, 0. ..=1.).text("fuzz")).changed(),
+            Material::Hair { color: tint, roughness } =>
+                color(ui, "color", tint) | ui.add(egui::Slider::new(roughness, 0. ..=1.).text("roughness")).changed(),
+            Material::Dielectric { ref_idx, .. } => ui.add(egui::Slider::new(ref_idx, 1. ..=3.).text("index of refraction")).changed(),
+            Material::Emissive { radiance } => {
+                let mut strength = radiance.x().max(radiance.y()).max(radiance.z());
+                let changed = ui.add(egui::Slider::new(&mut strength, 0. ..=100.).logarithmic(true).text("radiance")).changed();
+                if changed {
+                    let peak = radiance.x().max(radiance.y()).max(radiance.z());
+                    *radiance = match peak > 0. {
+                        true => strength / peak * &*radiance,
+                        false => Vec3::new(strength, strength, strength),
+                    };
+                }
+                changed
+            }
+        }
+    }
+}
+
+/// Renders the jobs one after another on the thread of its own: the preview first,
+/// then the full quality in passes, writing the finished image to the output.
+/// Stops with the job as soon as the generation moves past it.
+fn render_changes(jobs: Receiver<Job>, generation: Arc<AtomicUsize>, shown: Arc<Mutex<Option<Shown>>>, ctx: egui::Context, output: PathBuf) {
+    while let Ok(mut job) = jobs.recv() {
+        // only the last of the changes made while the previous one rendered is worth rendering
+        while let Ok(next) = jobs.try_recv() {
+            job = next;
+        }
+        let stale = || generation.load(Ordering::SeqCst) != job.generation;
+        let show = |frame: &FrameBuffer, status: String| {
+            *shown.lock().unwrap() = Some(Shown { image: display(frame, &job.settings), status });
+            ctx.request_repaint();
+        };
+        let now = Instant::now();
+
+        let (w, h, _) = job.settings.resolution(&job.scene);
+        let preview = RenderSettings {
+            width: Some((w / RenderSettings::PREVIEW_DOWNSCALE).max(1)),
+            height: Some((h / RenderSettings::PREVIEW_DOWNSCALE).max(1)),
+            resolution_scale: 1.,
+            ..job.settings.preview_settings()
+        };
+        show(&renderer::render_to_buffer(&job.scene, &preview), "Preview, refining...".to_string());
+        if stale() {
+            continue;
+        }
+
+        let settings = RenderSettings { passes: job.settings.passes.max(PASSES), ..job.settings.clone() };
+        let rendered = renderer::render_to_buffer_progressively(&job.scene, &settings, |frame, pass, passes| {
+            if stale() {
+                return Err(());
+            }
+            show(frame, format!("Pass {} of {}...", pass + 1, passes));
+            Ok(())
+        });
+        let Ok(frame) = rendered else { continue };
+        let written = renderer::write_buffer(&job.scene, &job.settings, &output, &frame);
+        show(&frame, match written {
+            Ok(_) => format!("Rendered in {} ms, written to {}", now.elapsed().as_millis(), output.display()),
+            Err(why) => format!("Rendered in {} ms, cannot write {}: {}", now.elapsed().as_millis(), output.display(), why),
+        });
+    }
+}
+
+/// The frame as the image file would show it, for the window.
+fn display(frame: &FrameBuffer, settings: &RenderSettings) -> egui::ColorImage {
+    let exposure = settings.auto_exposure.map_or(0., |key| output::metered_exposure(frame, key));
+    let image = output::encode(frame, exposure, settings);
+    egui::ColorImage::from_rgb([image.w, image.h], image.pixels.as_flattened())
+}
+
+/// Three fields of the coordinates of the point, true when any of them changed.
+fn drag_vec3(ui: &mut egui::Ui, label: &str, v: &mut Vec3) -> bool {
+    let mut xyz = [v.x(), v.y(), v.z()];
+    let changed = ui.horizontal(|ui| {
+        let changed = xyz.iter_mut().fold(false, |changed, value| changed | ui.add(egui::DragValue::new(value).speed(0.05)).changed());
+        ui.label(label);
+        changed
+    }).inner;
+    if changed {
+        *v = Vec3::new(xyz[0], xyz[1], xyz[2]);
+    }
+    changed
+}
+
+/// The color picker of the color, true when it changed.
+fn color(ui: &mut egui::Ui, label: &str, c: &mut Vec3) -> bool {
+    let mut rgb = [c.x(), c.y(), c.z()];
+    let changed = ui.horizontal(|ui| {
+        let changed = ui.color_edit_button_rgb(&mut rgb).changed();
+        ui.label(label);
+        changed
+    }).inner;
+    if changed {
+        *c = Vec3::new(rgb[0], rgb[1], rgb[2]);
+    }
+    changed
+}
+
+fn kind(surface: &Surface) -> String {
+    match surface {
+        Surface::Sphere { radius, .. } => format!("sphere r={}", radius),
+        Surface::Mesh { mesh, .. } => format!("{} mesh of {} vertices", mesh.kind, mesh.vertex_count()),
+        Surface::Curves { curves, .. } => format!("curves of {} pieces", curves.len()),
+        Surface::Points { points, .. } => format!("{} points", points.positions().len()),
+        Surface::Volume { volume, .. } => format!("volume of {:?} cells", volume.resolution()),
+    }
+}
@@ -0,0 +1,58 @@
+/**
+The watch mode: the scene file is rendered again every time it is saved.
+*/
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::renderer;
+use crate::renderer::settings::RenderSettings;
+use crate::scene::edit::Edit;
+use crate::scene::file;
+
+/// How often the modification time of the scene file is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Renders the scene file, with the layers over it, with the preview quality whenever
+/// the scene file changes. Never returns, the user stops it with Ctrl+C.
+///
+/// LEARN:
+/// The `!` (never) return type tells the compiler the function does not return,
+/// so the caller may use it where any type is expected.
+pub(crate) fn watch(scene_path: &Path, layers: &[PathBuf], edits: &[Edit], settings: &RenderSettings, output: &Path) -> ! {
+    // Full quality renders take minutes, while the author wants to see the change right away.
+    let settings = settings.preview_settings();
+    let mut last_modified: Option<SystemTime> = None;
+
+    println!("Watching {} for changes, press Ctrl+C to stop.", scene_path.display());
+
+    loop {
+        // Polling the modification time is not as efficient as the OS notifications
+        // (inotify, FSEvents), but it works everywhere without any dependencies.
+        let modified = std::fs::metadata(scene_path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified != last_modified {
+            last_modified = modified;
+
+            let now = Instant::now();
+            // The file may be broken while it is being edited, so the errors are reported
+            // and the watcher waits for the next change.
+            let result = file::load(scene_path)
+                .and_then(|mut scene| {
+                    layers.iter().try_for_each(|layer| file::layer(&mut scene, layer))?;
+                    Ok(scene)
+                })
+                .map_err(|why| why.to_string())
+                .and_then(|mut scene| {
+                    edits.iter().try_for_each(|edit| edit.apply(&mut scene))?;
+                    Ok(scene)
+                })
+                .and_then(|scene| renderer::render_scene(&scene, &settings, output)
+                    .map_err(|why| why.to_string()));
+            match result {
+                Ok(_) => println!("Rendered {} in {} ms.", output.display(), now.elapsed().as_millis()),
+                Err(why) => eprintln!("Error: {}", why),
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}